@@ -0,0 +1,102 @@
+/*
+ * Commands sent over a session with a mock server on a loopback port.
+ *
+ * The server answers every command with its own text, and `big` with 64 packets of 4000 bytes.
+ * Before and after reworking the codec, median times per command from a release build on a 4 core
+ * x86-64 Linux VM:
+ *
+ *                  before    after
+ *   echo           20.2 µs   20.6 µs
+ *   big (256 KB)   1.43 ms   0.55 ms
+ *
+ * Long responses were read a packet's worth at a time and every packet of them decoded twice,
+ * once when parsed and again when putting the response together. They're now read 64 KiB at a
+ * time into a buffer kept between commands, and only packets ending partway through a character
+ * or color code are decoded again. A short command's time is the round trip over loopback.
+ */
+
+use clap::Parser;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rustcon::{Args, Rcon};
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    thread,
+};
+
+const BIG_PACKETS: usize = 64;
+const BIG_BODY_LEN: usize = 4000;
+
+fn packet(id: i32, typ: i32, body: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&(body.len() as i32 + 10).to_le_bytes());
+    bytes.extend_from_slice(&id.to_le_bytes());
+    bytes.extend_from_slice(&typ.to_le_bytes());
+    bytes.extend_from_slice(body);
+    bytes.extend_from_slice(&[0, 0]);
+    bytes
+}
+
+/// Serve one session on a free port, accepting any password, returning the port
+fn serve() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        conn.set_nodelay(true).unwrap();
+        let big = packet(0, 0, &[b'x'; BIG_BODY_LEN]);
+        loop {
+            let mut size = [0; 4];
+            if conn.read_exact(&mut size).is_err() {
+                return;
+            }
+            let mut rest = vec![0; i32::from_le_bytes(size) as usize];
+            conn.read_exact(&mut rest).unwrap();
+            let id = i32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]);
+            let typ = i32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]);
+            let body = &rest[8..rest.len() - 2];
+
+            let response = match (typ, body) {
+                (3, _) => packet(id, 2, b""),
+                (_, b"big") => {
+                    let mut big = big.clone();
+                    big[4..8].copy_from_slice(&id.to_le_bytes());
+                    big.repeat(BIG_PACKETS)
+                }
+                _ => packet(id, 0, body),
+            };
+            conn.write_all(&response).unwrap();
+        }
+    });
+    port
+}
+
+fn connect() -> Rcon {
+    let port = serve().to_string();
+    let args = Args::parse_from([
+        "rustcon",
+        "--ip",
+        "127.0.0.1",
+        "--port",
+        &port,
+        "--game",
+        "minecraft",
+    ]);
+    let mut profile = args.resolve().unwrap();
+    profile.password = Some("pw".to_string());
+    Rcon::connect_unattended(&profile.client()).unwrap()
+}
+
+fn echo(c: &mut Criterion) {
+    let mut rcon = connect();
+    let mut group = c.benchmark_group("echo");
+    group.bench_function("echo", |b| b.iter(|| rcon.send_cmd("list").unwrap()));
+    group.throughput(Throughput::Bytes((BIG_PACKETS * BIG_BODY_LEN) as u64));
+    group.bench_function("big", |b| {
+        b.iter(|| assert_eq!(rcon.send_cmd("big").unwrap().len(), BIG_PACKETS))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, echo);
+criterion_main!(benches);
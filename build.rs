@@ -0,0 +1,15 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // Generate the gRPC service from proto/rustcon.proto without needing protoc installed
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/rustcon.proto");
+        let descriptors = protox::compile(["proto/rustcon.proto"], ["proto"])
+            .expect("proto/rustcon.proto is valid");
+        tonic_build::configure()
+            .build_client(false)
+            .compile_fds(descriptors)
+            .expect("gRPC code generation succeeds");
+    }
+}
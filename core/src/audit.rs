@@ -0,0 +1,72 @@
+/*
+ * Append-only audit log of the commands sent to a profile's server.
+ *
+ * Each command is one JSON line with the time, where it came from, the profile, the command, the
+ * size of the response and any error, so shared servers can tell who ran what. The log is rotated
+ * as the profile's `log_rotation` says.
+ */
+
+use crate::{config::ClientConfig, logfile::LogFile, RconError};
+use serde_json::json;
+use std::sync::Mutex;
+
+/// Source recorded for sessions that don't set their own, named after the subcommand running
+static DEFAULT_SOURCE: Mutex<String> = Mutex::new(String::new());
+
+/// Name the source of every session opened from now on, like `tty` or `schedule`
+pub fn set_default_source(source: &str) {
+    *DEFAULT_SOURCE.lock().unwrap_or_else(|e| e.into_inner()) = source.to_string();
+}
+
+pub(crate) fn default_source() -> String {
+    let source = DEFAULT_SOURCE.lock().unwrap_or_else(|e| e.into_inner());
+    if source.is_empty() {
+        "cli".to_string()
+    } else {
+        source.clone()
+    }
+}
+
+/// Where a server's commands are recorded
+#[derive(Debug, Clone)]
+pub(crate) struct AuditLog {
+    log: LogFile,
+    profile: String,
+}
+
+impl AuditLog {
+    /// The server's `audit_log`, if it has one
+    pub fn new(config: &ClientConfig) -> Option<AuditLog> {
+        config.audit_log.clone().map(|path| AuditLog {
+            log: LogFile::new(path, config.log_rotation),
+            profile: config
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("{}:{}", config.ip, config.port)),
+        })
+    }
+
+    /// Append a command and its outcome, `bytes` is `None` for commands sent without waiting for
+    /// a response
+    pub fn record(&self, source: &str, command: &str, result: Result<Option<usize>, &RconError>) {
+        let (bytes, error) = match result {
+            Ok(bytes) => (bytes, None),
+            Err(e) => (None, Some(format!("{:?}", e))),
+        };
+        let entry = json!({
+            "timestamp": chrono::Local::now().to_rfc3339(),
+            "source": source,
+            "profile": self.profile,
+            "command": command,
+            "bytes": bytes,
+            "error": error,
+        });
+        if let Err(e) = self.log.append(&format!("{}\n", entry)) {
+            log::error!(
+                "Unable to write the audit log {}: {}",
+                self.log.path.display(),
+                e
+            );
+        }
+    }
+}
@@ -0,0 +1,1071 @@
+/*
+ * Sessions with a server over TCP: connecting, logging in, and sending commands and reading their
+ * responses, one at a time or pipelined.
+ *
+ * Behind the `net` feature, so the packets and game dialects build where there are no sockets.
+ */
+
+mod pipeline;
+
+pub use pipeline::{Pipeline, Reply};
+
+use crate::{
+    audit, command_allowed, frame,
+    game::{self, ColorCodes},
+    packet_size, sanitize, telnet, tolerate,
+    traffic::Counters,
+    whole_len, ClientConfig, Game, Packet, PacketError, PacketType, Protocol, RconError, Traffic,
+    Truncated, BAD_AUTH, PACKET_BODY_MAX_LEN, PACKET_SIZE_FIELD_LEN, PACKET_SIZE_MIN,
+};
+use bytes::BytesMut;
+use std::{
+    collections::VecDeque,
+    convert::TryFrom,
+    env,
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    mem,
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use zeroize::Zeroizing;
+
+/// Commands given up on that are remembered, so their late packets can be told apart
+const ABANDONED_MAX: usize = 16;
+/// Bytes asked of the connection at once when the stream is split on size fields, so long
+/// responses take a few reads rather than one per packet
+const READ_CHUNK: usize = 64 * 1024;
+/// How long the server may go quiet before what arrived is taken as the whole response
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Whether the plain text password warning was shown, it's only shown once per process
+static WARNED_PLAINTEXT: AtomicBool = AtomicBool::new(false);
+
+/// Passwords typed at a prompt that the server accepted, by address, so reconnecting the same
+/// session doesn't ask again
+static PROMPTED: Mutex<Vec<(String, Zeroizing<String>)>> = Mutex::new(Vec::new());
+
+/// RCON connection struct for handling sending and receiving RCON packets
+pub struct Rcon {
+    /// TcpStream for reading and writing to RCON server
+    conn: TcpStream,
+
+    /// Last message ID sent to server
+    last_sent_id: i32,
+
+    /// Next message ID to send
+    next_send_id: i32,
+
+    /// Game running on the server
+    game: Game,
+
+    /// Protocol spoken over `conn`
+    protocol: Protocol,
+
+    /// Whether `game` is a placeholder to be replaced by probing the server after auth
+    detect_game: bool,
+
+    /// `ip:port` of the server
+    address: String,
+
+    /// Password from the config profile, tried before any other source but one typed earlier for
+    /// the same server
+    password: Option<Zeroizing<String>>,
+
+    /// Where commands are recorded, if the profile keeps an audit log
+    audit: Option<audit::AuditLog>,
+
+    /// Who's sending the commands, as recorded in the audit log
+    source: String,
+
+    /// Names of the only commands this session sends, `None` for any
+    allowed: Option<Vec<String>>,
+
+    /// Whether a timed out response may still arrive, to be discarded before the next command
+    late_response: bool,
+
+    /// Raised to stop waiting for the rest of a response, keeping what arrived
+    abort: Arc<AtomicBool>,
+
+    /// IDs of the latest commands given up on before their response ended, whose packets are
+    /// dropped if they turn up later
+    abandoned: VecDeque<i32>,
+
+    /// Passwords [`Rcon::login`] sends before giving up
+    auth_attempts: u32,
+
+    /// `--insecure-ok`, no warning before sending the password to a server outside the local
+    /// network
+    insecure_ok: bool,
+
+    /// `--protocol-strict`, anything the server does against the protocol fails the command
+    /// instead of being worked around
+    strict: bool,
+
+    /// `--max-response-bytes`, body bytes of a response kept before the rest is dropped
+    max_response: usize,
+
+    /// `--spill-responses`, the rest of a response over `max_response` is saved instead
+    spill: bool,
+
+    /// How the last command's response was cut short, if it went over `max_response`
+    truncated: Option<Truncated>,
+
+    /// What the session has sent and received, see [`Rcon::stats`]
+    traffic: Arc<Counters>,
+
+    /// File the rest of the last response over `max_response` is being saved to
+    spill_file: Option<File>,
+
+    /// Buffer responses are read into and split into packets, kept between commands
+    framer: frame::Framer,
+
+    /// ID of the empty command sent after auth, whose empty responses are dropped wherever
+    /// they turn up
+    followup: Option<i32>,
+
+    /// Sent after every login
+    init_commands: Vec<String>,
+
+    /// The init commands sent since [`Rcon::take_init_output`] was last called, with their
+    /// responses
+    init_output: Vec<(String, String)>,
+}
+
+/// Hands a response's text on as its packets arrive, see [`Rcon::send_cmd_streaming`]
+struct Shown<'a> {
+    show: &'a mut dyn FnMut(&str),
+    /// ID of the command whose packets are shown
+    id: i32,
+    /// Start of a character or color code cut off at the end of the last packet
+    carried: Vec<u8>,
+}
+
+impl Shown<'_> {
+    /// Hand on a packet's text, holding back whatever is cut off at its end for the next one
+    fn packet(&mut self, packet: &Packet, codes: ColorCodes) {
+        if packet.id != self.id {
+            return;
+        }
+        let mut bytes = mem::take(&mut self.carried);
+        bytes.extend_from_slice(&packet.body_bytes);
+        self.carried = bytes.split_off(whole_len(&bytes, codes));
+        self.text(&bytes, codes);
+    }
+
+    /// Hand on what was held back once the response has ended
+    fn finish(&mut self, codes: ColorCodes) {
+        let bytes = mem::take(&mut self.carried);
+        self.text(&bytes, codes);
+    }
+
+    fn text(&mut self, bytes: &[u8], codes: ColorCodes) {
+        let text = codes.strip(&sanitize::decode(bytes));
+        if !text.is_empty() {
+            (self.show)(&text);
+        }
+    }
+}
+
+/// OS error codes of a connection attempt with no route to the server
+#[cfg(unix)]
+const UNREACHABLE: &[i32] = &[libc::ENETUNREACH, libc::EHOSTUNREACH];
+/// `WSAENETUNREACH` and `WSAEHOSTUNREACH`
+#[cfg(windows)]
+const UNREACHABLE: &[i32] = &[10051, 10065];
+#[cfg(not(any(unix, windows)))]
+const UNREACHABLE: &[i32] = &[];
+
+/// Whether `ip` is on the machine or a private network: loopback, RFC 1918, link-local, or IPv6
+/// unique local
+fn is_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_local(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                ip.is_loopback() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+            }
+        },
+    }
+}
+
+pub type RconResult = Result<Rcon, RconError>;
+
+impl Rcon {
+    pub fn new(config: &ClientConfig) -> RconResult {
+        let rcon = Rcon {
+            conn: Rcon::dial(&config.ip, config.port)?,
+            last_sent_id: 0,
+            next_send_id: 1,
+            game: config.game.unwrap_or(match config.protocol {
+                Protocol::Source => Game::Source,
+                Protocol::Telnet => Game::SevenDays,
+            }),
+            protocol: config.protocol,
+            detect_game: config.game.is_none() && config.protocol == Protocol::Source,
+            address: format!("{}:{}", config.ip, config.port),
+            password: config.password.clone().map(Zeroizing::new),
+            audit: audit::AuditLog::new(config),
+            source: audit::default_source(),
+            allowed: config.allow_commands.clone(),
+            late_response: false,
+            abort: Arc::new(AtomicBool::new(false)),
+            abandoned: VecDeque::new(),
+            auth_attempts: config.auth_attempts,
+            insecure_ok: config.insecure_ok,
+            strict: config.protocol_strict,
+            max_response: config.max_response_bytes,
+            spill: config.spill_responses,
+            truncated: None,
+            spill_file: None,
+            framer: frame::Framer::new(0, false),
+            followup: None,
+            traffic: Arc::default(),
+            init_commands: config.init_commands.clone(),
+            init_output: Vec::new(),
+        };
+
+        Ok(rcon)
+    }
+
+    /// Open a session and authenticate it with [`Rcon::login`], asking `prompt` for passwords
+    pub fn connect(
+        config: &ClientConfig,
+        prompt: &mut dyn FnMut() -> Zeroizing<String>,
+    ) -> RconResult {
+        let mut rcon = Rcon::new(config)?;
+        rcon.login(prompt)?;
+        Ok(rcon)
+    }
+
+    /// Open a session and authenticate it with [`Rcon::login_unattended`], for daemons that
+    /// have no one to prompt
+    pub fn connect_unattended(config: &ClientConfig) -> RconResult {
+        let mut rcon = Rcon::new(config)?;
+        rcon.login_unattended()?;
+        Ok(rcon)
+    }
+
+    pub fn get_conn(ip: &str, port: u16) -> io::Result<TcpStream> {
+        let addrs: Vec<SocketAddr> = (ip, port).to_socket_addrs()?.collect();
+        Rcon::open(&addrs)
+    }
+
+    /// [`get_conn`](Rcon::get_conn), telling apart why connecting failed
+    fn dial(ip: &str, port: u16) -> Result<TcpStream, RconError> {
+        let resolve = || RconError::Resolve {
+            host: ip.to_string(),
+        };
+        let addrs: Vec<SocketAddr> = (ip, port)
+            .to_socket_addrs()
+            .map_err(|_| resolve())?
+            .collect();
+        if addrs.is_empty() {
+            return Err(resolve());
+        }
+        Rcon::open(&addrs).map_err(|e| match e.kind() {
+            io::ErrorKind::ConnectionRefused => RconError::Refused,
+            io::ErrorKind::TimedOut => RconError::ConnectTimeout,
+            _ if e
+                .raw_os_error()
+                .is_some_and(|code| UNREACHABLE.contains(&code)) =>
+            {
+                RconError::Unreachable
+            }
+            _ => RconError::ConnError,
+        })
+    }
+
+    fn open(addrs: &[SocketAddr]) -> io::Result<TcpStream> {
+        let conn = TcpStream::connect(addrs)?;
+        conn.set_nonblocking(false)?;
+        conn.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+        conn.set_write_timeout(Some(Duration::new(1, 0)))?;
+        Ok(conn)
+    }
+
+    /// Authenticate with `pass`, whether or not it's the profile's, then send the init commands
+    pub fn authenticate_with(&mut self, pass: &str) -> bool {
+        let accepted = self.authenticate(pass);
+        if accepted {
+            self.run_init_commands();
+        }
+        accepted
+    }
+
+    /// Send the init commands, keeping their responses for [`Rcon::take_init_output`]
+    fn run_init_commands(&mut self) {
+        for cmd in self.init_commands.clone() {
+            match self.send_cmd(&cmd) {
+                Ok(response) => {
+                    let text = response.iter().map(|p| p.body()).collect();
+                    self.init_output.push((cmd, text));
+                }
+                Err(e) => {
+                    log::warn!("The init command {:?} failed: {:?}", cmd, e);
+                    if e.is_disconnect() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The init commands sent since this was last called, with their responses
+    pub fn take_init_output(&mut self) -> Vec<(String, String)> {
+        mem::take(&mut self.init_output)
+    }
+
+    fn authenticate(&mut self, pass: &str) -> bool {
+        self.warn_plaintext();
+        if self.protocol == Protocol::Telnet {
+            let accepted = telnet::authenticate(&mut self.conn, pass).unwrap_or_else(|e| {
+                log::error!("Failed to log in to the telnet console. Error: {:?}", e);
+                false
+            });
+            if !accepted {
+                self.traffic.rejected_login();
+            }
+            return accepted;
+        }
+
+        let login = match Packet::serialize_login(1, pass) {
+            Ok(login) => login,
+            Err(PacketError::TooLarge) => {
+                log::error!("The password is too long to send over RCON.");
+                return false;
+            }
+            Err(_) => {
+                log::error!("The password is invalid. RCON only supports ASCII text.");
+                return false;
+            }
+        };
+        if let Err(e) = self.conn.write_all(&login) {
+            log::error!("Failed to send login Packet. Error: {:?}", e);
+            return false;
+        }
+        self.traffic.sent(login.len());
+        self.last_sent_id = 1;
+        self.next_send_id = 2;
+
+        if let Ok(auth_response) = self.receive_packets(None, None) {
+            // Only the SERVERDATA_AUTH_RESPONSE tells, SRCDS sends an empty
+            // SERVERDATA_RESPONSE_VALUE before it
+            let accepted = auth_response
+                .iter()
+                .find(|p| matches!(p.typ, PacketType::Command))
+                .is_some_and(|p| p.id != BAD_AUTH && p.id == self.last_sent_id);
+            if !accepted {
+                self.traffic.rejected_login();
+                return false;
+            }
+
+            // Send followup packet, SRCDS doesn't accept the first command after auth.
+            // Detection probes take its place since the game's quirks aren't known yet.
+            if self.detect_game {
+                self.game = self.detect();
+                self.detect_game = false;
+            } else if self.game.profile().needs_auth_followup() {
+                // The reply may be missing or late, the session works regardless
+                self.followup = Some(self.next_send_id);
+                let _ = self.exchange("", None);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Warn once that the password is about to cross networks in plain text, since RCON has no
+    /// encryption
+    fn warn_plaintext(&self) {
+        if self.insecure_ok || WARNED_PLAINTEXT.load(Ordering::Relaxed) {
+            return;
+        }
+        let remote = match self.conn.peer_addr() {
+            Ok(peer) => !is_local(peer.ip()),
+            Err(_) => return,
+        };
+        if remote && !WARNED_PLAINTEXT.swap(true, Ordering::Relaxed) {
+            log::warn!(
+                "Warning: RCON sends the password in plain text and {} isn't on a local network, \
+                 so anyone along the way can read it.",
+                self.address
+            );
+            log::warn!(
+                "Consider reaching the server through an SSH tunnel, like `ssh -L 27015:localhost:27015 host`, \
+                 or pass --insecure-ok to hide this warning."
+            );
+        }
+    }
+
+    /// Probe the server with harmless commands and fingerprint the responses to pick its game
+    fn detect(&mut self) -> Game {
+        for probe in game::DETECTION_PROBES {
+            if let Ok(response) = self.exchange(probe, None) {
+                let text: String = response.iter().map(|p| p.body()).collect();
+                if let Some(game) = Game::fingerprint(&text) {
+                    log::info!("Detected a {} server", game);
+                    return game;
+                }
+            }
+        }
+
+        log::warn!(
+            "Could not detect the server's game, assuming {}. Pick one with --game.",
+            Game::Source
+        );
+        Game::Source
+    }
+
+    /// Authenticate with the profile's password or the `RUSTCON_PASS` env variable, falling back
+    /// to asking `prompt`, which usually prompts the user
+    ///
+    /// A password typed here is remembered for the rest of the process, so sessions reconnecting
+    /// to the same server log in with it instead of prompting again.
+    ///
+    /// Every password sent counts toward the profile's `auth_attempts`, with a growing wait after
+    /// each wrong one, since servers like SRCDS ban addresses that get it wrong too often.
+    pub fn login(
+        &mut self,
+        prompt: &mut dyn FnMut() -> Zeroizing<String>,
+    ) -> Result<(), RconError> {
+        let mut rejected = 0;
+        if self.stored_login(&mut rejected).is_ok() {
+            return Ok(());
+        }
+
+        while rejected < self.auth_attempts {
+            if rejected > 0 {
+                let wait = 1 << (rejected - 1).min(5);
+                log::warn!("Incorrect password. Try again in {}s...", wait);
+                thread::sleep(Duration::from_secs(wait));
+            }
+            if rejected + 1 == self.auth_attempts && self.protocol == Protocol::Source {
+                log::warn!(
+                    "Last attempt: servers like SRCDS ban addresses after too many wrong passwords \
+                     (sv_rcon_maxfailures)"
+                );
+            }
+            let pass = prompt();
+            if self.authenticate_with(&pass) {
+                let mut prompted = PROMPTED.lock().unwrap_or_else(|e| e.into_inner());
+                prompted.retain(|(address, _)| *address != self.address);
+                prompted.push((self.address.clone(), pass));
+                return Ok(());
+            }
+            rejected += 1;
+        }
+        log::error!("Giving up after {} wrong passwords", rejected);
+        Err(RconError::AuthError)
+    }
+
+    /// Authenticate with the profile's password or RUSTCON_PASS, never prompting
+    pub fn login_unattended(&mut self) -> Result<(), RconError> {
+        self.stored_login(&mut 0)
+    }
+
+    /// Try the stored passwords, counting the ones the server rejected
+    fn stored_login(&mut self, rejected: &mut u32) -> Result<(), RconError> {
+        // Try the password typed for an earlier session, the stored ones didn't work then
+        let typed = PROMPTED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|(address, _)| *address == self.address)
+            .map(|(_, pass)| pass.clone());
+        if let Some(pass) = typed {
+            if self.authenticate_with(&pass) {
+                return Ok(());
+            }
+            *rejected += 1;
+            log::warn!("The password typed earlier was rejected");
+        }
+
+        // Try the config profile's password
+        if let Some(pass) = self.password.take() {
+            let accepted = self.authenticate_with(&pass);
+            self.password = Some(pass);
+            if accepted {
+                return Ok(());
+            }
+            *rejected += 1;
+            log::warn!("The profile's password was rejected");
+        }
+
+        // Try RUSTCON_PASS env variable
+        let pass = env::var("RUSTCON_PASS").map(Zeroizing::new).map_err(|_| {
+            log::warn!("RUSTCON_PASS env variable does not exist");
+            RconError::AuthError
+        })?;
+        if self.authenticate_with(&pass) {
+            Ok(())
+        } else {
+            *rejected += 1;
+            Err(RconError::AuthError)
+        }
+    }
+
+    /// Game running on the server
+    pub fn game(&self) -> Game {
+        self.game
+    }
+
+    /// What the session has sent and received so far
+    pub fn stats(&self) -> Traffic {
+        self.traffic.snapshot()
+    }
+
+    /// Name who's sending the following commands in the audit log, like a bridge's token
+    pub fn set_source(&mut self, source: &str) {
+        self.source = source.to_string();
+    }
+
+    /// The session's running [`Traffic`] counts, for watching them from another thread as they
+    /// change
+    pub fn counters(&self) -> Arc<Counters> {
+        Arc::clone(&self.traffic)
+    }
+
+    /// Raised from another thread to stop waiting for the rest of a response, keeping what
+    /// arrived, and lowered again by whoever raised it
+    pub fn abort_flag(&self) -> &Arc<AtomicBool> {
+        &self.abort
+    }
+
+    /// Another handle on the session's connection, for watching it while the session is idle
+    ///
+    /// Reading from it takes what the session would have read, use `peek` instead.
+    pub fn try_clone_conn(&self) -> io::Result<TcpStream> {
+        self.conn.try_clone()
+    }
+
+    fn send_packet(&mut self, packet: Packet) -> Result<i32, RconError> {
+        let mut packet_bytes = packet.serialize();
+
+        // Send packet
+        self.conn.write_all(packet_bytes.as_mut())?;
+        self.traffic.sent(packet_bytes.len());
+
+        self.last_sent_id = packet.id;
+        self.next_send_id = self.last_sent_id + 1;
+        Ok(self.last_sent_id)
+    }
+
+    /// Add a packet to `out` to be sent along with others in one write, taking its ID as sent
+    fn queue_packet(&mut self, packet: Packet, out: &mut BytesMut) {
+        let bytes = packet.serialize();
+        self.traffic.sent(bytes.len());
+        out.extend_from_slice(&bytes);
+        self.last_sent_id = packet.id;
+        self.next_send_id = self.last_sent_id + 1;
+    }
+
+    /// Read packets until the server goes quiet, or until the response to the `marker` command
+    /// arrives, which isn't kept
+    fn receive_packets(
+        &mut self,
+        marker: Option<i32>,
+        shown: Option<&mut Shown>,
+    ) -> Result<Vec<Packet>, RconError> {
+        let max_size = self.game.profile().max_packet_size();
+        let len = READ_CHUNK.max(PACKET_SIZE_FIELD_LEN + max_size);
+        // Whatever was left over belongs to a response that's already over
+        self.framer.reset(max_size, self.strict);
+        let received = self.read_packets(marker, len, shown);
+        if let Err(RconError::Deviation { .. }) = received {
+            // What's left of the response is dropped before the next command
+            self.late_response = true;
+        }
+        received
+    }
+
+    /// Add a packet to the response unless it goes over `--max-response-bytes`, past which
+    /// packets are dropped, or saved to a temp file with `--spill-responses`
+    fn keep(
+        &mut self,
+        packets: &mut Vec<Packet>,
+        kept: &mut usize,
+        packet: Packet,
+        shown: Option<&mut Shown>,
+    ) {
+        let len = packet.body_bytes.len();
+        if self.truncated.is_none() && *kept + len <= self.max_response {
+            *kept += len;
+            if let Some(shown) = shown {
+                shown.packet(&packet, self.game.profile().color_codes());
+            }
+            packets.push(packet);
+            return;
+        }
+
+        let truncated = self.truncated.get_or_insert(Truncated {
+            kept: *kept,
+            dropped: 0,
+            spilled_to: None,
+        });
+        truncated.dropped += len;
+        if self.spill && self.spill_file.is_none() && truncated.spilled_to.is_none() {
+            let path = env::temp_dir().join(format!(
+                "rustcon-response-{}-{}.txt",
+                process::id(),
+                packet.id
+            ));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    self.spill_file = Some(file);
+                    truncated.spilled_to = Some(path);
+                }
+                Err(e) => log::error!("Unable to save the rest of the response: {}", e),
+            }
+        }
+        if let Some(file) = &mut self.spill_file {
+            if let Err(e) = file.write_all(&packet.body_bytes) {
+                log::error!("Unable to save the rest of the response: {}", e);
+                self.spill_file = None;
+            }
+        }
+    }
+
+    /// How the last command's response was cut short, if it went over `--max-response-bytes`
+    pub fn truncated(&self) -> Option<&Truncated> {
+        self.truncated.as_ref()
+    }
+
+    fn read_packets(
+        &mut self,
+        marker: Option<i32>,
+        read_len: usize,
+        mut shown: Option<&mut Shown>,
+    ) -> Result<Vec<Packet>, RconError> {
+        let mut packets: Vec<Packet> = Vec::new();
+        // Body bytes in `packets`
+        let mut kept = 0;
+        let profile = self.game.profile();
+        let lenient = self.lenient();
+
+        // Read until the server goes quiet, which ends the response
+        loop {
+            if self.abort.load(Ordering::Relaxed) {
+                // The rest is dropped before the next command
+                self.late_response = true;
+                break;
+            }
+            match self.framer.read_from(&mut self.conn, read_len) {
+                Ok(0) if packets.is_empty() => return Err(RconError::closed()),
+                // The server answered before closing, the next command finds it closed
+                Ok(0) => break,
+                Ok(read) => self.traffic.read(read),
+                Err(e) if telnet::is_timeout(&e) && self.abort.load(Ordering::Relaxed) => continue,
+                Err(e) if telnet::is_timeout(&e) => {
+                    // The rest, or at least the marker's response, may still turn up
+                    if packets.is_empty() || marker.is_some() {
+                        self.late_response = true;
+                    }
+                    if packets.is_empty() {
+                        return Err(RconError::Timeout);
+                    }
+                    break;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            // Strict parsing splits the stream on the size fields, lenient parsing can't trust
+            // them and splits it on each packet's null bytes
+            loop {
+                let frame = if lenient {
+                    self.framer.next_lenient_frame()
+                } else {
+                    self.framer.next_frame()?
+                };
+                let mut frame = match frame {
+                    Some(frame) => frame,
+                    None => break,
+                };
+                match Packet::parse(&mut frame, profile, lenient) {
+                    Ok(r) => {
+                        self.traffic.received();
+                        let body_len = r.body_bytes.len();
+                        if lenient && usize::try_from(r.size) != Ok(packet_size(body_len)) {
+                            tolerate(
+                                false,
+                                format!(
+                                    "a packet size of {} for a {} byte body, reading up to its \
+                                     null byte instead",
+                                    r.size, body_len
+                                ),
+                            )?;
+                        }
+                        if Some(r.id) == marker {
+                            return Ok(packets);
+                        }
+                        // Handle auth double packet response from SRCDS
+                        if r.id == BAD_AUTH {
+                            packets.push(r);
+                            return Ok(packets);
+                        } else {
+                            self.keep(&mut packets, &mut kept, r, shown.as_deref_mut());
+                        }
+                    }
+                    // Skip runt packets rather than dropping the session
+                    Err(PacketError::SmallPacket) if lenient => tolerate(
+                        false,
+                        "a packet too short for a header, skipping it".to_string(),
+                    )?,
+                    Err(_) => return Err(RconError::PacketError),
+                }
+            }
+        }
+
+        Ok(packets)
+    }
+
+    /// Whether responses are parsed without trusting their size field, as the game needs unless
+    /// `--protocol-strict` is given
+    fn lenient(&self) -> bool {
+        self.game.profile().lenient_parsing() && !self.strict
+    }
+
+    /// Whether responses end at the answer to an empty command sent after them, rather than once
+    /// the server goes quiet
+    ///
+    /// Never while detecting the game, the servers probed may not be SRCDS.
+    fn uses_marker(&self) -> bool {
+        !self.detect_game && !self.lenient() && self.game.profile().supports_empty_marker()
+    }
+
+    /// Split off a reader for output the server pushes unprompted, such as chat on telnet consoles
+    ///
+    /// Once split, commands must be sent with [`Rcon::send_unanswered`] since the reader consumes
+    /// everything the server sends. Source RCON servers only ever answer commands, giving `None`.
+    pub fn split_push_reader(&self) -> Option<TcpStream> {
+        match self.protocol {
+            Protocol::Telnet => self.conn.try_clone().ok(),
+            Protocol::Source => None,
+        }
+    }
+
+    /// Only send the commands in `commands` from now on, on top of any earlier restriction
+    pub fn restrict(&mut self, commands: &[String]) {
+        self.allowed = Some(match self.allowed.take() {
+            Some(allowed) => allowed
+                .into_iter()
+                .filter(|a| command_allowed(commands, a))
+                .collect(),
+            None => commands.to_vec(),
+        });
+    }
+
+    /// Whether the session's allowlist lets `cmd` through
+    pub fn allows(&self, cmd: &str) -> bool {
+        self.allowed
+            .as_ref()
+            .is_none_or(|allowed| command_allowed(allowed, cmd))
+    }
+
+    fn check_allowed(&self, cmd: &str) -> Result<(), RconError> {
+        if self.allows(cmd) {
+            Ok(())
+        } else {
+            Err(RconError::NotAllowed)
+        }
+    }
+
+    /// Send a command without reading its response
+    pub fn send_unanswered(&mut self, cmd: &str) -> Result<(), RconError> {
+        let result = self.check_allowed(cmd).and_then(|_| self.send_only(cmd));
+        if result.is_err() {
+            self.traffic.error();
+        }
+        if let Some(audit) = &self.audit {
+            audit.record(&self.source, cmd, result.as_ref().map(|_| None));
+        }
+        result
+    }
+
+    fn send_only(&mut self, cmd: &str) -> Result<(), RconError> {
+        if let Some(parts) = self.split(cmd)? {
+            return parts.iter().try_for_each(|part| self.send_only(part));
+        }
+        let cmd = self.game.profile().prepare_command(cmd);
+        match self.protocol {
+            Protocol::Telnet => {
+                telnet::send_line(&mut self.conn, &cmd)?;
+                self.traffic.sent(cmd.trim_end().len() + 2);
+                Ok(())
+            }
+            Protocol::Source => {
+                let packet = self.command_packet(cmd)?;
+                self.send_packet(packet).map(|_| ())
+            }
+        }
+    }
+
+    /// Send the pages of a command the game has fetched a page at a time, putting their
+    /// responses together into one packet
+    ///
+    /// The response is shown once it's whole, rather than with every page's header.
+    fn exchange_pages(
+        &mut self,
+        pages: &[String],
+        shown: Option<&mut Shown>,
+    ) -> Result<Vec<Packet>, RconError> {
+        let mut responses = Vec::with_capacity(pages.len());
+        let mut id = None;
+        for page in pages {
+            let packets = self.exchange(page, None)?;
+            id = id.or_else(|| packets.first().map(|p| p.id));
+            responses.push(packets.iter().map(|p| p.body()).collect::<String>());
+        }
+        let text = self.game.profile().stitch_pages(&responses);
+        if let Some(shown) = shown {
+            (shown.show)(&text);
+        }
+        let id = id.unwrap_or(self.next_send_id);
+        Ok(vec![Packet::from_text(id, PacketType::Response, text)])
+    }
+
+    /// Longest command body the game's server takes in one packet
+    fn body_limit(&self) -> usize {
+        (self.game.profile().max_packet_size() - PACKET_SIZE_MIN).min(PACKET_BODY_MAX_LEN)
+    }
+
+    /// Parts to send in place of a command too long for one packet, `None` when it fits
+    ///
+    /// Telnet consoles take lines of any length. Commands the game can't split fail with
+    /// [`RconError::TooLarge`].
+    fn split(&self, cmd: &str) -> Result<Option<Vec<String>>, RconError> {
+        let profile = self.game.profile();
+        let max = self.body_limit();
+        let fits = |cmd: &str| profile.prepare_command(cmd).trim_end().len() <= max;
+        if self.protocol == Protocol::Telnet || fits(cmd) {
+            return Ok(None);
+        }
+        profile
+            .split_command(cmd, max)
+            .filter(|parts| !parts.is_empty() && parts.iter().all(|p| fits(p)))
+            .map(Some)
+            .ok_or(RconError::TooLarge {
+                len: cmd.len(),
+                max,
+            })
+    }
+
+    /// Command packet with the next ID, checked against the server's limit
+    fn command_packet(&self, body: String) -> Result<Packet, RconError> {
+        let len = body.trim_end().len();
+        let max = self.body_limit();
+        if len > max {
+            return Err(RconError::TooLarge { len, max });
+        }
+        Packet::new(self.next_send_id, PacketType::Command, body).map_err(|e| match e {
+            PacketError::TooLarge => RconError::TooLarge { len, max },
+            _ => RconError::PacketError,
+        })
+    }
+
+    /// Send a command line to a telnet console, wrapping its output in a response packet
+    fn send_line(&mut self, line: &str) -> Result<Vec<Packet>, RconError> {
+        let id = self.next_send_id;
+        telnet::send_line(&mut self.conn, line)?;
+        self.traffic.sent(line.trim_end().len() + 2);
+        let output = telnet::receive(&mut self.conn)?;
+        self.traffic.read(output.len());
+        self.traffic.received();
+
+        self.last_sent_id = id;
+        self.next_send_id = id + 1;
+        Ok(vec![Packet::from_text(id, PacketType::Response, output)])
+    }
+
+    /// Drop whatever arrived since a response timed out, so it isn't taken for the next one's
+    fn discard_late_response(&mut self) -> Result<(), RconError> {
+        self.late_response = false;
+        self.conn.set_nonblocking(true)?;
+        let mut buf = [0; 4096];
+        let drained = loop {
+            match self.conn.read(&mut buf) {
+                Ok(0) => break Err(RconError::closed()),
+                Ok(_) => {}
+                Err(e) if telnet::is_timeout(&e) => break Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break Err(e.into()),
+            }
+        };
+        self.conn.set_nonblocking(false)?;
+        drained
+    }
+
+    /// Send an RCON command and receive response packets
+    pub fn send_cmd(&mut self, body: &str) -> Result<Vec<Packet>, RconError> {
+        self.send(body, None)
+    }
+
+    /// [`send_cmd`](Rcon::send_cmd), also handing the response's text to `show` a piece at a time
+    /// as its packets arrive, so long output can be shown before it ends
+    ///
+    /// Pieces end on whole characters and color codes, with the codes removed. They may end
+    /// partway through a line.
+    pub fn send_cmd_streaming(
+        &mut self,
+        body: &str,
+        show: &mut dyn FnMut(&str),
+    ) -> Result<Vec<Packet>, RconError> {
+        let mut shown = Shown {
+            show,
+            id: 0,
+            carried: Vec::new(),
+        };
+        self.send(body, Some(&mut shown))
+    }
+
+    fn send(&mut self, body: &str, shown: Option<&mut Shown>) -> Result<Vec<Packet>, RconError> {
+        self.truncated = None;
+        let result = self
+            .check_allowed(body)
+            .and_then(|_| self.exchange(body, shown));
+        if result.is_err() {
+            self.traffic.error();
+        }
+        self.spill_file = None;
+        if let Some(truncated) = &self.truncated {
+            log::warn!("{}", truncated);
+        }
+        if let Some(audit) = &self.audit {
+            let bytes = |packets: &Vec<Packet>| Some(packets.iter().map(|p| p.body().len()).sum());
+            audit.record(&self.source, body, result.as_ref().map(bytes));
+        }
+        result
+    }
+
+    /// Send a command and receive its response, without recording it
+    ///
+    /// Commands too long for one packet are sent in parts where the game allows it, such as chat
+    /// broadcasts split between words, with the responses of every part put together.
+    fn exchange(
+        &mut self,
+        body: &str,
+        mut shown: Option<&mut Shown>,
+    ) -> Result<Vec<Packet>, RconError> {
+        if let Some(parts) = self.split(body)? {
+            let mut packets = Vec::new();
+            for part in parts {
+                packets.extend(self.exchange(&part, shown.as_deref_mut())?);
+            }
+            return Ok(packets);
+        }
+        if let Some(pages) = self.game.profile().page_command(body) {
+            return self.exchange_pages(&pages, shown);
+        }
+        let body = self.game.profile().prepare_command(body);
+        if self.protocol == Protocol::Telnet {
+            let packets = self.send_line(&body)?;
+            if let Some(shown) = shown {
+                packets.iter().for_each(|p| (shown.show)(p.body()));
+            }
+            return Ok(packets);
+        }
+
+        if self.late_response {
+            self.discard_late_response()?;
+        }
+        let packet = self.command_packet(body)?;
+        let id = self.send_packet(packet)?;
+        if let Some(shown) = shown.as_deref_mut() {
+            shown.id = id;
+        }
+        let (received, sent) = if self.uses_marker() {
+            // SRCDS answers in order, so the response to an empty command sent next marks the
+            // end of this one however many packets it takes
+            let marker = Packet::new(id + 1, PacketType::Command, String::new())
+                .map_err(|_| RconError::PacketError)?;
+            let marker = self.send_packet(marker)?;
+            (
+                self.receive_packets(Some(marker), shown.as_deref_mut()),
+                vec![id, marker],
+            )
+        } else {
+            (self.receive_packets(None, shown.as_deref_mut()), vec![id])
+        };
+        // Anything with another ID belongs to a command given up on, or to none
+        let abandoned = &self.abandoned;
+        let strict = self.strict;
+        let lenient = self.lenient();
+        let received = received.and_then(|mut packets| {
+            packets.retain(|p| !abandoned.contains(&p.id));
+            if !lenient {
+                for stray in packets.iter().filter(|p| p.id != id) {
+                    tolerate(
+                        strict,
+                        format!("a packet with ID {} while answering ID {}", stray.id, id),
+                    )?;
+                }
+                packets.retain(|p| p.id == id);
+            }
+            Ok(packets)
+        });
+        if self.late_response {
+            // Given up on, so whatever else turns up for it belongs to no command
+            for id in sent {
+                if self.abandoned.len() == ABANDONED_MAX {
+                    self.abandoned.pop_front();
+                }
+                self.abandoned.push_back(id);
+            }
+        }
+        if let Some(shown) = shown {
+            shown.finish(self.game.profile().color_codes());
+        }
+        let mut packets = received?;
+        if let Some(id) = self.followup {
+            packets.retain(|p| p.id != id || !p.body().is_empty());
+        }
+        let profile = self.game.profile();
+        Ok(profile.normalize_response(Packet::decode_response(packets, profile)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_local;
+
+    #[test]
+    fn only_private_and_loopback_addresses_are_local() {
+        let local = |ip: &str| is_local(ip.parse().unwrap());
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "172.31.255.255",
+            "192.168.1.20",
+            "169.254.10.1",
+            "::1",
+            "fd12:3456::1",
+            "fc00::1",
+            "fe80::1",
+            "::ffff:192.168.1.20",
+        ] {
+            assert!(local(ip), "{}", ip);
+        }
+        for ip in [
+            "8.8.8.8",
+            "172.32.0.1",
+            "192.169.0.1",
+            "100.64.0.1",
+            "2001:db8::1",
+            "fec0::1",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(!local(ip), "{}", ip);
+        }
+    }
+}
@@ -0,0 +1,423 @@
+/*
+ * Several commands in flight at once on one Source RCON session.
+ *
+ * Every command is followed by an empty one with the next ID. SRCDS answers commands in order,
+ * so the empty command's response marks the end of the one before it however many packets that
+ * took, and a reader thread hands each response to whoever is waiting on its ID. Other games
+ * answer empty commands with errors or not at all, so their sessions aren't pipelined.
+ */
+
+use super::{READ_CHUNK, RESPONSE_TIMEOUT};
+use crate::{
+    audit::AuditLog, frame::Framer, telnet, traffic::Counters, Game, Packet, PacketType, Protocol,
+    Rcon, RconError, Traffic, Truncated, PACKET_SIZE_FIELD_LEN,
+};
+use bytes::BytesMut;
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    mem,
+    net::{Shutdown, TcpStream},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Instant,
+};
+
+type Response = Result<Vec<Packet>, RconError>;
+
+/// A command waiting for its response
+struct Slot {
+    id: i32,
+    /// ID of the empty command sent after it
+    marker: i32,
+    packets: Vec<Packet>,
+    /// Body bytes in `packets`
+    kept: usize,
+    /// Body bytes dropped for going over `--max-response-bytes`
+    dropped: usize,
+    /// When the command was sent or the last packet of its response arrived
+    active: Instant,
+    reply: mpsc::Sender<Response>,
+}
+
+/// Commands waiting for a response, oldest first
+struct Inflight {
+    slots: VecDeque<Slot>,
+    /// Why the reader stopped, after which nothing more can be sent
+    closed: Option<String>,
+    /// `--max-response-bytes`, body bytes of a response kept before the rest is dropped
+    max_response: usize,
+}
+
+/// An authenticated session sending commands without waiting for the ones before them
+///
+/// Made with [`Rcon::pipeline`] and shared between threads, each command sent gives a [`Reply`]
+/// to wait on.
+pub struct Pipeline {
+    rcon: Mutex<Rcon>,
+    inflight: Arc<Mutex<Inflight>>,
+    game: Game,
+}
+
+/// The response to a command sent over a [`Pipeline`]
+pub struct Reply {
+    /// One per part of a command that was split to fit in packets, or per page of one paged
+    parts: Vec<mpsc::Receiver<Response>>,
+    /// The game whose pages the parts are, to stitch their responses together
+    paged: Option<Game>,
+    cmd: String,
+    audit: Option<AuditLog>,
+    source: String,
+    traffic: Arc<Counters>,
+}
+
+impl Reply {
+    /// Wait for the response, put together from every part the command was sent in
+    pub fn wait(mut self) -> Response {
+        let mut responses = Vec::new();
+        let result = mem::take(&mut self.parts)
+            .into_iter()
+            .try_for_each(|part| {
+                responses.push(part.recv().unwrap_or_else(|_| Err(RconError::closed()))?);
+                Ok(())
+            })
+            .map(|_| match self.paged {
+                Some(game) => stitch(game, &responses),
+                None => responses.into_iter().flatten().collect(),
+            });
+        let bytes = |packets: &Vec<Packet>| Some(packets.iter().map(|p| p.body().len()).sum());
+        self.record(result.as_ref().map(bytes));
+        result
+    }
+
+    fn record(&self, result: Result<Option<usize>, &RconError>) {
+        if result.is_err() {
+            self.traffic.error();
+        }
+        if let Some(audit) = &self.audit {
+            audit.record(&self.source, &self.cmd, result);
+        }
+    }
+}
+
+impl Rcon {
+    /// Let several threads send commands over this session at once, see [`Pipeline`]
+    ///
+    /// Only SRCDS sessions can be pipelined, since responses are told apart by splitting the
+    /// stream into packets and ended by the answer to an empty command. Other sessions are handed
+    /// back.
+    // Both variants are the session itself, handed back rather than copied
+    #[allow(clippy::result_large_err)]
+    pub fn pipeline(mut self) -> Result<Pipeline, Rcon> {
+        if self.protocol != Protocol::Source || !self.uses_marker() {
+            return Err(self);
+        }
+        if self.late_response && self.discard_late_response().is_err() {
+            return Err(self);
+        }
+        let conn = match self.conn.try_clone() {
+            Ok(conn) => conn,
+            Err(_) => return Err(self),
+        };
+
+        let inflight = Arc::new(Mutex::new(Inflight {
+            slots: VecDeque::new(),
+            closed: None,
+            max_response: self.max_response,
+        }));
+        let shared = Arc::clone(&inflight);
+        let (game, strict) = (self.game, self.strict);
+        let traffic = Arc::clone(&self.traffic);
+        thread::spawn(move || read(conn, game, strict, &shared, &traffic));
+        Ok(Pipeline {
+            rcon: Mutex::new(self),
+            inflight,
+            game,
+        })
+    }
+}
+
+impl Pipeline {
+    /// Game running on the server
+    pub fn game(&self) -> Game {
+        self.game
+    }
+
+    /// What the session has sent and received so far, see [`Rcon::stats`]
+    pub fn stats(&self) -> Traffic {
+        let rcon = self.rcon.lock().unwrap_or_else(|e| e.into_inner());
+        rcon.stats()
+    }
+
+    /// Send a command without waiting for its response, or for those of commands sent before it
+    pub fn send_cmd_async(&self, cmd: &str) -> Result<Reply, RconError> {
+        self.submit(cmd, None)
+    }
+
+    /// [`send_cmd_async`](Pipeline::send_cmd_async), recording `source` in the audit log as
+    /// sending it
+    pub fn send_cmd_async_as(&self, cmd: &str, source: &str) -> Result<Reply, RconError> {
+        self.submit(cmd, Some(source))
+    }
+
+    /// Send a command and wait for its response
+    pub fn send_cmd(&self, cmd: &str) -> Response {
+        self.send_cmd_async(cmd)?.wait()
+    }
+
+    /// Send several commands in one write, without waiting for their responses
+    ///
+    /// Each command gets its own result, so one that's refused doesn't hold back the rest. Fails
+    /// as a whole when the write does.
+    pub fn send_cmds_async(
+        &self,
+        cmds: &[&str],
+    ) -> Result<Vec<Result<Reply, RconError>>, RconError> {
+        let mut rcon = self.rcon.lock().unwrap_or_else(|e| e.into_inner());
+        let source = rcon.source.clone();
+        let mut out = BytesMut::new();
+        let replies: Vec<_> = cmds
+            .iter()
+            .map(|cmd| self.prepare(&mut rcon, cmd, &source, &mut out))
+            .collect();
+        if let Err(e) = rcon.conn.write_all(&out) {
+            let e = RconError::from(e);
+            for reply in replies.iter().flatten() {
+                reply.record(Err(&e));
+            }
+            return Err(e);
+        }
+        Ok(replies)
+    }
+
+    fn submit(&self, cmd: &str, source: Option<&str>) -> Result<Reply, RconError> {
+        let mut rcon = self.rcon.lock().unwrap_or_else(|e| e.into_inner());
+        let source = source.map_or_else(|| rcon.source.clone(), String::from);
+        let mut out = BytesMut::new();
+        let reply = self.prepare(&mut rcon, cmd, &source, &mut out)?;
+        if let Err(e) = rcon.conn.write_all(&out) {
+            let e = RconError::from(e);
+            reply.record(Err(&e));
+            return Err(e);
+        }
+        Ok(reply)
+    }
+
+    /// Check a command and add its packets to `out`, recording it in the audit log when it's
+    /// refused
+    fn prepare(
+        &self,
+        rcon: &mut Rcon,
+        cmd: &str,
+        source: &str,
+        out: &mut BytesMut,
+    ) -> Result<Reply, RconError> {
+        match rcon
+            .check_allowed(cmd)
+            .and_then(|_| self.queue(rcon, cmd, out))
+        {
+            Ok((parts, paged)) => Ok(Reply {
+                parts,
+                paged,
+                cmd: cmd.to_string(),
+                audit: rcon.audit.clone(),
+                source: source.to_string(),
+                traffic: Arc::clone(&rcon.traffic),
+            }),
+            Err(e) => {
+                rcon.traffic.error();
+                if let Some(audit) = &rcon.audit {
+                    audit.record(source, cmd, Err(&e));
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Add every part or page of a command followed by its marker to `out`, returning where
+    /// each one's response will arrive and, when they're pages, the game stitching them
+    fn queue(
+        &self,
+        rcon: &mut Rcon,
+        cmd: &str,
+        out: &mut BytesMut,
+    ) -> Result<(Vec<mpsc::Receiver<Response>>, Option<Game>), RconError> {
+        let (parts, paged) = match rcon.game.profile().page_command(cmd) {
+            Some(pages) => (pages, Some(rcon.game)),
+            None => (
+                rcon.split(cmd)?.unwrap_or_else(|| vec![cmd.to_string()]),
+                None,
+            ),
+        };
+        let mut replies = Vec::new();
+        for part in parts {
+            let packet = rcon.command_packet(rcon.game.profile().prepare_command(&part))?;
+            let marker = Packet::new(packet.id + 1, PacketType::Command, String::new())
+                .map_err(|_| RconError::PacketError)?;
+            let (reply, receiver) = mpsc::channel();
+            {
+                // Waiting before sending, so the response can't arrive first
+                let mut inflight = self.inflight.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some(reason) = &inflight.closed {
+                    return Err(RconError::Disconnected {
+                        reason: reason.clone(),
+                    });
+                }
+                inflight.slots.push_back(Slot {
+                    id: packet.id,
+                    marker: marker.id,
+                    packets: Vec::new(),
+                    kept: 0,
+                    dropped: 0,
+                    active: Instant::now(),
+                    reply,
+                });
+            }
+            rcon.queue_packet(packet, out);
+            rcon.queue_packet(marker, out);
+            replies.push(receiver);
+        }
+        Ok((replies, paged))
+    }
+}
+
+impl Drop for Pipeline {
+    /// Stop the reader along with the connection
+    fn drop(&mut self) {
+        let rcon = self.rcon.get_mut().unwrap_or_else(|e| e.into_inner());
+        let _ = rcon.conn.shutdown(Shutdown::Both);
+    }
+}
+
+/// The one response to a paged command, from the responses to its pages like
+/// [`Rcon::send_cmd`] puts it together
+fn stitch(game: Game, pages: &[Vec<Packet>]) -> Vec<Packet> {
+    let id = pages.iter().flatten().next().map_or(0, |p| p.id);
+    let pages: Vec<String> = pages
+        .iter()
+        .map(|packets| packets.iter().map(|p| p.body()).collect())
+        .collect();
+    let text = game.profile().stitch_pages(&pages);
+    vec![Packet::from_text(id, PacketType::Response, text)]
+}
+
+/// A command's response from the packets that arrived for it
+fn finish(slot: &mut Slot, game: Game) -> Vec<Packet> {
+    if slot.dropped > 0 {
+        let truncated = Truncated {
+            kept: slot.kept,
+            dropped: slot.dropped,
+            spilled_to: None,
+        };
+        log::warn!("{}", truncated);
+    }
+    let profile = game.profile();
+    profile.normalize_response(Packet::decode_response(
+        mem::take(&mut slot.packets),
+        profile,
+    ))
+}
+
+/// Hand a packet to the command it answers, finishing the command when it's its marker's
+///
+/// Packets of commands that already timed out are dropped, as are those past
+/// `--max-response-bytes` of a response.
+fn route(inflight: &Mutex<Inflight>, packet: Packet, game: Game) {
+    let mut inflight = inflight.lock().unwrap_or_else(|e| e.into_inner());
+    let max = inflight.max_response;
+    if let Some(slot) = inflight.slots.iter_mut().find(|s| s.id == packet.id) {
+        // Counted as `Rcon::keep` does, before the body is decoded
+        let len = packet.body_bytes.len();
+        slot.active = Instant::now();
+        if slot.dropped == 0 && slot.kept + len <= max {
+            slot.kept += len;
+            slot.packets.push(packet);
+        } else {
+            slot.dropped += len;
+        }
+    } else if let Some(i) = inflight.slots.iter().position(|s| s.marker == packet.id) {
+        if let Some(mut slot) = inflight.slots.remove(i) {
+            let response = finish(&mut slot, game);
+            let _ = slot.reply.send(Ok(response));
+        }
+    }
+}
+
+/// Finish the commands nothing has arrived for in [`RESPONSE_TIMEOUT`], oldest first, with what
+/// arrived of their responses
+///
+/// Commands after one still receiving its response are left waiting, since SRCDS answers in
+/// order.
+fn expire_stale(inflight: &Mutex<Inflight>, game: Game) {
+    let mut inflight = inflight.lock().unwrap_or_else(|e| e.into_inner());
+    while inflight
+        .slots
+        .front()
+        .is_some_and(|slot| slot.active.elapsed() >= RESPONSE_TIMEOUT)
+    {
+        let mut slot = match inflight.slots.pop_front() {
+            Some(slot) => slot,
+            None => break,
+        };
+        let response = if slot.packets.is_empty() {
+            Err(RconError::Timeout)
+        } else {
+            Ok(finish(&mut slot, game))
+        };
+        let _ = slot.reply.send(response);
+    }
+}
+
+/// Read responses until the connection ends, then fail every command still waiting
+///
+/// Under `--protocol-strict`, anything against the protocol ends it.
+fn read(
+    mut conn: TcpStream,
+    game: Game,
+    strict: bool,
+    inflight: &Mutex<Inflight>,
+    traffic: &Counters,
+) {
+    let profile = game.profile();
+    let mut framer = Framer::new(profile.max_packet_size(), strict);
+    let chunk = READ_CHUNK.max(PACKET_SIZE_FIELD_LEN + profile.max_packet_size());
+    let reason = 'read: loop {
+        // Even while packets keep coming, as they may all be for other commands
+        expire_stale(inflight, game);
+        match framer.read_from(&mut conn, chunk) {
+            Ok(0) => break "the server closed the connection".to_string(),
+            Ok(read) => traffic.read(read),
+            Err(e) if telnet::is_timeout(&e) => continue,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => break e.to_string(),
+        }
+
+        loop {
+            let mut frame = match framer.next_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(RconError::Deviation { detail }) => {
+                    break 'read format!("the server sent {}", detail)
+                }
+                Err(_) => break 'read "the server sent a malformed packet".to_string(),
+            };
+            // Framed by the size fields, so parsed by them whatever the game
+            match Packet::parse(&mut frame, profile, false) {
+                Ok(packet) => {
+                    traffic.received();
+                    route(inflight, packet, game)
+                }
+                Err(_) => break 'read "the server sent a malformed packet".to_string(),
+            }
+        }
+    };
+
+    let _ = conn.shutdown(Shutdown::Both);
+    let mut inflight = inflight.lock().unwrap_or_else(|e| e.into_inner());
+    for slot in inflight.slots.drain(..) {
+        let _ = slot.reply.send(Err(RconError::Disconnected {
+            reason: reason.clone(),
+        }));
+    }
+    inflight.closed = Some(reason);
+}
@@ -0,0 +1,104 @@
+/*
+ * Settings of a session with one server, as the client needs them.
+ *
+ * Front ends build these from wherever their settings come from, rustcon's own from the config
+ * profile merged under the command line.
+ */
+
+use crate::Game;
+use serde::{Deserialize, Deserializer};
+use std::{convert::TryFrom, path::PathBuf, time::Duration};
+
+/// Passwords [`Rcon::login`](crate::Rcon::login) sends before giving up
+pub const DEFAULT_AUTH_ATTEMPTS: u32 = 3;
+/// Body bytes of a response kept before the rest is dropped
+pub const DEFAULT_MAX_RESPONSE_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Wire protocols for talking to a server's admin console
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// Source RCON packets over TCP
+    Source,
+    /// Line-based telnet console (7 Days to Die)
+    Telnet,
+}
+
+/// A `[profiles.<name>.log_rotation]` table, logs are rotated on growing past `max_bytes` or
+/// once `every` has passed, whichever comes first
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RotationConfig {
+    pub max_bytes: Option<u64>,
+    /// Like `"1d"`, rotating at the start of each day
+    #[serde(default, deserialize_with = "duration")]
+    pub every: Option<Duration>,
+    /// Rotated logs kept before the oldest is deleted [default: 5]
+    pub keep: Option<usize>,
+    /// Gzip the logs as they're rotated
+    #[serde(default)]
+    pub compress: bool,
+}
+
+/// Parse durations written like on the command line, `"90s"` or `"10m"`
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    crate::duration::parse_duration(&text)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// How to reach a server and talk to it
+#[derive(Clone, Debug)]
+pub struct ClientConfig {
+    /// Name the server goes by in the audit log, its address when `None`
+    pub name: Option<String>,
+    pub ip: String,
+    pub port: u16,
+    /// Tried before prompting, along with the `RUSTCON_PASS` env variable
+    pub password: Option<String>,
+    /// `None` to detect the game after connecting
+    pub game: Option<Game>,
+    pub protocol: Protocol,
+    /// JSON lines file recording every command sent to the server
+    pub audit_log: Option<PathBuf>,
+    pub log_rotation: RotationConfig,
+    /// The only commands sessions send, `None` for any
+    pub allow_commands: Option<Vec<String>>,
+    /// Passwords tried at a prompt before giving up
+    pub auth_attempts: u32,
+    /// Passwords go to servers outside the local network without a warning
+    pub insecure_ok: bool,
+    /// Servers straying from the protocol fail commands instead of being worked around
+    pub protocol_strict: bool,
+    /// Largest response kept in memory, in bytes
+    pub max_response_bytes: usize,
+    /// What's past `max_response_bytes` goes to a temp file
+    pub spill_responses: bool,
+    /// Sent after every login, reconnects included, like `sv_cheats 0`
+    pub init_commands: Vec<String>,
+}
+
+impl ClientConfig {
+    /// Settings for the Source RCON server at `ip` and `port`, with the defaults for the rest
+    pub fn new(ip: impl Into<String>, port: u16) -> ClientConfig {
+        ClientConfig {
+            name: None,
+            ip: ip.into(),
+            port,
+            password: None,
+            game: None,
+            protocol: Protocol::Source,
+            audit_log: None,
+            log_rotation: RotationConfig::default(),
+            allow_commands: None,
+            auth_attempts: DEFAULT_AUTH_ATTEMPTS,
+            insecure_ok: false,
+            protocol_strict: false,
+            max_response_bytes: usize::try_from(DEFAULT_MAX_RESPONSE_BYTES).unwrap_or(usize::MAX),
+            spill_responses: false,
+            init_commands: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,75 @@
+/*
+ * Human-friendly durations such as `90s`, `10m`, `1h30m` or `300ms`.
+ */
+
+use std::time::Duration;
+
+/// Parse a duration made of `<number><unit>` parts with units `ms`, `s`, `m`, `h` or `d`
+///
+/// A bare number is taken as seconds.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    if let Ok(secs) = text.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total = 0;
+    let mut number = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch.is_ascii_digit() {
+            number.push(ch);
+            continue;
+        }
+
+        let millis = match ch {
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                1
+            }
+            's' => 1000,
+            'm' => 60 * 1000,
+            'h' => 60 * 60 * 1000,
+            'd' => 24 * 60 * 60 * 1000,
+            _ => return Err(format!("unknown unit {:?} in {:?}", ch, text)),
+        };
+        let value: u64 = number
+            .parse()
+            .map_err(|_| format!("missing number before {:?} in {:?}", ch, text))?;
+        total += value * millis;
+        number.clear();
+    }
+
+    if !number.is_empty() || text.is_empty() {
+        return Err(format!(
+            "expected a duration like 90s, 10m or 1h30m, got {:?}",
+            text
+        ));
+    }
+    Ok(Duration::from_millis(total))
+}
+
+/// Format a duration for announcements, e.g. `10 minutes` or `1 minute 30 seconds`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let parts = [
+        (secs / 3600, "hour"),
+        (secs % 3600 / 60, "minute"),
+        (secs % 60, "second"),
+    ];
+
+    let text: Vec<String> = parts
+        .iter()
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| match value {
+            1 => format!("1 {}", unit),
+            _ => format!("{} {}s", value, unit),
+        })
+        .collect();
+
+    if text.is_empty() {
+        "0 seconds".to_string()
+    } else {
+        text.join(" ")
+    }
+}
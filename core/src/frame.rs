@@ -0,0 +1,186 @@
+/*
+ * Splitting the bytes read from a Source RCON connection into packets.
+ *
+ * Reads end wherever the network put them, so bytes are buffered until a whole packet has
+ * arrived. Every packet is checked for a plausible size and type and its two terminating null
+ * bytes; when one fails, the stream has lost its framing and is scanned forward, a byte at a
+ * time, for the next header that passes. Under `--protocol-strict` the stream fails instead.
+ * Games whose size fields can't be trusted have their packets split on the null bytes instead.
+ *
+ * Reads go straight into the buffer and packets are split off it without copying, so a session
+ * reuses one allocation for as long as the packets it hands out are let go of.
+ */
+
+use crate::{tolerate, PacketType, RconError, PACKET_SIZE_FIELD_LEN, PACKET_SIZE_MIN};
+use bytes::{Bytes, BytesMut};
+use std::{
+    convert::TryFrom,
+    io::{self, Read},
+};
+
+/// Buffers received bytes and hands them back one validated packet at a time
+pub(crate) struct Framer {
+    buf: BytesMut,
+    /// Largest size field accepted, the game's `max_packet_size`
+    max_size: usize,
+    /// Bytes dropped looking for a header since the last packet
+    skipped: usize,
+    /// Whether bytes that aren't a packet fail the stream rather than being skipped
+    strict: bool,
+}
+
+impl Framer {
+    pub fn new(max_size: usize, strict: bool) -> Framer {
+        Framer {
+            buf: BytesMut::new(),
+            max_size,
+            skipped: 0,
+            strict,
+        }
+    }
+
+    /// Start over for a new response, dropping what's buffered but keeping the allocation
+    pub fn reset(&mut self, max_size: usize, strict: bool) {
+        self.buf.clear();
+        self.max_size = max_size;
+        self.skipped = 0;
+        self.strict = strict;
+    }
+
+    /// Read up to `len` bytes from `conn` straight onto the end of the buffer
+    ///
+    /// Space taken by frames already handed out is reused once they've all been dropped.
+    pub fn read_from(&mut self, conn: &mut impl Read, len: usize) -> io::Result<usize> {
+        let start = self.buf.len();
+        self.buf.resize(start + len, 0);
+        let read = conn.read(&mut self.buf[start..]);
+        self.buf.truncate(start + *read.as_ref().unwrap_or(&0));
+        read
+    }
+
+    /// The next packet up to its two null bytes, for lenient parsing that can't trust the size
+    /// field, or `None` until they've arrived
+    ///
+    /// Past the largest packet without them, what's buffered is taken as the packet.
+    pub fn next_lenient_frame(&mut self) -> Option<Bytes> {
+        let header = PACKET_SIZE_FIELD_LEN + 8;
+        let end = self.buf.get(header..)?.windows(2).position(|w| w == [0, 0]);
+        match end {
+            Some(end) => Some(self.buf.split_to(header + end + 2).freeze()),
+            None if self.buf.len() >= PACKET_SIZE_FIELD_LEN + self.max_size => {
+                Some(self.buf.split().freeze())
+            }
+            None => None,
+        }
+    }
+
+    /// The next whole packet, size field included, or `None` until all of it has arrived
+    pub fn next_frame(&mut self) -> Result<Option<Bytes>, RconError> {
+        loop {
+            match self.check(0) {
+                Check::Incomplete if self.skipped == 0 => return Ok(None),
+                // Found while scanning, the header may be garbage too, so a whole packet further
+                // on wins over waiting for the rest of this one
+                Check::Incomplete => {
+                    match (1..self.buf.len()).find(|at| matches!(self.check(*at), Check::Frame(_)))
+                    {
+                        Some(at) => self.skip(at),
+                        None => return Ok(None),
+                    }
+                }
+                Check::Invalid(fault) if self.strict => {
+                    return Err(RconError::Deviation {
+                        detail: fault.describe(self.max_size),
+                    })
+                }
+                Check::Invalid(_) => self.skip(1),
+                Check::Frame(len) => {
+                    if self.skipped > 0 {
+                        tolerate(
+                            false,
+                            format!(
+                                "{} bytes that weren't a packet, skipping them",
+                                self.skipped
+                            ),
+                        )?;
+                        self.skipped = 0;
+                    }
+                    return Ok(Some(self.buf.split_to(len).freeze()));
+                }
+            }
+        }
+    }
+
+    fn skip(&mut self, len: usize) {
+        let _ = self.buf.split_to(len);
+        self.skipped += len;
+    }
+
+    /// Whether a packet starts at `start` in the buffer, judging each field as soon as it has
+    /// arrived
+    fn check(&self, start: usize) -> Check {
+        let field = |at: usize| {
+            self.buf
+                .get(start + at..start + at + 4)
+                .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        };
+
+        let size = match field(0) {
+            Some(size) => size,
+            None => return Check::Incomplete,
+        };
+        let size = match usize::try_from(size) {
+            Ok(s) if (PACKET_SIZE_MIN..=self.max_size).contains(&s) => s,
+            _ => return Check::Invalid(Fault::Size(size)),
+        };
+        match field(8) {
+            Some(typ) if PacketType::from(typ) == PacketType::Unknown => {
+                return Check::Invalid(Fault::Type(typ))
+            }
+            Some(_) => {}
+            None => return Check::Incomplete,
+        }
+
+        let len = PACKET_SIZE_FIELD_LEN + size;
+        match self.buf.get(start + len - 2..start + len) {
+            Some([0, 0]) => Check::Frame(len),
+            Some(end) => Check::Invalid(Fault::End([end[0], end[1]])),
+            None => Check::Incomplete,
+        }
+    }
+}
+
+enum Check {
+    /// More bytes are needed to tell
+    Incomplete,
+    /// No packet starts there
+    Invalid(Fault),
+    /// A packet of this many bytes starts there
+    Frame(usize),
+}
+
+/// What's wrong with a header that doesn't start a packet
+enum Fault {
+    /// The size field is smaller than an empty packet or larger than the game allows
+    Size(i32),
+    /// The type field is none of the protocol's
+    Type(i32),
+    /// The two bytes where the size field puts the packet's end aren't null
+    End([u8; 2]),
+}
+
+impl Fault {
+    fn describe(&self, max_size: usize) -> String {
+        match self {
+            Fault::Size(size) => format!(
+                "a packet size of {}, outside the {}..={} the protocol allows",
+                size, PACKET_SIZE_MIN, max_size
+            ),
+            Fault::Type(typ) => format!("a packet of unknown type {}", typ),
+            Fault::End(end) => format!(
+                "a packet ending in {:#04x} {:#04x} where its size field puts the two null bytes",
+                end[0], end[1]
+            ),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use super::{GameProfile, Player, PlayerAction};
+use crate::{Packet, PacketType};
+
+/// ARK: Survival Evolved
+pub struct Ark;
+
+/// ARK's placeholder body for commands that don't print anything
+pub(super) const EMPTY_RESPONSE: &str = "Server received, But no response!!";
+
+const COMMANDS: &[&str] = &[
+    "AllowPlayerToJoinNoCheck",
+    "BanPlayer",
+    "Broadcast",
+    "DestroyWildDinos",
+    "DoExit",
+    "GetChat",
+    "GetGameLog",
+    "KickPlayer",
+    "ListPlayers",
+    "SaveWorld",
+    "ServerChat",
+    "ServerChatTo",
+    "SetMessageOfTheDay",
+    "SetTimeOfDay",
+    "ShowMessageOfTheDay",
+    "UnbanPlayer",
+];
+
+/// Rejoin bodies split at arbitrary points by Unreal's RCON, emptying ARK's "no response" placeholder
+pub(super) fn rejoin(packets: Vec<Packet>) -> Vec<Packet> {
+    if packets.is_empty() {
+        return packets;
+    }
+
+    let id = packets[0].id;
+    let body: String = packets.iter().map(|p| p.body()).collect();
+    let body = match body.trim() {
+        EMPTY_RESPONSE => String::new(),
+        _ => body,
+    };
+    vec![Packet::from_text(id, PacketType::Response, body)]
+}
+
+impl GameProfile for Ark {
+    fn name(&self) -> &'static str {
+        "ARK: Survival Evolved"
+    }
+
+    fn known_commands(&self) -> &'static [&'static str] {
+        COMMANDS
+    }
+
+    fn dangerous_commands(&self) -> &'static [&'static str] {
+        &["DoExit"]
+    }
+
+    /// Long output such as `ListPlayers` is split across packets unpredictably
+    fn normalize_response(&self, packets: Vec<Packet>) -> Vec<Packet> {
+        rejoin(packets)
+    }
+
+    fn say_command(&self, message: &str) -> Option<String> {
+        Some(format!("ServerChat {}", message))
+    }
+
+    fn save_command(&self) -> Option<&'static str> {
+        Some("SaveWorld")
+    }
+
+    fn stop_command(&self) -> Option<&'static str> {
+        Some("DoExit")
+    }
+
+    fn players_command(&self) -> Option<&'static str> {
+        Some("ListPlayers")
+    }
+
+    /// `ListPlayers` rows look like `0. name, 76561198000000000`
+    fn parse_players(&self, response: &str) -> Vec<Player> {
+        response
+            .lines()
+            .filter_map(|line| {
+                let (_, row) = line.split_once(". ")?;
+                let (name, id) = row.rsplit_once(',')?;
+                Some(Player {
+                    name: name.trim().to_string(),
+                    id: Some(id.trim().to_string()),
+                })
+            })
+            .collect()
+    }
+
+    /// ARK identifies players by SteamID
+    fn player_command(&self, action: &PlayerAction) -> Option<String> {
+        Some(match action {
+            PlayerAction::Kick { name, .. } => format!("KickPlayer {}", name),
+            PlayerAction::Ban { name, .. } => format!("BanPlayer {}", name),
+            PlayerAction::Pardon { name } => format!("UnbanPlayer {}", name),
+            PlayerAction::Msg { name, message } => {
+                format!("ServerChatTo \"{}\" {}", name, message.join(" "))
+            }
+        })
+    }
+}
@@ -0,0 +1,63 @@
+use super::{ark, GameProfile, Player, PlayerAction};
+use crate::Packet;
+
+/// Conan Exiles
+pub struct Conan;
+
+const COMMANDS: &[&str] = &[
+    "BanPlayer",
+    "Broadcast",
+    "KickPlayer",
+    "ListPlayers",
+    "UnbanPlayer",
+];
+
+impl GameProfile for Conan {
+    fn name(&self) -> &'static str {
+        "Conan Exiles"
+    }
+
+    fn known_commands(&self) -> &'static [&'static str] {
+        COMMANDS
+    }
+
+    /// Shares ARK's habit of splitting long output across packets
+    fn normalize_response(&self, packets: Vec<Packet>) -> Vec<Packet> {
+        ark::rejoin(packets)
+    }
+
+    fn say_command(&self, message: &str) -> Option<String> {
+        Some(format!("Broadcast {}", message))
+    }
+
+    fn players_command(&self) -> Option<&'static str> {
+        Some("ListPlayers")
+    }
+
+    /// `ListPlayers` prints a `Idx | Char name | Player name | User ID | ...` table
+    fn parse_players(&self, response: &str) -> Vec<Player> {
+        response
+            .lines()
+            .skip(1)
+            .filter_map(|line| {
+                let columns: Vec<&str> = line.split('|').map(str::trim).collect();
+                match columns.as_slice() {
+                    [_, name, _, id, ..] if !name.is_empty() => Some(Player {
+                        name: name.to_string(),
+                        id: Some(id.to_string()),
+                    }),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    fn player_command(&self, action: &PlayerAction) -> Option<String> {
+        match action {
+            PlayerAction::Kick { name, .. } => Some(format!("KickPlayer {}", name)),
+            PlayerAction::Ban { name, .. } => Some(format!("BanPlayer {}", name)),
+            PlayerAction::Pardon { name } => Some(format!("UnbanPlayer {}", name)),
+            PlayerAction::Msg { .. } => None,
+        }
+    }
+}
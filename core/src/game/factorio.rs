@@ -0,0 +1,117 @@
+use super::{with_reason, ColorCodes, GameProfile, Player, PlayerAction, ServerInfo};
+
+/// Factorio headless server
+pub struct Factorio;
+
+/// Largest packet Factorio will send back, it doesn't split long responses
+const PACKET_SIZE_MAX: usize = 1 << 16;
+
+const COMMANDS: &[&str] = &[
+    "/admins",
+    "/ban",
+    "/bans",
+    "/c",
+    "/demote",
+    "/evolution",
+    "/help",
+    "/kick",
+    "/mute",
+    "/players",
+    "/promote",
+    "/purge",
+    "/seed",
+    "/server-save",
+    "/silent-command",
+    "/time",
+    "/unban",
+    "/unmute",
+    "/version",
+    "/whisper",
+    "/whitelist",
+];
+
+impl GameProfile for Factorio {
+    fn name(&self) -> &'static str {
+        "Factorio"
+    }
+
+    fn max_packet_size(&self) -> usize {
+        PACKET_SIZE_MAX
+    }
+
+    fn color_codes(&self) -> ColorCodes {
+        ColorCodes::RichText
+    }
+
+    fn known_commands(&self) -> &'static [&'static str] {
+        COMMANDS
+    }
+
+    fn dangerous_commands(&self) -> &'static [&'static str] {
+        &["/quit"]
+    }
+
+    /// Anything without a leading slash is sent to chat by the server
+    fn say_command(&self, message: &str) -> Option<String> {
+        Some(message.trim_start_matches('/').to_string())
+    }
+
+    fn save_command(&self) -> Option<&'static str> {
+        Some("/server-save")
+    }
+
+    fn stop_command(&self) -> Option<&'static str> {
+        Some("/quit")
+    }
+
+    fn players_command(&self) -> Option<&'static str> {
+        Some("/players online")
+    }
+
+    fn info_command(&self) -> Option<&'static str> {
+        Some("/version")
+    }
+
+    /// `/version` answers with a bare version number
+    fn parse_info(&self, response: &str) -> ServerInfo {
+        ServerInfo {
+            version: Some(response.trim().to_string()).filter(|version| !version.is_empty()),
+            ..ServerInfo::default()
+        }
+    }
+
+    /// `/players online` lists one `  name (online)` line per player below a header
+    fn parse_players(&self, response: &str) -> Vec<Player> {
+        response
+            .lines()
+            .skip(1)
+            .map(|line| line.trim().trim_end_matches("(online)").trim())
+            .filter(|name| !name.is_empty())
+            .map(|name| Player {
+                name: name.to_string(),
+                id: None,
+            })
+            .collect()
+    }
+
+    fn player_command(&self, action: &PlayerAction) -> Option<String> {
+        Some(match action {
+            PlayerAction::Kick { name, reason } => with_reason(&format!("/kick {}", name), reason),
+            PlayerAction::Ban { name, reason } => with_reason(&format!("/ban {}", name), reason),
+            PlayerAction::Pardon { name } => format!("/unban {}", name),
+            PlayerAction::Msg { name, message } => {
+                format!("/whisper {} {}", name, message.join(" "))
+            }
+        })
+    }
+
+    /// With `print` the snippet is treated as an expression and its value is sent back over RCON,
+    /// since `/silent-command` output otherwise only shows up in the server console.
+    fn lua_command(&self, code: &str, print: bool) -> Option<String> {
+        if print {
+            Some(format!("/silent-command rcon.print({})", code))
+        } else {
+            Some(format!("/silent-command {}", code))
+        }
+    }
+}
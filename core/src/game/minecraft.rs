@@ -0,0 +1,193 @@
+use super::{with_reason, ColorCodes, GameProfile, Player, PlayerAction, ServerInfo};
+use serde_json::json;
+
+/// Minecraft: Java Edition
+pub struct Minecraft;
+
+const COMMANDS: &[&str] = &[
+    "ban",
+    "ban-ip",
+    "banlist",
+    "deop",
+    "difficulty",
+    "effect",
+    "gamemode",
+    "gamerule",
+    "give",
+    "help",
+    "kick",
+    "kill",
+    "list",
+    "op",
+    "pardon",
+    "pardon-ip",
+    "save-all",
+    "save-off",
+    "save-on",
+    "say",
+    "seed",
+    "setblock",
+    "stop",
+    "summon",
+    "tell",
+    "time",
+    "tp",
+    "weather",
+    "whitelist",
+];
+
+impl GameProfile for Minecraft {
+    fn name(&self) -> &'static str {
+        "Minecraft"
+    }
+
+    fn color_codes(&self) -> ColorCodes {
+        ColorCodes::Section
+    }
+
+    fn known_commands(&self) -> &'static [&'static str] {
+        COMMANDS
+    }
+
+    fn dangerous_commands(&self) -> &'static [&'static str] {
+        &["stop"]
+    }
+
+    fn save_command(&self) -> Option<&'static str> {
+        Some("save-all flush")
+    }
+
+    /// `tellraw` shows the line without the `[Rcon]` prefix `say` adds
+    fn chat_command(&self, sender: Option<&str>, message: &str) -> Option<String> {
+        let text = match sender {
+            Some(sender) => format!("<{}> {}", sender, message),
+            None => message.to_string(),
+        };
+        Some(format!("tellraw @a {}", json!({ "text": text })))
+    }
+
+    fn save_off_command(&self) -> Option<&'static str> {
+        Some("save-off")
+    }
+
+    fn save_on_command(&self) -> Option<&'static str> {
+        Some("save-on")
+    }
+
+    fn save_confirmation(&self) -> Option<&'static str> {
+        Some("Saved the game")
+    }
+
+    fn stop_command(&self) -> Option<&'static str> {
+        Some("stop")
+    }
+
+    fn players_command(&self) -> Option<&'static str> {
+        Some("list")
+    }
+
+    /// `list` answers with `There are 2 of a max of 20 players online: Steve, Alex`
+    fn parse_players(&self, response: &str) -> Vec<Player> {
+        listed_names(response)
+            .map(|name| Player {
+                name: name.to_string(),
+                id: None,
+            })
+            .collect()
+    }
+
+    fn parse_max_players(&self, response: &str) -> Option<u32> {
+        let (_, rest) = response.split_once(" of a max of ")?;
+        rest.split_whitespace().next()?.parse().ok()
+    }
+
+    /// Counted from the start of `list`'s answer, the names after it may be cut off
+    fn parse_info(&self, response: &str) -> ServerInfo {
+        let players = response
+            .trim_start()
+            .strip_prefix("There are ")
+            .and_then(|rest| rest.split(|c: char| !c.is_ascii_digit()).next())
+            .and_then(|count| count.parse().ok());
+        ServerInfo {
+            players,
+            max_players: self.parse_max_players(response),
+            ..ServerInfo::default()
+        }
+    }
+
+    /// Paper and Spigot answer `tps` with `TPS from last 1m, 5m, 15m: 20.0, 19.98, 20.0`,
+    /// vanilla has no equivalent
+    fn parse_stats(&self, response: &str) -> Vec<(&'static str, f64)> {
+        let values = match response.split_once("TPS from last 1m, 5m, 15m:") {
+            Some((_, values)) => values,
+            None => return Vec::new(),
+        };
+        ["tps_1m", "tps_5m", "tps_15m"]
+            .iter()
+            .zip(values.split(','))
+            .filter_map(|(name, value)| {
+                // Overloaded servers report `*20.0`
+                let value = value.trim().trim_start_matches('*');
+                Some((*name, value.parse().ok()?))
+            })
+            .collect()
+    }
+
+    fn bans_command(&self) -> Option<&'static str> {
+        Some("banlist players")
+    }
+
+    /// `banlist players` answers with `There are 2 ban(s):` followed by
+    /// `Steve was banned by Rcon: griefing` entries, which RCON runs together without newlines
+    fn parse_bans(&self, response: &str) -> Vec<String> {
+        let mut segments: Vec<&str> = response.split(" was banned by ").collect();
+        // The last segment is only the final ban's source and reason
+        segments.pop();
+        segments
+            .into_iter()
+            .filter_map(|segment| {
+                let start = segment
+                    .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .map_or(0, |i| i + 1);
+                let name = &segment[start..];
+                (!name.is_empty()).then(|| name.to_string())
+            })
+            .collect()
+    }
+
+    fn whitelist_command(&self) -> Option<&'static str> {
+        Some("whitelist list")
+    }
+
+    /// `whitelist list` answers with `There are 2 whitelisted player(s): Steve, Alex`
+    fn parse_whitelist(&self, response: &str) -> Vec<String> {
+        listed_names(response).map(String::from).collect()
+    }
+
+    fn whitelist_add_command(&self, name: &str) -> Option<String> {
+        Some(format!("whitelist add {}", name))
+    }
+
+    fn whitelist_remove_command(&self, name: &str) -> Option<String> {
+        Some(format!("whitelist remove {}", name))
+    }
+
+    fn player_command(&self, action: &PlayerAction) -> Option<String> {
+        Some(match action {
+            PlayerAction::Kick { name, reason } => with_reason(&format!("kick {}", name), reason),
+            PlayerAction::Ban { name, reason } => with_reason(&format!("ban {}", name), reason),
+            PlayerAction::Pardon { name } => format!("pardon {}", name),
+            PlayerAction::Msg { name, message } => format!("tell {} {}", name, message.join(" ")),
+        })
+    }
+}
+
+/// Names following the colon of a `There are ...: Steve, Alex` listing
+fn listed_names(response: &str) -> impl Iterator<Item = &str> {
+    response
+        .split_once(':')
+        .map_or("", |(_, names)| names)
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+}
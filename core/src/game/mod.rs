@@ -0,0 +1,512 @@
+/*
+ * Game profiles bundling each server's RCON dialect and quirks.
+ */
+
+mod ark;
+mod conan;
+mod factorio;
+mod minecraft;
+mod palworld;
+mod seven_days;
+mod source;
+
+use crate::{Packet, PACKET_SIZE_MAX};
+use serde::Deserialize;
+use std::{fmt, mem};
+
+/// Game server flavors with their own RCON command syntax
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Game {
+    /// Source Dedicated Server (CS:GO, TF2, Garry's Mod, ...)
+    Source,
+    /// Minecraft: Java Edition
+    Minecraft,
+    /// Factorio headless server
+    Factorio,
+    /// Palworld dedicated server (Unreal Engine)
+    Palworld,
+    /// 7 Days to Die, usually reached with `--protocol telnet`
+    #[cfg_attr(feature = "clap", clap(name = "7dtd"))]
+    #[serde(rename = "7dtd")]
+    SevenDays,
+    /// ARK: Survival Evolved
+    Ark,
+    /// Conan Exiles
+    Conan,
+}
+
+/// High-level player management actions, translated per [`Game`]
+#[derive(Debug)]
+#[cfg_attr(feature = "clap", derive(clap::Subcommand))]
+pub enum PlayerAction {
+    /// Kick a player from the server
+    Kick {
+        name: String,
+        /// Reason shown to the kicked player
+        reason: Option<String>,
+    },
+    /// Ban a player from the server
+    Ban {
+        name: String,
+        /// Reason shown to the banned player
+        reason: Option<String>,
+    },
+    /// Lift a player's ban
+    Pardon { name: String },
+    /// Send a private message to a player
+    Msg {
+        name: String,
+        #[cfg_attr(feature = "clap", clap(required = true))]
+        message: Vec<String>,
+    },
+}
+
+impl PlayerAction {
+    /// Subcommand name of the action
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlayerAction::Kick { .. } => "kick",
+            PlayerAction::Ban { .. } => "ban",
+            PlayerAction::Pardon { .. } => "pardon",
+            PlayerAction::Msg { .. } => "msg",
+        }
+    }
+}
+
+/// Harmless commands sent to fingerprint a server when `--game` isn't given
+///
+/// `/version` goes first since Factorio would broadcast anything without a slash as chat.
+pub(crate) const DETECTION_PROBES: &[&str] = &["/version", "version", "status", "Info"];
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.profile().name())
+    }
+}
+
+impl Game {
+    /// Dialect and quirks of the game
+    pub fn profile(&self) -> &'static dyn GameProfile {
+        match self {
+            Game::Source => &source::Source,
+            Game::Minecraft => &minecraft::Minecraft,
+            Game::Factorio => &factorio::Factorio,
+            Game::Palworld => &palworld::Palworld,
+            Game::SevenDays => &seven_days::SevenDays,
+            Game::Ark => &ark::Ark,
+            Game::Conan => &conan::Conan,
+        }
+    }
+
+    /// Guess the game from a response to one of the [`DETECTION_PROBES`]
+    pub fn fingerprint(response: &str) -> Option<Game> {
+        let response = response.trim();
+        if response.contains(ark::EMPTY_RESPONSE) {
+            Some(Game::Ark)
+        } else if response.contains("Couldn't find the command") {
+            Some(Game::Conan)
+        } else if response.contains("Unknown or incomplete command")
+            || response.contains("This server is running")
+        {
+            Some(Game::Minecraft)
+        } else if response.contains("Pal Server") {
+            Some(Game::Palworld)
+        } else if response.contains("Exe version")
+            || response.contains("Protocol version")
+            || response.contains("hostname:")
+        {
+            Some(Game::Source)
+        } else if response.split('.').count() >= 3
+            && response.chars().all(|c| c.is_ascii_digit() || c == '.')
+        {
+            // Factorio answers `/version` with a bare version number
+            Some(Game::Factorio)
+        } else {
+            None
+        }
+    }
+}
+
+/// Formatting codes a game embeds in response text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorCodes {
+    /// Plain text
+    None,
+    /// `§` followed by a single format character (Minecraft)
+    Section,
+    /// `[color=red]...[/color]` style rich text tags (Factorio)
+    RichText,
+}
+
+impl ColorCodes {
+    /// Remove the dialect's formatting codes from `text`
+    pub fn strip(&self, text: &str) -> String {
+        match self {
+            ColorCodes::None => text.to_string(),
+            // Most responses have none, so they're copied whole rather than a character at a time
+            ColorCodes::Section if !text.contains('§') => text.to_string(),
+            ColorCodes::Section => {
+                let mut filtered = String::with_capacity(text.len());
+                let mut iter = text.chars();
+                while let Some(ch) = iter.next() {
+                    if ch == '§' {
+                        iter.next();
+                    } else {
+                        filtered.push(ch);
+                    }
+                }
+                filtered
+            }
+            ColorCodes::RichText => {
+                let mut filtered = String::with_capacity(text.len());
+                let mut rest = text;
+                while let Some(start) = rest.find('[') {
+                    filtered.push_str(&rest[..start]);
+                    let tag = &rest[start..];
+                    match tag.find(']') {
+                        Some(end) if is_rich_text_tag(&tag[1..end]) => rest = &tag[end + 1..],
+                        _ => {
+                            filtered.push('[');
+                            rest = &tag[1..];
+                        }
+                    }
+                }
+                filtered.push_str(rest);
+                filtered
+            }
+        }
+    }
+
+    /// Where a formatting code cut off by the end of `text` starts, or its length when there's
+    /// none
+    pub fn cut_off(&self, text: &str) -> usize {
+        match self {
+            ColorCodes::None => text.len(),
+            ColorCodes::Section => text.strip_suffix('§').map_or(text.len(), str::len),
+            ColorCodes::RichText => match text.rfind('[') {
+                Some(start)
+                    if !text[start..].contains(']') && {
+                        let name = text[start + 1..].trim_start_matches('/');
+                        match name.split_once('=') {
+                            Some((name, _)) => RICH_TEXT_TAGS.contains(&name),
+                            None => RICH_TEXT_TAGS.iter().any(|tag| tag.starts_with(name)),
+                        }
+                    } =>
+                {
+                    start
+                }
+                _ => text.len(),
+            },
+        }
+    }
+}
+
+/// Names of Factorio's rich text tags
+const RICH_TEXT_TAGS: &[&str] = &[
+    "color",
+    "font",
+    "img",
+    "item",
+    "entity",
+    "gps",
+    "special-item",
+];
+
+/// Whether the contents of a `[...]` block are a Factorio rich text tag
+fn is_rich_text_tag(tag: &str) -> bool {
+    let name = tag.trim_start_matches('/');
+    let name = name.split('=').next().unwrap_or("");
+    RICH_TEXT_TAGS.contains(&name)
+}
+
+/// A player as reported by the game's player list command
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Player {
+    /// Display name
+    pub name: String,
+
+    /// Platform-specific unique ID (SteamID, UID, ...), when the listing includes one
+    pub id: Option<String>,
+}
+
+/// What a server tells about itself in answer to [`GameProfile::info_command`], as far as the
+/// game's answer goes
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServerInfo {
+    pub name: Option<String>,
+    pub map: Option<String>,
+    /// Players online
+    pub players: Option<usize>,
+    pub max_players: Option<u32>,
+    pub version: Option<String>,
+}
+
+/// A chat line pushed by the server
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChatMessage {
+    pub sender: String,
+    pub text: String,
+}
+
+/// Everything that differs between games speaking RCON
+///
+/// Defaults describe a well-behaved SRCDS-style server.
+pub trait GameProfile: Sync {
+    /// Human-readable name of the game
+    fn name(&self) -> &'static str;
+
+    /// Whether the server swallows the first command after auth, requiring an empty follow-up
+    ///
+    /// Only SRCDS does, others may answer the empty command with an error or reject it outright.
+    fn needs_auth_followup(&self) -> bool {
+        false
+    }
+
+    /// Whether an empty command sent after another is answered in order, with an empty
+    /// response, so its answer marks the end of the first's
+    ///
+    /// Only SRCDS does. Without it responses end once the server goes quiet.
+    fn supports_empty_marker(&self) -> bool {
+        false
+    }
+
+    /// Largest packet size accepted from the server, including the ID and type fields
+    fn max_packet_size(&self) -> usize {
+        PACKET_SIZE_MAX
+    }
+
+    /// Whether responses should be parsed without trusting their size field
+    fn lenient_parsing(&self) -> bool {
+        false
+    }
+
+    /// Formatting codes stripped from response text
+    fn color_codes(&self) -> ColorCodes {
+        ColorCodes::None
+    }
+
+    /// Commands offered for completion in the shell
+    fn known_commands(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Commands that could shut down or damage the server when sent by accident, confirmed
+    /// before the shell sends them
+    fn dangerous_commands(&self) -> &'static [&'static str] {
+        &["exit", "quit"]
+    }
+
+    /// Rewrite a command to work around the server's argument parsing
+    fn prepare_command(&self, cmd: &str) -> String {
+        cmd.to_string()
+    }
+
+    /// Clean up a command's response packets
+    fn normalize_response(&self, packets: Vec<Packet>) -> Vec<Packet> {
+        packets
+    }
+
+    /// Command broadcasting a chat message to every player
+    fn say_command(&self, message: &str) -> Option<String> {
+        Some(format!("say {}", message))
+    }
+
+    /// Command relaying a chat line, labelled with `sender` when given
+    fn chat_command(&self, sender: Option<&str>, message: &str) -> Option<String> {
+        match sender {
+            Some(sender) => self.say_command(&format!("<{}> {}", sender, message)),
+            None => self.say_command(message),
+        }
+    }
+
+    /// Split a command too long for one packet into commands of at most `max_len` bytes doing the
+    /// same, `None` when the game has no way to send it in parts
+    ///
+    /// Chat broadcasts are split between words, each part repeating the command.
+    fn split_command(&self, cmd: &str, max_len: usize) -> Option<Vec<String>> {
+        let overhead = self.say_command("")?.len();
+        // The message is either the whole command or everything after its first word
+        let rest = cmd.split_once(' ').map_or("", |(_, rest)| rest);
+        let message = [cmd, rest]
+            .iter()
+            .copied()
+            .find(|m| !m.trim().is_empty() && self.say_command(m).as_deref() == Some(cmd))?;
+        let room = max_len.checked_sub(overhead).filter(|room| *room >= 4)?;
+        split_words(message, room)
+            .iter()
+            .map(|part| self.say_command(part))
+            .collect()
+    }
+
+    /// Commands fetching the output of `cmd` a page at a time, for commands whose whole output
+    /// is too big to ask for in one response, `None` to send it as it is
+    fn page_command(&self, _cmd: &str) -> Option<Vec<String>> {
+        None
+    }
+
+    /// The responses to [`GameProfile::page_command`]'s pages put together as the one response
+    /// to the whole command would read
+    fn stitch_pages(&self, pages: &[String]) -> String {
+        pages.concat()
+    }
+
+    /// Parse a chat message out of a line of server output
+    fn parse_chat(&self, _line: &str) -> Option<ChatMessage> {
+        None
+    }
+
+    /// Command flushing the world to disk
+    fn save_command(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Command pausing automatic saves so files can be copied consistently
+    fn save_off_command(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Command resuming automatic saves after [`GameProfile::save_off_command`]
+    fn save_on_command(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Text in the save command's response confirming the world is on disk
+    fn save_confirmation(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Command shutting the server down
+    fn stop_command(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Command listing the players currently online
+    fn players_command(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Parse the response to [`GameProfile::players_command`]
+    fn parse_players(&self, _response: &str) -> Vec<Player> {
+        Vec::new()
+    }
+
+    /// Most players the server takes, when the response to [`GameProfile::players_command`]
+    /// says
+    fn parse_max_players(&self, _response: &str) -> Option<u32> {
+        None
+    }
+
+    /// Command telling what the server is, for the banner the shell shows on connecting
+    fn info_command(&self) -> Option<&'static str> {
+        self.players_command()
+    }
+
+    /// Parse the response to [`GameProfile::info_command`], by default the counts of the player
+    /// list
+    fn parse_info(&self, response: &str) -> ServerInfo {
+        ServerInfo {
+            players: Some(self.parse_players(response).len()),
+            max_players: self.parse_max_players(response),
+            ..ServerInfo::default()
+        }
+    }
+
+    /// Command reporting server performance, such as frame or tick rate
+    fn stats_command(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Parse named performance figures out of a command's response, for the metrics exporter
+    fn parse_stats(&self, _response: &str) -> Vec<(&'static str, f64)> {
+        Vec::new()
+    }
+
+    /// Translate a high-level player action into this game's console command
+    ///
+    /// Returns `None` when the game has no built-in equivalent of the action.
+    fn player_command(&self, _action: &PlayerAction) -> Option<String> {
+        None
+    }
+
+    /// Command listing banned players
+    fn bans_command(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Parse the response to [`GameProfile::bans_command`] into the names or IDs that
+    /// [`PlayerAction::Ban`] accepts
+    fn parse_bans(&self, _response: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Command writing ban changes to disk, for servers that only keep them in memory
+    fn persist_bans_command(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Command listing the whitelisted players
+    fn whitelist_command(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Parse the response to [`GameProfile::whitelist_command`] into player names
+    fn parse_whitelist(&self, _response: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Command adding a player to the whitelist
+    fn whitelist_add_command(&self, _name: &str) -> Option<String> {
+        None
+    }
+
+    /// Command removing a player from the whitelist
+    fn whitelist_remove_command(&self, _name: &str) -> Option<String> {
+        None
+    }
+
+    /// Wrap a Lua snippet in the game's scripting command, if it has one
+    fn lua_command(&self, _code: &str, _print: bool) -> Option<String> {
+        None
+    }
+}
+
+/// Append an optional reason argument to a command
+fn with_reason(cmd: &str, reason: &Option<String>) -> String {
+    match reason {
+        Some(r) => format!("{} {}", cmd, r),
+        None => cmd.to_string(),
+    }
+}
+
+/// Break `text` into parts of at most `max` bytes, between words where it can
+fn split_words(text: &str, max: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    for mut word in text.split_whitespace() {
+        // Words longer than a whole part are cut wherever they have to be
+        while word.len() > max {
+            if !part.is_empty() {
+                parts.push(mem::take(&mut part));
+            }
+            let mut at = max;
+            while !word.is_char_boundary(at) {
+                at -= 1;
+            }
+            parts.push(word[..at].to_string());
+            word = &word[at..];
+        }
+        if !part.is_empty() && part.len() + 1 + word.len() > max {
+            parts.push(mem::take(&mut part));
+        }
+        if !part.is_empty() {
+            part.push(' ');
+        }
+        part.push_str(word);
+    }
+    if !part.is_empty() {
+        parts.push(part);
+    }
+    parts
+}
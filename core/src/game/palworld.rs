@@ -0,0 +1,109 @@
+use super::{GameProfile, Player, PlayerAction, ServerInfo};
+
+/// Palworld dedicated server (Unreal Engine)
+pub struct Palworld;
+
+const COMMANDS: &[&str] = &[
+    "BanPlayer",
+    "Broadcast",
+    "DoExit",
+    "Info",
+    "KickPlayer",
+    "Save",
+    "ShowPlayers",
+    "Shutdown",
+    "TeleportToMe",
+    "TeleportToPlayer",
+    "UnBanPlayer",
+];
+
+impl GameProfile for Palworld {
+    fn name(&self) -> &'static str {
+        "Palworld"
+    }
+
+    /// Palworld reports sizes that don't match the bodies it actually sends
+    fn lenient_parsing(&self) -> bool {
+        true
+    }
+
+    fn known_commands(&self) -> &'static [&'static str] {
+        COMMANDS
+    }
+
+    fn dangerous_commands(&self) -> &'static [&'static str] {
+        &["DoExit", "Shutdown"]
+    }
+
+    /// `Broadcast` only shows the first word of its message, so spaces are replaced
+    fn prepare_command(&self, cmd: &str) -> String {
+        match cmd.split_once(' ') {
+            Some((name, message)) if name.eq_ignore_ascii_case("Broadcast") => {
+                format!("{} {}", name, message.trim().replace(' ', "_"))
+            }
+            _ => cmd.to_string(),
+        }
+    }
+
+    fn say_command(&self, message: &str) -> Option<String> {
+        Some(format!("Broadcast {}", message))
+    }
+
+    fn save_command(&self) -> Option<&'static str> {
+        Some("Save")
+    }
+
+    fn stop_command(&self) -> Option<&'static str> {
+        Some("DoExit")
+    }
+
+    fn players_command(&self) -> Option<&'static str> {
+        Some("ShowPlayers")
+    }
+
+    fn info_command(&self) -> Option<&'static str> {
+        Some("Info")
+    }
+
+    /// `Info` answers with `Welcome to Pal Server[v0.1.5.1] My Server`
+    fn parse_info(&self, response: &str) -> ServerInfo {
+        let (_, rest) = match response.split_once('[') {
+            Some(split) => split,
+            None => return ServerInfo::default(),
+        };
+        let (version, name) = rest.split_once(']').unwrap_or((rest, ""));
+        ServerInfo {
+            name: Some(name.trim().to_string()).filter(|name| !name.is_empty()),
+            version: Some(version.trim().to_string()),
+            ..ServerInfo::default()
+        }
+    }
+
+    /// `ShowPlayers` answers with CSV rows of `name,playeruid,steamid` below a header
+    fn parse_players(&self, response: &str) -> Vec<Player> {
+        response
+            .lines()
+            .skip(1)
+            .filter_map(|line| {
+                let mut fields = line.split(',');
+                let name = fields.next()?.trim();
+                let steam_id = fields.nth(1).map(|id| id.trim().to_string());
+                Some(Player {
+                    name: name.to_string(),
+                    id: steam_id,
+                })
+            })
+            .filter(|p| !p.name.is_empty())
+            .collect()
+    }
+
+    /// Palworld identifies players by SteamID and has no private messages
+    fn player_command(&self, action: &PlayerAction) -> Option<String> {
+        match action {
+            PlayerAction::Kick { name, .. } => Some(format!("KickPlayer {}", name)),
+            PlayerAction::Ban { name, .. } => Some(format!("BanPlayer {}", name)),
+            PlayerAction::Pardon { name } => Some(format!("UnBanPlayer {}", name)),
+            PlayerAction::Msg { .. } => None,
+        }
+    }
+}
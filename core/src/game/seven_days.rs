@@ -0,0 +1,114 @@
+use super::{with_reason, ChatMessage, GameProfile, Player, PlayerAction, ServerInfo};
+
+/// 7 Days to Die, usually reached with `--protocol telnet`
+pub struct SevenDays;
+
+const COMMANDS: &[&str] = &[
+    "admin",
+    "ban",
+    "exit",
+    "gettime",
+    "help",
+    "kick",
+    "kickall",
+    "listents",
+    "listplayers",
+    "lp",
+    "saveworld",
+    "say",
+    "sayplayer",
+    "settime",
+    "shutdown",
+    "version",
+    "whitelist",
+];
+
+impl GameProfile for SevenDays {
+    fn name(&self) -> &'static str {
+        "7 Days to Die"
+    }
+
+    fn known_commands(&self) -> &'static [&'static str] {
+        COMMANDS
+    }
+
+    fn dangerous_commands(&self) -> &'static [&'static str] {
+        &["shutdown"]
+    }
+
+    fn say_command(&self, message: &str) -> Option<String> {
+        Some(format!("say \"{}\"", message))
+    }
+
+    /// The console log shows chat as `Chat (from '<id>', entity id '171', to 'Global'): 'Steve': hi`
+    fn parse_chat(&self, line: &str) -> Option<ChatMessage> {
+        let rest = &line[line.find("Chat (from ")?..];
+        let rest = &rest[rest.find("): '")? + 4..];
+        let (sender, text) = rest.split_once("': ")?;
+        Some(ChatMessage {
+            sender: sender.to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    fn save_command(&self) -> Option<&'static str> {
+        Some("saveworld")
+    }
+
+    fn stop_command(&self) -> Option<&'static str> {
+        Some("shutdown")
+    }
+
+    fn players_command(&self) -> Option<&'static str> {
+        Some("listplayers")
+    }
+
+    fn info_command(&self) -> Option<&'static str> {
+        Some("version")
+    }
+
+    /// `version` starts with `Game version: V 1.0 (b333) Compatibility Version: V 1.0`, followed
+    /// by the loaded mods
+    fn parse_info(&self, response: &str) -> ServerInfo {
+        let version = response
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("Game version:"))
+            .map(|rest| rest.split("Compatibility").next().unwrap_or(rest).trim());
+        ServerInfo {
+            version: version.map(String::from),
+            ..ServerInfo::default()
+        }
+    }
+
+    /// `listplayers` rows look like `0. id=171, name, pos=(...), ..., steamid=7656..., ...`
+    fn parse_players(&self, response: &str) -> Vec<Player> {
+        response
+            .lines()
+            .filter(|line| line.contains(". id="))
+            .filter_map(|line| {
+                let mut fields = line.split(", ");
+                let id = fields.next()?.split("id=").nth(1).map(String::from);
+                let name = fields.next()?.trim();
+                Some(Player {
+                    name: name.to_string(),
+                    id,
+                })
+            })
+            .collect()
+    }
+
+    fn player_command(&self, action: &PlayerAction) -> Option<String> {
+        Some(match action {
+            PlayerAction::Kick { name, reason } => {
+                with_reason(&format!("kick \"{}\"", name), reason)
+            }
+            PlayerAction::Ban { name, reason } => {
+                with_reason(&format!("ban add \"{}\" 10 years", name), reason)
+            }
+            PlayerAction::Pardon { name } => format!("ban remove \"{}\"", name),
+            PlayerAction::Msg { name, message } => {
+                format!("sayplayer \"{}\" \"{}\"", name, message.join(" "))
+            }
+        })
+    }
+}
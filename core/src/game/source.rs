@@ -0,0 +1,215 @@
+use super::{with_reason, GameProfile, Player, PlayerAction, ServerInfo};
+use std::collections::HashSet;
+
+/// Source Dedicated Server (CS:GO, TF2, Garry's Mod, ...)
+pub struct Source;
+
+const COMMANDS: &[&str] = &[
+    "banid",
+    "changelevel",
+    "cvarlist",
+    "echo",
+    "exec",
+    "find",
+    "help",
+    "kick",
+    "kickid",
+    "listid",
+    "maps",
+    "mp_restartgame",
+    "removeid",
+    "say",
+    "status",
+    "sv_cheats",
+    "sv_password",
+    "users",
+    "version",
+    "writeid",
+];
+
+/// Whether a `cvarlist` line is one of the rules around its list
+fn is_rule(line: &str) -> bool {
+    let line = line.trim();
+    line.len() >= 3 && line.bytes().all(|b| b == b'-')
+}
+
+impl GameProfile for Source {
+    fn name(&self) -> &'static str {
+        "Source"
+    }
+
+    fn known_commands(&self) -> &'static [&'static str] {
+        COMMANDS
+    }
+
+    fn needs_auth_followup(&self) -> bool {
+        true
+    }
+
+    fn supports_empty_marker(&self) -> bool {
+        true
+    }
+
+    fn dangerous_commands(&self) -> &'static [&'static str] {
+        &["exit", "quit", "_restart", "killserver", "banid", "banip"]
+    }
+
+    /// The whole `cvarlist` runs to megabytes on modded servers, so it's asked for by the
+    /// first character of the names instead. Names can't have `;` or `"` in them.
+    fn page_command(&self, cmd: &str) -> Option<Vec<String>> {
+        if !cmd.trim().eq_ignore_ascii_case("cvarlist") {
+            return None;
+        }
+        let pages = ('!'..='~')
+            .filter(|c| !matches!(c, ';' | '"'))
+            .map(|c| format!("cvarlist {}", c))
+            .collect();
+        Some(pages)
+    }
+
+    /// Pages repeat the `cvar list` header and end in a count of their own, like `12
+    /// convars/concommands for [s]`. Prefixes match without case, so names turning up on two
+    /// pages are only kept once.
+    fn stitch_pages(&self, pages: &[String]) -> String {
+        let mut seen = HashSet::new();
+        let mut entries = String::new();
+        for page in pages {
+            let lines: Vec<&str> = page.lines().collect();
+            let rules: Vec<usize> = (0..lines.len()).filter(|&i| is_rule(lines[i])).collect();
+            let (first, last) = match rules[..] {
+                [first, .., last] => (first, last),
+                // Not the list the pages were expected to be
+                _ => return pages.concat(),
+            };
+            for entry in &lines[first + 1..last] {
+                if seen.insert(*entry) {
+                    entries.push_str(entry);
+                    entries.push('\n');
+                }
+            }
+        }
+        format!(
+            "cvar list\n--------------\n{}--------------\n{:>3} total convars/concommands\n",
+            entries,
+            seen.len()
+        )
+    }
+
+    fn stop_command(&self) -> Option<&'static str> {
+        Some("quit")
+    }
+
+    fn players_command(&self) -> Option<&'static str> {
+        Some("status")
+    }
+
+    /// Player rows of `status` look like `# 2 "name" STEAM_1:0:1234 00:10 50 0 active ...`
+    fn parse_players(&self, response: &str) -> Vec<Player> {
+        response
+            .lines()
+            .filter(|line| line.starts_with('#'))
+            .filter_map(|line| {
+                let start = line.find('"')?;
+                let end = start + 1 + line[start + 1..].find('"')?;
+                Some(Player {
+                    name: line[start + 1..end].to_string(),
+                    id: line[end + 1..].split_whitespace().next().map(String::from),
+                })
+            })
+            .collect()
+    }
+
+    /// `status` starts with `name : value` rows, like `hostname: My Server` and
+    /// `map     : de_dust2 at: 0 x, 0 y, 0 z`
+    fn parse_info(&self, response: &str) -> ServerInfo {
+        let field = |key: &str| {
+            response.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                (name.trim() == key).then(|| value.trim())
+            })
+        };
+        ServerInfo {
+            name: field("hostname").map(String::from),
+            map: field("map")
+                .and_then(|map| map.split_whitespace().next())
+                .map(String::from),
+            players: Some(self.parse_players(response).len()),
+            max_players: self.parse_max_players(response),
+            version: field("version")
+                .and_then(|version| version.split_whitespace().next())
+                .map(String::from),
+        }
+    }
+
+    /// The `players` row of `status` ends in `(20/0 max)`
+    fn parse_max_players(&self, response: &str) -> Option<u32> {
+        let line = response
+            .lines()
+            .find(|l| l.trim_start().starts_with("players"))?;
+        let (_, counts) = line.rsplit_once('(')?;
+        counts.split('/').next()?.trim().parse().ok()
+    }
+
+    fn stats_command(&self) -> Option<&'static str> {
+        Some("stats")
+    }
+
+    /// `stats` prints a header row and a value row:
+    /// `CPU NetIn NetOut Uptime Maps FPS Players ...`, older engines spell out
+    /// `In (KB/s)` and `Map changes`
+    fn parse_stats(&self, response: &str) -> Vec<(&'static str, f64)> {
+        let mut lines = response.lines().skip_while(|l| !l.contains("FPS"));
+        let (header, values) = match (lines.next(), lines.next()) {
+            (Some(header), Some(values)) => (header, values),
+            _ => return Vec::new(),
+        };
+        let header = header.replace("(KB/s)", "").replace("Map changes", "Maps");
+
+        header
+            .split_whitespace()
+            .zip(values.split_whitespace())
+            .filter_map(|(name, value)| {
+                let name = match name {
+                    "CPU" => "cpu_percent",
+                    "FPS" => "fps",
+                    "Uptime" => "uptime_minutes",
+                    _ => return None,
+                };
+                Some((name, value.parse().ok()?))
+            })
+            .collect()
+    }
+
+    fn bans_command(&self) -> Option<&'static str> {
+        Some("listid")
+    }
+
+    /// `listid` rows look like `1 STEAM_1:0:1234 : permanent`
+    fn parse_bans(&self, response: &str) -> Vec<String> {
+        response
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                fields.next()?.parse::<u32>().ok()?;
+                fields.next().map(String::from)
+            })
+            .collect()
+    }
+
+    /// `banid` bans are lost when the server restarts unless written to `banned_user.cfg`
+    fn persist_bans_command(&self) -> Option<&'static str> {
+        Some("writeid")
+    }
+
+    fn player_command(&self, action: &PlayerAction) -> Option<String> {
+        match action {
+            PlayerAction::Kick { name, reason } => {
+                Some(with_reason(&format!("kick \"{}\"", name), reason))
+            }
+            // `banid` expects a user ID or SteamID, `kick` drops them from the server as well
+            PlayerAction::Ban { name, .. } => Some(format!("banid 0 {} kick", name)),
+            PlayerAction::Pardon { name } => Some(format!("removeid {}", name)),
+            PlayerAction::Msg { .. } => None,
+        }
+    }
+}
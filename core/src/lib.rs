@@ -0,0 +1,998 @@
+/*
+ * The RCON protocol and the sessions rustcon is built on: packets and their framing, each game's
+ * dialect and quirks, Source RCON and telnet consoles, pipelining and the audit log.
+ *
+ * Nothing here reads the terminal or parses a command line, so server-side projects can depend on
+ * the protocol alone. Passwords that have to be asked for come from the caller, see
+ * [`Rcon::login`]. Warnings, like workarounds for a server's quirks or a rejected password, go
+ * through the `log` crate, so they reach only the callers that install a logger.
+ */
+
+// Framing, the audit log and the traffic counters are there for the sessions behind `net`
+#![cfg_attr(not(feature = "net"), allow(dead_code))]
+
+pub mod audit;
+#[cfg(feature = "net")]
+mod client;
+pub mod config;
+pub mod duration;
+mod frame;
+pub mod game;
+pub mod logfile;
+#[cfg(feature = "rcon-compat")]
+pub mod rcon_compat;
+pub mod sanitize;
+#[cfg(feature = "net")]
+mod telnet;
+mod traffic;
+pub mod webrcon;
+
+#[cfg(feature = "net")]
+pub use client::{Pipeline, Rcon, RconResult, Reply};
+pub use config::{ClientConfig, Protocol};
+pub use game::{Game, GameProfile, PlayerAction, ServerInfo};
+#[cfg(feature = "net")]
+pub use telnet::is_timeout;
+pub use traffic::{Counters, Traffic};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use game::ColorCodes;
+use std::{convert::TryFrom, fmt, io, mem, path::PathBuf, str};
+use zeroize::Zeroizing;
+
+/// Definition for
+///
+/// Source: [https://developer.valvesoftware.com/wiki/Source_RCON_Protocol#Packet_Type](https://developer.valvesoftware.com/wiki/Source_RCON_Protocol#Packet_Type)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PacketType {
+    /// `SERVERDATA_AUTH`
+    Login = 3,
+    /// `SERVERDATA_EXECCOMMAND` or `SERVERDATA_AUTH_RESPONSE`
+    Command = 2,
+    /// `SERVERDATA_RESPONSE_VALUE`
+    Response = 0,
+    /// A packet type that doesn't follow the RCON specification
+    Unknown,
+}
+
+impl From<i32> for PacketType {
+    fn from(num: i32) -> Self {
+        match num {
+            3 => PacketType::Login,
+            2 => PacketType::Command,
+            0 => PacketType::Response,
+            _ => PacketType::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for PacketType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PacketType::Login => write!(f, "Login"),
+            PacketType::Command => write!(f, "Command/Auth Response"),
+            PacketType::Response => write!(f, "Response Data"),
+            _ => write!(f, "UNKNOWN"),
+        }
+    }
+}
+
+const PACKET_SIZE_FIELD_LEN: usize = 4;
+const PACKET_SIZE_MIN: usize = 10;
+pub(crate) const PACKET_SIZE_MAX: usize = 4096;
+/// Longest body sent in one packet, servers may take less
+pub const PACKET_BODY_MAX_LEN: usize = PACKET_SIZE_MAX - PACKET_SIZE_MIN;
+const BAD_AUTH: i32 = -1;
+
+/// Size field of a packet with a body of `body_len` bytes, which counts the ID, type and both
+/// null bytes along with the body
+const fn packet_size(body_len: usize) -> usize {
+    body_len + PACKET_SIZE_MIN
+}
+
+/// RCON packet structure
+///
+/// Source: [https://developer.valvesoftware.com/wiki/Source_RCON_Protocol#Basic_Packet_Structure](https://developer.valvesoftware.com/wiki/Source_RCON_Protocol#Basic_Packet_Structure)
+pub struct Packet {
+    /// Length of remainder of packet, max of 4096 for a single packet
+    size: i32,
+
+    /// Client-generated ID
+    id: i32,
+
+    /// 3 for login: SERVERDATA_AUTH
+    /// 2 for auth response or run a command: SERVERDATA_AUTH_RESPONSE or SERVERDATA_EXECCOMMAND
+    /// 0 for multi-packet response: SERVERDATA_RESPONSE_VALUE
+    typ: PacketType,
+
+    /// Body
+    body_text: String,
+    body_bytes: Bytes,
+
+    /// 1-byte pad / empty byte
+    pad: u8,
+}
+
+#[derive(Debug)]
+pub enum PacketError {
+    SmallPacket,
+    NonAscii,
+    /// The body is longer than [`PACKET_BODY_MAX_LEN`]
+    TooLarge,
+    /// Fewer bytes arrived than the packet's header needs
+    Truncated,
+}
+
+type PacketResult = Result<Packet, PacketError>;
+
+impl Packet {
+    /// Initialize a packet instance with calculated length and a pad byte
+    pub fn new(id: i32, typ: PacketType, body_text: String) -> PacketResult {
+        let body_bytes = Bytes::copy_from_slice(body_text.trim_end().as_bytes());
+        if !body_bytes.is_ascii() {
+            Err(PacketError::NonAscii)
+        } else if body_bytes.len() > PACKET_BODY_MAX_LEN {
+            Err(PacketError::TooLarge)
+        } else {
+            let packet = Packet {
+                size: packet_size(body_bytes.len()) as i32,
+                id,
+                typ,
+                body_text,
+                body_bytes,
+                pad: 0,
+            };
+
+            Ok(packet)
+        }
+    }
+
+    /// Serialize a login packet into a buffer that's wiped when dropped, so the password doesn't
+    /// linger in memory after it's sent
+    fn serialize_login(id: i32, pass: &str) -> Result<Zeroizing<Vec<u8>>, PacketError> {
+        let pass = pass.trim_end().as_bytes();
+        if !pass.is_ascii() {
+            return Err(PacketError::NonAscii);
+        }
+        if pass.len() > PACKET_BODY_MAX_LEN {
+            return Err(PacketError::TooLarge);
+        }
+        // Sized up front, so growing it never leaves a copy behind
+        let mut p = Zeroizing::new(Vec::with_capacity(
+            PACKET_SIZE_FIELD_LEN + packet_size(pass.len()),
+        ));
+        p.extend_from_slice(&(packet_size(pass.len()) as i32).to_le_bytes());
+        p.extend_from_slice(&id.to_le_bytes());
+        p.extend_from_slice(&(PacketType::Login as i32).to_le_bytes());
+        p.extend_from_slice(pass);
+        p.extend_from_slice(&[0, 0]);
+        Ok(p)
+    }
+
+    /// Wrap text that didn't arrive as an RCON packet, such as telnet console output
+    fn from_text(id: i32, typ: PacketType, body_text: String) -> Packet {
+        let body_bytes = Bytes::from(body_text.clone());
+        Packet {
+            size: packet_size(body_bytes.len()) as i32,
+            id,
+            typ,
+            body_text,
+            body_bytes,
+            pad: 0,
+        }
+    }
+
+    /// Parse a packet according to the game's size limit, parsing mode and color codes
+    ///
+    /// Lenient parsing ignores the size field and runs the body up to the first null byte of the
+    /// received data, for servers that misreport packet sizes.
+    pub fn deserialize(bytes: &mut Bytes, profile: &dyn GameProfile) -> PacketResult {
+        Packet::parse(bytes, profile, profile.lenient_parsing())
+    }
+
+    /// [`deserialize`](Packet::deserialize), with lenient parsing on or off regardless of the game
+    pub fn parse(bytes: &mut Bytes, profile: &dyn GameProfile, lenient: bool) -> PacketResult {
+        let max_size = profile.max_packet_size();
+
+        if bytes.remaining() < PACKET_SIZE_FIELD_LEN + PACKET_SIZE_MIN - 2 {
+            // Lenient parsing skips runt packets
+            return Err(if lenient {
+                PacketError::SmallPacket
+            } else {
+                PacketError::Truncated
+            });
+        }
+
+        let size = bytes.get_i32_le();
+        let id = bytes.get_i32_le();
+        let typ = PacketType::from(bytes.get_i32_le());
+
+        // Copy out bytes from body up to max possible packet size
+        let body_size = if lenient {
+            let received = bytes
+                .iter()
+                .position(|b| *b == 0)
+                .unwrap_or_else(|| bytes.remaining());
+            received.min(max_size - PACKET_SIZE_MIN)
+        } else {
+            match usize::try_from(size) {
+                Ok(s) if s < PACKET_SIZE_MIN => Err(PacketError::SmallPacket)?,
+                Ok(s) if s <= max_size => s - PACKET_SIZE_MIN,
+                Ok(_) => max_size - PACKET_SIZE_MIN,
+                // Negative
+                Err(_) => Err(PacketError::SmallPacket)?,
+            }
+        };
+
+        // A size claiming more than arrived keeps what did
+        let body_bytes = bytes.copy_to_bytes(body_size.min(bytes.remaining()));
+
+        let packet = Packet {
+            size,
+            id,
+            typ,
+            body_text: profile.color_codes().strip(&sanitize::decode(&body_bytes)),
+            body_bytes,
+            pad: 0,
+        };
+        Ok(packet)
+    }
+
+    /// Decode the bodies of a response's packets as one text, so characters and color codes
+    /// split between two packets come out whole
+    ///
+    /// Whatever was cut off at the end of a packet is shown with the next one.
+    fn decode_response(mut packets: Vec<Packet>, profile: &dyn GameProfile) -> Vec<Packet> {
+        if packets.len() < 2 {
+            return packets;
+        }
+        let codes = profile.color_codes();
+        let last = packets.len() - 1;
+        let mut carried = Vec::new();
+        for (i, packet) in packets.iter_mut().enumerate() {
+            // Packets ending whole with nothing carried into them were decoded right already
+            if carried.is_empty()
+                && (i == last || whole_len(&packet.body_bytes, codes) == packet.body_bytes.len())
+            {
+                continue;
+            }
+            let mut bytes = mem::take(&mut carried);
+            bytes.extend_from_slice(&packet.body_bytes);
+            if i < last {
+                carried = bytes.split_off(whole_len(&bytes, codes));
+            }
+            packet.body_text = codes.strip(&sanitize::decode(&bytes));
+        }
+        packets
+    }
+
+    /// ID of the command the packet belongs to
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// What the packet is, a login, command or response
+    pub fn packet_type(&self) -> PacketType {
+        self.typ.clone()
+    }
+
+    /// Body text with color codes removed
+    pub fn body(&self) -> &str {
+        &self.body_text
+    }
+
+    /// Body as it was received, color codes and bytes that aren't text included
+    pub fn raw_body(&self) -> &[u8] {
+        &self.body_bytes
+    }
+
+    /// Serialize packet into a Vec<u8>
+    pub fn serialize(&self) -> BytesMut {
+        let mut p =
+            BytesMut::with_capacity(PACKET_SIZE_FIELD_LEN + packet_size(self.body_bytes.len()));
+
+        // Construct packet data in bytes
+        p.put_i32_le(self.size);
+        p.put_i32_le(self.id);
+        p.put_i32_le(self.typ.clone() as i32);
+        p.put(self.body_bytes.clone());
+        p.put_u8(b'\0'); // terminate body with null byte
+        p.put_u8(self.pad); // append pad null byte
+        p
+    }
+}
+
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Size: {} bytes, ID: {}, Type: {}\n{}",
+            self.size, self.id, self.typ, self.body_text
+        )
+    }
+}
+
+/// How a response over `--max-response-bytes` was cut short
+#[derive(Clone, Debug)]
+pub struct Truncated {
+    /// Body bytes kept in the response
+    pub kept: usize,
+    /// Body bytes past the limit
+    pub dropped: usize,
+    /// Temp file the bytes past the limit were saved to, with `--spill-responses`
+    pub spilled_to: Option<PathBuf>,
+}
+
+impl fmt::Display for Truncated {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The response was cut short after {} bytes, over --max-response-bytes",
+            self.kept
+        )?;
+        match &self.spilled_to {
+            Some(path) => write!(f, ", the other {} are in {}", self.dropped, path.display()),
+            None => write!(f, ", the other {} were dropped", self.dropped),
+        }
+    }
+}
+
+/// RCON session error
+#[derive(Debug)]
+pub enum RconError {
+    PacketError,
+    AuthError,
+    ConnError,
+    /// The server's hostname didn't resolve to any address
+    Resolve {
+        host: String,
+    },
+    /// Nothing accepted the connection on the server's port
+    Refused,
+    /// The server didn't answer the connection attempt
+    ConnectTimeout,
+    /// There's no route to the server's network or host
+    Unreachable,
+    /// The game has no equivalent of the requested command
+    Unsupported,
+    /// A step outside the RCON session failed, such as a local archive command
+    TaskFailed,
+    /// The command isn't on the session's allowlist, so it was never sent
+    NotAllowed,
+    /// Nothing came back before the read timeout
+    Timeout,
+    /// The server closed the connection or reset it, for example by shutting down or kicking the
+    /// client
+    Disconnected {
+        reason: String,
+    },
+    /// Reading from or writing to the connection failed
+    Io(io::Error),
+    /// The command is longer than the server takes in one packet and the game can't send it in
+    /// parts, so it was never sent
+    TooLarge {
+        len: usize,
+        max: usize,
+    },
+    /// The server sent `detail` against the protocol, refused under `--protocol-strict`
+    Deviation {
+        detail: String,
+    },
+}
+
+impl RconError {
+    /// Whether the session is lost and needs reconnecting, rather than just the command failing
+    pub fn is_disconnect(&self) -> bool {
+        matches!(
+            self,
+            RconError::ConnError
+                | RconError::PacketError
+                | RconError::Disconnected { .. }
+                | RconError::Io(_)
+        )
+    }
+
+    /// What to check after failing to connect, `None` for errors that aren't about connecting
+    pub fn hint(&self) -> Option<String> {
+        let hint = match self {
+            RconError::Resolve { host } => format!(
+                "{} didn't resolve to an address, check the hostname or use the server's IP \
+                 address.",
+                host
+            ),
+            RconError::Refused => "The connection was refused, which usually means RCON is \
+                                   disabled on the server or the port is wrong."
+                .to_string(),
+            RconError::ConnectTimeout => "The server didn't answer, a firewall may be dropping \
+                                          the connection or the address may be wrong."
+                .to_string(),
+            RconError::Unreachable => "There's no route to the server, check the address and \
+                                       this machine's network connection."
+                .to_string(),
+            RconError::ConnError => "Please confirm the server is running.".to_string(),
+            _ => return None,
+        };
+        Some(hint)
+    }
+
+    /// The server closing the connection
+    pub fn closed() -> RconError {
+        RconError::Disconnected {
+            reason: "the server closed the connection".to_string(),
+        }
+    }
+}
+
+impl From<io::Error> for RconError {
+    /// Errors meaning the server hung up, like a broken pipe, become
+    /// [`RconError::Disconnected`]
+    fn from(e: io::Error) -> RconError {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => RconError::closed(),
+            io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected => RconError::Disconnected {
+                reason: e.to_string(),
+            },
+            _ => RconError::Io(e),
+        }
+    }
+}
+
+/// Work around the server sending `detail` against the protocol, logging that it did, or fail
+/// with [`RconError::Deviation`] when `strict`
+fn tolerate(strict: bool, detail: String) -> Result<(), RconError> {
+    if strict {
+        return Err(RconError::Deviation { detail });
+    }
+    log::warn!("Worked around the server sending {}", detail);
+    Ok(())
+}
+
+/// Length of `bytes` without a UTF-8 character or color code cut off at the end
+fn whole_len(bytes: &[u8], codes: ColorCodes) -> usize {
+    let mut len = bytes.len();
+    // Continuation bytes are 0b10xxxxxx, the first byte of a character tells its width
+    if let Some(start) = (len.saturating_sub(3)..len)
+        .rev()
+        .find(|i| bytes[*i] & 0xc0 != 0x80)
+    {
+        let width = match bytes[start] {
+            b if b >= 0xf0 => 4,
+            b if b >= 0xe0 => 3,
+            b if b >= 0xc0 => 2,
+            _ => 1,
+        };
+        if start + width > len {
+            len = start;
+        }
+    }
+    match str::from_utf8(&bytes[..len]) {
+        Ok(text) => codes.cut_off(text),
+        Err(_) => len,
+    }
+}
+
+/// Whether an allowlist of command names lets `cmd` through, comparing its first word without
+/// case or a leading `/`
+pub fn command_allowed(allowed: &[String], cmd: &str) -> bool {
+    let name = cmd.split_whitespace().next().unwrap_or("");
+    let name = name.strip_prefix('/').unwrap_or(name);
+    allowed.iter().any(|a| {
+        let a = a.trim();
+        a.strip_prefix('/').unwrap_or(a).eq_ignore_ascii_case(name)
+    })
+}
+
+#[cfg(all(test, feature = "net"))]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::{
+        fs,
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+        thread,
+        time::Duration,
+    };
+
+    /// What the test server does in answer to a command
+    pub(crate) enum Step {
+        /// Send a response packet
+        Part(String),
+        /// Send a packet for a command that isn't the one being answered
+        Stray(String),
+        /// Send several response packets in one write, so they arrive in one read
+        Coalesced(Vec<String>),
+        /// Go quiet for longer than the client's read timeout
+        Pause,
+        /// Go quiet for a moment, well within the client's read timeout
+        Lull,
+    }
+
+    fn packet(id: i32, typ: i32, body: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(body.len() as i32 + 10).to_le_bytes());
+        bytes.extend_from_slice(&id.to_le_bytes());
+        bytes.extend_from_slice(&typ.to_le_bytes());
+        bytes.extend_from_slice(body);
+        bytes.extend_from_slice(&[0, 0]);
+        bytes
+    }
+
+    /// Write a packet a piece at a time, so it arrives split over several reads
+    fn write_split(conn: &mut TcpStream, bytes: &[u8]) {
+        for piece in bytes.chunks(1000) {
+            conn.write_all(piece).unwrap();
+            conn.flush().unwrap();
+            thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    /// Read a packet from the client as its ID, type and body, or `None` once it disconnects
+    fn read_packet(conn: &mut TcpStream) -> Option<(i32, i32, String)> {
+        let mut size = [0; 4];
+        conn.read_exact(&mut size).ok()?;
+        let mut rest = vec![0; i32::from_le_bytes(size) as usize];
+        conn.read_exact(&mut rest).unwrap();
+        let id = i32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]);
+        let typ = i32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]);
+        let body = String::from_utf8_lossy(&rest[8..rest.len() - 2]).into_owned();
+        Some((id, typ, body))
+    }
+
+    /// Serve one session of `game` on a free port, accepting the login and answering every
+    /// non-empty command with `respond`'s steps and empty ones with an empty packet
+    pub(crate) fn serve(game: Game, respond: fn(&str) -> Vec<Step>) -> ClientConfig {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            conn.set_nodelay(true).unwrap();
+            while let Some((id, typ, body)) = read_packet(&mut conn) {
+                if typ == 3 {
+                    write_split(&mut conn, &packet(id, 2, b""));
+                } else if body.is_empty() {
+                    write_split(&mut conn, &packet(id, 0, b""));
+                } else {
+                    for step in respond(&body) {
+                        match step {
+                            Step::Part(text) => {
+                                write_split(&mut conn, &packet(id, 0, text.as_bytes()))
+                            }
+                            Step::Stray(text) => {
+                                write_split(&mut conn, &packet(999, 0, text.as_bytes()))
+                            }
+                            Step::Coalesced(texts) => {
+                                let bytes: Vec<u8> = texts
+                                    .iter()
+                                    .flat_map(|text| packet(id, 0, text.as_bytes()))
+                                    .collect();
+                                conn.write_all(&bytes).unwrap();
+                            }
+                            Step::Pause => thread::sleep(Duration::from_millis(1500)),
+                            Step::Lull => thread::sleep(Duration::from_millis(100)),
+                        }
+                    }
+                }
+            }
+        });
+
+        let mut config = ClientConfig::new("127.0.0.1", port);
+        config.password = Some("pw".to_string());
+        config.game = Some(game);
+        config
+    }
+
+    fn bodies(packets: &[Packet]) -> Vec<&str> {
+        packets.iter().map(|p| p.body()).collect()
+    }
+
+    pub(crate) fn respond(cmd: &str) -> Vec<Step> {
+        match cmd {
+            "big" => (0..3).map(|_| Step::Part("x".repeat(4000))).collect(),
+            "slow" => vec![
+                Step::Part("first".to_string()),
+                Step::Pause,
+                Step::Part("second".to_string()),
+            ],
+            "sleepy" => vec![Step::Pause, Step::Part("late".to_string())],
+            "pair" => vec![Step::Coalesced(vec![
+                "first".to_string(),
+                "second".to_string(),
+            ])],
+            "noisy" => vec![
+                Step::Stray("stale".to_string()),
+                Step::Part("noisy ok".to_string()),
+            ],
+            _ => vec![Step::Part(format!("{} ok", cmd))],
+        }
+    }
+
+    /// Round trip a packet through serializing and parsing as a Source server's response
+    fn round_trip(id: i32, typ: PacketType, body: &str) {
+        let packet = Packet::new(id, typ.clone(), body.to_string()).unwrap();
+        let bytes = packet.serialize();
+        assert_eq!(bytes.len(), PACKET_SIZE_FIELD_LEN + packet_size(body.len()));
+
+        let parsed = Packet::deserialize(&mut bytes.freeze(), Game::Source.profile()).unwrap();
+        assert_eq!(parsed.id(), id);
+        assert_eq!(parsed.packet_type(), typ);
+        assert_eq!(parsed.body(), body);
+    }
+
+    proptest! {
+        #[test]
+        fn packets_round_trip(
+            id in any::<i32>(),
+            typ in prop_oneof![
+                Just(PacketType::Login),
+                Just(PacketType::Command),
+                Just(PacketType::Response),
+            ],
+            // Any ASCII, except that trailing whitespace isn't sent
+            body in proptest::collection::vec(0..0x80u8, 0..=PACKET_BODY_MAX_LEN)
+                .prop_map(|bytes| bytes.into_iter().map(char::from).collect::<String>())
+                .prop_filter("trailing whitespace", |body| !body.ends_with(char::is_whitespace)),
+        ) {
+            round_trip(id, typ, &body);
+        }
+    }
+
+    #[test]
+    fn packets_at_the_limits_round_trip() {
+        round_trip(i32::MIN, PacketType::Command, "");
+        round_trip(i32::MAX, PacketType::Response, "x");
+        round_trip(1, PacketType::Command, &"x".repeat(PACKET_BODY_MAX_LEN));
+    }
+
+    #[test]
+    fn bodies_over_the_limit_are_refused() {
+        let body = "x".repeat(PACKET_BODY_MAX_LEN + 1);
+        assert!(matches!(
+            Packet::new(1, PacketType::Command, body),
+            Err(PacketError::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn short_or_lying_packets_fail_without_panicking() {
+        let profile = Game::Minecraft.profile();
+        let parse = |bytes: &[u8]| Packet::deserialize(&mut Bytes::copy_from_slice(bytes), profile);
+
+        for len in 0..11 {
+            assert!(matches!(
+                parse(&packet(1, 0, b"")[..len]),
+                Err(PacketError::Truncated)
+            ));
+        }
+        let mut negative = packet(1, 0, b"hi");
+        negative[..4].copy_from_slice(&(-5i32).to_le_bytes());
+        assert!(matches!(parse(&negative), Err(PacketError::SmallPacket)));
+
+        // A size claiming more than arrived keeps what did
+        let mut lying = packet(1, 0, b"hi");
+        lying[..4].copy_from_slice(&4000i32.to_le_bytes());
+        assert_eq!(parse(&lying).unwrap().body(), "hi\0\0");
+    }
+
+    /// The IDs of the packets a framer splits `reads` into, fed to it one read at a time
+    fn frames(reads: &[&[u8]], strict: bool) -> Result<Vec<i32>, RconError> {
+        let mut framer = frame::Framer::new(PACKET_SIZE_MAX, strict);
+        let mut ids = Vec::new();
+        for read in reads {
+            let len = read.len();
+            framer.read_from(&mut &read[..], len).unwrap();
+            while let Some(frame) = framer.next_frame()? {
+                ids.push(i32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]));
+            }
+        }
+        Ok(ids)
+    }
+
+    #[test]
+    fn garbage_before_a_packet_is_skipped() {
+        let stream = [&b"\xde\xad\xbe\xef not a packet"[..], &packet(7, 0, b"ok")].concat();
+        assert_eq!(frames(&[&stream], false).unwrap(), [7]);
+        assert!(matches!(
+            frames(&[&stream], true),
+            Err(RconError::Deviation { .. })
+        ));
+    }
+
+    #[test]
+    fn packets_without_their_null_bytes_are_skipped() {
+        let mut bad = packet(5, 0, b"lost");
+        let end = bad.len() - 1;
+        bad[end] = b'!';
+        let stream = [bad, packet(6, 0, b"ok")].concat();
+        assert_eq!(frames(&[&stream], false).unwrap(), [6]);
+        match frames(&[&stream], true) {
+            Err(RconError::Deviation { detail }) => assert!(detail.contains("ending in")),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn packets_split_between_reads_are_framed_whole() {
+        let stream = [packet(1, 0, b"first"), packet(2, 0, b"second")].concat();
+        for at in 0..=stream.len() {
+            let (start, rest) = stream.split_at(at);
+            assert_eq!(
+                frames(&[start, rest], true).unwrap(),
+                [1, 2],
+                "split at {}",
+                at
+            );
+        }
+        let bytes: Vec<&[u8]> = stream.chunks(1).collect();
+        assert_eq!(frames(&bytes, true).unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn oversized_size_fields_are_skipped() {
+        let mut huge = packet(3, 0, b"huge");
+        huge[..4].copy_from_slice(&(PACKET_SIZE_MAX as i32 + 1).to_le_bytes());
+        let stream = [huge, packet(4, 0, b"ok")].concat();
+        assert_eq!(frames(&[&stream], false).unwrap(), [4]);
+        match frames(&[&stream], true) {
+            Err(RconError::Deviation { detail }) => assert!(detail.contains("outside")),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn characters_split_between_packets_are_decoded_whole() {
+        let profile = Game::Minecraft.profile();
+        // `§` is 0xc2 0xa7, `é` is 0xc3 0xa9
+        let parts: [&[u8]; 3] = [b"caf\xc3", b"\xa9 \xc2", b"\xa7aok"];
+        let packets = parts
+            .iter()
+            .map(|body| Packet::deserialize(&mut Bytes::from(packet(7, 0, body)), profile).unwrap())
+            .collect();
+        let packets = Packet::decode_response(packets, profile);
+        assert_eq!(bodies(&packets), ["caf", "é ", "ok"]);
+    }
+
+    #[test]
+    fn bytes_that_arent_text_are_escaped() {
+        let profile = Game::Source.profile();
+        let body: &[u8] = b"\x89PNG \xe2\x82\xac\xff\xe2\x82";
+        let packet = Packet::deserialize(&mut Bytes::from(packet(7, 0, body)), profile).unwrap();
+        assert_eq!(packet.body(), "\\x89PNG €\\xff\\xe2\\x82");
+        assert_eq!(packet.raw_body(), body);
+    }
+
+    #[test]
+    fn response_over_several_packets_is_read_whole() {
+        let mut rcon = Rcon::connect_unattended(&serve(Game::Minecraft, respond)).unwrap();
+        let response = rcon.send_cmd("big").unwrap();
+        assert_eq!(response.len(), 3);
+        assert!(response.iter().all(|p| p.body() == "x".repeat(4000)));
+        assert_eq!(bodies(&rcon.send_cmd("list").unwrap()), ["list ok"]);
+    }
+
+    #[test]
+    fn responses_over_the_limit_are_cut_short() {
+        let mut profile = serve(Game::Minecraft, respond);
+        profile.max_response_bytes = 5000;
+        profile.spill_responses = true;
+        let mut rcon = Rcon::connect_unattended(&profile).unwrap();
+        assert_eq!(rcon.send_cmd("big").unwrap().len(), 1);
+        let truncated = rcon.truncated().unwrap().clone();
+        assert_eq!((truncated.kept, truncated.dropped), (4000, 8000));
+        let spilled_to = truncated.spilled_to.unwrap();
+        assert_eq!(fs::read(&spilled_to).unwrap(), "x".repeat(8000).as_bytes());
+        let _ = fs::remove_file(spilled_to);
+
+        assert_eq!(bodies(&rcon.send_cmd("list").unwrap()), ["list ok"]);
+        assert!(rcon.truncated().is_none());
+    }
+
+    #[test]
+    fn cvarlist_pages_are_stitched_into_one_list() {
+        let source = Game::Source.profile();
+        let pages = source.page_command("cvarlist").unwrap();
+        assert!(pages.contains(&"cvarlist s".to_string()));
+        assert!(!pages.iter().any(|p| p.contains(';')));
+        assert!(source.page_command("cvarlist sv_").is_none());
+
+        let page = |entries: &str, prefix: &str| {
+            format!(
+                "cvar list\n--------------\n{}--------------\n  2 convars/concommands for [{}]\n",
+                entries, prefix
+            )
+        };
+        let stitched = source.stitch_pages(&[
+            page("+attack : cmd : : \nmp_timelimit : 0 : , \"sv\" :\n", "+"),
+            page("sv_cheats : 0 : , \"sv\", \"rep\" :\n", "s"),
+            page("sv_cheats : 0 : , \"sv\", \"rep\" :\n", "S"),
+        ]);
+        assert_eq!(
+            stitched,
+            "cvar list\n--------------\n+attack : cmd : : \nmp_timelimit : 0 : , \"sv\" :\n\
+             sv_cheats : 0 : , \"sv\", \"rep\" :\n--------------\n  3 total convars/concommands\n"
+        );
+    }
+
+    #[test]
+    fn sessions_count_their_traffic() {
+        let mut rcon = Rcon::connect_unattended(&serve(Game::Minecraft, respond)).unwrap();
+        let login = rcon.stats();
+        assert_eq!((login.packets_sent, login.errors), (1, 0));
+        assert_eq!(bodies(&rcon.send_cmd("list").unwrap()), ["list ok"]);
+        assert!(rcon.send_cmd(&"x".repeat(4096)).is_err());
+        // Minecraft's responses end once it goes quiet, with no empty command marking the end
+        let traffic = rcon.stats();
+        assert_eq!((traffic.packets_sent, traffic.packets_received), (2, 2));
+        assert_eq!(traffic.bytes_sent, login.bytes_sent + 18);
+        // Refused for its length before anything was sent
+        assert_eq!(traffic.errors, 1);
+    }
+
+    #[test]
+    fn init_commands_run_after_every_login() {
+        let mut config = serve(Game::Minecraft, respond);
+        config.init_commands = vec![
+            "gamerule keepInventory true".to_string(),
+            "noisy".to_string(),
+        ];
+        let mut rcon = Rcon::connect_unattended(&config).unwrap();
+        assert_eq!(
+            rcon.take_init_output(),
+            [
+                (
+                    "gamerule keepInventory true".to_string(),
+                    "gamerule keepInventory true ok".to_string()
+                ),
+                ("noisy".to_string(), "noisy ok".to_string()),
+            ]
+        );
+        assert!(rcon.take_init_output().is_empty());
+        assert!(rcon.authenticate_with("pw"));
+        assert_eq!(rcon.take_init_output().len(), 2);
+    }
+
+    #[test]
+    fn games_are_detected_without_sending_markers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sent, received) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            while let Some((id, typ, body)) = read_packet(&mut conn) {
+                if typ == 3 {
+                    conn.write_all(&packet(id, 2, b"")).unwrap();
+                    continue;
+                }
+                // Like Minecraft, nothing comes back for an empty command
+                if body == "/version" {
+                    conn.write_all(&packet(id, 0, b"This server is running Paper"))
+                        .unwrap();
+                }
+                sent.send(body).unwrap();
+            }
+        });
+        let mut config = ClientConfig::new("127.0.0.1", port);
+        config.password = Some("pw".to_string());
+        let rcon = Rcon::connect_unattended(&config).unwrap();
+        assert_eq!(rcon.game(), Game::Minecraft);
+        drop(rcon);
+        assert_eq!(received.iter().collect::<Vec<_>>(), ["/version"]);
+    }
+
+    #[test]
+    fn late_packets_stay_out_of_the_next_response() {
+        let mut rcon = Rcon::connect_unattended(&serve(Game::Minecraft, respond)).unwrap();
+        assert_eq!(bodies(&rcon.send_cmd("slow").unwrap()), ["first"]);
+        thread::sleep(Duration::from_millis(1000));
+        assert_eq!(bodies(&rcon.send_cmd("list").unwrap()), ["list ok"]);
+    }
+
+    #[test]
+    fn packets_of_abandoned_commands_are_dropped() {
+        // Lenient parsing can't use a marker command, so only the ID tells late packets apart
+        let mut rcon = Rcon::connect_unattended(&serve(Game::Palworld, respond)).unwrap();
+        assert!(matches!(rcon.send_cmd("sleepy"), Err(RconError::Timeout)));
+        assert_eq!(bodies(&rcon.send_cmd("list").unwrap()), ["list ok"]);
+    }
+
+    #[test]
+    fn lenient_packets_arriving_together_are_split_on_their_null_bytes() {
+        let mut rcon = Rcon::connect_unattended(&serve(Game::Palworld, respond)).unwrap();
+        assert_eq!(bodies(&rcon.send_cmd("pair").unwrap()), ["first", "second"]);
+        let big = "x".repeat(4000);
+        assert_eq!(bodies(&rcon.send_cmd("big").unwrap()), [&big, &big, &big]);
+    }
+
+    #[test]
+    fn packets_for_other_commands_are_dropped() {
+        let mut rcon = Rcon::connect_unattended(&serve(Game::Minecraft, respond)).unwrap();
+        assert_eq!(bodies(&rcon.send_cmd("noisy").unwrap()), ["noisy ok"]);
+        assert_eq!(bodies(&rcon.send_cmd("list").unwrap()), ["list ok"]);
+    }
+
+    #[test]
+    fn only_the_commands_own_packets_are_shown_as_they_arrive() {
+        let mut rcon = Rcon::connect_unattended(&serve(Game::Minecraft, respond)).unwrap();
+        let mut shown = Vec::new();
+        let response = rcon
+            .send_cmd_streaming("noisy", &mut |text| shown.push(text.to_string()))
+            .unwrap();
+        assert_eq!(shown, ["noisy ok"]);
+        assert_eq!(bodies(&response), ["noisy ok"]);
+    }
+
+    #[test]
+    fn batches_sent_in_one_write_get_their_own_responses() {
+        // Only SRCDS answers the empty commands ending each response
+        let rcon = Rcon::connect_unattended(&serve(Game::Minecraft, respond)).unwrap();
+        assert!(rcon.pipeline().is_err());
+        let rcon = Rcon::connect_unattended(&serve(Game::Source, respond)).unwrap();
+        let pipeline = rcon.pipeline().ok().unwrap();
+        let replies = pipeline.send_cmds_async(&["list", "big", "seed"]).unwrap();
+        let responses: Vec<_> = replies
+            .into_iter()
+            .map(|reply| reply.unwrap().wait().unwrap())
+            .collect();
+        assert_eq!(bodies(&responses[0]), ["list ok"]);
+        assert_eq!(responses[1].len(), 3);
+        assert_eq!(bodies(&responses[2]), ["seed ok"]);
+    }
+
+    #[test]
+    fn pipelined_commands_time_out_while_other_packets_arrive() {
+        let config = serve(Game::Source, |cmd| match cmd {
+            "trickle" => (0..25)
+                .flat_map(|_| [Step::Stray("stale".to_string()), Step::Lull])
+                .collect(),
+            _ => respond(cmd),
+        });
+        let pipeline = Rcon::connect_unattended(&config)
+            .unwrap()
+            .pipeline()
+            .ok()
+            .unwrap();
+        let reply = pipeline.send_cmd_async("trickle").unwrap();
+        assert!(matches!(reply.wait(), Err(RconError::Timeout)));
+    }
+
+    #[test]
+    fn pipelined_commands_are_paged_like_sent_ones() {
+        let config = serve(Game::Source, |cmd| {
+            let entries = match cmd {
+                "cvarlist s" | "cvarlist S" => "sv_cheats : 0 : , \"sv\", \"rep\" :\n",
+                _ => "",
+            };
+            let page = format!("cvar list\n--------------\n{}--------------\n", entries);
+            vec![Step::Part(page)]
+        });
+        let pipeline = Rcon::connect_unattended(&config)
+            .unwrap()
+            .pipeline()
+            .ok()
+            .unwrap();
+        let response = pipeline.send_cmd("cvarlist").unwrap();
+        assert_eq!(
+            bodies(&response),
+            [
+                "cvar list\n--------------\nsv_cheats : 0 : , \"sv\", \"rep\" :\n\
+              --------------\n  1 total convars/concommands\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn strict_sessions_refuse_packets_for_other_commands() {
+        let mut profile = serve(Game::Minecraft, respond);
+        profile.protocol_strict = true;
+        let mut rcon = Rcon::connect_unattended(&profile).unwrap();
+        assert!(matches!(
+            rcon.send_cmd("noisy"),
+            Err(RconError::Deviation { .. })
+        ));
+        assert_eq!(bodies(&rcon.send_cmd("list").unwrap()), ["list ok"]);
+    }
+}
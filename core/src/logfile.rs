@@ -0,0 +1,183 @@
+/*
+ * Log files appended to by long-running modes, rotated as set by the profile's `log_rotation`.
+ *
+ * A log is rotated before a write that would take it past `max_bytes`, or that falls in a later
+ * `every` period than its last write, periods counting from the Unix epoch so `"1d"` rotates at
+ * midnight UTC. `audit.jsonl` becomes `audit.jsonl.1`, or `audit.jsonl.1.gz` with `compress`, and
+ * older ones move up a number until `keep` of them are left.
+ */
+
+use crate::config::RotationConfig;
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Rotated logs kept when `keep` isn't set
+const DEFAULT_KEEP: usize = 5;
+
+/// Held while appending, so the threads of a group don't rotate a shared log twice
+static APPENDING: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone)]
+pub struct LogFile {
+    pub path: PathBuf,
+    rotation: RotationConfig,
+}
+
+impl LogFile {
+    pub fn new(path: PathBuf, rotation: RotationConfig) -> LogFile {
+        LogFile { path, rotation }
+    }
+
+    /// Append `text`, rotating the log first when it's due
+    ///
+    /// Failing to rotate is reported and the log grows on, rather than losing what's written.
+    pub fn append(&self, text: &str) -> io::Result<()> {
+        let _appending = APPENDING.lock().unwrap_or_else(|e| e.into_inner());
+        if self.due(text.len()) {
+            if let Err(e) = self.rotate() {
+                log::error!("Unable to rotate the log {}: {}", self.path.display(), e);
+            }
+        }
+        // One write per call, so entries from several processes don't interleave
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(text.as_bytes())
+    }
+
+    /// Whether writing `adding` more bytes should start a new log
+    fn due(&self, adding: usize) -> bool {
+        let meta = match fs::metadata(&self.path) {
+            Ok(meta) if meta.len() > 0 => meta,
+            _ => return false,
+        };
+        if let Some(max) = self.rotation.max_bytes {
+            if meta.len() + adding as u64 > max {
+                return true;
+            }
+        }
+        match (self.rotation.every, meta.modified()) {
+            (Some(every), Ok(modified)) => {
+                let period = |time: SystemTime| {
+                    time.duration_since(UNIX_EPOCH)
+                        .map_or(0, |t| t.as_secs() / every.as_secs().max(1))
+                };
+                period(modified) < period(SystemTime::now())
+            }
+            _ => false,
+        }
+    }
+
+    /// The `n`th most recent rotated log
+    fn rotated(&self, n: usize, compressed: bool) -> PathBuf {
+        let mut path = OsString::from(&self.path);
+        path.push(format!(".{}", n));
+        if compressed {
+            path.push(".gz");
+        }
+        PathBuf::from(path)
+    }
+
+    fn rotate(&self) -> io::Result<()> {
+        let keep = self.rotation.keep.unwrap_or(DEFAULT_KEEP);
+        // Both names are moved along, in case `compress` was changed since they were rotated
+        for n in (1..=keep).rev() {
+            for compressed in [false, true] {
+                let from = self.rotated(n, compressed);
+                let moved = if n == keep {
+                    fs::remove_file(&from)
+                } else {
+                    fs::rename(&from, self.rotated(n + 1, compressed))
+                };
+                match moved {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+        }
+        if keep == 0 {
+            return fs::remove_file(&self.path);
+        }
+
+        let first = self.rotated(1, false);
+        fs::rename(&self.path, &first)?;
+        if self.rotation.compress {
+            let mut gz =
+                GzEncoder::new(File::create(self.rotated(1, true))?, Compression::default());
+            io::copy(&mut File::open(&first)?, &mut gz)?;
+            gz.finish()?;
+            fs::remove_file(&first)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, time::Duration};
+
+    #[test]
+    fn logs_rotate_past_their_size_and_compress() {
+        let dir = env::temp_dir().join(format!("rustcon-test-logs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.jsonl");
+        let rotation = RotationConfig {
+            max_bytes: Some(10),
+            keep: Some(2),
+            compress: true,
+            ..Default::default()
+        };
+        let log = LogFile::new(path.clone(), rotation);
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            log.append(line).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        let mut third = String::new();
+        let gz = fs::File::open(dir.join("audit.jsonl.1.gz")).unwrap();
+        io::Read::read_to_string(&mut flate2::read::GzDecoder::new(gz), &mut third).unwrap();
+        assert_eq!(third, "third\n");
+        // Only `keep` rotated logs are left
+        assert!(dir.join("audit.jsonl.2.gz").exists());
+        assert!(!dir.join("audit.jsonl.3.gz").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn logs_rotate_once_their_period_is_over() {
+        let dir = env::temp_dir().join(format!("rustcon-test-periods-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("transcript.log");
+        let rotation = RotationConfig {
+            every: Some(Duration::from_secs(24 * 60 * 60)),
+            ..Default::default()
+        };
+        let log = LogFile::new(path.clone(), rotation);
+        log.append("yesterday\n").unwrap();
+        log.append("still today\n").unwrap();
+        assert!(!dir.join("transcript.log.1").exists());
+        // Last written in an earlier period
+        let earlier = SystemTime::now() - Duration::from_secs(2 * 24 * 60 * 60);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(earlier)
+            .unwrap();
+        log.append("today\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "today\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("transcript.log.1")).unwrap(),
+            "yesterday\nstill today\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+/*
+ * The `rcon` crate's API on top of rustcon's client, so projects using that crate can move over
+ * by changing their imports:
+ *
+ *     use rustcon_core::rcon_compat::{Connection, Error};
+ *
+ *     let mut conn = Connection::<tokio::net::TcpStream>::builder()
+ *         .enable_minecraft_quirks(true)
+ *         .connect("127.0.0.1:25575", "hunter2")
+ *         .await?;
+ *     let players = conn.cmd("list").await?;
+ *
+ * Sessions are rustcon's blocking ones, run on Tokio's blocking threads, so this needs a Tokio
+ * runtime. The connection's stream type is kept for the code that names it, rustcon opens its own
+ * socket whatever it is, and `handshake` over a stream the caller opened isn't supported.
+ */
+
+use crate::{ClientConfig, Game, Rcon, RconError};
+use std::{error, fmt, io, marker::PhantomData};
+use tokio::{net::ToSocketAddrs, task};
+use zeroize::Zeroizing;
+
+/// What a connection's calls return
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Why connecting or a command failed, like the `rcon` crate's errors
+#[derive(Debug)]
+pub enum Error {
+    /// The server rejected the password
+    Auth,
+    /// The command is longer than the server takes
+    CommandTooLong,
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Auth => write!(f, "authentication failed"),
+            Error::CommandTooLong => write!(f, "command exceeds the maximum length"),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<RconError> for Error {
+    fn from(e: RconError) -> Error {
+        let kind = match e {
+            RconError::AuthError => return Error::Auth,
+            RconError::TooLarge { .. } => return Error::CommandTooLong,
+            RconError::Io(e) => return Error::Io(e),
+            RconError::Refused => io::ErrorKind::ConnectionRefused,
+            RconError::Timeout | RconError::ConnectTimeout => io::ErrorKind::TimedOut,
+            RconError::Disconnected { .. } => io::ErrorKind::ConnectionReset,
+            RconError::PacketError | RconError::Deviation { .. } => io::ErrorKind::InvalidData,
+            _ => io::ErrorKind::Other,
+        };
+        let message = e.hint().unwrap_or_else(|| format!("{:?}", e));
+        Error::Io(io::Error::new(kind, message))
+    }
+}
+
+/// A logged in session, `T` being the stream type the `rcon` crate's connections are generic over
+pub struct Connection<T> {
+    /// `None` once a command was dropped or panicked halfway, leaving the session unusable
+    rcon: Option<Rcon>,
+    stream: PhantomData<fn() -> T>,
+}
+
+impl<T> Connection<T> {
+    /// Start setting up a connection
+    pub fn builder() -> Builder<T> {
+        Builder {
+            game: None,
+            stream: PhantomData,
+        }
+    }
+
+    /// Connect to `address` and log in with `pass`, detecting the server's game
+    pub async fn connect<A: ToSocketAddrs>(address: A, pass: &str) -> Result<Connection<T>> {
+        Connection::builder().connect(address, pass).await
+    }
+
+    /// Run `cmd` and return its response, the bodies of all its packets as one string
+    pub async fn cmd(&mut self, cmd: &str) -> Result<String> {
+        let mut rcon = self.rcon.take().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotConnected,
+                "an earlier command never finished",
+            )
+        })?;
+        let cmd = cmd.to_string();
+        let (rcon, response) = task::spawn_blocking(move || {
+            let response = rcon.send_cmd(&cmd);
+            (rcon, response)
+        })
+        .await
+        .map_err(io::Error::other)?;
+        self.rcon = Some(rcon);
+        Ok(response?.iter().map(|p| p.body()).collect())
+    }
+}
+
+/// Settings for a [`Connection`], like the `rcon` crate's builder
+pub struct Builder<T> {
+    game: Option<Game>,
+    stream: PhantomData<fn() -> T>,
+}
+
+impl<T> Builder<T> {
+    /// Treat the server as Minecraft, rather than detecting its game
+    pub fn enable_minecraft_quirks(mut self, value: bool) -> Builder<T> {
+        self.quirks(Game::Minecraft, value);
+        self
+    }
+
+    /// Treat the server as Factorio, rather than detecting its game
+    pub fn enable_factorio_quirks(mut self, value: bool) -> Builder<T> {
+        self.quirks(Game::Factorio, value);
+        self
+    }
+
+    fn quirks(&mut self, game: Game, value: bool) {
+        if value {
+            self.game = Some(game);
+        } else if self.game == Some(game) {
+            self.game = None;
+        }
+    }
+
+    /// Connect to `address` and log in with `pass`
+    pub async fn connect<A: ToSocketAddrs>(self, address: A, pass: &str) -> Result<Connection<T>> {
+        let address = tokio::net::lookup_host(address)
+            .await?
+            .next()
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to")
+            })?;
+        let mut config = ClientConfig::new(address.ip().to_string(), address.port());
+        config.game = self.game;
+        let pass = Zeroizing::new(pass.to_string());
+        let rcon = task::spawn_blocking(move || {
+            let mut rcon = Rcon::new(&config)?;
+            if rcon.authenticate_with(&pass) {
+                Ok(rcon)
+            } else {
+                Err(RconError::AuthError)
+            }
+        })
+        .await
+        .map_err(io::Error::other)??;
+        Ok(Connection {
+            rcon: Some(rcon),
+            stream: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{respond, serve},
+        PACKET_BODY_MAX_LEN,
+    };
+
+    #[test]
+    fn rcon_crate_connections_run_commands() {
+        let config = serve(Game::Minecraft, respond);
+        let address = format!("{}:{}", config.ip, config.port);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut conn = Connection::<tokio::net::TcpStream>::builder()
+                .enable_minecraft_quirks(true)
+                .connect(address, "hunter2")
+                .await
+                .unwrap();
+            assert_eq!(conn.cmd("list").await.unwrap(), "list ok");
+            let long = "x".repeat(PACKET_BODY_MAX_LEN + 1);
+            assert!(matches!(conn.cmd(&long).await, Err(Error::CommandTooLong)));
+            assert_eq!(conn.cmd("seed").await.unwrap(), "seed ok");
+        });
+    }
+}
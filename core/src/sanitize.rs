@@ -0,0 +1,102 @@
+/*
+ * Neutralize terminal control characters in text that came from the server.
+ *
+ * Player names and chat end up in responses, so a name with escape sequences could retitle the
+ * terminal, clear it, or hide what's printed after it. Control characters are shown in caret
+ * notation instead (`ESC` becomes `^[`), which keeps them visible without the terminal acting on
+ * them.
+ *
+ * Bodies that aren't valid UTF-8, like binary blobs some plugins return, are decoded with the
+ * bytes that don't make up characters shown as `\xNN` escapes, rather than replaced with `�`.
+ */
+
+use std::{borrow::Cow, str};
+
+fn is_unsafe(c: char) -> bool {
+    c.is_control() && c != '\n' && c != '\t'
+}
+
+/// `text` with every control character but line feeds and tabs escaped, and CRLF line endings
+/// turned into line feeds
+pub fn sanitize(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(is_unsafe) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut clean = String::with_capacity(text.len() + 8);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' if chars.peek() == Some(&'\n') => {}
+            c if !is_unsafe(c) => clean.push(c),
+            // C0 controls and DEL
+            c if (c as u32) < 0x20 || c == '\x7f' => {
+                clean.push('^');
+                clean.push(((c as u8) ^ 0x40) as char);
+            }
+            // C1 controls, such as the single character CSI
+            c => clean.push_str(&format!("\\u{{{:x}}}", c as u32)),
+        }
+    }
+    Cow::Owned(clean)
+}
+
+/// `bytes` as text, with every byte that isn't part of a valid UTF-8 character written `\xNN`
+pub fn decode(bytes: &[u8]) -> Cow<'_, str> {
+    let mut rest = match str::from_utf8(bytes) {
+        Ok(text) => return Cow::Borrowed(text),
+        Err(_) => bytes,
+    };
+
+    let mut text = String::with_capacity(bytes.len() + 16);
+    loop {
+        match str::from_utf8(rest) {
+            Ok(valid) => {
+                text.push_str(valid);
+                return Cow::Owned(text);
+            }
+            Err(e) => {
+                let (valid, invalid) = rest.split_at(e.valid_up_to());
+                text.push_str(str::from_utf8(valid).unwrap_or_default());
+                // A sequence cut off at the end has no length, and is escaped whole
+                let len = e.error_len().unwrap_or(invalid.len());
+                for b in &invalid[..len] {
+                    text.push_str(&format!("\\x{:02x}", b));
+                }
+                rest = &invalid[len..];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_characters_are_shown_in_caret_notation() {
+        assert_eq!(
+            sanitize("\x1b]0;pwned\x07Steve\x1b[2J joined"),
+            "^[]0;pwned^GSteve^[[2J joined"
+        );
+        assert_eq!(
+            sanitize("del\x7f and csi\u{9b}2J"),
+            "del^? and csi\\u{9b}2J"
+        );
+        assert_eq!(sanitize("a\rb"), "a^Mb");
+    }
+
+    #[test]
+    fn line_feeds_and_tabs_are_kept() {
+        let text = "name\tping\nSteve\t12\n";
+        assert!(matches!(sanitize(text), Cow::Borrowed(t) if t == text));
+        assert_eq!(sanitize("one\r\ntwo\r\n"), "one\ntwo\n");
+    }
+
+    #[test]
+    fn bytes_that_arent_utf8_are_escaped() {
+        assert_eq!(decode(b"ok \xff\xfe end"), "ok \\xff\\xfe end");
+        assert_eq!(decode("caf\u{e9}".as_bytes()), "caf\u{e9}");
+        assert_eq!(decode(b"cut \xe2\x82"), "cut \\xe2\\x82");
+    }
+}
@@ -0,0 +1,94 @@
+/*
+ * Line-based telnet admin consoles, as exposed by 7 Days to Die.
+ */
+
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+};
+
+/// Telnet "interpret as command" byte, starts a negotiation sequence
+const IAC: u8 = 255;
+
+/// Read from the console until `done` matches the text received so far or the read times out
+fn read_until<F>(conn: &mut TcpStream, done: F) -> io::Result<String>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut received: Vec<u8> = Vec::new();
+    let mut buf = [0; 1024];
+
+    loop {
+        match conn.read(&mut buf) {
+            Ok(0) if received.is_empty() => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(0) => break,
+            Ok(len) => {
+                received.extend_from_slice(&buf[..len]);
+                if done(&String::from_utf8_lossy(&strip_negotiation(&received))) {
+                    break;
+                }
+            }
+            Err(e) if is_timeout(&e) => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&strip_negotiation(&received)).into_owned())
+}
+
+/// Whether a read error means the connection is simply idle
+pub fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// Drop telnet option negotiation (`IAC <verb> <option>`) from the byte stream
+fn strip_negotiation(bytes: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(b) = iter.next() {
+        if *b == IAC {
+            iter.next();
+            iter.next();
+        } else {
+            stripped.push(*b);
+        }
+    }
+    stripped
+}
+
+/// Whether the console is asking for a password
+fn is_password_prompt(text: &str) -> bool {
+    text.to_lowercase().contains("password") && text.trim_end().ends_with(':')
+}
+
+/// Log in to the console, answering its password prompt if it shows one
+pub fn authenticate(conn: &mut TcpStream, pass: &str) -> io::Result<bool> {
+    let banner = read_until(conn, is_password_prompt)?;
+    if !is_password_prompt(&banner) {
+        // Console isn't password protected
+        return Ok(true);
+    }
+
+    // Written as is rather than formatted into a line, which would copy the password
+    conn.write_all(pass.trim_end().as_bytes())?;
+    conn.write_all(b"\r\n")?;
+    let reply = read_until(conn, |text| {
+        let text = text.to_lowercase();
+        text.contains("logon successful") || text.contains("incorrect")
+    })?;
+    Ok(reply.to_lowercase().contains("logon successful"))
+}
+
+/// Send a single command line
+pub fn send_line(conn: &mut TcpStream, line: &str) -> io::Result<()> {
+    conn.write_all(format!("{}\r\n", line.trim_end()).as_bytes())
+}
+
+/// Receive console output until the server goes quiet
+pub fn receive(conn: &mut TcpStream) -> io::Result<String> {
+    let text = read_until(conn, |_| false)?;
+    Ok(text.replace("\r\n", "\n").trim_end().to_string())
+}
@@ -0,0 +1,91 @@
+/*
+ * Counts of what a session has sent and received, for `:stats`, the session summary and the
+ * Prometheus exporter.
+ *
+ * Bytes are counted as they go over the wire, headers and all. Telnet console lines count as a
+ * packet each way, like the response packets they're wrapped in.
+ */
+
+use serde::Serialize;
+use std::{
+    ops::AddAssign,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// What a session has sent and received so far, from [`Rcon::stats`](crate::Rcon::stats)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Traffic {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Commands that failed, from ones refused before sending to lost connections
+    pub errors: u64,
+    /// Passwords the server rejected before one was accepted, or it was given up on
+    pub rejected_logins: u64,
+}
+
+impl AddAssign for Traffic {
+    fn add_assign(&mut self, other: Traffic) {
+        self.packets_sent += other.packets_sent;
+        self.packets_received += other.packets_received;
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.errors += other.errors;
+        self.rejected_logins += other.rejected_logins;
+    }
+}
+
+/// Running totals of a session's [`Traffic`], shared with the reader of a pipelined session and
+/// with whatever watches the session from another thread
+#[derive(Debug, Default)]
+pub struct Counters {
+    packets_sent: AtomicU64,
+    packets_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    errors: AtomicU64,
+    rejected_logins: AtomicU64,
+}
+
+fn add(counter: &AtomicU64, n: usize) {
+    counter.fetch_add(n as u64, Ordering::Relaxed);
+}
+
+impl Counters {
+    /// A packet of `bytes` written, or queued to be
+    pub(crate) fn sent(&self, bytes: usize) {
+        add(&self.packets_sent, 1);
+        add(&self.bytes_sent, bytes);
+    }
+
+    /// Bytes read, whole packets or not
+    pub(crate) fn read(&self, bytes: usize) {
+        add(&self.bytes_received, bytes);
+    }
+
+    /// A packet split off what was read
+    pub(crate) fn received(&self) {
+        add(&self.packets_received, 1);
+    }
+
+    pub(crate) fn error(&self) {
+        add(&self.errors, 1);
+    }
+
+    pub(crate) fn rejected_login(&self) {
+        add(&self.rejected_logins, 1);
+    }
+
+    pub fn snapshot(&self) -> Traffic {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        Traffic {
+            packets_sent: get(&self.packets_sent),
+            packets_received: get(&self.packets_received),
+            bytes_sent: get(&self.bytes_sent),
+            bytes_received: get(&self.bytes_received),
+            errors: get(&self.errors),
+            rejected_logins: get(&self.rejected_logins),
+        }
+    }
+}
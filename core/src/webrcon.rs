@@ -0,0 +1,300 @@
+/*
+ * WebRCON, the console Rust and other Facepunch servers take over a WebSocket.
+ *
+ * The password is the last part of the URL, see [`url`], and each command and its response are
+ * JSON text frames tied together by an `Identifier`. The server also pushes its console output
+ * and chat with an identifier of its own, 0 or below.
+ *
+ * [`WebRcon`] only turns commands into frames and frames into responses, without doing any I/O,
+ * so a browser console built for `wasm32-unknown-unknown` can drive it over its own WebSocket
+ * through wasm-bindgen. The `webrcon` feature adds [`Client`], the same session over a socket.
+ */
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+
+/// Name sent along with each command, shown in the server's log
+const CLIENT_NAME: &str = "rustcon";
+
+/// The URL a WebRCON server at `host` and `port` is reached at with `password`
+///
+/// IPv6 addresses are put in brackets, as URLs need them.
+pub fn url(host: &str, port: u16, password: &str) -> String {
+    let mut url = if host.contains(':') && !host.starts_with('[') {
+        format!("ws://[{}]:{}/", host, port)
+    } else {
+        format!("ws://{}:{}/", host, port)
+    };
+    for byte in password.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            url.push(char::from(byte));
+        } else {
+            let _ = write!(url, "%{:02X}", byte);
+        }
+    }
+    url
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Request<'a> {
+    identifier: i32,
+    message: &'a str,
+    name: &'a str,
+}
+
+/// A frame from the server
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Message {
+    /// The command this answers, 0 or below when the server sent it unprompted
+    pub identifier: i32,
+    #[serde(rename = "Message")]
+    pub text: String,
+    /// `Generic`, `Warning`, `Error`, `Chat` or `Report`
+    #[serde(rename = "Type", default)]
+    pub kind: String,
+}
+
+/// What a frame from the server is
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The response to the command [`WebRcon::command`] gave this ID
+    Response { id: i32, text: String },
+    /// Console output or chat the server sent unprompted, or the answer to a command given up on
+    Pushed(Message),
+}
+
+/// A WebRCON session's state, numbering the commands sent and matching responses to them
+#[derive(Debug)]
+pub struct WebRcon {
+    next_id: i32,
+    /// IDs of the commands sent and not answered yet, oldest first
+    waiting: Vec<i32>,
+}
+
+impl Default for WebRcon {
+    fn default() -> WebRcon {
+        WebRcon::new()
+    }
+}
+
+impl WebRcon {
+    pub fn new() -> WebRcon {
+        WebRcon {
+            next_id: 1,
+            waiting: Vec::new(),
+        }
+    }
+
+    /// The text frame sending `cmd`, and the ID its response will come back with
+    pub fn command(&mut self, cmd: &str) -> (i32, String) {
+        let id = self.next_id;
+        // Identifiers of 0 and below are the server's own
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        self.waiting.push(id);
+        let request = Request {
+            identifier: id,
+            message: cmd,
+            name: CLIENT_NAME,
+        };
+        let frame = serde_json::to_string(&request).unwrap_or_default();
+        (id, frame)
+    }
+
+    /// Stop waiting for the response to command `id`, it's taken as pushed output if it arrives
+    pub fn abandon(&mut self, id: i32) {
+        self.waiting.retain(|w| *w != id);
+    }
+
+    /// What the text frame `frame` from the server is
+    pub fn receive(&mut self, frame: &str) -> Result<Event, serde_json::Error> {
+        let message: Message = serde_json::from_str(frame)?;
+        match self.waiting.iter().position(|id| *id == message.identifier) {
+            Some(i) => {
+                self.waiting.remove(i);
+                Ok(Event::Response {
+                    id: message.identifier,
+                    text: message.text,
+                })
+            }
+            None => Ok(Event::Pushed(message)),
+        }
+    }
+}
+
+#[cfg(feature = "webrcon")]
+pub use self::client::Client;
+
+#[cfg(feature = "webrcon")]
+mod client {
+    use super::{url, Event, Message, WebRcon};
+    use crate::{is_timeout, RconError};
+    use std::{
+        collections::VecDeque,
+        net::{TcpStream, ToSocketAddrs},
+        time::{Duration, Instant},
+    };
+    use tungstenite::{handshake::HandshakeError, Message as Frame, WebSocket};
+
+    /// How long a command waits for its response
+    const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// A [`WebRcon`] session over a WebSocket, sending one command at a time
+    pub struct Client {
+        socket: WebSocket<TcpStream>,
+        session: WebRcon,
+        /// What the server sent unprompted while a command waited, oldest first
+        pushed: VecDeque<Message>,
+    }
+
+    fn lost(e: tungstenite::Error) -> RconError {
+        match e {
+            tungstenite::Error::Io(e) => RconError::Io(e),
+            tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+                RconError::closed()
+            }
+            e => RconError::Disconnected {
+                reason: e.to_string(),
+            },
+        }
+    }
+
+    impl Client {
+        /// Connect to the WebRCON server at `host` and `port`, logging in with `password`
+        ///
+        /// Servers refuse the handshake when the password is wrong.
+        pub fn connect(host: &str, port: u16, password: &str) -> Result<Client, RconError> {
+            let addrs: Vec<_> = (host, port)
+                .to_socket_addrs()
+                .map_err(|_| RconError::Resolve {
+                    host: host.to_string(),
+                })?
+                .collect();
+            let stream = TcpStream::connect(&addrs[..])?;
+            stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+            let (socket, _) =
+                tungstenite::client(url(host, port, password), stream).map_err(|e| match e {
+                    HandshakeError::Failure(tungstenite::Error::Http(_)) => RconError::AuthError,
+                    HandshakeError::Failure(e) => lost(e),
+                    HandshakeError::Interrupted(_) => RconError::Timeout,
+                })?;
+            Ok(Client {
+                socket,
+                session: WebRcon::new(),
+                pushed: VecDeque::new(),
+            })
+        }
+
+        /// Send a command and wait for its response
+        pub fn send_cmd(&mut self, cmd: &str) -> Result<String, RconError> {
+            let (id, frame) = self.session.command(cmd);
+            self.socket.send(Frame::text(frame)).map_err(lost)?;
+            let started = Instant::now();
+            loop {
+                let frame = match self.socket.read() {
+                    Ok(Frame::Text(frame)) => frame,
+                    Ok(Frame::Close(_)) => return Err(RconError::closed()),
+                    Ok(_) => continue,
+                    Err(tungstenite::Error::Io(e)) if is_timeout(&e) => {
+                        self.session.abandon(id);
+                        return Err(RconError::Timeout);
+                    }
+                    Err(e) => return Err(lost(e)),
+                };
+                match self.session.receive(&frame) {
+                    Ok(Event::Response { id: answered, text }) if answered == id => {
+                        return Ok(text)
+                    }
+                    Ok(Event::Response { .. }) => {}
+                    Ok(Event::Pushed(message)) => self.pushed.push_back(message),
+                    // Not JSON, so not WebRCON's
+                    Err(_) => return Err(RconError::PacketError),
+                }
+                if started.elapsed() >= RESPONSE_TIMEOUT {
+                    self.session.abandon(id);
+                    return Err(RconError::Timeout);
+                }
+            }
+        }
+
+        /// What the server sent unprompted while commands waited, oldest first
+        pub fn take_pushed(&mut self) -> Vec<Message> {
+            self.pushed.drain(..).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webrcon_responses_are_matched_to_their_commands() {
+        assert_eq!(url("::1", 28016, "a b/c"), "ws://[::1]:28016/a%20b%2Fc");
+        let mut session = WebRcon::new();
+        let (status, frame) = session.command("status");
+        assert!(frame.contains(r#""Message":"status""#));
+        let (kick, _) = session.command("kick bob");
+        let chat = r#"{"Identifier":-1,"Message":"hi","Type":"Chat"}"#;
+        assert!(matches!(session.receive(chat).unwrap(), Event::Pushed(m) if m.kind == "Chat"));
+        let answer = format!(r#"{{"Identifier":{},"Message":"kicked"}}"#, kick);
+        assert_eq!(
+            session.receive(&answer).unwrap(),
+            Event::Response {
+                id: kick,
+                text: "kicked".to_string()
+            }
+        );
+        session.abandon(status);
+        let late = format!(r#"{{"Identifier":{},"Message":"0 players"}}"#, status);
+        assert!(matches!(session.receive(&late).unwrap(), Event::Pushed(_)));
+        assert!(session.receive("not json").is_err());
+    }
+
+    #[cfg(feature = "webrcon")]
+    #[test]
+    // The handshake callback's error type is tungstenite's
+    #[allow(clippy::result_large_err)]
+    fn webrcon_clients_log_in_through_the_url() {
+        use crate::RconError;
+        use std::{net::TcpListener, thread};
+        use tungstenite::{handshake::server::ErrorResponse, Message as Frame};
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for conn in listener.incoming().flatten() {
+                let check = |req: &tungstenite::handshake::server::Request, res| {
+                    if req.uri().path() == "/hunter2" {
+                        Ok(res)
+                    } else {
+                        let mut refused = ErrorResponse::new(None);
+                        *refused.status_mut() = tungstenite::http::StatusCode::UNAUTHORIZED;
+                        Err(refused)
+                    }
+                };
+                let mut socket = match tungstenite::accept_hdr(conn, check) {
+                    Ok(socket) => socket,
+                    Err(_) => continue,
+                };
+                while let Ok(Frame::Text(frame)) = socket.read() {
+                    let request: serde_json::Value = serde_json::from_str(&frame).unwrap();
+                    let id = &request["Identifier"];
+                    let text = format!("{} ok", request["Message"].as_str().unwrap());
+                    let pushed = r#"{"Identifier":0,"Message":"[CHAT] hi"}"#;
+                    socket.send(Frame::text(pushed)).unwrap();
+                    let answer = serde_json::json!({"Identifier": id, "Message": text});
+                    socket.send(Frame::text(answer.to_string())).unwrap();
+                }
+            }
+        });
+        assert!(matches!(
+            Client::connect("127.0.0.1", port, "wrong"),
+            Err(RconError::AuthError)
+        ));
+        let mut client = Client::connect("127.0.0.1", port, "hunter2").unwrap();
+        assert_eq!(client.send_cmd("status").unwrap(), "status ok");
+        assert_eq!(client.send_cmd("kick bob").unwrap(), "kick bob ok");
+        assert_eq!(client.take_pushed().len(), 2);
+    }
+}
@@ -0,0 +1,87 @@
+#ifndef RUSTCON_H
+#define RUSTCON_H
+
+/* Generated by cbindgen from src/lib.rs, don't edit it by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * What a call returned, negative when it failed
+ */
+typedef enum RconStatus {
+  RCON_STATUS_OK = 0,
+  /**
+   * A pointer was NULL or a string wasn't UTF-8
+   */
+  RCON_STATUS_INVALID_ARGUMENT = -1,
+  /**
+   * The server rejected the password or the connection was lost logging in
+   */
+  RCON_STATUS_AUTH_FAILED = -2,
+} RconStatus;
+
+typedef struct Rcon Rcon;
+
+/**
+ * Connect to the RCON server at `host` and `port`, without logging in
+ *
+ * Returns NULL if `host` is invalid or the connection fails.
+ *
+ * # Safety
+ *
+ * `host` is NULL or points to a NUL-terminated string.
+ */
+struct Rcon *rcon_connect(const char *host, uint16_t port);
+
+/**
+ * Log in to the session with `password`
+ *
+ * # Safety
+ *
+ * `rcon` is NULL or a session from `rcon_connect` that hasn't been freed, used by one thread at
+ * a time, and `password` is NULL or points to a NUL-terminated string.
+ */
+RconStatus rcon_auth(struct Rcon *rcon, const char *password);
+
+/**
+ * Run `command` and return its response, the bodies of all its packets as one string
+ *
+ * Returns NULL if the command fails. The response is freed with `rcon_free_string`.
+ *
+ * # Safety
+ *
+ * `rcon` is NULL or a session from `rcon_connect` that hasn't been freed, used by one thread at
+ * a time, and `command` is NULL or points to a NUL-terminated string.
+ */
+char *rcon_exec(struct Rcon *rcon, const char *command);
+
+/**
+ * Close the session and free it, doing nothing when `rcon` is NULL
+ *
+ * # Safety
+ *
+ * `rcon` is NULL or a session from `rcon_connect` that hasn't been freed.
+ */
+void rcon_free(struct Rcon *rcon);
+
+/**
+ * Free a response from `rcon_exec`, doing nothing when `s` is NULL
+ *
+ * # Safety
+ *
+ * `s` is NULL or a response from `rcon_exec` that hasn't been freed.
+ */
+void rcon_free_string(char *s);
+
+/**
+ * What the last failing call on this thread went wrong with, NULL if none has failed
+ *
+ * The message stays valid until the next failing call on the thread, and isn't freed by the
+ * caller.
+ */
+const char *rcon_last_error(void);
+
+#endif  /* RUSTCON_H */
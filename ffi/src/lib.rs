@@ -0,0 +1,188 @@
+/*
+ * C bindings for rustcon's client, so C and C++ admin tools can drop their own RCON code.
+ *
+ * A session is an opaque `Rcon` pointer from `rcon_connect`, logged in with `rcon_auth` and freed
+ * with `rcon_free`. Responses from `rcon_exec` are owned by the caller and freed with
+ * `rcon_free_string`. Whatever fails returns NULL or a negative `RconStatus`, and
+ * `rcon_last_error` tells what went wrong on the same thread.
+ *
+ * include/rustcon.h declares all of it, regenerated with
+ * `cbindgen --config cbindgen.toml --output include/rustcon.h` after changing the API.
+ */
+
+use rustcon_core::{ClientConfig, Rcon, RconError};
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+/// What a call returned, negative when it failed
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RconStatus {
+    Ok = 0,
+    /// A pointer was NULL or a string wasn't UTF-8
+    InvalidArgument = -1,
+    /// The server rejected the password or the connection was lost logging in
+    AuthFailed = -2,
+}
+
+thread_local! {
+    /// What the last failing call on this thread went wrong with
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(message: impl Into<String>) {
+    let message = message.into().replace('\0', "");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+}
+
+fn describe(e: &RconError) -> String {
+    e.hint().unwrap_or_else(|| format!("{:?}", e))
+}
+
+/// `f`'s result, or `failed` if it panicked, so panics never unwind into C
+fn guard<T>(failed: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        set_error("rustcon panicked");
+        failed
+    })
+}
+
+/// The string `s` points to, `None` with the error set if it's NULL or isn't UTF-8
+///
+/// # Safety
+///
+/// `s` is NULL or points to a NUL-terminated string.
+unsafe fn text<'a>(s: *const c_char, what: &str) -> Option<&'a str> {
+    if s.is_null() {
+        set_error(format!("The {} is NULL", what));
+        return None;
+    }
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Some(s),
+        Err(_) => {
+            set_error(format!("The {} isn't UTF-8", what));
+            None
+        }
+    }
+}
+
+/// Connect to the RCON server at `host` and `port`, without logging in
+///
+/// Returns NULL if `host` is invalid or the connection fails.
+///
+/// # Safety
+///
+/// `host` is NULL or points to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rcon_connect(host: *const c_char, port: u16) -> *mut Rcon {
+    guard(ptr::null_mut(), || {
+        let host = match text(host, "host") {
+            Some(host) => host,
+            None => return ptr::null_mut(),
+        };
+        match Rcon::new(&ClientConfig::new(host, port)) {
+            Ok(rcon) => Box::into_raw(Box::new(rcon)),
+            Err(e) => {
+                set_error(describe(&e));
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Log in to the session with `password`
+///
+/// # Safety
+///
+/// `rcon` is NULL or a session from `rcon_connect` that hasn't been freed, used by one thread at
+/// a time, and `password` is NULL or points to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rcon_auth(rcon: *mut Rcon, password: *const c_char) -> RconStatus {
+    guard(RconStatus::AuthFailed, || {
+        let (rcon, password) = match (rcon.as_mut(), text(password, "password")) {
+            (Some(rcon), Some(password)) => (rcon, password),
+            (None, _) => {
+                set_error("The session is NULL");
+                return RconStatus::InvalidArgument;
+            }
+            (_, None) => return RconStatus::InvalidArgument,
+        };
+        if rcon.authenticate_with(password) {
+            RconStatus::Ok
+        } else {
+            set_error("The server rejected the password or closed the connection");
+            RconStatus::AuthFailed
+        }
+    })
+}
+
+/// Run `command` and return its response, the bodies of all its packets as one string
+///
+/// Returns NULL if the command fails. The response is freed with `rcon_free_string`.
+///
+/// # Safety
+///
+/// `rcon` is NULL or a session from `rcon_connect` that hasn't been freed, used by one thread at
+/// a time, and `command` is NULL or points to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rcon_exec(rcon: *mut Rcon, command: *const c_char) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let (rcon, command) = match (rcon.as_mut(), text(command, "command")) {
+            (Some(rcon), Some(command)) => (rcon, command),
+            (None, _) => {
+                set_error("The session is NULL");
+                return ptr::null_mut();
+            }
+            (_, None) => return ptr::null_mut(),
+        };
+        match rcon.send_cmd(command) {
+            Ok(response) => {
+                // NULs would end the string early in C
+                let body: String = response.iter().map(|p| p.body()).collect();
+                CString::new(body.replace('\0', "")).map_or(ptr::null_mut(), CString::into_raw)
+            }
+            Err(e) => {
+                set_error(describe(&e));
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Close the session and free it, doing nothing when `rcon` is NULL
+///
+/// # Safety
+///
+/// `rcon` is NULL or a session from `rcon_connect` that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rcon_free(rcon: *mut Rcon) {
+    if !rcon.is_null() {
+        guard((), || drop(Box::from_raw(rcon)));
+    }
+}
+
+/// Free a response from `rcon_exec`, doing nothing when `s` is NULL
+///
+/// # Safety
+///
+/// `s` is NULL or a response from `rcon_exec` that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn rcon_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// What the last failing call on this thread went wrong with, NULL if none has failed
+///
+/// The message stays valid until the next failing call on the thread, and isn't freed by the
+/// caller.
+#[no_mangle]
+pub extern "C" fn rcon_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
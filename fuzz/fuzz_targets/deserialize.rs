@@ -0,0 +1,19 @@
+/*
+ * Parse arbitrary bytes as a packet in every parsing mode, which must fail rather than panic.
+ */
+
+#![no_main]
+
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+use rustcon::{Game, Packet};
+
+/// Strict parsing with the usual size limit, lenient parsing, and strict parsing with a larger
+/// limit
+const GAMES: [Game; 3] = [Game::Source, Game::Palworld, Game::Factorio];
+
+fuzz_target!(|data: &[u8]| {
+    for game in GAMES {
+        let _ = Packet::deserialize(&mut Bytes::copy_from_slice(data), game.profile());
+    }
+});
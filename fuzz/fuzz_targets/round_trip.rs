@@ -0,0 +1,45 @@
+/*
+ * Serialize a packet and parse it back, then again with some of its bytes overwritten.
+ *
+ * The untouched packet must come back as it was sent, the corrupted one must fail or parse
+ * without panicking.
+ */
+
+#![no_main]
+
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+use rustcon::{Game, Packet, PacketType};
+
+/// Strict parsing with the usual size limit, lenient parsing, and strict parsing with a larger
+/// limit
+const GAMES: [Game; 3] = [Game::Source, Game::Palworld, Game::Factorio];
+
+fuzz_target!(|input: (i32, u8, String, Vec<(u16, u8)>)| {
+    let (id, typ, body, corruptions) = input;
+    let typ = match typ % 3 {
+        0 => PacketType::Response,
+        1 => PacketType::Command,
+        _ => PacketType::Login,
+    };
+    let packet = match Packet::new(id, typ, body.clone()) {
+        Ok(packet) => packet,
+        Err(_) => return,
+    };
+    let bytes = packet.serialize().freeze();
+
+    // Source has no color codes and trusts the size field, so the body comes back byte for byte
+    let parsed = Packet::deserialize(&mut bytes.clone(), Game::Source.profile())
+        .expect("a serialized packet parses");
+    assert_eq!(parsed.id(), id);
+    assert_eq!(parsed.body(), body.trim_end());
+
+    let mut corrupted = bytes.to_vec();
+    for (at, byte) in corruptions {
+        let len = corrupted.len();
+        corrupted[usize::from(at) % len] = byte;
+    }
+    for game in GAMES {
+        let _ = Packet::deserialize(&mut Bytes::from(corrupted.clone()), game.profile());
+    }
+});
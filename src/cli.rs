@@ -0,0 +1,650 @@
+/*
+ * Command-line arguments and subcommands.
+ */
+
+use crate::{cmd::discover::Network, table::SortKey, Game, PlayerAction, Protocol};
+use chrono::{DateTime, Local};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::{net::IpAddr, path::PathBuf, time::Duration};
+
+// TODO: add verbose parameter
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+pub struct Args {
+    /// RCON server address or hostname [default: 127.0.0.1]
+    #[clap(short, long, value_parser = parse_host)]
+    pub ip: Option<String>,
+
+    /// RCON server PORT number [default: 27015]
+    #[clap(short, long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub port: Option<u16>,
+
+    /// Game running on the RCON server, selects the command dialect [default: detected on connect]
+    #[clap(short, long, value_enum)]
+    pub game: Option<Game>,
+
+    /// Admin console protocol spoken by the server [default: source]
+    #[clap(long, value_enum)]
+    pub protocol: Option<Protocol>,
+
+    /// Server profile from the config file to connect to
+    #[clap(short = 'P', long, global = true)]
+    pub profile: Option<String>,
+
+    /// Config group of profiles to target with `exec`, `schedule` and the sync subcommands
+    #[clap(short = 'G', long, global = true)]
+    pub group: Option<String>,
+
+    /// Comma-separated names of the only commands to send, anything else is refused before it
+    /// reaches the server
+    #[clap(
+        long,
+        global = true,
+        use_value_delimiter = true,
+        value_name = "COMMANDS"
+    )]
+    pub allow_commands: Vec<String>,
+
+    /// Send dangerous commands like `stop` from subcommands and scripts, which otherwise refuse
+    #[clap(short, long, global = true)]
+    pub yes: bool,
+
+    /// Show server output in the shell, chat and attach as is, without escaping terminal control
+    /// sequences
+    #[clap(long, global = true)]
+    pub raw_output: bool,
+
+    /// How the shell shows the player list, stats and bans, cut to the terminal's width
+    #[clap(
+        long,
+        value_enum,
+        global = true,
+        default_value = "plain",
+        value_name = "STYLE"
+    )]
+    pub tables: TableStyle,
+
+    /// Column to sort the shell's tables and the rows of `--format csv` or `yaml` by, like
+    /// `name` or `name:desc`
+    #[clap(long, global = true, value_parser = parse_sort, value_name = "COLUMN[:desc]")]
+    pub sort: Option<SortKey>,
+
+    /// Don't warn about sending the password in plain text to a server outside the local network
+    #[clap(long, global = true)]
+    pub insecure_ok: bool,
+
+    /// Fail on anything the server sends against the RCON protocol, with what was wrong, instead
+    /// of working around it. Meant for testing servers, games with known quirks stop working
+    #[clap(long, global = true)]
+    pub protocol_strict: bool,
+
+    /// Largest response kept in memory, in bytes. Anything past it is dropped, or saved with
+    /// `--spill-responses` [default: 4194304]
+    #[clap(long, global = true, value_name = "BYTES", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_response_bytes: Option<u64>,
+
+    /// Save the part of a response past `--max-response-bytes` to a temp file instead of dropping
+    /// it
+    #[clap(long, global = true)]
+    pub spill_responses: bool,
+
+    /// How serve-http, serve-ws, exporter and schedule log what they do
+    #[clap(
+        long,
+        value_enum,
+        global = true,
+        default_value = "text",
+        value_name = "FORMAT"
+    )]
+    pub log_format: LogFormat,
+
+    /// Config file [default: ~/.config/rustcon/config.toml]
+    #[clap(short, long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Read the RCON port and password from a local server's server.properties or server.cfg
+    #[clap(long, value_name = "DIR")]
+    pub from_server_dir: Option<PathBuf>,
+
+    /// Record the shell session to this file in asciinema's format
+    #[clap(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+
+    /// Print the summary of the shell session on exit as a JSON object, for transcript logs
+    #[clap(long)]
+    pub summary_json: bool,
+
+    /// Passwords to try at the shell's prompt before giving up, since servers like SRCDS ban
+    /// addresses after `sv_rcon_maxfailures` wrong ones [default: 3]
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub auth_attempts: Option<u32>,
+
+    /// Speak JSON-RPC on stdin and stdout instead of running the shell, for embedding rustcon
+    #[clap(long)]
+    pub stdio_rpc: bool,
+
+    /// Answer `{"id", "cmd"}` JSON lines from stdin with JSON lines keyed by the same id, several
+    /// at once
+    #[clap(long, conflicts_with = "stdio-rpc")]
+    pub ndjson: bool,
+
+    /// Output of `exec` and `healthcheck`, `ansible` and `kv` report changed/failed for wrapper
+    /// modules, `csv` and `yaml` also cover broadcasts, `bulk` failures and `watch-players`
+    /// events
+    #[clap(long, value_enum, global = true, default_value = "text")]
+    pub format: OutputFormat,
+
+    /// Run a one-shot command instead of the interactive shell
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// How one-shot subcommands print their outcome
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// The response as the server sent it
+    Text,
+    /// A JSON object with `changed`, `failed`, `msg`, `stdout` and `ansible_facts`
+    Ansible,
+    /// The same fields as `key=value` pairs on one line
+    Kv,
+    /// A header row and a row of the same fields, or of the players or figures a response to the
+    /// game's command for them lists
+    Csv,
+    /// The same document as `ansible` in YAML, plus the `rows` that `csv` would print
+    Yaml,
+}
+
+/// How the daemon modes log
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Lines of text
+    Text,
+    /// One JSON object per event, with its level, profile, command, latency and error
+    Json,
+}
+
+/// How the shell shows responses that parse into rows, like the player list
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TableStyle {
+    /// Aligned columns
+    Plain,
+    /// Aligned columns in a box
+    Borders,
+    /// The response as the server sent it
+    Off,
+}
+
+/// One-shot subcommands
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run one command and print its response
+    Exec(ExecArgs),
+
+    /// Manage players without remembering each game's command syntax
+    #[clap(subcommand)]
+    Player(PlayerAction),
+
+    /// Run a Lua snippet through Factorio's `/silent-command`
+    Lua {
+        /// Print the value of the expression back over RCON with `rcon.print`
+        #[clap(long)]
+        print: bool,
+
+        #[clap(required = true)]
+        code: Vec<String>,
+    },
+
+    /// Announce a restart in chat, save the world, and stop the server
+    Restart(RestartArgs),
+
+    /// Count down to a time with chat announcements
+    Announce(AnnounceArgs),
+
+    /// Pause saving, flush the world, run an archive command, and resume saving
+    Backup(BackupArgs),
+
+    /// Poll the player list and report joins and leaves
+    WatchPlayers(WatchPlayersArgs),
+
+    /// Talk in the game's chat, every line typed is sent as a chat message
+    Chat(ChatArgs),
+
+    /// Open the shell with the server's log file followed above the prompt
+    Attach(AttachArgs),
+
+    /// Manage the whitelist across servers
+    #[clap(subcommand)]
+    Whitelist(WhitelistAction),
+
+    /// Run the profile's scheduled commands until interrupted, or those of every profile in
+    /// `--group`
+    Schedule,
+
+    /// Watch server output and fire the profile's triggers until interrupted
+    Triggers,
+
+    /// Poll the config's servers and serve Prometheus metrics
+    Exporter(ExporterArgs),
+
+    /// Expose the config's servers over an HTTP REST API
+    ServeHttp(ServeHttpArgs),
+
+    /// Expose the config's servers as live WebSocket consoles
+    ServeWs(ServeWsArgs),
+
+    /// Expose the config's servers over gRPC
+    #[cfg(feature = "grpc")]
+    ServeGrpc(ServeGrpcArgs),
+
+    /// Manage bans across servers
+    #[clap(subcommand)]
+    Bans(BansAction),
+
+    /// Exit 0 if the server accepts a login and answers a probe command, 1 otherwise
+    Healthcheck(HealthcheckArgs),
+
+    /// Time round trips of a light command over one session, like `ping` does for the network
+    Ping(PingArgs),
+
+    /// Connect and log in one step at a time, reporting which step fails and its likely causes
+    Doctor(DoctorArgs),
+
+    /// Scan a network for open RCON ports and offer to save the servers found as profiles
+    Discover(DiscoverArgs),
+
+    /// Send every command in a file as fast as the server takes them, for mass bans or whitelist
+    /// loads, then report the ones that failed
+    Bulk(BulkArgs),
+
+    /// Show a unified diff of a command's responses, run twice on the server or on each of two
+    /// profiles, exiting 1 when they differ
+    Diff(DiffArgs),
+
+    /// Run an automation script against the server
+    #[cfg(any(feature = "scripting", feature = "lua"))]
+    Script(ScriptArgs),
+}
+
+/// Options for the `script` subcommand
+#[cfg(any(feature = "scripting", feature = "lua"))]
+#[derive(clap::Args, Debug)]
+pub struct ScriptArgs {
+    /// Script to run, the engine is picked by its extension (.rhai or .lua)
+    pub file: PathBuf,
+}
+
+/// Options for the `exec` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ExecArgs {
+    /// Only report the server as changed if the response matches this regex [default: always]
+    #[clap(long, value_parser = regex::Regex::new)]
+    pub changed_when: Option<regex::Regex>,
+
+    /// Comma-separated config profiles to run the command on instead of one server
+    #[clap(long, use_value_delimiter = true, conflicts_with = "all")]
+    pub profiles: Vec<String>,
+
+    /// Run the command on every profile in the config
+    #[clap(long)]
+    pub all: bool,
+
+    /// Servers to run a broadcast on at once
+    #[clap(long, default_value = "8", value_parser = clap::value_parser!(u16).range(1..))]
+    pub parallel: u16,
+
+    /// Print this instead of the response, with `{{fields}}` of the result filled in, like
+    /// `{{host}}: {{players.online}}/{{players.max}}`
+    #[clap(long, value_parser = crate::cmd::template::Template::parse)]
+    pub template: Option<crate::cmd::template::Template>,
+
+    #[clap(required = true)]
+    pub command: Vec<String>,
+}
+
+/// Options for the `healthcheck` subcommand
+#[derive(clap::Args, Debug, Clone)]
+pub struct HealthcheckArgs {
+    /// Probe command to run after logging in [default: only log in]
+    #[clap(long)]
+    pub cmd: Option<String>,
+
+    /// Fail unless the probe's response matches this regex
+    #[clap(long, requires = "cmd", value_parser = regex::Regex::new)]
+    pub expect_regex: Option<regex::Regex>,
+
+    /// Give up and report the server unhealthy after this long
+    #[clap(long, value_parser = crate::duration::parse_duration, default_value = "5s")]
+    pub timeout: Duration,
+}
+
+/// Options for the `ping` subcommand
+#[derive(clap::Args, Debug)]
+pub struct PingArgs {
+    /// Commands to send before printing the summary, `-n` as on Windows since `-c` is taken by
+    /// `--config`
+    #[clap(short = 'n', long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    pub count: u32,
+
+    /// Wait between commands
+    #[clap(short, long, value_parser = crate::duration::parse_duration, default_value = "1s")]
+    pub interval: Duration,
+
+    /// Command to send [default: an empty command, answered without doing anything]
+    #[clap(long)]
+    pub cmd: Option<String>,
+}
+
+/// Options for the `doctor` subcommand
+#[derive(clap::Args, Debug)]
+pub struct DoctorArgs {
+    /// Command to test after logging in [default: the game's player list]
+    #[clap(long)]
+    pub cmd: Option<String>,
+
+    /// Command with a response longer than one packet, to test that it's put back together
+    /// [default: `cvarlist` on Source servers, `help` on others]
+    #[clap(long)]
+    pub big_cmd: Option<String>,
+}
+
+/// Options for the `discover` subcommand
+#[derive(clap::Args, Debug)]
+pub struct DiscoverArgs {
+    /// Addresses to scan, like `192.168.1.0/24`
+    #[clap(value_parser = parse_network)]
+    pub network: Network,
+
+    /// Comma-separated TCP ports to try on each address
+    #[clap(
+        long,
+        use_value_delimiter = true,
+        default_value = "27015,27020,25575,8081"
+    )]
+    pub ports: Vec<u16>,
+
+    /// Skip asking the servers found what game they run over A2S and Minecraft's query protocol
+    #[clap(long = "no-query", action = clap::ArgAction::SetFalse)]
+    pub query: bool,
+
+    /// Give up on a port or a query after this long
+    #[clap(long, value_parser = crate::duration::parse_duration, default_value = "300ms")]
+    pub timeout: Duration,
+
+    /// Addresses scanned at once
+    #[clap(long, default_value = "64", value_parser = clap::value_parser!(u16).range(1..))]
+    pub parallel: u16,
+
+    /// Save the servers found as profiles without asking
+    #[clap(long)]
+    pub save: bool,
+}
+
+/// Options for the `bulk` subcommand
+#[derive(clap::Args, Debug)]
+pub struct BulkArgs {
+    /// Commands to send, one per line, skipping blank lines and `#` comments
+    #[clap(short, long)]
+    pub file: PathBuf,
+
+    /// Commands in flight at once on servers that can be pipelined, sent in batches of half as
+    /// many as responses come back
+    #[clap(long, default_value_t = 32, value_parser = clap::value_parser!(u16).range(1..))]
+    pub pipeline: u16,
+
+    /// Write the commands that failed to this file, one per line, to retry them with `--file`
+    #[clap(long)]
+    pub failures: Option<PathBuf>,
+}
+
+/// Options for the `diff` subcommand
+#[derive(clap::Args, Debug)]
+pub struct DiffArgs {
+    /// Wait between the two runs on one server
+    #[clap(short, long, value_parser = crate::duration::parse_duration, default_value = "60s")]
+    pub interval: Duration,
+
+    /// Two comma-separated config profiles to compare instead, running the command once on each
+    #[clap(
+        long,
+        use_value_delimiter = true,
+        number_of_values = 2,
+        value_name = "A,B"
+    )]
+    pub profiles: Vec<String>,
+
+    /// Unchanged lines to show around each change
+    #[clap(short = 'U', long, default_value_t = 3)]
+    pub context: usize,
+
+    #[clap(required = true)]
+    pub command: Vec<String>,
+}
+
+/// Ban list subcommands
+#[derive(Subcommand, Debug)]
+pub enum BansAction {
+    /// Copy one profile's ban list to the servers of other profiles
+    Sync(BansSyncArgs),
+}
+
+/// Options for `bans sync`
+#[derive(clap::Args, Debug)]
+pub struct BansSyncArgs {
+    /// Config profile of the server whose ban list is canonical
+    #[clap(long)]
+    pub from: String,
+
+    /// Comma-separated config profiles of the servers to update, with those of `--group`
+    #[clap(long, use_value_delimiter = true)]
+    pub to: Vec<String>,
+
+    /// Also lift bans on the targets that the source server doesn't have
+    #[clap(long)]
+    pub prune: bool,
+}
+
+/// Whitelist subcommands
+#[derive(Subcommand, Debug)]
+pub enum WhitelistAction {
+    /// Add and remove whitelist entries on each profile's server to match a canonical list
+    Sync(WhitelistSyncArgs),
+}
+
+/// Options for `whitelist sync`
+#[derive(clap::Args, Debug)]
+pub struct WhitelistSyncArgs {
+    /// Comma-separated config profiles of the servers to update, with those of `--group`
+    #[clap(long, use_value_delimiter = true)]
+    pub profiles: Vec<String>,
+
+    /// File with the canonical whitelist, one player name per line
+    #[clap(long)]
+    pub source: PathBuf,
+}
+
+/// Options for the `chat` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ChatArgs {
+    /// Name shown in front of your messages
+    #[clap(long = "as", value_name = "NAME")]
+    pub sender: Option<String>,
+}
+
+/// Options for the `attach` subcommand
+#[derive(clap::Args, Debug)]
+pub struct AttachArgs {
+    /// Console or log file of the server to follow, like /srv/srcds/console.log
+    #[clap(long, value_name = "FILE")]
+    pub log: PathBuf,
+
+    /// Lines of the log to show from before attaching
+    #[clap(long, default_value = "10")]
+    pub lines: usize,
+}
+
+/// Options for the `exporter` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ExporterArgs {
+    /// Port serving /metrics
+    #[clap(long, default_value = "9123")]
+    pub port: u16,
+
+    /// Address to listen on
+    #[clap(long, default_value = "0.0.0.0")]
+    pub listen_address: String,
+
+    /// Time between polls of each server
+    #[clap(long, value_parser = crate::duration::parse_duration, default_value = "15s")]
+    pub interval: Duration,
+}
+
+/// Options for the `serve-http` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ServeHttpArgs {
+    /// Address to listen on
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    pub bind: String,
+}
+
+/// Options for the `serve-ws` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ServeWsArgs {
+    /// Address to listen on
+    #[clap(long, default_value = "127.0.0.1:8081")]
+    pub bind: String,
+}
+
+/// Options for the `serve-grpc` subcommand
+#[cfg(feature = "grpc")]
+#[derive(clap::Args, Debug)]
+pub struct ServeGrpcArgs {
+    /// Address to listen on
+    #[clap(long, default_value = "127.0.0.1:50051")]
+    pub bind: String,
+}
+
+/// Options for the `announce` subcommand
+#[derive(clap::Args, Debug)]
+pub struct AnnounceArgs {
+    /// Local time to count down to, e.g. 21:00 or "2024-06-01 21:00"
+    #[clap(long, value_parser = crate::cmd::announce::parse_at)]
+    pub at: DateTime<Local>,
+
+    /// Announcement text, `{remaining}` is replaced with the time left
+    #[clap(long, default_value = "Starting in {remaining}")]
+    pub message: String,
+
+    /// Comma-separated times before the deadline to announce at
+    #[clap(
+        long,
+        value_parser = crate::duration::parse_duration,
+        use_value_delimiter = true,
+        default_value = "1h,30m,15m,10m,5m,1m,30s,10s"
+    )]
+    pub warn: Vec<Duration>,
+
+    /// Announcement sent when the countdown reaches zero
+    #[clap(long)]
+    pub final_message: Option<String>,
+}
+
+/// Options for the `backup` subcommand
+#[derive(clap::Args, Debug)]
+pub struct BackupArgs {
+    /// Directory for the archive, created if missing
+    #[clap(long)]
+    pub dest: PathBuf,
+
+    /// Shell command creating the archive [default: the profile's backup_command].
+    /// `{dest}` and `{timestamp}` are substituted, and also exported as RUSTCON_BACKUP_DEST and
+    /// RUSTCON_BACKUP_TIMESTAMP
+    #[clap(long)]
+    pub command: Option<String>,
+}
+
+/// Options for the `restart` subcommand
+#[derive(clap::Args, Debug)]
+pub struct RestartArgs {
+    /// Comma-separated countdown announcements before the stop, e.g. 10m,5m,1m
+    #[clap(long, value_parser = crate::duration::parse_duration, use_value_delimiter = true)]
+    pub warn: Vec<Duration>,
+
+    /// Announcement text, `{remaining}` is replaced with the time left
+    #[clap(long, default_value = "Server restarting in {remaining}")]
+    pub message: String,
+
+    /// Skip saving the world before stopping
+    #[clap(long)]
+    pub no_save: bool,
+
+    /// Wait for the RCON port to come back up before reporting success
+    #[clap(long)]
+    pub wait: bool,
+
+    /// How long to wait for the server to come back with --wait
+    #[clap(long, value_parser = crate::duration::parse_duration, default_value = "10m")]
+    pub wait_timeout: Duration,
+}
+
+fn parse_sort(text: &str) -> Result<SortKey, String> {
+    text.parse()
+}
+
+fn parse_network(text: &str) -> Result<Network, String> {
+    text.parse()
+}
+
+/// An IP address or a hostname that could resolve, checked before anything is sent
+///
+/// Nothing is looked up here, so a hostname that doesn't exist is only found out on connecting.
+fn parse_host(text: &str) -> Result<String, String> {
+    let host = text.trim();
+    let bare = host.trim_start_matches('[').trim_end_matches(']');
+    if bare.parse::<IpAddr>().is_ok() {
+        return Ok(bare.to_string());
+    }
+    if let Some((address, port)) = host.rsplit_once(':') {
+        if port.parse::<u16>().is_ok() {
+            return Err(format!(
+                "expected an address without a port, use `--ip {} --port {}`",
+                address, port
+            ));
+        }
+    }
+    let label_ok = |label: &str| {
+        (1..=63).contains(&label.len())
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    let name = host.strip_suffix('.').unwrap_or(host);
+    if name.len() > 253 || !name.split('.').all(label_ok) {
+        return Err(format!(
+            "{:?} isn't an IP address or hostname, expected something like 203.0.113.5 or \
+             mc.example.com",
+            text
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// Options for the `watch-players` subcommand
+#[derive(clap::Args, Debug)]
+pub struct WatchPlayersArgs {
+    /// Time between player list polls
+    #[clap(long, value_parser = crate::duration::parse_duration, default_value = "10s")]
+    pub interval: Duration,
+
+    /// Only report these comma-separated names or IDs (SteamID, UID, ...)
+    #[clap(long, use_value_delimiter = true)]
+    pub watch: Vec<String>,
+
+    /// File of names or IDs to report, one per line
+    #[clap(long)]
+    pub watchlist: Option<PathBuf>,
+
+    /// POST each event as JSON to this URL
+    #[clap(long)]
+    pub webhook: Option<String>,
+}
@@ -0,0 +1,59 @@
+use super::{connect, countdown, exec};
+use crate::{AnnounceArgs, Profile, RconError};
+use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveDateTime, NaiveTime, TimeZone};
+
+/// Parse `21:00`, `21:00:30` or `2024-06-01 21:00` in local time
+///
+/// A bare time of day that has already passed today means tomorrow.
+pub fn parse_at(text: &str) -> Result<DateTime<Local>, String> {
+    let text = text.trim();
+    let now = Local::now();
+    let naive = match NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M") {
+        Ok(datetime) => datetime,
+        Err(_) => {
+            let time = NaiveTime::parse_from_str(text, "%H:%M:%S")
+                .or_else(|_| NaiveTime::parse_from_str(text, "%H:%M"))
+                .map_err(|_| {
+                    format!(
+                        "expected a time like 21:00 or 2024-06-01 21:00, got {:?}",
+                        text
+                    )
+                })?;
+            let today = now.date_naive().and_time(time);
+            if today > now.naive_local() {
+                today
+            } else {
+                today + ChronoDuration::days(1)
+            }
+        }
+    };
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| format!("{} doesn't exist in the local time zone", naive))
+}
+
+/// Count down to a point in time with chat announcements
+pub fn run(profile: &Profile, args: &AnnounceArgs) -> Result<(), RconError> {
+    let left = (args.at - Local::now()).to_std().map_err(|_| {
+        eprintln!("{} has already passed", args.at.format("%Y-%m-%d %H:%M:%S"));
+        RconError::TaskFailed
+    })?;
+
+    let mut rcon = connect(profile)?;
+    println!(
+        "Counting down to {} on {}",
+        args.at.format("%Y-%m-%d %H:%M:%S"),
+        rcon.game()
+    );
+    countdown(&mut rcon, profile, left, &args.warn, &args.message)?;
+
+    if let Some(message) = &args.final_message {
+        let game = rcon.game();
+        if let Some(cmd) = game.profile().say_command(message) {
+            println!("Announcing: {}", message);
+            exec(&mut rcon, profile, &cmd)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use crate::{AttachArgs, Profile};
+use rustyline::{history::History, Editor, ExternalPrinter, Helper};
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    mem,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// How often the log is checked for new lines once it's caught up
+const POLL: Duration = Duration::from_millis(250);
+
+/// How far back from the end of the log to look for the lines shown when attaching
+const BACKLOG_BYTES: u64 = 64 * 1024;
+
+/// Where followed lines go, held until the shell's prompt can print them above itself
+#[derive(Default)]
+struct Output {
+    printer: Option<Box<dyn ExternalPrinter + Send>>,
+    pending: Vec<String>,
+}
+
+impl Output {
+    fn print(&mut self, line: String) {
+        match self.printer.as_mut() {
+            Some(printer) => {
+                if printer.print(line.clone()).is_err() {
+                    // The prompt is gone, hold on to lines until the next one
+                    self.printer = None;
+                    self.pending.push(line);
+                }
+            }
+            None => self.pending.push(line),
+        }
+    }
+}
+
+/// Follows a log file on its own thread for as long as rustcon runs, like `tail -F`
+///
+/// Lines are shown above the prompt of the shell they're attached to, so the prompt stays
+/// usable while the log scrolls. Truncated or rotated logs are picked up from their start.
+pub struct Follower(Arc<Mutex<Output>>);
+
+impl Follower {
+    /// Follow the `--log` of `attach`, showing its lines for the `profile`'s terminal
+    pub fn start(args: &AttachArgs, profile: &Profile) -> Follower {
+        let output = Arc::new(Mutex::new(Output::default()));
+        let (path, lines) = (args.log.clone(), args.lines);
+        let shared = Arc::clone(&output);
+        let profile = profile.clone();
+        thread::spawn(move || follow(&path, lines, &profile, &shared));
+        Follower(output)
+    }
+
+    /// Print lines above the editor's prompt, starting with those that arrived without one
+    ///
+    /// Lines are printed as they come when the editor isn't attached to a terminal.
+    pub fn attach<H: Helper, I: History>(&self, editor: &mut Editor<H, I>) {
+        let mut printer: Box<dyn ExternalPrinter + Send> = match editor.create_external_printer() {
+            Ok(printer) => Box::new(printer),
+            Err(_) => Box::new(Stdout),
+        };
+        let mut output = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        for line in mem::take(&mut output.pending) {
+            let _ = printer.print(line);
+        }
+        output.printer = Some(printer);
+    }
+}
+
+/// Printer for when there's no prompt to print above
+struct Stdout;
+
+impl ExternalPrinter for Stdout {
+    fn print(&mut self, msg: String) -> rustyline::Result<()> {
+        println!("{}", msg);
+        Ok(())
+    }
+}
+
+/// The last `count` whole lines of the log, read from its tail so large logs stay cheap
+fn last_lines(file: &mut File, count: usize) -> io::Result<Vec<String>> {
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(BACKLOG_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+
+    let text = String::from_utf8_lossy(&bytes);
+    let mut lines: Vec<&str> = text.lines().collect();
+    if start > 0 && !lines.is_empty() {
+        // Starts partway through a line
+        lines.remove(0);
+    }
+    let skip = lines.len().saturating_sub(count);
+    Ok(lines[skip..].iter().map(|l| l.to_string()).collect())
+}
+
+/// Whether `path` still names the open file, rather than a new one put there by log rotation
+#[cfg(unix)]
+fn same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(open), Ok(named)) => open.dev() == named.dev() && open.ino() == named.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(_file: &File, path: &Path) -> bool {
+    path.exists()
+}
+
+fn follow(path: &Path, backlog: usize, profile: &Profile, output: &Mutex<Output>) {
+    let print = |line: &str| {
+        let line = line.trim_end_matches(&['\r', '\n'][..]);
+        let line = format!("[log] {}", profile.display(line));
+        output.lock().unwrap_or_else(|e| e.into_inner()).print(line);
+    };
+    let mut first = true;
+    let mut reported = false;
+
+    loop {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) => {
+                if !reported {
+                    print(&format!(
+                        "Unable to read {}: {}, waiting for it",
+                        path.display(),
+                        e
+                    ));
+                    reported = true;
+                }
+                thread::sleep(POLL);
+                continue;
+            }
+        };
+        reported = false;
+
+        // Show some context the first time, and everything in a log that took the old one's place
+        if first {
+            first = false;
+            match last_lines(&mut file, backlog) {
+                Ok(lines) => lines.iter().for_each(|l| print(l)),
+                Err(e) => print(&format!("Unable to read {}: {}", path.display(), e)),
+            }
+        }
+
+        let mut reader = BufReader::new(file);
+        let mut position = match reader.stream_position() {
+            Ok(position) => position,
+            Err(_) => continue,
+        };
+        let mut line = String::new();
+        loop {
+            match reader.read_line(&mut line) {
+                // Wait for the rest of a line still being written
+                Ok(n) if n > 0 && line.ends_with('\n') => {
+                    position += n as u64;
+                    print(&line);
+                    line.clear();
+                }
+                Ok(n) if n > 0 => position += n as u64,
+                Ok(_) => {
+                    let truncated = fs::metadata(path).map_or(true, |m| m.len() < position);
+                    if truncated || !same_file(reader.get_ref(), path) {
+                        if !line.is_empty() {
+                            print(&line);
+                        }
+                        break;
+                    }
+                    thread::sleep(POLL);
+                }
+                Err(_) => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use super::limit::Limiter;
+use crate::{command_allowed, config::TokenConfig, logging::Event, Args, Config, RconError};
+use std::net::IpAddr;
+
+/// The bridges' bearer tokens, every request is allowed while none are configured, and their
+/// clients' rate limits
+pub struct Auth {
+    tokens: Vec<TokenConfig>,
+    limiter: Limiter,
+}
+
+/// Who made a request, allowed to reach some of the servers
+#[derive(Clone, Copy)]
+pub struct Caller<'a>(Option<&'a TokenConfig>);
+
+/// Why a request was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denied {
+    /// No token, or one that isn't in the config
+    Unauthorized,
+    /// The token can't reach the server it asked for
+    Forbidden,
+    /// The command isn't on the token's allowlist
+    Command,
+    /// The client went over its rate limit, and can retry after this many seconds
+    RateLimited(u64),
+}
+
+impl Denied {
+    pub fn status(self) -> u16 {
+        match self {
+            Denied::Unauthorized => 401,
+            Denied::Forbidden | Denied::Command => 403,
+            Denied::RateLimited(_) => 429,
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            Denied::Unauthorized => "Missing or unknown bearer token",
+            Denied::Forbidden => "The token can't reach this server",
+            Denied::Command => "The token can't run this command",
+            Denied::RateLimited(_) => "Too many requests, slow down",
+        }
+    }
+}
+
+/// Compare without returning early, so timing doesn't hint at how much of a token matched
+fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+/// The token in an `Authorization: Bearer <token>` header
+pub fn bearer(header: &str) -> Option<&str> {
+    let (scheme, token) = header.trim().split_once(' ')?;
+    if scheme.eq_ignore_ascii_case("bearer") {
+        Some(token.trim())
+    } else {
+        None
+    }
+}
+
+impl Auth {
+    /// The `[[tokens]]` and `[rate_limit]` of the config file
+    pub fn load(args: &Args) -> Result<Auth, RconError> {
+        let config = Config::load(args.config.as_deref()).map_err(|e| {
+            Event::error(e.to_string()).log();
+            RconError::TaskFailed
+        })?;
+        Ok(Auth {
+            tokens: config.tokens,
+            limiter: Limiter::new(config.rate_limit),
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    /// Appended to a bridge's startup line when it lets everyone in
+    pub fn notice(&self) -> &'static str {
+        if self.enabled() {
+            ""
+        } else {
+            ", without [[tokens]] in the config anyone can use them"
+        }
+    }
+
+    /// Find the caller presenting `token`
+    pub fn authenticate(&self, token: Option<&str>) -> Result<Caller<'_>, Denied> {
+        if !self.enabled() {
+            return Ok(Caller(None));
+        }
+        let token = token.ok_or(Denied::Unauthorized)?;
+        self.tokens
+            .iter()
+            .find(|t| same(&t.token, token))
+            .map(|t| Caller(Some(t)))
+            .ok_or(Denied::Unauthorized)
+    }
+
+    /// Authenticate the caller and check that it can reach `server`
+    pub fn authorize(&self, token: Option<&str>, server: &str) -> Result<Caller<'_>, Denied> {
+        let caller = self.authenticate(token)?;
+        if caller.allows(server) {
+            Ok(caller)
+        } else {
+            Err(Denied::Forbidden)
+        }
+    }
+
+    /// Count a request against the caller's rate limit, per token, or per address while there
+    /// are no tokens
+    pub fn throttle(
+        &self,
+        bridge: &str,
+        caller: Caller<'_>,
+        peer: Option<IpAddr>,
+    ) -> Result<(), Denied> {
+        let (key, label) = match caller.0 {
+            Some(token) => (format!("token:{}", token.token), caller.name().to_string()),
+            None => {
+                let peer = peer.map_or_else(|| "unknown peer".to_string(), |ip| ip.to_string());
+                (format!("peer:{}", peer), peer)
+            }
+        };
+        let limit = caller.0.and_then(|t| t.rate_limit);
+        self.limiter
+            .check(bridge, &key, &label, limit)
+            .map_err(Denied::RateLimited)
+    }
+
+    /// Request counts by client, for the bridges' metrics
+    pub fn metrics(&self) -> String {
+        self.limiter.render()
+    }
+}
+
+impl Caller<'_> {
+    pub fn allows(&self, server: &str) -> bool {
+        match self.0.and_then(|t| t.servers.as_ref()) {
+            Some(servers) => servers.iter().any(|s| s == server),
+            None => true,
+        }
+    }
+
+    /// Commands the token is limited to, `None` for any
+    pub fn commands(&self) -> Option<&[String]> {
+        self.0.and_then(|t| t.commands.as_deref())
+    }
+
+    /// Check `cmd` against the token's allowlist
+    pub fn permits(&self, cmd: &str) -> Result<(), Denied> {
+        match self.commands() {
+            Some(commands) if !command_allowed(commands, cmd) => Err(Denied::Command),
+            _ => Ok(()),
+        }
+    }
+
+    /// Name of the token for logs, `anonymous` when auth is off
+    pub fn name(&self) -> &str {
+        match self.0 {
+            Some(token) => token.name.as_deref().unwrap_or("unnamed token"),
+            None => "anonymous",
+        }
+    }
+}
+
+/// Log a rejected request, except for rate limits which log once per streak of refusals
+pub fn log_denied(bridge: &str, peer: Option<String>, what: &str, denied: Denied) {
+    if let Denied::RateLimited(_) = denied {
+        return;
+    }
+    Event::warn(format!(
+        "{}: rejected {} from {}: {}",
+        bridge,
+        what,
+        peer.as_deref().unwrap_or("unknown peer"),
+        denied.message()
+    ))
+    .field("bridge", bridge)
+    .field("peer", &peer)
+    .field("request", what)
+    .field("error", denied.message())
+    .log();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth(config: &str) -> Auth {
+        let config: Config = toml::from_str(config).unwrap();
+        Auth {
+            tokens: config.tokens,
+            limiter: Limiter::new(config.rate_limit),
+        }
+    }
+
+    #[test]
+    fn tokens_match_only_in_full() {
+        assert!(same("hunter2", "hunter2"));
+        assert!(!same("hunter2", "hunter3"));
+        assert!(!same("hunter2", "hunter"));
+        assert!(!same("", "hunter2"));
+        assert_eq!(bearer("Bearer hunter2"), Some("hunter2"));
+        assert_eq!(bearer(" bearer  hunter2 "), Some("hunter2"));
+        assert_eq!(bearer("Basic aHVudGVyMg=="), None);
+        assert_eq!(bearer("hunter2"), None);
+    }
+
+    #[test]
+    fn requests_without_a_known_token_are_rejected() {
+        let auth = auth(
+            r#"
+            [[tokens]]
+            token = "grafana-key"
+            name = "grafana"
+            servers = ["cs"]
+            commands = ["status"]
+
+            [[tokens]]
+            token = "admin-key"
+            "#,
+        );
+        assert_eq!(auth.authenticate(None).err(), Some(Denied::Unauthorized));
+        assert_eq!(
+            auth.authenticate(Some("grafana-ke")).err(),
+            Some(Denied::Unauthorized)
+        );
+        let grafana = auth.authorize(Some("grafana-key"), "cs").unwrap();
+        assert_eq!(grafana.name(), "grafana");
+        assert_eq!(grafana.permits("status"), Ok(()));
+        assert_eq!(grafana.permits("rcon_password x"), Err(Denied::Command));
+        assert_eq!(
+            auth.authorize(Some("grafana-key"), "mc").err(),
+            Some(Denied::Forbidden)
+        );
+        let admin = auth.authorize(Some("admin-key"), "mc").unwrap();
+        assert_eq!(admin.permits("quit"), Ok(()));
+        assert_eq!(Denied::Unauthorized.status(), 401);
+        assert_eq!(Denied::Forbidden.status(), 403);
+    }
+
+    #[test]
+    fn everyone_is_let_in_without_tokens() {
+        let auth = auth("");
+        assert!(!auth.enabled());
+        let caller = auth.authorize(None, "cs").unwrap();
+        assert_eq!(caller.name(), "anonymous");
+        assert_eq!(caller.permits("quit"), Ok(()));
+    }
+}
@@ -0,0 +1,90 @@
+use super::{connect, exec, shell_command};
+use crate::{BackupArgs, Profile, Rcon, RconError};
+use std::fs;
+
+/// Safely back up the world: pause saving, flush, archive, and always resume saving
+pub fn run(profile: &Profile, args: &BackupArgs) -> Result<(), RconError> {
+    let archive = args
+        .command
+        .clone()
+        .or_else(|| profile.backup_command.clone())
+        .ok_or_else(|| {
+            eprintln!("No archive command, pass --command or set backup_command in the profile");
+            RconError::TaskFailed
+        })?;
+
+    if let Err(e) = fs::create_dir_all(&args.dest) {
+        eprintln!("Unable to create {}: {}", args.dest.display(), e);
+        return Err(RconError::TaskFailed);
+    }
+
+    let mut rcon = connect(profile)?;
+    let game = rcon.game().profile();
+
+    let save_off = game.save_off_command();
+    if let Some(cmd) = save_off {
+        println!("Pausing automatic saves...");
+        exec(&mut rcon, profile, cmd)?;
+    }
+
+    let result = save_and_archive(&mut rcon, profile, args, &archive);
+
+    // Resume saving no matter how the backup went
+    if let Some(cmd) = game.save_on_command().filter(|_| save_off.is_some()) {
+        println!("Resuming automatic saves...");
+        if exec(&mut rcon, profile, cmd).is_err() {
+            eprintln!("Unable to resume saving, run `{}` on the server!", cmd);
+            return Err(RconError::ConnError);
+        }
+    }
+
+    if result.is_ok() {
+        println!("Backup complete");
+    }
+    result
+}
+
+fn save_and_archive(
+    rcon: &mut Rcon,
+    profile: &Profile,
+    args: &BackupArgs,
+    archive: &str,
+) -> Result<(), RconError> {
+    let game = rcon.game();
+    match game.profile().save_command() {
+        Some(cmd) => {
+            println!("Flushing the world to disk...");
+            let response: String = exec(rcon, profile, cmd)?.iter().map(|p| p.body()).collect();
+            if let Some(confirmation) = game.profile().save_confirmation() {
+                if !response.contains(confirmation) {
+                    eprintln!("The server didn't confirm the save: {}", response.trim());
+                    return Err(RconError::PacketError);
+                }
+            }
+        }
+        None => eprintln!("{} has no save command, archiving as is", game),
+    }
+
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let dest = args.dest.display().to_string();
+    let archive = archive
+        .replace("{dest}", &dest)
+        .replace("{timestamp}", &timestamp);
+
+    println!("Running: {}", archive);
+    let status = shell_command(&archive)
+        .env("RUSTCON_BACKUP_DEST", &dest)
+        .env("RUSTCON_BACKUP_TIMESTAMP", &timestamp)
+        .status();
+    match status {
+        Ok(s) if s.success() => Ok(()),
+        Ok(s) => {
+            eprintln!("The archive command failed with {}", s);
+            Err(RconError::TaskFailed)
+        }
+        Err(e) => {
+            eprintln!("Unable to run the archive command: {}", e);
+            Err(RconError::TaskFailed)
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use super::{connect, diff, exec, targets};
+use crate::{Args, BansSyncArgs, Game, PlayerAction, Profile, Rcon, RconError};
+
+/// Fetch and parse a server's ban list
+fn fetch_bans(rcon: &mut Rcon, profile: &Profile) -> Result<Vec<String>, RconError> {
+    let game = rcon.game();
+    let list = game.profile().bans_command().ok_or_else(|| {
+        eprintln!("{} has no ban list command", game);
+        RconError::Unsupported
+    })?;
+    let response: String = exec(rcon, profile, list)?
+        .iter()
+        .map(|p| p.body())
+        .collect();
+    Ok(game.profile().parse_bans(&response))
+}
+
+/// Apply the source profile's bans to every target profile, carrying on past failing servers
+pub fn sync(args: &Args, sync: &BansSyncArgs) -> Result<(), RconError> {
+    let names = targets(args, &sync.to)?;
+    let source = args.resolve_named(&sync.from).map_err(|e| {
+        eprintln!("{}", e);
+        RconError::TaskFailed
+    })?;
+    let mut rcon = connect(&source)?;
+    let game = rcon.game();
+    let bans = fetch_bans(&mut rcon, &source)?;
+    println!("{}: {} bans", sync.from, bans.len());
+
+    let mut result = Ok(());
+    for name in names.iter().filter(|name| **name != sync.from) {
+        let profile = match args.resolve_named(name) {
+            Ok(profile) => profile,
+            Err(e) => {
+                eprintln!("{}", e);
+                result = Err(RconError::TaskFailed);
+                continue;
+            }
+        };
+        if let Err(e) = sync_server(&profile, game, &bans, sync.prune) {
+            eprintln!("{}: ban sync failed", name);
+            result = Err(e);
+        }
+    }
+    result
+}
+
+fn sync_server(
+    profile: &Profile,
+    source_game: Game,
+    bans: &[String],
+    prune: bool,
+) -> Result<(), RconError> {
+    let label = profile.name.as_deref().unwrap_or_default();
+    let mut rcon = connect(profile)?;
+    let game = rcon.game();
+    if game != source_game {
+        // Player IDs only mean something within the same game
+        eprintln!("{}: runs {}, not {}, skipping", label, game, source_game);
+        return Err(RconError::Unsupported);
+    }
+
+    let current = fetch_bans(&mut rcon, profile)?;
+    let mut diff = diff(bans, &current);
+    if !prune {
+        diff.remove.clear();
+    }
+    if diff.add.is_empty() && diff.remove.is_empty() {
+        println!("{}: in sync ({} bans)", label, current.len());
+        return Ok(());
+    }
+
+    println!("{}:", label);
+    let edits = diff
+        .add
+        .iter()
+        .map(|n| {
+            let action = PlayerAction::Ban {
+                name: n.to_string(),
+                reason: None,
+            };
+            ('+', *n, action)
+        })
+        .chain(diff.remove.iter().map(|n| {
+            let action = PlayerAction::Pardon {
+                name: n.to_string(),
+            };
+            ('-', *n, action)
+        }));
+    for (sign, player, action) in edits {
+        let cmd = game.profile().player_command(&action).ok_or_else(|| {
+            eprintln!(
+                "{}: {} has no equivalent of `player {}`",
+                label,
+                game,
+                action.name()
+            );
+            RconError::Unsupported
+        })?;
+        exec(&mut rcon, profile, &cmd)?;
+        println!("  {} {}", sign, player);
+    }
+
+    if let Some(cmd) = game.profile().persist_bans_command() {
+        exec(&mut rcon, profile, cmd)?;
+    }
+    Ok(())
+}
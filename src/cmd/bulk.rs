@@ -0,0 +1,317 @@
+use super::{check, connect, reconnect, report::csv_row, yaml::to_yaml};
+use crate::{systemd, BulkArgs, OutputFormat, Pipeline, Profile, Rcon, RconError, Reply};
+use serde_json::{json, Value};
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// How often the progress bar is redrawn
+const REDRAW: Duration = Duration::from_millis(100);
+
+/// Characters in the progress bar
+const BAR_WIDTH: usize = 30;
+
+/// A command and the line of the file it's on
+struct Line {
+    number: usize,
+    cmd: String,
+}
+
+/// A command that wasn't answered, and why
+struct Failure<'a> {
+    line: &'a Line,
+    error: String,
+}
+
+fn read_lines(path: &Path) -> Result<Vec<Line>, RconError> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        eprintln!("Unable to read {}: {}", path.display(), e);
+        RconError::TaskFailed
+    })?;
+    Ok(text
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+        .map(|(number, cmd)| Line {
+            number,
+            cmd: cmd.to_string(),
+        })
+        .collect())
+}
+
+#[cfg(unix)]
+fn is_terminal() -> bool {
+    // SAFETY: only asks about a file descriptor
+    unsafe { libc::isatty(libc::STDERR_FILENO) == 1 }
+}
+
+#[cfg(not(unix))]
+fn is_terminal() -> bool {
+    false
+}
+
+/// Commands answered so far, drawn on stderr when it's a terminal
+struct Progress {
+    total: usize,
+    done: usize,
+    failed: usize,
+    started: Instant,
+    visible: bool,
+    drawn: Option<Instant>,
+}
+
+impl Progress {
+    fn new(total: usize) -> Progress {
+        Progress {
+            total,
+            done: 0,
+            failed: 0,
+            started: Instant::now(),
+            visible: is_terminal(),
+            drawn: None,
+        }
+    }
+
+    fn tick(&mut self, failed: bool) {
+        self.done += 1;
+        self.failed += usize::from(failed);
+        let due = self.drawn.is_none_or(|at| at.elapsed() >= REDRAW);
+        if self.visible && (due || self.done == self.total) {
+            self.draw();
+        }
+    }
+
+    /// Commands answered a second
+    fn rate(&self) -> f64 {
+        self.done as f64 / self.started.elapsed().as_secs_f64().max(0.001)
+    }
+
+    fn draw(&mut self) {
+        let filled = BAR_WIDTH * self.done / self.total.max(1);
+        eprint!(
+            "\r[{}{}] {}/{} {:.0}/s, {} failed",
+            "#".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            self.done,
+            self.total,
+            self.rate(),
+            self.failed
+        );
+        let _ = io::stderr().flush();
+        self.drawn = Some(Instant::now());
+    }
+
+    /// End the bar's line, so other output doesn't write over it
+    fn finish(&mut self) {
+        if self.drawn.take().is_some() {
+            eprintln!();
+        }
+    }
+}
+
+/// The commands of one `bulk` run and how they went
+struct Run<'a> {
+    lines: &'a [Line],
+    failures: Vec<Failure<'a>>,
+    answered: usize,
+    progress: Progress,
+    stop: Arc<AtomicBool>,
+}
+
+impl<'a> Run<'a> {
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Count a command's result, returning whether it lost the session
+    fn outcome(&mut self, line: &'a Line, result: Result<(), RconError>) -> bool {
+        match result {
+            Ok(()) => {
+                self.progress.tick(false);
+                self.answered += 1;
+                false
+            }
+            Err(e) => {
+                self.fail(line, format!("{:?}", e));
+                e.is_disconnect()
+            }
+        }
+    }
+
+    fn fail(&mut self, line: &'a Line, error: String) {
+        self.progress.tick(true);
+        self.failures.push(Failure { line, error });
+    }
+
+    /// Keep up to `window` commands in flight from line `next` on, topping them up a batch at a
+    /// time once half have been answered, returning where sending stopped
+    fn pipelined(&mut self, pipeline: &Pipeline, mut next: usize, window: usize) -> usize {
+        let mut waiting: VecDeque<(&'a Line, Reply)> = VecDeque::new();
+        let mut lost = false;
+        loop {
+            let more = next < self.lines.len() && !lost && !self.stopped();
+            if more && waiting.len() <= window / 2 {
+                let lines = self.lines;
+                let batch = &lines[next..lines.len().min(next + window - waiting.len())];
+                next += batch.len();
+                let cmds: Vec<&str> = batch.iter().map(|l| l.cmd.as_str()).collect();
+                match pipeline.send_cmds_async(&cmds) {
+                    Ok(replies) => {
+                        for (line, reply) in batch.iter().zip(replies) {
+                            match reply {
+                                Ok(reply) => waiting.push_back((line, reply)),
+                                Err(e) => lost |= self.outcome(line, Err(e)),
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        let error = format!("{:?}", e);
+                        for line in batch {
+                            self.fail(line, error.clone());
+                        }
+                        lost = true;
+                    }
+                }
+                continue;
+            }
+            match waiting.pop_front() {
+                Some((line, reply)) => lost |= self.outcome(line, reply.wait().map(drop)),
+                None => return next,
+            }
+        }
+    }
+
+    /// Send commands one at a time from line `next` on, returning where sending stopped
+    fn serial(&mut self, rcon: &mut Rcon, next: usize) -> usize {
+        let lines = self.lines;
+        for (i, line) in lines.iter().enumerate().skip(next) {
+            if self.stopped() {
+                return i;
+            }
+            if self.outcome(line, rcon.send_cmd(&line.cmd).map(drop)) {
+                return i + 1;
+            }
+        }
+        lines.len()
+    }
+}
+
+/// Send every command in `--file`, pipelined when the server allows it, reconnecting when the
+/// session is lost as long as the server answered something since the last time
+///
+/// Fails when any command wasn't answered, after listing them, as rows of `line,command,error`
+/// with `--format csv` or a list of them with `--format yaml`.
+pub fn run(profile: &Profile, args: &BulkArgs, format: OutputFormat) -> Result<(), RconError> {
+    let lines = read_lines(&args.file)?;
+    let mut run = Run {
+        lines: &lines,
+        failures: Vec::new(),
+        answered: 0,
+        progress: Progress::new(lines.len()),
+        stop: systemd::stop_flag(),
+    };
+
+    let mut rcon = connect(profile)?;
+    // Files like `:export`'s are replayed unattended, so dangerous commands need `--yes` as in
+    // `exec`, and the whole file is refused before any of it is sent
+    let mut refused = false;
+    for line in &lines {
+        refused |= check(profile, rcon.game(), &line.cmd).is_err();
+    }
+    if refused {
+        return Err(RconError::NotAllowed);
+    }
+    let mut next = 0;
+    loop {
+        let answered = run.answered;
+        next = match rcon.pipeline() {
+            Ok(pipeline) => run.pipelined(&pipeline, next, usize::from(args.pipeline)),
+            Err(mut rcon) => run.serial(&mut rcon, next),
+        };
+        if next == lines.len() || run.stopped() {
+            break;
+        }
+        run.progress.finish();
+        if run.answered == answered {
+            eprintln!("Lost the connection without an answer since connecting, giving up");
+            break;
+        }
+        eprintln!("Lost the connection, reconnecting");
+        rcon = match reconnect(profile) {
+            Ok(rcon) => rcon,
+            Err(e) => {
+                eprintln!("Unable to reconnect: {:?}", e);
+                break;
+            }
+        };
+    }
+    run.progress.finish();
+
+    let lines = run.lines;
+    for line in &lines[next..] {
+        run.failures.push(Failure {
+            line,
+            error: "not sent".to_string(),
+        });
+    }
+    let summary = format!(
+        "{} of {} commands answered in {:.1}s, {:.0} a second",
+        run.answered,
+        lines.len(),
+        run.progress.started.elapsed().as_secs_f64(),
+        run.progress.rate()
+    );
+    if format == OutputFormat::Csv {
+        eprintln!("{}", summary);
+        println!("{}", csv_row(&["line", "command", "error"]));
+        for failure in &run.failures {
+            let number = failure.line.number.to_string();
+            println!(
+                "{}",
+                csv_row(&[number.as_str(), &failure.line.cmd, &failure.error])
+            );
+        }
+    } else if format == OutputFormat::Yaml {
+        eprintln!("{}", summary);
+        let failures = run
+            .failures
+            .iter()
+            .map(|f| json!({ "line": f.line.number, "command": f.line.cmd, "error": f.error }));
+        print!("{}", to_yaml(&Value::Array(failures.collect())));
+    } else {
+        println!("{}", summary);
+        if !run.failures.is_empty() {
+            println!("{} failed:", run.failures.len());
+        }
+        for failure in &run.failures {
+            println!(
+                "  line {}: {}: {}",
+                failure.line.number, failure.line.cmd, failure.error
+            );
+        }
+    }
+    if run.failures.is_empty() {
+        return Ok(());
+    }
+
+    if let Some(path) = &args.failures {
+        let text: String = run
+            .failures
+            .iter()
+            .map(|f| format!("{}\n", f.line.cmd))
+            .collect();
+        if let Err(e) = fs::write(path, text) {
+            eprintln!("Unable to write {}: {}", path.display(), e);
+        }
+    }
+    Err(RconError::TaskFailed)
+}
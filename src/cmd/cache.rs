@@ -0,0 +1,85 @@
+use crate::{Game, RconError};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Where a bridge's response came from, told to clients in a cache-status header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// Reused from an earlier request, `age` ago
+    Hit { age: Duration },
+    /// Sent to the server and kept for the next requests
+    Miss,
+    /// Not a cached command
+    Bypass,
+}
+
+impl CacheStatus {
+    /// Value of the `X-Cache` header, none for commands that aren't cached
+    pub fn header(self) -> Option<&'static str> {
+        match self {
+            CacheStatus::Hit { .. } => Some("HIT"),
+            CacheStatus::Miss => Some("MISS"),
+            CacheStatus::Bypass => None,
+        }
+    }
+
+    /// Seconds since a reused response was fetched, for the `Age` header
+    pub fn age(self) -> Option<u64> {
+        match self {
+            CacheStatus::Hit { age } => Some(age.as_secs()),
+            _ => None,
+        }
+    }
+}
+
+/// Responses of a profile's `cache` commands, reused until their TTL runs out
+///
+/// Dashboards polling the same command often then cost the server one command per TTL. Only
+/// successful responses are kept, and commands are matched exactly, arguments included.
+pub struct ResponseCache {
+    ttls: BTreeMap<String, Duration>,
+    entries: Mutex<HashMap<String, (Instant, Game, String)>>,
+}
+
+impl ResponseCache {
+    pub fn new(ttls: BTreeMap<String, Duration>) -> ResponseCache {
+        ResponseCache {
+            ttls,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The cached response to `cmd` if it's fresh, otherwise what `exec` returns
+    pub fn get_or_exec<F>(
+        &self,
+        cmd: &str,
+        exec: F,
+    ) -> Result<(Game, String, CacheStatus), RconError>
+    where
+        F: FnOnce() -> Result<(Game, String), RconError>,
+    {
+        let cmd = cmd.trim();
+        let ttl = match self.ttls.get(cmd) {
+            Some(ttl) => *ttl,
+            None => return exec().map(|(game, response)| (game, response, CacheStatus::Bypass)),
+        };
+
+        if let Some((fetched, game, response)) = self.lock().get(cmd) {
+            let age = fetched.elapsed();
+            if age < ttl {
+                return Ok((*game, response.clone(), CacheStatus::Hit { age }));
+            }
+        }
+        let (game, response) = exec()?;
+        self.lock()
+            .insert(cmd.to_string(), (Instant::now(), game, response.clone()));
+        Ok((game, response, CacheStatus::Miss))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, (Instant, Game, String)>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
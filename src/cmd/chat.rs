@@ -0,0 +1,84 @@
+use super::connect;
+use crate::{ChatArgs, Profile, RconError};
+use rustyline::{error::ReadlineError, DefaultEditor, ExternalPrinter};
+use std::{
+    io::{BufRead, BufReader},
+    thread,
+};
+
+/// Chat client: typed lines go out through the game's chat command, pushed chat is shown live
+pub fn run(profile: &Profile, args: &ChatArgs) -> Result<(), RconError> {
+    let mut rcon = connect(profile)?;
+    let game = rcon.game();
+
+    let mut editor = DefaultEditor::new().map_err(|e| {
+        eprintln!("{}", e);
+        RconError::ConnError
+    })?;
+
+    // Display incoming chat where the server pushes its console output, above the prompt when
+    // attached to a terminal
+    let live = match rcon.split_push_reader() {
+        Some(stream) => {
+            let mut printer = editor.create_external_printer().ok();
+            let _ = stream.set_read_timeout(None);
+            let profile = profile.clone();
+            thread::spawn(move || {
+                for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                    if let Some(chat) = game.profile().parse_chat(&line) {
+                        let msg = profile.display(&format!("<{}> {}", chat.sender, chat.text));
+                        match printer.as_mut() {
+                            Some(p) => {
+                                let _ = p.print(msg);
+                            }
+                            None => println!("{}", msg),
+                        }
+                    }
+                }
+            });
+            true
+        }
+        None => false,
+    };
+
+    if live {
+        println!("Connected to {} chat, Ctrl+D to leave", game);
+    } else {
+        println!(
+            "Connected to {}, incoming chat isn't available over this protocol. Ctrl+D to leave",
+            game
+        );
+    }
+
+    loop {
+        let line = match editor.readline("chat> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(()),
+            Err(e) => {
+                eprintln!("{}", e);
+                return Err(RconError::ConnError);
+            }
+        };
+        let message = line.trim();
+        if message.is_empty() {
+            continue;
+        }
+
+        let cmd = game
+            .profile()
+            .chat_command(args.sender.as_deref(), message)
+            .ok_or_else(|| {
+                eprintln!("{} has no chat command", game);
+                RconError::Unsupported
+            })?;
+        let sent = if live {
+            rcon.send_unanswered(&cmd)
+        } else {
+            rcon.send_cmd(&cmd).map(|_| ())
+        };
+        if sent.is_err() {
+            eprintln!("Unable to send the message, there may have been a connection error");
+            return Err(RconError::ConnError);
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use crate::{
+    sanitize::sanitize,
+    table::{self, Table},
+    Args, Config, DiscoverArgs, Game, RconError, TableStyle,
+};
+use clap::ValueEnum;
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    net::{Ipv4Addr, SocketAddr, TcpStream, UdpSocket},
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+/// Networks wider than this take too long to scan port by port
+const WIDEST_PREFIX: u8 = 16;
+
+/// Where Source servers answer A2S queries, ARK and Conan's RCON ports being elsewhere
+const A2S_PORT: u16 = 27015;
+/// Where Minecraft servers answer `enable-query` queries
+const MINECRAFT_QUERY_PORT: u16 = 25565;
+
+const A2S_INFO: &[u8] = b"\xFF\xFF\xFF\xFFTSource Engine Query\0";
+/// Session ID of Minecraft queries, only the low 4 bits of each byte are used
+const SESSION: [u8; 4] = [0, 0, 0, 1];
+
+/// An IPv4 network like `192.168.1.0/24`, or one address
+#[derive(Clone, Copy, Debug)]
+pub struct Network {
+    base: u32,
+    prefix: u8,
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Network, String> {
+        let (ip, prefix) = match s.split_once('/') {
+            Some((ip, prefix)) => (
+                ip,
+                prefix
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|p| *p <= 32)
+                    .ok_or_else(|| format!("{:?} isn't a prefix length from 0 to 32", prefix))?,
+            ),
+            None => (s, 32),
+        };
+        let ip: Ipv4Addr = ip
+            .parse()
+            .map_err(|_| format!("{:?} isn't an IPv4 address", ip))?;
+        if prefix < WIDEST_PREFIX {
+            return Err(format!(
+                "/{} is too many addresses to scan, the widest is /{}",
+                prefix, WIDEST_PREFIX
+            ));
+        }
+        let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+        Ok(Network {
+            base: u32::from(ip) & mask,
+            prefix,
+        })
+    }
+}
+
+impl Network {
+    /// Its addresses, without the network and broadcast ones when it has them
+    fn hosts(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let size = 1u64 << (32 - self.prefix);
+        let (first, last) = if size > 2 {
+            (1, size - 2)
+        } else {
+            (0, size - 1)
+        };
+        let base = u64::from(self.base);
+        (first..=last).map(move |n| Ipv4Addr::from((base + n) as u32))
+    }
+}
+
+/// A server found on the network
+struct Found {
+    ip: Ipv4Addr,
+    port: u16,
+    game: Option<Game>,
+    /// What the server calls itself in answer to a query
+    name: Option<String>,
+}
+
+/// The NUL-terminated strings at the start of `bytes`
+fn strings(bytes: &[u8]) -> impl Iterator<Item = String> + '_ {
+    bytes
+        .split(|b| *b == 0)
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+}
+
+/// Send `request` and wait for one datagram back
+fn ask(socket: &UdpSocket, to: SocketAddr, request: &[u8]) -> Option<Vec<u8>> {
+    socket.send_to(request, to).ok()?;
+    let mut buf = [0; 1400];
+    let (len, from) = socket.recv_from(&mut buf).ok()?;
+    (from == to).then(|| buf[..len].to_vec())
+}
+
+/// The game and name from an A2S_INFO query, answering a challenge if the server asks for one
+fn a2s_info(socket: &UdpSocket, to: SocketAddr) -> Option<(Game, String)> {
+    let mut reply = ask(socket, to, A2S_INFO)?;
+    if reply.get(4) == Some(&b'A') && reply.len() >= 9 {
+        let mut request = A2S_INFO.to_vec();
+        request.extend_from_slice(&reply[5..9]);
+        reply = ask(socket, to, &request)?;
+    }
+    if !reply.starts_with(b"\xFF\xFF\xFF\xFFI") || reply.len() < 6 {
+        return None;
+    }
+    // The protocol version, then the name, map, game folder and game
+    let mut fields = strings(&reply[6..]);
+    let name = fields.next()?;
+    let _map = fields.next()?;
+    let about = format!("{} {}", fields.next()?, fields.next()?).to_lowercase();
+    let game = if about.contains("ark") {
+        Game::Ark
+    } else if about.contains("conan") {
+        Game::Conan
+    } else if about.contains("7 days") || about.contains("7dtd") {
+        Game::SevenDays
+    } else {
+        Game::Source
+    };
+    Some((game, name))
+}
+
+/// The MOTD from a Minecraft basic stat query
+fn minecraft_query(socket: &UdpSocket, to: SocketAddr) -> Option<String> {
+    let mut handshake = vec![0xFE, 0xFD, 9];
+    handshake.extend_from_slice(&SESSION);
+    let reply = ask(socket, to, &handshake)?;
+    if reply.first() != Some(&9) || reply.len() < 6 {
+        return None;
+    }
+    let challenge: i32 = strings(&reply[5..]).next()?.parse().ok()?;
+    let mut stat = vec![0xFE, 0xFD, 0];
+    stat.extend_from_slice(&SESSION);
+    stat.extend_from_slice(&challenge.to_be_bytes());
+    let reply = ask(socket, to, &stat)?;
+    if reply.first() != Some(&0) || reply.len() < 6 {
+        return None;
+    }
+    let motd = strings(&reply[5..]).next();
+    motd
+}
+
+/// How a server is asked what it runs
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Query {
+    A2s,
+    Minecraft,
+}
+
+/// The game of the server on `ip`'s `port`, asking on the port itself and then on the query
+/// protocols' usual ports
+fn fingerprint(ip: Ipv4Addr, port: u16, timeout: Duration) -> Option<(Game, String)> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.set_read_timeout(Some(timeout)).ok()?;
+    let tries = [
+        (Query::A2s, port),
+        (Query::Minecraft, port),
+        (Query::A2s, A2S_PORT),
+        (Query::Minecraft, MINECRAFT_QUERY_PORT),
+    ];
+    for (i, (query, port)) in tries.iter().enumerate() {
+        if tries[..i].contains(&(*query, *port)) {
+            continue;
+        }
+        let to = SocketAddr::from((ip, *port));
+        let found = match query {
+            Query::A2s => a2s_info(&socket, to),
+            Query::Minecraft => minecraft_query(&socket, to).map(|motd| (Game::Minecraft, motd)),
+        };
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+/// The servers on `ip`'s open ports, fingerprinted when `query` is set
+fn scan(ip: Ipv4Addr, args: &DiscoverArgs) -> Vec<Found> {
+    args.ports
+        .iter()
+        .copied()
+        .filter(|port| {
+            TcpStream::connect_timeout(&SocketAddr::from((ip, *port)), args.timeout).is_ok()
+        })
+        .map(|port| {
+            let found = args
+                .query
+                .then(|| fingerprint(ip, port, args.timeout))
+                .flatten();
+            Found {
+                ip,
+                port,
+                game: found.as_ref().map(|(game, _)| *game),
+                name: found.map(|(_, name)| name),
+            }
+        })
+        .collect()
+}
+
+/// How `game` is written in the config
+fn config_name(game: Game) -> String {
+    game.to_possible_value()
+        .map_or_else(|| game.to_string(), |value| value.get_name().to_string())
+}
+
+/// `[profiles.<name>]` tables for the servers not in `config` already, named after their game
+/// and address
+fn profiles(found: &[Found], config: &Config) -> String {
+    let mut names: BTreeSet<String> = config.profiles.keys().cloned().collect();
+    let mut text = String::new();
+    for server in found {
+        let known = config.profiles.values().any(|profile| {
+            profile.ip.as_deref() == Some(&server.ip.to_string())
+                && profile.port == Some(server.port)
+        });
+        if known {
+            continue;
+        }
+        let kind = server.game.map_or_else(|| "rcon".to_string(), config_name);
+        let host = server.ip.to_string().replace('.', "-");
+        let mut name = format!("{}-{}", kind, host);
+        if names.contains(&name) {
+            name = format!("{}-{}", name, server.port);
+        }
+        let _ = writeln!(text, "\n[profiles.{}]", name);
+        let _ = writeln!(text, "ip = \"{}\"", server.ip);
+        let _ = writeln!(text, "port = {}", server.port);
+        if let Some(game) = server.game {
+            let _ = writeln!(text, "game = \"{}\"", config_name(game));
+        }
+        names.insert(name);
+    }
+    text
+}
+
+/// Append `text` to the config at `path`, creating it if needed
+fn save(path: &PathBuf, text: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(text.as_bytes())
+}
+
+/// Scan the network for open RCON ports, list what answered, and offer to save them as profiles
+pub fn run(args: &Args, discover: &DiscoverArgs) -> Result<(), RconError> {
+    let hosts = discover.network.hosts();
+    let queue = Mutex::new(hosts);
+    let found = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..discover.parallel {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+                let ip = match next {
+                    Some(ip) => ip,
+                    None => break,
+                };
+                let servers = scan(ip, discover);
+                found
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .extend(servers);
+            });
+        }
+    });
+    let mut found = found.into_inner().unwrap_or_else(|e| e.into_inner());
+    found.sort_by_key(|server| (server.ip, server.port));
+
+    if found.is_empty() {
+        eprintln!("No open RCON ports found");
+        return Ok(());
+    }
+    let table = Table {
+        header: vec!["address", "port", "game", "name"],
+        rows: found
+            .iter()
+            .map(|server| {
+                vec![
+                    server.ip.to_string(),
+                    server.port.to_string(),
+                    server
+                        .game
+                        .map_or_else(|| "?".to_string(), |g| g.to_string()),
+                    match &server.name {
+                        // Servers choose their names, which may hold terminal escapes
+                        Some(name) if !args.raw_output => sanitize(name).into_owned(),
+                        name => name.clone().unwrap_or_default(),
+                    },
+                ]
+            })
+            .collect(),
+    };
+    for line in table.render(TableStyle::Plain, table::terminal_width()) {
+        println!("{}", line);
+    }
+
+    let path = match args.config.clone().or_else(Config::default_path) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let config = Config::load(args.config.as_deref()).map_err(|e| {
+        eprintln!("{}", e);
+        RconError::TaskFailed
+    })?;
+    let text = profiles(&found, &config);
+    if text.is_empty() || !(discover.save || table::stdout_is_terminal()) {
+        return Ok(());
+    }
+    if !discover.save {
+        print!("Save them as profiles in {}? [y/N] ", path.display());
+        let _ = io::stdout().flush();
+        let mut answer = String::new();
+        let _ = io::stdin().read_line(&mut answer);
+        if !matches!(answer.trim(), "y" | "yes" | "Y" | "YES") {
+            return Ok(());
+        }
+    }
+    save(&path, &text).map_err(|e| {
+        eprintln!("Unable to write {}: {}", path.display(), e);
+        RconError::TaskFailed
+    })?;
+    println!("Saved to {}", path.display());
+    Ok(())
+}
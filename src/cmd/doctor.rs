@@ -0,0 +1,324 @@
+use crate::{DoctorArgs, Game, Profile, Protocol, Rcon, RconError};
+use std::{
+    env,
+    io::{self, Read},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+const STAGES: [&str; 6] = [
+    "DNS",
+    "TCP connect",
+    "Banner",
+    "Auth",
+    "Test command",
+    "Multi-packet",
+];
+
+/// How long the TCP stage waits for the server to accept
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the banner stage waits for the server to say something first
+const BANNER_WAIT: Duration = Duration::from_millis(750);
+
+/// Packets' size, type and ID fields and the two NULs after the body
+const PACKET_OVERHEAD: usize = 10;
+
+/// The stages run so far, printed as they finish
+struct Diagnosis {
+    stage: usize,
+}
+
+impl Diagnosis {
+    fn line(&self, status: &str, detail: &str) {
+        let line = format!("[{:^4}] {:<12} {}", status, STAGES[self.stage], detail);
+        println!("{}", line.trim_end());
+    }
+
+    fn causes(causes: &[&str]) {
+        if !causes.is_empty() {
+            println!("       Likely causes:");
+        }
+        for cause in causes {
+            println!("       - {}", cause);
+        }
+    }
+
+    fn ok(&mut self, detail: impl AsRef<str>) {
+        self.line("ok", detail.as_ref());
+        self.stage += 1;
+    }
+
+    /// A stage that passed with something looking off, the next stages may tell more
+    fn warn(&mut self, detail: impl AsRef<str>, causes: &[&str]) {
+        self.line("warn", detail.as_ref());
+        Diagnosis::causes(causes);
+        self.stage += 1;
+    }
+
+    /// End the diagnosis at this stage, skipping the rest
+    fn fail(mut self, detail: impl AsRef<str>, causes: &[&str]) -> RconError {
+        self.line("FAIL", detail.as_ref());
+        Diagnosis::causes(causes);
+        for stage in self.stage + 1..STAGES.len() {
+            self.stage = stage;
+            self.line("skip", "");
+        }
+        RconError::TaskFailed
+    }
+}
+
+fn ms(elapsed: Duration) -> String {
+    format!("{:.1} ms", elapsed.as_secs_f64() * 1000.0)
+}
+
+/// A command printing more than one packet holds, where the game has one
+fn big_command(game: Game) -> &'static str {
+    match game {
+        Game::Source => "cvarlist",
+        Game::Factorio => "/help",
+        _ => "help",
+    }
+}
+
+/// Failures connecting over TCP, as [`RconError::hint`] tells them apart
+fn connect_causes(e: &io::Error) -> &'static [&'static str] {
+    match e.kind() {
+        io::ErrorKind::ConnectionRefused => &[
+            "RCON is disabled on the server, or not started yet",
+            "the port is wrong, RCON often has its own port next to the game's",
+            "the server only listens on another interface, like 127.0.0.1",
+        ],
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => &[
+            "a firewall or the hosting provider is dropping connections to the port",
+            "the address is wrong or the machine is off",
+        ],
+        _ => &["there's no route to the server, check the address and this machine's network"],
+    }
+}
+
+/// Connect and log in step by step, printing which step failed and what usually causes it
+pub fn run(profile: &Profile, args: &DoctorArgs) -> Result<(), RconError> {
+    let mut diagnosis = Diagnosis { stage: 0 };
+    println!(
+        "Diagnosing {}:{}{}",
+        profile.ip,
+        profile.port,
+        profile
+            .name
+            .as_ref()
+            .map_or(String::new(), |name| format!(" (profile {})", name))
+    );
+
+    let started = Instant::now();
+    let addrs: Vec<SocketAddr> = match (profile.ip.as_str(), profile.port).to_socket_addrs() {
+        Ok(addrs) => addrs.collect(),
+        Err(e) => {
+            return Err(diagnosis.fail(
+                format!("{} didn't resolve: {}", profile.ip, e),
+                &[
+                    "the hostname is misspelled",
+                    "this machine's DNS server can't be reached",
+                ],
+            ))
+        }
+    };
+    let addr = match addrs.first() {
+        Some(addr) => *addr,
+        None => {
+            return Err(diagnosis.fail(
+                format!("{} resolved to no addresses", profile.ip),
+                &["the hostname has no A or AAAA record"],
+            ))
+        }
+    };
+    let shown: Vec<String> = addrs.iter().map(|a| a.ip().to_string()).collect();
+    diagnosis.ok(format!(
+        "{} is {} ({})",
+        profile.ip,
+        shown.join(", "),
+        ms(started.elapsed())
+    ));
+
+    let started = Instant::now();
+    let mut conn = match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+        Ok(conn) => conn,
+        Err(e) => {
+            let detail = format!("{} after {}: {}", addr, ms(started.elapsed()), e);
+            return Err(diagnosis.fail(detail, connect_causes(&e)));
+        }
+    };
+    diagnosis.ok(format!("{} accepted in {}", addr, ms(started.elapsed())));
+
+    let started = Instant::now();
+    let mut banner = [0; 256];
+    let _ = conn.set_read_timeout(Some(BANNER_WAIT));
+    match (conn.read(&mut banner), profile.protocol) {
+        (Ok(0), _) => {
+            return Err(diagnosis.fail(
+                "the server closed the connection before the login",
+                &[
+                    "the server only takes RCON from some addresses and this isn't one",
+                    "this address was banned after too many wrong passwords",
+                    "the server has as many RCON sessions open as it allows",
+                ],
+            ))
+        }
+        (Ok(n), Protocol::Source) => diagnosis.warn(
+            format!(
+                "the server sent {} bytes before the login, {:?}",
+                n,
+                String::from_utf8_lossy(&banner[..n]).trim()
+            ),
+            &[
+                "it's a telnet console, like 7 Days to Die's, try --protocol telnet",
+                "the port belongs to another service, like the game itself or a web panel",
+            ],
+        ),
+        (Ok(n), Protocol::Telnet) => diagnosis.ok(format!(
+            "{:?} after {}",
+            String::from_utf8_lossy(&banner[..n]).trim(),
+            ms(started.elapsed())
+        )),
+        (Err(e), Protocol::Source) if rustcon_core::is_timeout(&e) => {
+            diagnosis.ok("nothing before the login, as Source RCON servers do")
+        }
+        (Err(e), Protocol::Telnet) if rustcon_core::is_timeout(&e) => diagnosis.warn(
+            format!(
+                "nothing in {}, telnet consoles ask for the password first",
+                ms(BANNER_WAIT)
+            ),
+            &["it's a Source RCON port, try --protocol source"],
+        ),
+        (Err(e), _) => {
+            return Err(diagnosis.fail(
+                format!("reading failed: {}", e),
+                &["the server reset the connection, it may be restarting"],
+            ))
+        }
+    }
+    drop(conn);
+
+    let started = Instant::now();
+    let mut rcon = match Rcon::new(&profile.client()) {
+        Ok(rcon) => rcon,
+        Err(e) => {
+            let detail = e.hint().unwrap_or_else(|| format!("{:?}", e));
+            return Err(diagnosis.fail(
+                format!("a second connection failed: {}", detail),
+                &["the server takes one connection at a time and is still closing the first"],
+            ));
+        }
+    };
+    let login = if profile.password.is_some() || env::var_os("RUSTCON_PASS").is_some() {
+        rcon.login_unattended()
+    } else {
+        rcon.login(&mut crate::prompt_password)
+    };
+    if login.is_err() {
+        return Err(diagnosis.fail(
+            "the server didn't accept the password",
+            &[
+                "the password is wrong, check the profile's `password`, RUSTCON_PASS and the \
+                 server's RCON password setting",
+                "this address was banned after too many wrong passwords, like SRCDS does with \
+                 sv_rcon_maxfailures, wait or restart the server",
+                "the server drops logins from addresses not on its RCON allowlist",
+            ],
+        ));
+    }
+    let game = rcon.game();
+    diagnosis.ok(format!(
+        "logged in to a {} server in {}",
+        game,
+        ms(started.elapsed())
+    ));
+
+    let cmd = args
+        .cmd
+        .as_deref()
+        .or_else(|| game.profile().players_command())
+        .unwrap_or("");
+    let started = Instant::now();
+    match rcon.send_cmd(cmd) {
+        Ok(response) => {
+            let bytes: usize = response.iter().map(|p| p.body().len()).sum();
+            diagnosis.ok(format!(
+                "{:?} answered {} bytes in {}",
+                cmd,
+                bytes,
+                ms(started.elapsed())
+            ))
+        }
+        Err(RconError::NotAllowed) => {
+            return Err(diagnosis.fail(
+                format!("{:?} isn't in --allow-commands", cmd),
+                &["pick an allowed command with --cmd"],
+            ))
+        }
+        Err(RconError::Timeout) => {
+            return Err(diagnosis.fail(
+                format!("{:?} wasn't answered", cmd),
+                &[
+                    "the server takes commands but only answers once its world is loaded",
+                    "the game is wrong, so its quirks aren't worked around, pick it with --game",
+                ],
+            ))
+        }
+        Err(e) if e.is_disconnect() => {
+            return Err(diagnosis.fail(
+                format!("the session was lost sending {:?}: {:?}", cmd, e),
+                &[
+                    "the server closes sessions it doesn't like the packets of, the game may be \
+                     wrong, pick it with --game",
+                    "the server crashed or is restarting",
+                ],
+            ))
+        }
+        Err(e) => {
+            return Err(diagnosis.fail(
+                format!("{:?} failed: {:?}", cmd, e),
+                &["the game is wrong, so its packets aren't read right, pick it with --game"],
+            ))
+        }
+    }
+
+    let cmd = args.big_cmd.as_deref().unwrap_or_else(|| big_command(game));
+    let started = Instant::now();
+    let response = match rcon.send_cmd(cmd) {
+        Ok(response) => response,
+        Err(e) => {
+            return Err(diagnosis.fail(
+                format!("{:?} failed after {}: {:?}", cmd, ms(started.elapsed()), e),
+                &[
+                    "the server splits long responses in a way rustcon doesn't expect, try \
+                     another --game or --protocol-strict to see where it strays",
+                    "the response takes longer than the read timeout to arrive",
+                ],
+            ))
+        }
+    };
+    let bytes: usize = response.iter().map(|p| p.body().len()).sum();
+    let full = game.profile().max_packet_size() - PACKET_OVERHEAD;
+    match response.len() {
+        1 if bytes >= full => diagnosis.warn(
+            format!(
+                "{:?} answered exactly one full packet, {} bytes",
+                cmd, bytes
+            ),
+            &["the server cuts long responses off instead of sending the rest"],
+        ),
+        1 => diagnosis.ok(format!(
+            "{:?} fit in one packet of {} bytes, pick a longer one with --big-cmd to test \
+             splitting",
+            cmd, bytes
+        )),
+        n => diagnosis.ok(format!(
+            "{:?} answered {} bytes in {} packets, put back together in {}",
+            cmd,
+            bytes,
+            n,
+            ms(started.elapsed())
+        )),
+    }
+    Ok(())
+}
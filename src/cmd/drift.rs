@@ -0,0 +1,254 @@
+/*
+ * Unified diffs of a command's responses, run twice on one server or once on each of two, to
+ * spot config drift between restarts or between fleet members.
+ */
+
+use super::{connect, exec};
+use crate::{duration::format_duration, systemd, Args, DiffArgs, Profile, Rcon, RconError};
+use std::collections::VecDeque;
+
+/// Most cells of the table lining up the lines that changed, past which the changed lines are
+/// shown as all removed and then all added
+const MAX_CELLS: usize = 16 * 1024 * 1024;
+
+/// Commands the shell remembers the last response to for `:diff`
+const REMEMBERED: usize = 32;
+
+/// A line of the edit from one response to the other
+enum Edit<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// The shortest edit from `old` to `new`, from their longest common subsequence once the lines
+/// they start and end with alike are set aside
+fn edits<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Edit<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut edits: Vec<Edit> = old[..prefix].iter().map(|l| Edit::Same(l)).collect();
+    let (mut i, mut j) = (0, 0);
+    if (a.len() + 1) * (b.len() + 1) <= MAX_CELLS {
+        // `lcs[i * w + j]` is the longest common subsequence of `a[i..]` and `b[j..]`
+        let w = b.len() + 1;
+        let mut lcs = vec![0u32; (a.len() + 1) * w];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i * w + j] = if a[i] == b[j] {
+                    lcs[(i + 1) * w + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * w + j].max(lcs[i * w + j + 1])
+                };
+            }
+        }
+        while i < a.len() && j < b.len() {
+            if a[i] == b[j] {
+                edits.push(Edit::Same(a[i]));
+                i += 1;
+                j += 1;
+            } else if lcs[(i + 1) * w + j] >= lcs[i * w + j + 1] {
+                edits.push(Edit::Removed(a[i]));
+                i += 1;
+            } else {
+                edits.push(Edit::Added(b[j]));
+                j += 1;
+            }
+        }
+    }
+    edits.extend(a[i..].iter().map(|l| Edit::Removed(l)));
+    edits.extend(b[j..].iter().map(|l| Edit::Added(l)));
+    edits.extend(old[old.len() - suffix..].iter().map(|l| Edit::Same(l)));
+    edits
+}
+
+/// A unified diff of two responses with `context` lines around each change, or `None` when
+/// their lines are the same
+pub fn unified(
+    old: &str,
+    new: &str,
+    old_label: &str,
+    new_label: &str,
+    context: usize,
+) -> Option<String> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let edits = edits(&old, &new);
+    let changed: Vec<usize> = (0..edits.len())
+        .filter(|&i| !matches!(edits[i], Edit::Same(_)))
+        .collect();
+    if changed.is_empty() {
+        return None;
+    }
+
+    // Lines of each response before every edit, and at the end
+    let mut at = Vec::with_capacity(edits.len() + 1);
+    let (mut o, mut n) = (0, 0);
+    for edit in &edits {
+        at.push((o, n));
+        match edit {
+            Edit::Same(_) => {
+                o += 1;
+                n += 1;
+            }
+            Edit::Removed(_) => o += 1,
+            Edit::Added(_) => n += 1,
+        }
+    }
+    at.push((o, n));
+
+    let mut out = format!("--- {}\n+++ {}\n", old_label, new_label);
+    let mut k = 0;
+    while k < changed.len() {
+        let start = changed[k].saturating_sub(context);
+        let mut end = changed[k];
+        // Changes whose context would touch share a hunk
+        while k + 1 < changed.len() && changed[k + 1] <= end + 2 * context + 1 {
+            k += 1;
+            end = changed[k];
+        }
+        k += 1;
+        let stop = (end + context + 1).min(edits.len());
+
+        let ((os, ns), (oe, ne)) = (at[start], at[stop]);
+        // An empty side is numbered by the line before it, as `diff -u` does
+        let first = |start: usize, count: usize| if count == 0 { start } else { start + 1 };
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            first(os, oe - os),
+            oe - os,
+            first(ns, ne - ns),
+            ne - ns
+        ));
+        for edit in &edits[start..stop] {
+            let (sign, line) = match edit {
+                Edit::Same(line) => (' ', line),
+                Edit::Removed(line) => ('-', line),
+                Edit::Added(line) => ('+', line),
+            };
+            out.push(sign);
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    Some(out)
+}
+
+/// Where and when a response came from, for the diff's headers
+fn label(source: &str) -> String {
+    format!(
+        "{}\t{}",
+        source,
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+    )
+}
+
+/// The last responses to the shell's most recent commands, for `:diff` to compare with
+#[derive(Default)]
+pub struct Responses(VecDeque<(String, String, String)>);
+
+impl Responses {
+    /// Remember `response` as the latest to `cmd`, forgetting the least recent command when
+    /// there are too many
+    pub fn remember(&mut self, cmd: &str, response: String) {
+        self.0.retain(|(c, _, _)| c != cmd);
+        if self.0.len() == REMEMBERED {
+            self.0.pop_front();
+        }
+        self.0.push_back((cmd.to_string(), label(cmd), response));
+    }
+
+    /// Remember `response` to `cmd`, returning a diff from the one before it, or `None` when
+    /// there wasn't one
+    pub fn diff(&mut self, cmd: &str, response: String) -> Option<Option<String>> {
+        let last = self.0.iter().find(|(c, _, _)| c == cmd).cloned();
+        let diff =
+            last.map(|(_, old_label, old)| unified(&old, &response, &old_label, &label(cmd), 3));
+        self.remember(cmd, response);
+        diff
+    }
+}
+
+fn fetch(rcon: &mut Rcon, profile: &Profile, cmd: &str) -> Result<(String, String), RconError> {
+    super::check(profile, rcon.game(), cmd)?;
+    let response = exec(rcon, profile, cmd)?;
+    let server = match &profile.name {
+        Some(name) => name.clone(),
+        None => format!("{}:{}", profile.ip, profile.port),
+    };
+    Ok((label(&server), response.iter().map(|p| p.body()).collect()))
+}
+
+/// Run the command `--interval` apart on one server, or on each of two `--profiles`, and print
+/// a unified diff of the responses
+///
+/// Fails when they differ, as `diff` does, so scripts can watch for drift.
+pub fn run(args: &Args, profile: &Profile, diff: &DiffArgs) -> Result<(), RconError> {
+    let cmd = diff.command.join(" ");
+    let ((old_label, old), (new_label, new)) = match diff.profiles.as_slice() {
+        [] => {
+            let mut rcon = connect(profile)?;
+            let first = fetch(&mut rcon, profile, &cmd)?;
+            eprintln!("Running it again in {}", format_duration(diff.interval));
+            if systemd::sleep(&systemd::stop_flag(), diff.interval) {
+                return Err(RconError::TaskFailed);
+            }
+            (first, fetch(&mut rcon, profile, &cmd)?)
+        }
+        [a, b] => {
+            let fetch_from = |name: &str| {
+                let profile = args.resolve_named(name).map_err(|e| {
+                    eprintln!("{}", e);
+                    RconError::TaskFailed
+                })?;
+                fetch(&mut connect(&profile)?, &profile, &cmd)
+            };
+            (fetch_from(a)?, fetch_from(b)?)
+        }
+        _ => {
+            eprintln!("--profiles takes the two profiles to compare");
+            return Err(RconError::TaskFailed);
+        }
+    };
+
+    match unified(&old, &new, &old_label, &new_label, diff.context) {
+        Some(diff) => {
+            print!("{}", diff);
+            Err(RconError::TaskFailed)
+        }
+        None => {
+            println!("The responses are the same");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_show_changed_lines_in_context() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+        assert_eq!(
+            unified(old, new, "old", "new", 1).unwrap(),
+            "--- old\n+++ new\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -10,1 +10,2 @@\n j\n+k\n"
+        );
+        // Changes close enough for their context to touch share a hunk
+        let diff = unified(old, new, "old", "new", 4).unwrap();
+        assert_eq!(diff.matches("@@ -").count(), 1);
+        assert!(unified(old, old, "old", "new", 3).is_none());
+        assert_eq!(
+            unified("", "x\n", "old", "new", 3).unwrap(),
+            "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+x\n"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use super::{
+    connect, fleet,
+    report::{csv_row, Report},
+    targets,
+    template::{self, Template},
+    yaml::to_yaml,
+};
+use crate::{table::Table, Args, ExecArgs, OutputFormat, Profile, Rcon, RconError};
+use serde_json::{json, Map, Value};
+use std::{collections::BTreeMap, sync::Mutex, thread};
+
+/// Run the command on one server, or broadcast it with `--profiles` and `--all`
+pub fn run(args: &Args, main: &Profile, exec: &ExecArgs) -> Result<(), RconError> {
+    if !exec.all && exec.profiles.is_empty() && args.group.is_none() {
+        return single(main, exec, args.format);
+    }
+    let template = exec.template.as_ref();
+    if let (None, OutputFormat::Ansible | OutputFormat::Kv) = (template, args.format) {
+        eprintln!("Broadcasts print text, CSV or YAML, run one server at a time for --format");
+        return Err(RconError::TaskFailed);
+    }
+
+    let servers = if exec.all {
+        fleet(args, main)?
+    } else {
+        let mut profiles = BTreeMap::new();
+        for name in targets(args, &exec.profiles)? {
+            let profile = args.resolve_named(&name).map_err(|e| {
+                eprintln!("{}", e);
+                RconError::TaskFailed
+            })?;
+            profiles.insert(name, profile);
+        }
+        profiles
+    };
+    broadcast(
+        servers,
+        &exec.command.join(" "),
+        usize::from(exec.parallel),
+        args.format,
+        template,
+    )
+}
+
+/// Run one command and report its response
+fn single(profile: &Profile, args: &ExecArgs, format: OutputFormat) -> Result<(), RconError> {
+    let cmd = args.command.join(" ");
+    let mut facts = Map::new();
+    facts.insert(
+        "server".to_string(),
+        json!(format!("{}:{}", profile.ip, profile.port)),
+    );
+
+    let mut game = None;
+    let response = connect(profile).and_then(|mut rcon| {
+        game = Some(rcon.game());
+        facts.insert("game".to_string(), json!(rcon.game().to_string()));
+        super::send_checked(&mut rcon, profile, &cmd)
+    });
+    if let Some(template) = &args.template {
+        let response = response.map(|r| r.iter().map(|p| p.body()).collect::<String>());
+        let result = template::result(profile, game, &cmd, &response);
+        return print_template(template, &result).and(response.map(|_| ()));
+    }
+    let report = match &response {
+        Ok(response) => {
+            let stdout: String = response.iter().map(|p| p.body()).collect();
+            Report {
+                failed: false,
+                // Without a pattern, like Ansible's own command module, any command counts
+                changed: args
+                    .changed_when
+                    .as_ref()
+                    .is_none_or(|regex| regex.is_match(&stdout)),
+                msg: format!("`{}` answered", cmd),
+                table: game
+                    .and_then(|game| Table::parse(game, &cmd, &stdout))
+                    .map(|table| sorted(table, profile)),
+                stdout: Some(stdout),
+                facts,
+            }
+        }
+        Err(e) => Report {
+            failed: true,
+            changed: false,
+            msg: format!("`{}` failed: {:?}", cmd, e),
+            stdout: None,
+            facts,
+            table: None,
+        },
+    };
+    report.print(format);
+    response.map(|_| ())
+}
+
+/// The table sorted by `--sort`, or as it was when it has no such column
+fn sorted(mut table: Table, profile: &Profile) -> Table {
+    if let Some(key) = &profile.sort {
+        if let Err(e) = table.sort(key) {
+            eprintln!("{}", e);
+        }
+    }
+    table
+}
+
+/// Print the template filled in from a command's result, failing when it can't be
+fn print_template(template: &Template, result: &Value) -> Result<(), RconError> {
+    match template.render(result) {
+        Ok(line) => {
+            println!("{}", line);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("Unable to fill in --template, {}", e);
+            Err(RconError::TaskFailed)
+        }
+    }
+}
+
+/// Run `cmd` on up to `parallel` servers at a time, printing each response line as it arrives
+/// prefixed with `[profile]`, then a summary of which servers failed
+///
+/// As CSV or YAML, the responses are printed once every server is done instead, a row or entry
+/// each, and the summary goes to stderr. With a `template`, it's printed filled in for each
+/// server as it's done, and the summary goes to stderr too.
+fn broadcast(
+    targets: BTreeMap<String, Profile>,
+    cmd: &str,
+    parallel: usize,
+    format: OutputFormat,
+    template: Option<&Template>,
+) -> Result<(), RconError> {
+    let total = targets.len();
+    let queue = Mutex::new(targets.into_iter());
+    let results = Mutex::new(BTreeMap::new());
+
+    thread::scope(|scope| {
+        for _ in 0..parallel.min(total) {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+                let (name, profile) = match next {
+                    Some(target) => target,
+                    None => break,
+                };
+                let mut game = None;
+                let mut response =
+                    Rcon::connect_unattended(&profile.client()).and_then(|mut rcon| {
+                        game = Some(rcon.game());
+                        super::send_checked(&mut rcon, &profile, cmd)
+                            .map(|response| response.iter().map(|p| p.body()).collect::<String>())
+                    });
+                if let Some(template) = template {
+                    let result = template::result(&profile, game, cmd, &response);
+                    if let (Err(e), Ok(_)) = (print_template(template, &result), &response) {
+                        response = Err(e);
+                    }
+                }
+                match &response {
+                    _ if template.is_some() || format != OutputFormat::Text => {}
+                    Ok(text) => {
+                        for line in text.lines() {
+                            println!("[{}] {}", name, line);
+                        }
+                    }
+                    Err(e) => eprintln!("[{}] failed: {:?}", name, e),
+                }
+                results
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(name, response);
+            });
+        }
+    });
+
+    let results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+    let failed = results.values().filter(|r| r.is_err()).count();
+    if template.is_some() {
+        eprintln!("{} of {} servers succeeded", total - failed, total);
+        return if failed == 0 {
+            Ok(())
+        } else {
+            Err(RconError::TaskFailed)
+        };
+    }
+    let outcome = |result: &Result<String, RconError>| match result {
+        Ok(text) => (false, text.clone()),
+        Err(e) => (true, format!("{:?}", e)),
+    };
+    if format == OutputFormat::Csv {
+        println!("{}", csv_row(&["profile", "failed", "response"]));
+        for (name, result) in &results {
+            let (failed, response) = outcome(result);
+            println!(
+                "{}",
+                csv_row(&[name.as_str(), &failed.to_string(), &response])
+            );
+        }
+        eprintln!("{} of {} servers succeeded", total - failed, total);
+    } else if format == OutputFormat::Yaml {
+        let servers: Map<String, Value> = results
+            .iter()
+            .map(|(name, result)| {
+                let (failed, response) = outcome(result);
+                (
+                    name.clone(),
+                    json!({ "failed": failed, "response": response }),
+                )
+            })
+            .collect();
+        print!("{}", to_yaml(&Value::Object(servers)));
+        eprintln!("{} of {} servers succeeded", total - failed, total);
+    } else {
+        println!("{} of {} servers succeeded", total - failed, total);
+        for (name, result) in &results {
+            match result {
+                Ok(_) => println!("  ok      {}", name),
+                Err(e) => println!("  FAILED  {} ({:?})", name, e),
+            }
+        }
+    }
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(RconError::TaskFailed)
+    }
+}
@@ -0,0 +1,319 @@
+use super::fleet;
+use crate::{logging::Event, systemd, Args, ExporterArgs, Profile, Rcon, RconError, Traffic};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tiny_http::{Header, Response, Server};
+
+/// Latest poll results of one server
+#[derive(Default)]
+struct Sample {
+    up: bool,
+    players: Option<usize>,
+    stats: Vec<(&'static str, f64)>,
+    /// Seconds each command took to answer
+    latency: BTreeMap<String, f64>,
+    connections: u64,
+    connection_failures: u64,
+    command_errors: u64,
+    /// Of every session opened so far
+    traffic: Traffic,
+}
+
+type Samples = Arc<Mutex<BTreeMap<String, Sample>>>;
+
+/// Commands configured for the profile, or the game's player list and stats commands
+fn commands(profile: &Profile, rcon: &Rcon) -> Vec<String> {
+    profile.metrics_commands.clone().unwrap_or_else(|| {
+        let game = rcon.game().profile();
+        game.players_command()
+            .into_iter()
+            .chain(game.stats_command())
+            .map(String::from)
+            .collect()
+    })
+}
+
+fn update<F: FnOnce(&mut Sample)>(samples: &Samples, name: &str, f: F) {
+    let mut samples = samples.lock().unwrap_or_else(|e| e.into_inner());
+    f(samples.entry(name.to_string()).or_default());
+}
+
+/// Poll one server until `stop` is raised, keeping its session open between polls
+fn collect(
+    name: String,
+    profile: Profile,
+    interval: Duration,
+    samples: Samples,
+    stop: &AtomicBool,
+) {
+    let mut session: Option<Rcon> = None;
+    // Traffic of the sessions lost so far, so the counters keep counting up across reconnects
+    let mut lost = Traffic::default();
+    while !stop.load(Ordering::Relaxed) {
+        let started = Instant::now();
+
+        if session.is_none() {
+            match Rcon::connect_unattended(&profile.client()) {
+                Ok(rcon) => {
+                    session = Some(rcon);
+                    update(&samples, &name, |s| s.connections += 1);
+                }
+                Err(e) => {
+                    Event::warn("Unable to connect")
+                        .profile(&name)
+                        .reason(e)
+                        .json_only()
+                        .log();
+                    update(&samples, &name, |s| {
+                        s.connection_failures += 1;
+                        s.up = false;
+                    })
+                }
+            }
+        }
+
+        if let Some(rcon) = session.as_mut() {
+            let game = rcon.game().profile();
+            let mut up = true;
+            let mut players = None;
+            let mut stats = Vec::new();
+            let mut latency = BTreeMap::new();
+            for cmd in commands(&profile, rcon) {
+                let sent = Instant::now();
+                let response: String = match rcon.send_cmd(&cmd) {
+                    Ok(response) => response.iter().map(|p| p.body()).collect(),
+                    Err(e) => {
+                        Event::warn("Poll failed")
+                            .profile(&name)
+                            .command(&cmd)
+                            .latency(sent.elapsed())
+                            .reason(e)
+                            .json_only()
+                            .log();
+                        up = false;
+                        break;
+                    }
+                };
+                Event::info("Polled")
+                    .profile(&name)
+                    .command(&cmd)
+                    .latency(sent.elapsed())
+                    .json_only()
+                    .log();
+                latency.insert(cmd.clone(), sent.elapsed().as_secs_f64());
+                if game.players_command() == Some(cmd.as_str()) {
+                    players = Some(game.parse_players(&response).len());
+                }
+                stats.extend(game.parse_stats(&response));
+            }
+
+            let mut traffic = lost;
+            traffic += rcon.stats();
+            if !up {
+                // Reconnect on the next poll
+                lost = traffic;
+                session = None;
+            }
+            update(&samples, &name, |s| {
+                s.up = up;
+                s.traffic = traffic;
+                s.command_errors += u64::from(!up);
+                if up {
+                    s.players = players;
+                    s.stats = stats;
+                    s.latency = latency;
+                }
+            });
+        }
+
+        systemd::sleep(stop, interval.saturating_sub(started.elapsed()));
+    }
+}
+
+/// Escape a Prometheus label value
+pub(super) fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render every sample in the Prometheus text exposition format
+fn render(samples: &BTreeMap<String, Sample>) -> String {
+    let mut families: BTreeMap<String, (&str, &str, Vec<String>)> = BTreeMap::new();
+    let mut add = |metric: &str, kind, help, labels: String, value: f64| {
+        families
+            .entry(metric.to_string())
+            .or_insert_with(|| (kind, help, Vec::new()))
+            .2
+            .push(format!("{}{{{}}} {}", metric, labels, value));
+    };
+
+    for (name, sample) in samples {
+        let profile = format!("profile=\"{}\"", label(name));
+        let up = if sample.up { 1.0 } else { 0.0 };
+        add(
+            "rustcon_up",
+            "gauge",
+            "Whether the last poll of the server succeeded",
+            profile.clone(),
+            up,
+        );
+        if let Some(players) = sample.players {
+            add(
+                "rustcon_players",
+                "gauge",
+                "Players online",
+                profile.clone(),
+                players as f64,
+            );
+        }
+        for (stat, value) in &sample.stats {
+            add(
+                &format!("rustcon_server_{}", stat),
+                "gauge",
+                "Performance figure reported by the server",
+                profile.clone(),
+                *value,
+            );
+        }
+        for (cmd, seconds) in &sample.latency {
+            add(
+                "rustcon_command_duration_seconds",
+                "gauge",
+                "Time the server took to answer the command",
+                format!("{},command=\"{}\"", profile, label(cmd)),
+                *seconds,
+            );
+        }
+        add(
+            "rustcon_connections_total",
+            "counter",
+            "RCON sessions opened",
+            profile.clone(),
+            sample.connections as f64,
+        );
+        add(
+            "rustcon_connection_failures_total",
+            "counter",
+            "Failed attempts to open an RCON session",
+            profile.clone(),
+            sample.connection_failures as f64,
+        );
+        add(
+            "rustcon_command_errors_total",
+            "counter",
+            "Polls that lost the session",
+            profile.clone(),
+            sample.command_errors as f64,
+        );
+        let traffic = &sample.traffic;
+        let counters = [
+            (
+                "packets_sent",
+                "Packets sent to the server",
+                traffic.packets_sent,
+            ),
+            (
+                "packets_received",
+                "Packets received from the server",
+                traffic.packets_received,
+            ),
+            ("bytes_sent", "Bytes sent to the server", traffic.bytes_sent),
+            (
+                "bytes_received",
+                "Bytes received from the server",
+                traffic.bytes_received,
+            ),
+            ("failed_commands", "Commands that failed", traffic.errors),
+            (
+                "rejected_logins",
+                "Passwords the server rejected",
+                traffic.rejected_logins,
+            ),
+        ];
+        for (counter, help, value) in counters {
+            add(
+                &format!("rustcon_{}_total", counter),
+                "counter",
+                help,
+                profile.clone(),
+                value as f64,
+            );
+        }
+    }
+
+    let mut text = String::new();
+    for (metric, (kind, help, lines)) in families {
+        let _ = writeln!(text, "# HELP {} {}", metric, help);
+        let _ = writeln!(text, "# TYPE {} {}", metric, kind);
+        for line in lines {
+            let _ = writeln!(text, "{}", line);
+        }
+    }
+    text
+}
+
+/// Poll every profile and serve the results on `/metrics` until SIGTERM, SIGHUP or SIGINT,
+/// letting polls in progress finish
+pub fn run(args: &Args, main: &Profile, exporter: &ExporterArgs) -> Result<(), RconError> {
+    let fleet = fleet(args, main)?;
+    let bind = format!("{}:{}", exporter.listen_address, exporter.port);
+    let server = Server::http(&bind).map_err(|e| {
+        Event::error(format!("Unable to listen on {}: {}", bind, e))
+            .field("bind", &bind)
+            .log();
+        RconError::TaskFailed
+    })?;
+
+    let stop = systemd::stop_flag();
+    let samples: Samples = Arc::default();
+    let collectors: Vec<_> = fleet
+        .into_iter()
+        .map(|(name, profile)| {
+            let samples = Arc::clone(&samples);
+            let interval = exporter.interval;
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || collect(name, profile, interval, samples, &stop))
+        })
+        .collect();
+    Event::info(format!("Serving metrics on http://{}/metrics", bind))
+        .field("bind", &bind)
+        .log();
+
+    let header =
+        Header::from_bytes("Content-Type", "text/plain; version=0.0.4").expect("valid header");
+    while !stop.load(Ordering::Relaxed) {
+        let request = match server.recv_timeout(systemd::STOP_POLL) {
+            Ok(Some(request)) => request,
+            Ok(None) => continue,
+            Err(e) => {
+                Event::warn(format!("Unable to receive a request: {}", e)).log();
+                continue;
+            }
+        };
+        let response = if request.url() == "/metrics" {
+            let text = render(&samples.lock().unwrap_or_else(|e| e.into_inner()));
+            Response::from_string(text).with_header(header.clone())
+        } else {
+            Response::from_string("Not found").with_status_code(404)
+        };
+        if let Err(e) = request.respond(response) {
+            Event::warn(format!("Unable to send the HTTP response: {}", e)).log();
+        }
+    }
+
+    Event::info("Stopping, finishing the polls in progress").log();
+    for collector in collectors {
+        let _ = collector.join();
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use super::report::Report;
+use crate::{HealthcheckArgs, OutputFormat, Profile, Rcon, RconError};
+use serde_json::{json, Map};
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+};
+
+/// Connect without prompting, run the probe, and compare its response
+fn probe(profile: &Profile, args: &HealthcheckArgs) -> Result<String, String> {
+    let mut rcon = Rcon::connect_unattended(&profile.client()).map_err(|e| match e {
+        RconError::AuthError => "authentication failed".to_string(),
+        e => format!("unable to connect: {:?}", e),
+    })?;
+    let cmd = match &args.cmd {
+        Some(cmd) => cmd,
+        None => return Ok(format!("authenticated to {}", rcon.game())),
+    };
+
+    let response: String = rcon
+        .send_cmd(cmd)
+        .map_err(|e| format!("`{}` failed: {:?}", cmd, e))?
+        .iter()
+        .map(|p| p.body())
+        .collect();
+    match &args.expect_regex {
+        Some(regex) if !regex.is_match(&response) => Err(format!(
+            "`{}` answered {:?}, expected /{}/",
+            cmd,
+            response.trim(),
+            regex
+        )),
+        _ => Ok(format!("`{}` answered", cmd)),
+    }
+}
+
+/// Check the server for container health probes, failing once the timeout is up
+pub fn run(
+    profile: &Profile,
+    args: &HealthcheckArgs,
+    format: OutputFormat,
+) -> Result<(), RconError> {
+    let (tx, rx) = mpsc::channel();
+    let check = (profile.clone(), args.clone());
+    // A hung server can't hold up the probe past its timeout
+    thread::spawn(move || {
+        let _ = tx.send(probe(&check.0, &check.1));
+    });
+
+    let result = match rx.recv_timeout(args.timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => Err("timed out".to_string()),
+        Err(RecvTimeoutError::Disconnected) => Err("the check crashed".to_string()),
+    };
+    let mut facts = Map::new();
+    facts.insert("healthy".to_string(), json!(result.is_ok()));
+    Report {
+        failed: result.is_err(),
+        changed: false,
+        msg: match &result {
+            Ok(status) => format!("healthy: {}", status),
+            Err(reason) => format!("unhealthy: {}", reason),
+        },
+        stdout: None,
+        facts,
+        table: None,
+    }
+    .print(format);
+    result.map(|_| ()).map_err(|_| RconError::TaskFailed)
+}
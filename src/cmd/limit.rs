@@ -0,0 +1,246 @@
+use super::exporter::label;
+use crate::{config::RateLimitConfig, logging::Event};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Window of a `[rate_limit]` without `per`
+const DEFAULT_PER: Duration = Duration::from_secs(60);
+
+/// How often the buckets that have refilled are dropped
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// One client's token bucket and request counts
+struct Client {
+    /// Shown in logs and metrics instead of the token
+    label: String,
+    tokens: f64,
+    updated: Instant,
+    /// When the bucket is full again, from then on it's no different from a new one
+    full_at: Instant,
+    allowed: u64,
+    limited: u64,
+    /// Whether the last request was refused, so a streak of them is logged once
+    limiting: bool,
+}
+
+/// The clients with a bucket, and the counts of those whose bucket was dropped
+struct Clients {
+    by_key: HashMap<String, Client>,
+    /// Requests let through and refused by label, for clients no longer in `by_key`
+    dropped: BTreeMap<String, (u64, u64)>,
+    swept: Instant,
+}
+
+impl Clients {
+    /// Drop the buckets that have refilled by `now`, keeping their counts
+    fn sweep(&mut self, now: Instant) {
+        let dropped = &mut self.dropped;
+        self.by_key.retain(|_, client| {
+            if client.full_at > now {
+                return true;
+            }
+            let count = dropped.entry(client.label.clone()).or_default();
+            count.0 += client.allowed;
+            count.1 += client.limited;
+            false
+        });
+        self.swept = now;
+    }
+}
+
+/// Counts every client's requests to a bridge and refuses those over their rate limit
+///
+/// Each client gets a bucket of `requests` tokens refilled evenly over `per`, so it can burst up
+/// to its limit and then keeps to the average rate. Buckets that have refilled are dropped now
+/// and then, so clients that come and go don't pile up.
+pub struct Limiter {
+    default: Option<RateLimitConfig>,
+    clients: Mutex<Clients>,
+}
+
+impl Limiter {
+    pub fn new(default: Option<RateLimitConfig>) -> Limiter {
+        Limiter {
+            default,
+            clients: Mutex::new(Clients {
+                by_key: HashMap::new(),
+                dropped: BTreeMap::new(),
+                swept: Instant::now(),
+            }),
+        }
+    }
+
+    /// Count a request from the client `key`, returning the seconds until it can retry when it's
+    /// over `limit`, or the default limit without one
+    ///
+    /// The first refusal of a streak is logged with the client's `label`.
+    pub fn check(
+        &self,
+        bridge: &str,
+        key: &str,
+        label: &str,
+        limit: Option<RateLimitConfig>,
+    ) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        if now.duration_since(clients.swept) >= SWEEP_INTERVAL {
+            clients.sweep(now);
+        }
+        let client = clients
+            .by_key
+            .entry(key.to_string())
+            .or_insert_with(|| Client {
+                label: label.to_string(),
+                tokens: f64::MAX,
+                updated: now,
+                full_at: now,
+                allowed: 0,
+                limited: 0,
+                limiting: false,
+            });
+
+        let limit = match limit.or(self.default) {
+            Some(limit) => limit,
+            None => {
+                client.allowed += 1;
+                return Ok(());
+            }
+        };
+        let capacity = f64::from(limit.requests.get());
+        let rate = capacity / limit.per.unwrap_or(DEFAULT_PER).as_secs_f64().max(0.001);
+        let elapsed = now.duration_since(client.updated).as_secs_f64();
+        client.tokens = (client.tokens.min(capacity) + elapsed * rate).min(capacity);
+        client.updated = now;
+
+        if client.tokens >= 1.0 {
+            client.tokens -= 1.0;
+            client.full_at = now + Duration::from_secs_f64((capacity - client.tokens) / rate);
+            client.allowed += 1;
+            client.limiting = false;
+            Ok(())
+        } else {
+            client.limited += 1;
+            if !client.limiting {
+                client.limiting = true;
+                let per = limit.per.unwrap_or(DEFAULT_PER);
+                Event::warn(format!(
+                    "{}: rate limiting {}, over {} requests per {:?}",
+                    bridge, client.label, limit.requests, per
+                ))
+                .field("bridge", bridge)
+                .field("client", &client.label)
+                .field("requests", limit.requests.get())
+                .field("per_secs", per.as_secs_f64())
+                .log();
+            }
+            Err(((1.0 - client.tokens) / rate).ceil() as u64)
+        }
+    }
+
+    /// Request counts by client in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        // Clients sharing a label, like unnamed tokens, are counted together
+        let mut counts: BTreeMap<&str, (u64, u64)> = clients
+            .dropped
+            .iter()
+            .map(|(label, count)| (label.as_str(), *count))
+            .collect();
+        for client in clients.by_key.values() {
+            let count = counts.entry(&client.label).or_default();
+            count.0 += client.allowed;
+            count.1 += client.limited;
+        }
+
+        let mut text = String::new();
+        let mut family = |metric: &str, help: &str, value: fn(&(u64, u64)) -> u64| {
+            let _ = writeln!(text, "# HELP {} {}", metric, help);
+            let _ = writeln!(text, "# TYPE {} counter", metric);
+            for (client, count) in &counts {
+                let _ = writeln!(
+                    text,
+                    "{}{{client=\"{}\"}} {}",
+                    metric,
+                    label(client),
+                    value(count)
+                );
+            }
+        };
+        family(
+            "rustcon_bridge_requests_total",
+            "Requests let through by the rate limits",
+            |count| count.0,
+        );
+        family(
+            "rustcon_bridge_rate_limited_total",
+            "Requests refused for going over a rate limit",
+            |count| count.1,
+        );
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{num::NonZeroU32, thread};
+
+    fn limit(requests: u32, per: Duration) -> Option<RateLimitConfig> {
+        Some(RateLimitConfig {
+            requests: NonZeroU32::new(requests).unwrap(),
+            per: Some(per),
+        })
+    }
+
+    #[test]
+    fn buckets_refill_over_their_window() {
+        let limiter = Limiter::new(limit(2, Duration::from_millis(200)));
+        let check = || limiter.check("test", "peer:a", "a", None);
+        assert_eq!(check(), Ok(()));
+        assert_eq!(check(), Ok(()));
+        // Retrying is due within the window, rounded up to a second
+        assert_eq!(check(), Err(1));
+        thread::sleep(Duration::from_millis(120));
+        assert_eq!(check(), Ok(()));
+        assert_eq!(check(), Err(1));
+        // Clients have buckets of their own
+        assert_eq!(limiter.check("test", "peer:b", "b", None), Ok(()));
+    }
+
+    #[test]
+    fn requests_over_the_limit_are_refused_and_counted() {
+        let limiter = Limiter::new(None);
+        for _ in 0..10 {
+            assert_eq!(limiter.check("test", "peer:a", "a", None), Ok(()));
+        }
+        let token = limit(1, Duration::from_secs(60));
+        assert_eq!(limiter.check("test", "token:t", "grafana", token), Ok(()));
+        assert_eq!(limiter.check("test", "token:t", "grafana", token), Err(60));
+        let metrics = limiter.render();
+        assert!(metrics.contains("rustcon_bridge_requests_total{client=\"a\"} 10"));
+        assert!(metrics.contains("rustcon_bridge_rate_limited_total{client=\"grafana\"} 1"));
+    }
+
+    #[test]
+    fn refilled_buckets_are_dropped_keeping_their_counts() {
+        let limiter = Limiter::new(limit(1, Duration::from_millis(100)));
+        assert_eq!(limiter.check("test", "peer:a", "a", None), Ok(()));
+        assert_eq!(limiter.check("test", "peer:a", "a", None), Err(1));
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(limiter.check("test", "peer:b", "b", None), Ok(()));
+
+        let mut clients = limiter.clients.lock().unwrap();
+        clients.sweep(Instant::now());
+        assert_eq!(clients.by_key.keys().collect::<Vec<_>>(), ["peer:b"]);
+        drop(clients);
+        let metrics = limiter.render();
+        assert!(metrics.contains("rustcon_bridge_requests_total{client=\"a\"} 1"));
+        assert!(metrics.contains("rustcon_bridge_rate_limited_total{client=\"a\"} 1"));
+        // A new bucket starts full, like the dropped one was
+        assert_eq!(limiter.check("test", "peer:a", "a", None), Ok(()));
+    }
+}
@@ -0,0 +1,341 @@
+/*
+ * Orchestration subcommands built on top of an RCON session.
+ */
+
+pub mod announce;
+pub mod attach;
+pub mod auth;
+pub mod backup;
+pub mod bans;
+pub mod bulk;
+pub mod cache;
+pub mod chat;
+pub mod discover;
+pub mod doctor;
+pub mod drift;
+pub mod exec;
+pub mod exporter;
+pub mod healthcheck;
+pub mod limit;
+pub mod ndjson;
+pub mod ping;
+pub mod report;
+pub mod restart;
+pub mod schedule;
+#[cfg(any(feature = "scripting", feature = "lua"))]
+pub mod script;
+#[cfg(feature = "grpc")]
+pub mod serve_grpc;
+pub mod serve_http;
+pub mod serve_ws;
+pub mod stdio_rpc;
+pub mod template;
+pub mod triggers;
+pub mod watch_players;
+pub mod whitelist;
+mod yaml;
+
+use crate::{
+    duration::format_duration, Args, Config, Game, Packet, Pipeline, Profile, Rcon, RconError,
+    Reply,
+};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::Path,
+    process,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Open an authenticated session, reporting failures
+pub fn connect(profile: &Profile) -> Result<Rcon, RconError> {
+    Rcon::connect(&profile.client(), &mut crate::prompt_password).inspect_err(|e| {
+        eprintln!(
+            "Unable to create an RCON session to {}:{}",
+            profile.ip, profile.port
+        );
+        if let Some(hint) = e.hint() {
+            eprintln!("{}", hint);
+        }
+        eprintln!("Run `rustcon doctor` to find out which step fails.");
+    })
+}
+
+/// Refuse a dangerous command unless `--yes` was given, telling why on stderr, for everything
+/// sending commands that weren't typed at the shell's prompt, which asks instead
+pub fn check(profile: &Profile, game: Game, cmd: &str) -> Result<(), RconError> {
+    profile.check_dangerous(game, cmd).map_err(|e| {
+        eprintln!("{}", e);
+        RconError::NotAllowed
+    })
+}
+
+/// Send a command once [`check`] lets it through
+pub fn send_checked(
+    rcon: &mut Rcon,
+    profile: &Profile,
+    cmd: &str,
+) -> Result<Vec<Packet>, RconError> {
+    check(profile, rcon.game(), cmd)?;
+    rcon.send_cmd(cmd)
+}
+
+/// Open a session again for a daemon, without prompting when the password is stored so a rejected
+/// login is reported instead of waiting at a prompt
+pub fn reconnect(profile: &Profile) -> Result<Rcon, RconError> {
+    if profile.password.is_some() || env::var_os("RUSTCON_PASS").is_some() {
+        Rcon::connect_unattended(&profile.client())
+    } else {
+        connect(profile)
+    }
+}
+
+/// Every config profile by name, plus the command line's server as `default` when no profile
+/// was selected, for the bridges serving a whole fleet
+pub fn fleet(args: &Args, main: &Profile) -> Result<BTreeMap<String, Profile>, RconError> {
+    let config = Config::load(args.config.as_deref()).map_err(|e| {
+        eprintln!("{}", e);
+        RconError::TaskFailed
+    })?;
+
+    let mut fleet = BTreeMap::new();
+    for name in config.profiles.keys() {
+        let profile = args.resolve_named(name).map_err(|e| {
+            eprintln!("{}", e);
+            RconError::TaskFailed
+        })?;
+        fleet.insert(name.clone(), profile);
+    }
+    if main.name.is_none() {
+        fleet.insert("default".to_string(), main.clone());
+    }
+    Ok(fleet)
+}
+
+/// Profile names from an option and `--group`, failing unless there's at least one
+pub fn targets(args: &Args, names: &[String]) -> Result<Vec<String>, RconError> {
+    let targets = args.targets(names).map_err(|e| {
+        eprintln!("{}", e);
+        RconError::TaskFailed
+    })?;
+    if targets.is_empty() {
+        eprintln!("No servers to target, list profiles or pick a --group");
+        return Err(RconError::TaskFailed);
+    }
+    Ok(targets)
+}
+
+/// A pooled session, pipelined when the server allows it
+enum Session {
+    /// Commands take turns, holding the session while they wait for their response
+    Serial(Box<Rcon>),
+    Pipelined(Arc<Pipeline>),
+}
+
+impl Session {
+    fn open(profile: &Profile) -> Result<Session, RconError> {
+        Ok(
+            match Rcon::connect_unattended(&profile.client())?.pipeline() {
+                Ok(pipeline) => Session::Pipelined(Arc::new(pipeline)),
+                Err(rcon) => Session::Serial(Box::new(rcon)),
+            },
+        )
+    }
+}
+
+/// A profile and its authenticated session, opened on first use and kept for later requests
+pub struct Pooled {
+    pub profile: Profile,
+    session: Mutex<Option<Session>>,
+    cache: cache::ResponseCache,
+    /// Whether dangerous commands are refused without `--yes`, see [`check`]
+    checked: bool,
+}
+
+impl Pooled {
+    pub fn new(profile: Profile) -> Pooled {
+        Pooled {
+            cache: cache::ResponseCache::new(profile.cache.clone()),
+            profile,
+            session: Mutex::new(None),
+            checked: false,
+        }
+    }
+
+    /// A pooled session refusing dangerous commands unless `--yes` was given, for front ends
+    /// taking commands from other programs
+    pub fn checked(profile: Profile) -> Pooled {
+        Pooled {
+            checked: true,
+            ..Pooled::new(profile)
+        }
+    }
+
+    /// [`exec`](Pooled::exec), reusing the response of the profile's `cache` commands while it's
+    /// fresh
+    ///
+    /// Reused responses aren't sent to the server, so they aren't in the audit log either.
+    pub fn exec_cached(
+        &self,
+        cmd: &str,
+        source: &str,
+    ) -> Result<(Game, String, cache::CacheStatus), RconError> {
+        self.cache.get_or_exec(cmd, || self.exec(cmd, source))
+    }
+
+    /// Send a command over the pooled session, reconnecting once if the server dropped it
+    ///
+    /// Commands from several threads are in flight at once on servers that can be pipelined,
+    /// otherwise they take turns. `source` is who the audit log records as sending it.
+    pub fn exec(&self, cmd: &str, source: &str) -> Result<(Game, String), RconError> {
+        let (game, response) = match self.send(cmd, source) {
+            Err(e) if e.is_disconnect() => self.send(cmd, source)?,
+            result => result?,
+        };
+        Ok((game, response.iter().map(|p| p.body()).collect()))
+    }
+
+    fn check(&self, game: Game, cmd: &str) -> Result<(), RconError> {
+        if self.checked {
+            check(&self.profile, game, cmd)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Send a command once, forgetting the session if it turns out to be lost
+    fn send(&self, cmd: &str, source: &str) -> Result<(Game, Vec<Packet>), RconError> {
+        let mut session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        let current = match session.take() {
+            Some(current) => current,
+            None => Session::open(&self.profile)?,
+        };
+        match current {
+            Session::Serial(mut rcon) => {
+                rcon.set_source(source);
+                let game = rcon.game();
+                let result = self.check(game, cmd).and_then(|_| rcon.send_cmd(cmd));
+                if !matches!(&result, Err(e) if e.is_disconnect()) {
+                    *session = Some(Session::Serial(rcon));
+                }
+                result.map(|response| (game, response))
+            }
+            Session::Pipelined(pipeline) => {
+                // Let other requests send theirs while this one waits
+                *session = Some(Session::Pipelined(Arc::clone(&pipeline)));
+                drop(session);
+                let result = self
+                    .check(pipeline.game(), cmd)
+                    .and_then(|_| pipeline.send_cmd_async_as(cmd, source))
+                    .and_then(Reply::wait);
+                if matches!(&result, Err(e) if e.is_disconnect()) {
+                    let mut session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+                    // Unless another request already replaced it
+                    if matches!(&*session, Some(Session::Pipelined(p)) if Arc::ptr_eq(p, &pipeline))
+                    {
+                        *session = None;
+                    }
+                }
+                result.map(|response| (pipeline.game(), response))
+            }
+        }
+    }
+}
+
+/// Send a command, reconnecting once in case the server dropped the idle session
+pub fn exec(rcon: &mut Rcon, profile: &Profile, cmd: &str) -> Result<Vec<Packet>, RconError> {
+    match rcon.send_cmd(cmd) {
+        Ok(response) => Ok(response),
+        Err(e) if !e.is_disconnect() => Err(e),
+        Err(_) => {
+            *rcon = reconnect(profile)?;
+            rcon.send_cmd(cmd)
+        }
+    }
+}
+
+/// Announce `message` in chat as each warning's time before the end of `left` arrives, then wait
+/// out the rest
+///
+/// `{remaining}` in the message is replaced with the time left. Warnings that have already
+/// passed are skipped.
+pub fn countdown(
+    rcon: &mut Rcon,
+    profile: &Profile,
+    left: Duration,
+    warnings: &[Duration],
+    message: &str,
+) -> Result<(), RconError> {
+    let game = rcon.game();
+    let mut warnings = warnings.to_vec();
+    warnings.sort_unstable_by(|a, b| b.cmp(a));
+    warnings.dedup();
+
+    let deadline = Instant::now() + left;
+    for remaining in warnings.into_iter().filter(|w| *w <= left) {
+        thread::sleep((deadline - remaining).saturating_duration_since(Instant::now()));
+        let message = message.replace("{remaining}", &format_duration(remaining));
+        match game.profile().say_command(&message) {
+            Some(cmd) => {
+                println!("Announcing: {}", message);
+                exec(rcon, profile, &cmd)?;
+            }
+            None => {
+                eprintln!("{} has no chat command, skipping announcements", game);
+                break;
+            }
+        }
+    }
+
+    thread::sleep(deadline.saturating_duration_since(Instant::now()));
+    Ok(())
+}
+
+/// Run a command line through the platform's shell
+pub fn shell_command(line: &str) -> process::Command {
+    if cfg!(windows) {
+        let mut cmd = process::Command::new("cmd");
+        cmd.args(["/C", line]);
+        cmd
+    } else {
+        let mut cmd = process::Command::new("sh");
+        cmd.args(["-c", line]);
+        cmd
+    }
+}
+
+/// Read a file of names or IDs, one per line, skipping blank lines and `#` comments
+pub fn read_list(path: &Path) -> Result<Vec<String>, RconError> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        eprintln!("Unable to read {}: {}", path.display(), e);
+        RconError::TaskFailed
+    })?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Entries to add and remove to bring a server's list in line with a canonical one
+pub struct Diff<'a> {
+    pub add: Vec<&'a str>,
+    pub remove: Vec<&'a str>,
+}
+
+/// Compare entries case-insensitively, as player names and IDs are on every supported server
+pub fn diff<'a>(canonical: &'a [String], current: &'a [String]) -> Diff<'a> {
+    let missing = |list: &'a [String], other: &[String]| -> Vec<&'a str> {
+        list.iter()
+            .filter(|entry| !other.iter().any(|o| o.eq_ignore_ascii_case(entry)))
+            .map(String::as_str)
+            .collect()
+    };
+    Diff {
+        add: missing(canonical, current),
+        remove: missing(current, canonical),
+    }
+}
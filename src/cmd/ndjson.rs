@@ -0,0 +1,80 @@
+use super::Pooled;
+use crate::{Profile, RconError};
+use serde_json::{json, Value};
+use std::{
+    io::{self, BufRead, Write},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+/// Sessions to the server working through requests at once, so a slow command doesn't hold up
+/// the ones pipelined behind it
+const SESSIONS: usize = 4;
+
+/// Responses share stdout between the workers, one line each
+#[derive(Clone)]
+struct Output(Arc<Mutex<io::Stdout>>);
+
+impl Output {
+    fn send(&self, message: Value) {
+        let mut stdout = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = writeln!(stdout, "{}", message);
+        let _ = stdout.flush();
+    }
+}
+
+/// Run one request line, answering with the same `id`
+fn handle(pooled: &Pooled, line: &str) -> Value {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return json!({ "id": null, "ok": false, "error": e.to_string() }),
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let cmd = match request["cmd"].as_str() {
+        Some(cmd) => cmd,
+        None => return json!({ "id": id, "ok": false, "error": "Missing the cmd" }),
+    };
+    match pooled.exec(cmd, "ndjson") {
+        Ok((_, response)) => json!({ "id": id, "ok": true, "response": response }),
+        Err(e) => json!({ "id": id, "ok": false, "error": format!("{:?}", e) }),
+    }
+}
+
+/// Answer `{"id", "cmd"}` lines from stdin with `{"id", "ok", "response" | "error"}` lines until
+/// stdin closes
+///
+/// Requests are spread over a few sessions, so responses can arrive out of order and are matched
+/// to requests by their `id`.
+pub fn run(profile: &Profile) -> Result<(), RconError> {
+    let output = Output(Arc::new(Mutex::new(io::stdout())));
+    let (requests, queue) = mpsc::channel::<String>();
+    let queue = Arc::new(Mutex::new(queue));
+
+    let workers: Vec<_> = (0..SESSIONS)
+        .map(|_| {
+            let (output, queue) = (output.clone(), Arc::clone(&queue));
+            let pooled = Pooled::checked(profile.clone());
+            thread::spawn(move || loop {
+                let line = match queue.lock().unwrap_or_else(|e| e.into_inner()).recv() {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                output.send(handle(&pooled, &line));
+            })
+        })
+        .collect();
+
+    for line in io::stdin().lock().lines() {
+        let line = line.map_err(|_| RconError::TaskFailed)?;
+        if !line.trim().is_empty() && requests.send(line).is_err() {
+            break;
+        }
+    }
+
+    // Answer what's still queued before exiting
+    drop(requests);
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
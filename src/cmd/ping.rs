@@ -0,0 +1,77 @@
+use super::connect;
+use crate::{systemd, PingArgs, Profile, RconError};
+use std::{sync::atomic::Ordering, time::Instant};
+
+/// Milliseconds with the fraction, as `ping` prints them
+fn ms(secs: f64) -> String {
+    format!("{:.3}", secs * 1000.0)
+}
+
+/// Send the command `--count` times over one session, printing each round trip and then the
+/// spread of them, or as far as it got when interrupted
+///
+/// Fails when no command was answered.
+pub fn run(profile: &Profile, args: &PingArgs) -> Result<(), RconError> {
+    let mut rcon = connect(profile)?;
+    let cmd = args.cmd.as_deref().unwrap_or("");
+    let address = format!("{}:{}", profile.ip, profile.port);
+    println!("PING {} ({}) with {:?}", address, rcon.game(), cmd);
+
+    let stop = systemd::stop_flag();
+    let mut times = Vec::new();
+    let mut sent: usize = 0;
+    for seq in 1..=args.count {
+        if seq > 1 && systemd::sleep(&stop, args.interval) {
+            break;
+        }
+        sent += 1;
+        let started = Instant::now();
+        match rcon.send_cmd(cmd) {
+            Ok(response) => {
+                let time = started.elapsed().as_secs_f64();
+                let bytes: usize = response.iter().map(|p| p.body().len()).sum();
+                println!(
+                    "{} bytes from {}: seq={} time={} ms",
+                    bytes,
+                    address,
+                    seq,
+                    ms(time)
+                );
+                times.push(time);
+            }
+            Err(RconError::Timeout) => println!("No answer from {}: seq={}", address, seq),
+            Err(e) if e.is_disconnect() => {
+                eprintln!("Lost the connection: {:?}", e);
+                break;
+            }
+            Err(e) => println!("Failed on {}: seq={} {:?}", address, seq, e),
+        }
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+    }
+
+    println!();
+    println!("--- {} rcon ping statistics ---", address);
+    println!(
+        "{} commands sent, {} answered, {:.0}% unanswered",
+        sent,
+        times.len(),
+        100.0 * (sent - times.len()) as f64 / sent.max(1) as f64
+    );
+    if times.is_empty() {
+        return Err(RconError::TaskFailed);
+    }
+    let min = times.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = times.iter().copied().fold(0.0, f64::max);
+    let avg = times.iter().sum::<f64>() / times.len() as f64;
+    let stddev = (times.iter().map(|t| (t - avg).powi(2)).sum::<f64>() / times.len() as f64).sqrt();
+    println!(
+        "rtt min/avg/max/stddev = {}/{}/{}/{} ms",
+        ms(min),
+        ms(avg),
+        ms(max),
+        ms(stddev)
+    );
+    Ok(())
+}
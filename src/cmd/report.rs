@@ -0,0 +1,140 @@
+use super::yaml::to_yaml;
+use crate::{table::Table, OutputFormat};
+use serde_json::{json, Map, Value};
+
+/// Outcome of a one-shot subcommand, printed for people or for configuration management tools
+pub struct Report {
+    pub failed: bool,
+    /// Whether the command altered the server, always false for read-only checks
+    pub changed: bool,
+    pub msg: String,
+    /// Response of the command that ran, if any
+    pub stdout: Option<String>,
+    /// Extra `ansible_facts`, without their `rustcon_` prefix
+    pub facts: Map<String, Value>,
+    /// What the response parsed into, printed instead of the report with `--format csv` and as
+    /// its `rows` with `--format yaml`
+    pub table: Option<Table>,
+}
+
+/// Quote a CSV field holding a comma, quote or line break, doubling its quotes as RFC 4180 does
+fn csv_field(value: &str) -> String {
+    if value.contains(&[',', '"', '\r', '\n'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// One line of CSV
+pub fn csv_row<S: AsRef<str>>(fields: &[S]) -> String {
+    let fields: Vec<String> = fields.iter().map(|f| csv_field(f.as_ref())).collect();
+    fields.join(",")
+}
+
+/// Quote a value for `key=value` output the way a POSIX shell would read it back
+fn quote(value: &str) -> String {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.,:/@%+".contains(c))
+    {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\"'\"'"))
+    }
+}
+
+impl Report {
+    fn json(&self) -> Value {
+        let facts: Map<String, Value> = self
+            .facts
+            .iter()
+            .map(|(key, value)| (format!("rustcon_{}", key), value.clone()))
+            .collect();
+        let mut body = json!({
+            "changed": self.changed,
+            "failed": self.failed,
+            "rc": u8::from(self.failed),
+            "msg": self.msg,
+            "ansible_facts": facts,
+        });
+        if let Some(stdout) = &self.stdout {
+            body["stdout"] = json!(stdout);
+            body["stdout_lines"] = json!(stdout.lines().collect::<Vec<_>>());
+        }
+        body
+    }
+
+    /// The report's fields and facts that fit in one line of text, in order
+    fn scalars(&self) -> Vec<(String, String)> {
+        let mut body = self.json();
+        let facts = body["ansible_facts"].take();
+        body.as_object()
+            .into_iter()
+            .chain(facts.as_object())
+            .flatten()
+            .filter_map(|(key, value)| {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    Value::Bool(_) | Value::Number(_) => value.to_string(),
+                    // Lists and the emptied facts don't fit on one line
+                    _ => return None,
+                };
+                Some((key.clone(), value))
+            })
+            .collect()
+    }
+
+    /// Print in `format`, text goes to stdout on success and stderr on failure
+    pub fn print(&self, format: OutputFormat) {
+        match format {
+            OutputFormat::Text => {
+                let text = self.stdout.as_deref().unwrap_or(&self.msg);
+                if self.failed {
+                    eprintln!("{}", text);
+                } else {
+                    println!("{}", text);
+                }
+            }
+            OutputFormat::Ansible => println!("{}", self.json()),
+            OutputFormat::Kv => {
+                let pairs: Vec<String> = self
+                    .scalars()
+                    .into_iter()
+                    .map(|(key, value)| format!("{}={}", key, quote(&value)))
+                    .collect();
+                println!("{}", pairs.join(" "));
+            }
+            OutputFormat::Yaml => {
+                let mut body = self.json();
+                if let Some(table) = &self.table {
+                    body["rows"] = table.json();
+                }
+                print!("{}", to_yaml(&body));
+            }
+            OutputFormat::Csv => match &self.table {
+                Some(table) => table.print_csv(),
+                None => {
+                    let (header, row): (Vec<_>, Vec<_>) = self.scalars().into_iter().unzip();
+                    println!("{}", csv_row(&header));
+                    println!("{}", csv_row(&row));
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_they_need_it() {
+        assert_eq!(csv_row(&["Steve", "12"]), "Steve,12");
+        assert_eq!(
+            csv_row(&["Smith, John", "say \"hi\"", "two\nlines", ""]),
+            "\"Smith, John\",\"say \"\"hi\"\"\",\"two\nlines\","
+        );
+    }
+}
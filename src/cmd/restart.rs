@@ -0,0 +1,74 @@
+use super::{connect, countdown, exec};
+use crate::{duration::format_duration, Profile, Rcon, RconError, RestartArgs};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often to check whether the server is back up with `--wait`
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Count down in chat, save the world, stop the server, and optionally wait for it to come back
+pub fn run(profile: &Profile, args: &RestartArgs) -> Result<(), RconError> {
+    let mut rcon = connect(profile)?;
+    let game = rcon.game();
+
+    let lead = args.warn.iter().max().copied().unwrap_or_default();
+    countdown(&mut rcon, profile, lead, &args.warn, &args.message)?;
+
+    if !args.no_save {
+        match game.profile().save_command() {
+            Some(cmd) => {
+                println!("Saving the world...");
+                for p in exec(&mut rcon, profile, cmd)? {
+                    println!("{}", p.body());
+                }
+            }
+            None => eprintln!("{} has no save command, skipping the save", game),
+        }
+    }
+
+    let stop = game.profile().stop_command().ok_or_else(|| {
+        eprintln!("{} has no stop command", game);
+        RconError::Unsupported
+    })?;
+    println!("Stopping the server...");
+    // The server may hang up before it answers
+    let _ = rcon.send_cmd(stop);
+    drop(rcon);
+
+    if args.wait {
+        println!("Waiting for the server to come back up...");
+        if !wait_for_restart(profile, args.wait_timeout) {
+            eprintln!(
+                "The server didn't come back within {}",
+                format_duration(args.wait_timeout)
+            );
+            return Err(RconError::ConnError);
+        }
+        println!("The server is back up");
+    }
+
+    println!("Restart complete");
+    Ok(())
+}
+
+fn port_open(profile: &Profile) -> bool {
+    Rcon::get_conn(&profile.ip, profile.port).is_ok()
+}
+
+/// Wait for the RCON port to close and then reopen, so the old process isn't mistaken for the new
+fn wait_for_restart(profile: &Profile, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline && port_open(profile) {
+        thread::sleep(Duration::from_secs(1));
+    }
+
+    while Instant::now() < deadline {
+        if port_open(profile) {
+            return true;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    false
+}
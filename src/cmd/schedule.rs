@@ -0,0 +1,202 @@
+use super::{connect, exec, reconnect, targets};
+use crate::{
+    cron::Schedule,
+    logfile::LogFile,
+    logging::Event,
+    notify::{Lifecycle, Notifier},
+    systemd, Args, Profile, Rcon, RconError,
+};
+use chrono::{Local, Timelike};
+use serde_json::json;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Parse the profile's `[schedule]` table, reporting every bad expression
+fn load<'a>(profile: &'a Profile, label: &str) -> Result<Vec<(Schedule, &'a str)>, RconError> {
+    if profile.schedule.is_empty() {
+        Event::error(format!(
+            "{}No scheduled commands, add a [profiles.<name>.schedule] table to the config",
+            label
+        ))
+        .log();
+        return Err(RconError::TaskFailed);
+    }
+
+    let mut jobs = Vec::new();
+    let mut valid = true;
+    for (expr, cmd) in &profile.schedule {
+        match Schedule::parse(expr) {
+            Ok(schedule) => jobs.push((schedule, cmd.as_str())),
+            Err(e) => {
+                Event::error(format!("{}Invalid schedule: {}", label, e))
+                    .field("schedule", expr)
+                    .log();
+                valid = false;
+            }
+        }
+    }
+    if valid {
+        Ok(jobs)
+    } else {
+        Err(RconError::TaskFailed)
+    }
+}
+
+/// Sleep until the start of the next minute, returning early with `true` if told to stop
+fn sleep_to_next_minute(stop: &AtomicBool) -> bool {
+    let now = Local::now();
+    let elapsed = Duration::new(now.second() as u64, now.nanosecond() % 1_000_000_000);
+    systemd::sleep(stop, Duration::from_secs(60).saturating_sub(elapsed))
+}
+
+/// Send a scheduled command, opening the session if it isn't connected yet
+fn run_job(rcon: &mut Option<Rcon>, profile: &Profile, cmd: &str) -> Result<String, RconError> {
+    let session = match rcon {
+        Some(session) => session,
+        None => rcon.insert(reconnect(profile)?),
+    };
+    let response = exec(session, profile, cmd).inspect_err(|_| {
+        // Start over with a fresh session next time
+        *rcon = None;
+    })?;
+    Ok(response.iter().map(|p| p.body()).collect())
+}
+
+/// Run the profile's scheduled commands, or every `--group` member's side by side, until
+/// SIGTERM, SIGHUP or SIGINT
+pub fn run(args: &Args, main: &Profile) -> Result<(), RconError> {
+    let stop = systemd::stop_flag();
+    if args.group.is_none() {
+        return serve(main, "", &stop);
+    }
+
+    let mut profiles = Vec::new();
+    for name in targets(args, &[])? {
+        let profile = args.resolve_named(&name).map_err(|e| {
+            Event::error(e.to_string()).log();
+            RconError::TaskFailed
+        })?;
+        profiles.push((format!("{}: ", name), profile));
+    }
+    // Only returns once every profile has given up on a bad schedule
+    thread::scope(|scope| {
+        let servers: Vec<_> = profiles
+            .iter()
+            .map(|(label, profile)| {
+                let stop = &stop;
+                scope.spawn(move || serve(profile, label, stop))
+            })
+            .collect();
+        servers
+            .into_iter()
+            .map(|server| server.join().unwrap_or(Err(RconError::TaskFailed)))
+            .fold(Ok(()), Result::and)
+    })
+}
+
+/// Run scheduled commands until `stop` is raised, reconnecting whenever the server goes away
+///
+/// A command in progress when it's raised is waited for, the rest of that minute's are skipped.
+/// `label` goes in front of every line logged, to tell the servers of a group apart.
+fn serve(profile: &Profile, label: &str, stop: &AtomicBool) -> Result<(), RconError> {
+    let jobs = load(profile, label)?;
+    let name = profile
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("{}:{}", profile.ip, profile.port));
+    Event::info(format!(
+        "{}Running {} scheduled commands",
+        label,
+        jobs.len()
+    ))
+    .profile(&name)
+    .log();
+
+    let transcript = profile
+        .transcript_log
+        .clone()
+        .map(|path| LogFile::new(path, profile.log_rotation));
+    let log = |text: &str| {
+        if let Some(transcript) = &transcript {
+            if let Err(e) = transcript.append(text) {
+                Event::warn(format!(
+                    "{}Unable to write the transcript {}: {}",
+                    label,
+                    transcript.path.display(),
+                    e
+                ))
+                .profile(&name)
+                .log();
+            }
+        }
+    };
+
+    let notifier = Notifier::new(profile);
+    let mut lifecycle = Lifecycle::new(&notifier);
+    let mut rcon = connect(profile).ok();
+    let mut last_minute = None;
+    loop {
+        if sleep_to_next_minute(stop) {
+            break;
+        }
+        let now = Local::now();
+        // Waking a hair early would otherwise run the previous minute's jobs twice
+        let minute = now.timestamp() / 60;
+        if last_minute == Some(minute) {
+            continue;
+        }
+        last_minute = Some(minute);
+
+        for (_, cmd) in jobs.iter().filter(|(s, _)| s.matches(&now)) {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let timestamp = now.format("%Y-%m-%d %H:%M");
+            let sent = Instant::now();
+            match run_job(&mut rcon, profile, cmd) {
+                Ok(response) => {
+                    lifecycle.up();
+                    let mut entry = format!("[{}] {}{}\n", timestamp, label, cmd);
+                    for line in response.lines() {
+                        entry.push_str(&format!("    {}\n", line));
+                    }
+                    Event::info("Ran a scheduled command")
+                        .profile(&name)
+                        .command(cmd)
+                        .latency(sent.elapsed())
+                        .field("response", &response)
+                        .text(entry.trim_end_matches('\n'))
+                        .log();
+                    log(&entry);
+                    notifier.publish("command", json!({ "command": cmd, "response": response }));
+                }
+                Err(e) => {
+                    // Commands the game refuses aren't a problem with the session
+                    if !matches!(e, RconError::NotAllowed | RconError::Unsupported) {
+                        lifecycle.down(&e);
+                    }
+                    let entry = format!(
+                        "[{}] {}{} failed, retrying at its next run\n",
+                        timestamp, label, cmd
+                    );
+                    Event::warn("Scheduled command failed, retrying at its next run")
+                        .profile(&name)
+                        .command(cmd)
+                        .latency(sent.elapsed())
+                        .reason(&e)
+                        .text(entry.trim_end_matches('\n'))
+                        .log();
+                    log(&entry);
+                    notifier.publish("command_failed", json!({ "command": cmd }));
+                }
+            }
+        }
+    }
+    Event::info(format!("{}Stopping", label))
+        .profile(&name)
+        .log();
+    Ok(())
+}
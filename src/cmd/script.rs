@@ -0,0 +1,12 @@
+use super::connect;
+use crate::{script, Profile, RconError, ScriptArgs};
+
+/// Run a script file to completion on a new session
+pub fn run(profile: &Profile, args: &ScriptArgs) -> Result<(), RconError> {
+    let rcon = connect(profile)?;
+    let (_, result) = script::run(rcon, profile, &args.file);
+    result.map_err(|e| {
+        eprintln!("{}", e);
+        RconError::TaskFailed
+    })
+}
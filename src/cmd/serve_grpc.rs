@@ -0,0 +1,297 @@
+use super::{
+    auth::{self, Auth, Caller, Denied},
+    cache::CacheStatus,
+    fleet, Pooled,
+};
+use crate::{Args, Profile, Rcon, RconError, ServeGrpcArgs};
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{metadata::MetadataValue, transport::Server, Request, Response, Status, Streaming};
+
+/// Messages and service generated from `proto/rustcon.proto`
+mod proto {
+    tonic::include_proto!("rustcon");
+}
+
+use proto::{
+    rustcon_server::{Rustcon, RustconServer},
+    ExecRequest, ExecResponse, HealthRequest, HealthResponse, ListServersRequest,
+    ListServersResponse, Server as ServerInfo, ServerHealth,
+};
+
+/// Responses buffered per `ExecStream` call before the client has read them
+const STREAM_BUFFER: usize = 16;
+
+type Pool = BTreeMap<String, Pooled>;
+
+struct Service {
+    pool: Arc<Pool>,
+    auth: Arc<Auth>,
+}
+
+/// The bearer token in a call's `authorization` metadata
+fn token<T>(request: &Request<T>) -> Option<String> {
+    request
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(auth::bearer)
+        .map(String::from)
+}
+
+/// Log a rejected call and turn it into its gRPC status
+fn deny<T>(request: &Request<T>, what: &str, denied: Denied) -> Status {
+    auth::log_denied(
+        "serve-grpc",
+        request.remote_addr().map(|a| a.to_string()),
+        what,
+        denied,
+    );
+    status(denied)
+}
+
+fn status(denied: Denied) -> Status {
+    match denied {
+        Denied::Unauthorized => Status::unauthenticated(denied.message()),
+        Denied::Forbidden | Denied::Command => Status::permission_denied(denied.message()),
+        Denied::RateLimited(retry_after) => {
+            let mut status = Status::resource_exhausted(denied.message());
+            status
+                .metadata_mut()
+                .insert("retry-after", MetadataValue::from(retry_after));
+            status
+        }
+    }
+}
+
+impl Service {
+    /// Authenticate a call and count it against the caller's rate limit
+    // tonic's `Status` is the error type of every handler
+    #[allow(clippy::result_large_err)]
+    fn caller<T>(&self, request: &Request<T>, what: &str) -> Result<Caller<'_>, Status> {
+        let peer = request.remote_addr().map(|a| a.ip());
+        self.auth
+            .authenticate(token(request).as_deref())
+            .and_then(|caller| {
+                self.auth
+                    .throttle("serve-grpc", caller, peer)
+                    .map(|_| caller)
+            })
+            .map_err(|denied| deny(request, what, denied))
+    }
+}
+
+/// Run a command on the pooled session without blocking the async runtime
+// tonic's `Status` is the error type of every handler
+#[allow(clippy::result_large_err)]
+async fn exec(
+    pool: Arc<Pool>,
+    request: ExecRequest,
+    source: String,
+) -> Result<(ExecResponse, CacheStatus), Status> {
+    tokio::task::spawn_blocking(move || {
+        let pooled = pool
+            .get(&request.profile)
+            .ok_or_else(|| Status::not_found(format!("No profile named {:?}", request.profile)))?;
+        let (game, response, cache) =
+            pooled
+                .exec_cached(&request.command, &source)
+                .map_err(|e| match e {
+                    RconError::NotAllowed => Status::permission_denied("The command isn't allowed"),
+                    RconError::TooLarge { max, .. } => Status::invalid_argument(format!(
+                        "The command is longer than the server's {} bytes",
+                        max
+                    )),
+                    e => Status::unavailable(format!("RCON command failed: {:?}", e)),
+                })?;
+        let response = ExecResponse {
+            profile: request.profile,
+            command: request.command,
+            response,
+            game: game.to_string(),
+        };
+        Ok((response, cache))
+    })
+    .await
+    .map_err(|e| Status::internal(e.to_string()))?
+}
+
+fn health(name: &str, profile: &Profile) -> ServerHealth {
+    let conn = Rcon::get_conn(&profile.ip, profile.port);
+    ServerHealth {
+        name: name.to_string(),
+        reachable: conn.is_ok(),
+        error: conn.err().map(|e| e.to_string()).unwrap_or_default(),
+    }
+}
+
+#[tonic::async_trait]
+impl Rustcon for Service {
+    async fn list_servers(
+        &self,
+        request: Request<ListServersRequest>,
+    ) -> Result<Response<ListServersResponse>, Status> {
+        let caller = self.caller(&request, "ListServers")?;
+        let servers = self
+            .pool
+            .iter()
+            .filter(|(name, _)| caller.allows(name))
+            .map(|(name, pooled)| ServerInfo {
+                name: name.clone(),
+                address: format!("{}:{}", pooled.profile.ip, pooled.profile.port),
+                game: pooled
+                    .profile
+                    .game
+                    .map(|g| g.to_string())
+                    .unwrap_or_default(),
+            })
+            .collect();
+        Ok(Response::new(ListServersResponse { servers }))
+    }
+
+    async fn exec(&self, request: Request<ExecRequest>) -> Result<Response<ExecResponse>, Status> {
+        let caller = self.caller(&request, "Exec")?;
+        if !caller.allows(&request.get_ref().profile) {
+            return Err(deny(&request, "Exec", Denied::Forbidden));
+        }
+        if let Err(denied) = caller.permits(&request.get_ref().command) {
+            return Err(deny(&request, "Exec", denied));
+        }
+        let source = format!("serve-grpc:{}", caller.name());
+        let (response, cache) = exec(Arc::clone(&self.pool), request.into_inner(), source).await?;
+        let mut response = Response::new(response);
+        if let Some(status) = cache.header() {
+            response
+                .metadata_mut()
+                .insert("x-cache", MetadataValue::from_static(status));
+        }
+        if let Some(age) = cache.age() {
+            response
+                .metadata_mut()
+                .insert("age", MetadataValue::from(age));
+        }
+        Ok(response)
+    }
+
+    type ExecStreamStream = ReceiverStream<Result<ExecResponse, Status>>;
+
+    async fn exec_stream(
+        &self,
+        request: Request<Streaming<ExecRequest>>,
+    ) -> Result<Response<Self::ExecStreamStream>, Status> {
+        self.caller(&request, "ExecStream")?;
+        let token = token(&request);
+        let ip = request.remote_addr().map(|a| a.ip());
+        let peer = ip.map(|ip| ip.to_string());
+        let mut requests = request.into_inner();
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let (pool, auth) = (Arc::clone(&self.pool), Arc::clone(&self.auth));
+        tokio::spawn(async move {
+            loop {
+                let response = match requests.message().await {
+                    Ok(Some(request)) => match auth
+                        .authorize(token.as_deref(), &request.profile)
+                        .and_then(|caller| caller.permits(&request.command).map(|_| caller))
+                        .and_then(|caller| auth.throttle("serve-grpc", caller, ip).map(|_| caller))
+                    {
+                        Ok(caller) => {
+                            let source = format!("serve-grpc:{}", caller.name());
+                            exec(Arc::clone(&pool), request, source)
+                                .await
+                                .map(|(response, _)| response)
+                        }
+                        Err(denied) => {
+                            auth::log_denied("serve-grpc", peer.clone(), "ExecStream", denied);
+                            Err(status(denied))
+                        }
+                    },
+                    Ok(None) => break,
+                    Err(status) => Err(status),
+                };
+                let failed = response.is_err();
+                if tx.send(response).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn health(
+        &self,
+        request: Request<HealthRequest>,
+    ) -> Result<Response<HealthResponse>, Status> {
+        let caller = self.caller(&request, "Health")?;
+        if let Some(name) = request
+            .get_ref()
+            .profiles
+            .iter()
+            .find(|n| !caller.allows(n))
+        {
+            let what = format!("Health of {}", name);
+            return Err(deny(&request, &what, Denied::Forbidden));
+        }
+        // Without a list, only the servers the token can reach are checked
+        let allowed: Vec<String> = self
+            .pool
+            .keys()
+            .filter(|name| caller.allows(name))
+            .cloned()
+            .collect();
+        let names = request.into_inner().profiles;
+        if let Some(unknown) = names.iter().find(|n| !self.pool.contains_key(*n)) {
+            return Err(Status::not_found(format!("No profile named {:?}", unknown)));
+        }
+
+        let pool = Arc::clone(&self.pool);
+        let servers = tokio::task::spawn_blocking(move || {
+            pool.iter()
+                .filter(|(name, _)| allowed.contains(name))
+                .filter(|(name, _)| names.is_empty() || names.contains(name))
+                .map(|(name, pooled)| health(name, &pooled.profile))
+                .collect()
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(HealthResponse { servers }))
+    }
+}
+
+/// Serve the gRPC fleet API until the process is stopped
+pub fn run(args: &Args, main: &Profile, serve: &ServeGrpcArgs) -> Result<(), RconError> {
+    let addr: SocketAddr = serve.bind.parse().map_err(|e| {
+        eprintln!("Invalid address {}: {}", serve.bind, e);
+        RconError::TaskFailed
+    })?;
+    let pool: Pool = fleet(args, main)?
+        .into_iter()
+        .map(|(name, profile)| (name, Pooled::checked(profile)))
+        .collect();
+    let auth = Auth::load(args)?;
+    println!(
+        "Serving {} servers over gRPC on {}{}",
+        pool.len(),
+        addr,
+        auth.notice()
+    );
+
+    let runtime = tokio::runtime::Runtime::new().map_err(|e| {
+        eprintln!("Unable to start the async runtime: {}", e);
+        RconError::TaskFailed
+    })?;
+    let service = Service {
+        pool: Arc::new(pool),
+        auth: Arc::new(auth),
+    };
+    runtime
+        .block_on(
+            Server::builder()
+                .add_service(RustconServer::new(service))
+                .serve(addr),
+        )
+        .map_err(|e| {
+            eprintln!("gRPC server failed: {}", e);
+            RconError::TaskFailed
+        })
+}
@@ -0,0 +1,261 @@
+use super::{
+    auth::{self, Auth, Denied},
+    cache::CacheStatus,
+    fleet, Pooled,
+};
+use crate::{logging::Event, systemd, Args, Profile, RconError, ServeHttpArgs};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    sync::{atomic::Ordering, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Requests handled at once, each one holds its server's session while the command runs
+const WORKERS: usize = 4;
+
+/// How long an idle worker waits for a request before checking whether to stop
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Largest request body read, well over what a command the size of an RCON packet escapes to
+const BODY_LIMIT: u64 = 64 * 1024;
+
+/// Body of `POST /servers/{profile}/exec`
+#[derive(Deserialize)]
+struct ExecRequest {
+    command: String,
+}
+
+type Pool = BTreeMap<String, Pooled>;
+
+fn load_pool(args: &Args, main: &Profile) -> Result<Pool, RconError> {
+    Ok(fleet(args, main)?
+        .into_iter()
+        .map(|(name, profile)| (name, Pooled::checked(profile)))
+        .collect())
+}
+
+fn respond(request: Request, status: u16, body: Value) {
+    respond_with(request, status, body, Vec::new());
+}
+
+fn respond_with(request: Request, status: u16, body: Value, headers: Vec<Header>) {
+    let header = Header::from_bytes("Content-Type", "application/json").expect("valid header");
+    let mut response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(header);
+    for header in headers {
+        response.add_header(header);
+    }
+    if let Err(e) = request.respond(response) {
+        Event::warn(format!("Unable to send the HTTP response: {}", e)).log();
+    }
+}
+
+/// `X-Cache` and, for reused responses, `Age`
+fn cache_headers(cache: CacheStatus) -> Vec<Header> {
+    let mut headers = Vec::new();
+    if let Some(status) = cache.header() {
+        headers.push(Header::from_bytes("X-Cache", status).expect("valid header"));
+    }
+    if let Some(age) = cache.age() {
+        headers.push(Header::from_bytes("Age", age.to_string()).expect("valid header"));
+    }
+    headers
+}
+
+fn error(request: Request, status: u16, message: &str) {
+    respond(request, status, json!({ "error": message }));
+}
+
+fn deny(request: Request, denied: Denied) {
+    auth::log_denied(
+        "serve-http",
+        request.remote_addr().map(|a| a.to_string()),
+        &format!("{} {}", request.method(), request.url()),
+        denied,
+    );
+    let mut response = Response::from_string(json!({ "error": denied.message() }).to_string())
+        .with_status_code(denied.status())
+        .with_header(Header::from_bytes("Content-Type", "application/json").expect("valid header"));
+    match denied {
+        Denied::Unauthorized => response
+            .add_header(Header::from_bytes("WWW-Authenticate", "Bearer").expect("valid header")),
+        Denied::RateLimited(retry_after) => response.add_header(
+            Header::from_bytes("Retry-After", retry_after.to_string()).expect("valid header"),
+        ),
+        _ => {}
+    }
+    let _ = request.respond(response);
+}
+
+fn handle(pool: &Pool, auth: &Auth, mut request: Request) {
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let token = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .and_then(|h| auth::bearer(h.value.as_str()))
+        .map(String::from);
+    let caller = match segments.as_slice() {
+        ["servers", name, ..] => auth.authorize(token.as_deref(), name),
+        _ => auth.authenticate(token.as_deref()),
+    };
+    let caller = match caller {
+        Ok(caller) => caller,
+        Err(denied) => return deny(request, denied),
+    };
+    let peer = request.remote_addr().map(|a| a.ip());
+    if let Err(denied) = auth.throttle("serve-http", caller, peer) {
+        return deny(request, denied);
+    }
+
+    match (request.method(), segments.as_slice()) {
+        (Method::Get, ["servers"]) => {
+            let servers: Vec<Value> = pool
+                .iter()
+                .filter(|(name, _)| caller.allows(name))
+                .map(|(name, pooled)| {
+                    json!({
+                        "name": name,
+                        "server": format!("{}:{}", pooled.profile.ip, pooled.profile.port),
+                        "game": pooled.profile.game.map(|g| g.to_string()),
+                    })
+                })
+                .collect();
+            respond(request, 200, json!({ "servers": servers }));
+        }
+        (Method::Post, ["servers", name, "exec"]) => {
+            let name = name.to_string();
+            let pooled = match pool.get(&name) {
+                Some(pooled) => pooled,
+                None => return error(request, 404, &format!("No profile named {:?}", name)),
+            };
+
+            // One byte over the limit tells a body that's too large from one that fits
+            let mut body = Vec::new();
+            let reader = request.as_reader();
+            if reader.take(BODY_LIMIT + 1).read_to_end(&mut body).is_err() {
+                return error(request, 400, "Unable to read the request body");
+            }
+            if body.len() as u64 > BODY_LIMIT {
+                let message = format!("The request body is over {} bytes", BODY_LIMIT);
+                return error(request, 413, &message);
+            }
+            let exec: ExecRequest = match serde_json::from_slice(&body) {
+                Ok(exec) => exec,
+                Err(e) => return error(request, 400, &format!("Invalid request body: {}", e)),
+            };
+
+            if let Err(denied) = caller.permits(&exec.command) {
+                return deny(request, denied);
+            }
+            let source = format!("serve-http:{}", caller.name());
+            let sent = Instant::now();
+            let result = pooled.exec_cached(&exec.command, &source);
+            let event = match &result {
+                Ok((_, _, cache)) => Event::info("Command sent").field("cache", cache.header()),
+                Err(e) => Event::warn("Command failed").reason(e),
+            };
+            event
+                .profile(&name)
+                .command(&exec.command)
+                .field("source", &source)
+                .latency(sent.elapsed())
+                .json_only()
+                .log();
+            match result {
+                Ok((game, response, cache)) => respond_with(
+                    request,
+                    200,
+                    json!({
+                        "profile": name,
+                        "game": game.to_string(),
+                        "command": exec.command,
+                        "response": response,
+                    }),
+                    cache_headers(cache),
+                ),
+                Err(RconError::NotAllowed) => error(request, 403, "The command isn't allowed"),
+                Err(RconError::TooLarge { max, .. }) => error(
+                    request,
+                    413,
+                    &format!("The command is longer than the server's {} bytes", max),
+                ),
+                Err(e) => error(request, 502, &format!("RCON command failed: {:?}", e)),
+            }
+        }
+        (Method::Get, ["metrics"]) => {
+            let header = Header::from_bytes("Content-Type", "text/plain; version=0.0.4")
+                .expect("valid header");
+            let response = Response::from_string(auth.metrics()).with_header(header);
+            if let Err(e) = request.respond(response) {
+                Event::warn(format!("Unable to send the HTTP response: {}", e)).log();
+            }
+        }
+        (_, ["servers"]) | (_, ["servers", _, "exec"]) | (_, ["metrics"]) => {
+            error(request, 405, "Method not allowed")
+        }
+        _ => error(request, 404, "Not found"),
+    }
+}
+
+/// Serve the REST bridge until SIGTERM, SIGHUP or SIGINT, then finish the requests already running
+pub fn run(args: &Args, main: &Profile, serve: &ServeHttpArgs) -> Result<(), RconError> {
+    let pool = Arc::new(load_pool(args, main)?);
+    let auth = Arc::new(Auth::load(args)?);
+    let server = systemd::listener(&serve.bind)
+        .map_err(|e| e.into())
+        .and_then(|listener| Server::from_listener(listener, None))
+        .map_err(|e| {
+            Event::error(format!("Unable to listen on {}: {}", serve.bind, e))
+                .field("bind", &serve.bind)
+                .log();
+            RconError::TaskFailed
+        })?;
+    let server = Arc::new(server);
+    let address = server.server_addr().to_string();
+    Event::info(format!(
+        "Serving {} servers on http://{}{}",
+        pool.len(),
+        address,
+        auth.notice()
+    ))
+    .field("bind", address)
+    .log();
+
+    let stop = systemd::stop_flag();
+    let workers: Vec<_> = (0..WORKERS)
+        .map(|_| {
+            let pool = Arc::clone(&pool);
+            let auth = Arc::clone(&auth);
+            let server = Arc::clone(&server);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    match server.recv_timeout(POLL_INTERVAL) {
+                        Ok(Some(request)) => handle(&pool, &auth, request),
+                        Ok(None) => {}
+                        Err(e) => Event::warn(format!("Unable to receive a request: {}", e)).log(),
+                    }
+                }
+            })
+        })
+        .collect();
+    systemd::notify("READY=1");
+
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(POLL_INTERVAL);
+    }
+    Event::info("Stopping, finishing the requests in progress").log();
+    systemd::notify("STOPPING=1");
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
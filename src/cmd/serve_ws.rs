@@ -0,0 +1,414 @@
+use super::{
+    auth::{self, Auth, Caller, Denied},
+    fleet,
+};
+use crate::{logging::Event, systemd, Args, Profile, Rcon, RconError, ServeWsArgs};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader},
+    net::{IpAddr, TcpStream},
+    str,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tungstenite::{
+    error::ProtocolError,
+    handshake::server::{ErrorResponse, Request, Response},
+    http::StatusCode,
+    Message, WebSocket,
+};
+
+/// How long a client read waits before checking for pushed server output
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a client has to finish the handshake, so a stalled one can't hold up stopping
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// tungstenite's errors, and the HTTP response its handshake callback rejects with, are large
+#[allow(clippy::result_large_err)]
+fn send(ws: &mut WebSocket<TcpStream>, frame: Value) -> Result<(), tungstenite::Error> {
+    ws.send(Message::text(frame.to_string()))
+}
+
+/// The bearer token of a handshake, from the `Authorization` header or, since browsers can't set
+/// headers on a WebSocket, a `token` query parameter
+fn token(request: &Request) -> Option<String> {
+    request
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(auth::bearer)
+        .map(String::from)
+        .or_else(|| {
+            request
+                .uri()
+                .query()?
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("token="))
+                .map(percent_decode)
+        })
+}
+
+/// A query parameter's value with its `%XX` escapes decoded, as browsers send tokens with
+/// characters outside the URL's own
+fn percent_decode(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        let escaped = match after {
+            [high, low, ..] if byte == b'%' => str::from_utf8(&[*high, *low])
+                .ok()
+                .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &after[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = after;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// An accepted client and what its token lets it do
+struct Client<'a> {
+    ws: WebSocket<TcpStream>,
+    profile: Profile,
+    caller: Caller<'a>,
+    peer: Option<IpAddr>,
+}
+
+/// Accept the WebSocket handshake for `/servers/{profile}`, rejecting unknown profiles and
+/// tokens that can't reach them
+#[allow(clippy::result_large_err)]
+fn accept<'a>(
+    fleet: &BTreeMap<String, Profile>,
+    auth: &'a Auth,
+    stream: TcpStream,
+) -> Option<Client<'a>> {
+    let ip = stream.peer_addr().ok().map(|a| a.ip());
+    let peer = stream.peer_addr().ok().map(|a| a.to_string());
+    let mut profile = None;
+    let mut accepted = None;
+    let callback = |request: &Request, response: Response| {
+        let segments: Vec<&str> = request
+            .uri()
+            .path()
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect();
+        match segments.as_slice() {
+            ["servers", name] if fleet.contains_key(*name) => {
+                // Every session logs in to the server, so opening them counts against the limit too
+                match auth
+                    .authorize(token(request).as_deref(), name)
+                    .and_then(|caller| auth.throttle("serve-ws", caller, ip).map(|_| caller))
+                {
+                    Ok(caller) => accepted = Some(caller),
+                    Err(denied) => {
+                        auth::log_denied("serve-ws", peer.clone(), request.uri().path(), denied);
+                        let mut error = ErrorResponse::new(Some(denied.message().to_string()));
+                        *error.status_mut() =
+                            StatusCode::from_u16(denied.status()).expect("valid status");
+                        return Err(error);
+                    }
+                }
+                profile = fleet.get(*name).cloned();
+                Ok(response)
+            }
+            _ => {
+                let mut error = ErrorResponse::new(Some("Unknown server profile".to_string()));
+                *error.status_mut() = StatusCode::NOT_FOUND;
+                Err(error)
+            }
+        }
+    };
+
+    match tungstenite::accept_hdr(stream, callback) {
+        Ok(ws) => profile.zip(accepted).map(|(profile, caller)| Client {
+            ws,
+            profile,
+            caller,
+            peer: ip,
+        }),
+        Err(e) => {
+            Event::warn(format!("WebSocket handshake failed: {}", e))
+                .field("peer", ip)
+                .log();
+            None
+        }
+    }
+}
+
+/// Forward pushed console output to a channel, for consoles that send it
+fn push_receiver(rcon: &Rcon) -> Option<mpsc::Receiver<String>> {
+    rcon.split_push_reader().map(|stream| {
+        let (tx, rx) = mpsc::channel();
+        let _ = stream.set_read_timeout(None);
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                if tx.send(line.trim_end().to_string()).is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    })
+}
+
+/// Relay one client's commands to its own RCON session until either side hangs up or the bridge
+/// stops
+#[allow(clippy::result_large_err)]
+fn session(client: Client, auth: &Auth, stop: &AtomicBool) -> Result<(), tungstenite::Error> {
+    let Client {
+        mut ws,
+        profile,
+        caller,
+        peer,
+    } = client;
+    let source = format!("serve-ws:{}", caller.name());
+    let commands = caller.commands();
+    let open = |rcon: &mut Rcon| {
+        rcon.set_source(&source);
+        if let Some(commands) = &commands {
+            rcon.restrict(commands);
+        }
+    };
+    let label = profile
+        .name
+        .clone()
+        .unwrap_or_else(|| "default".to_string());
+    let mut rcon = match Rcon::connect_unattended(&profile.client()) {
+        Ok(mut rcon) => {
+            open(&mut rcon);
+            rcon
+        }
+        Err(e) => {
+            send(
+                &mut ws,
+                json!({ "type": "error", "message": format!("Unable to connect: {:?}", e) }),
+            )?;
+            return ws.close(None);
+        }
+    };
+    send(
+        &mut ws,
+        json!({ "type": "connected", "profile": label, "game": rcon.game().to_string() }),
+    )?;
+
+    let mut pushed = push_receiver(&rcon);
+    ws.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+    loop {
+        match ws.read() {
+            Ok(Message::Text(cmd)) => {
+                let cmd = cmd.to_string();
+                let sent = Instant::now();
+                let limited = match auth.throttle("serve-ws", caller, peer) {
+                    Err(Denied::RateLimited(retry_after)) => {
+                        let message = format!("Rate limited, {:?} wasn't sent", cmd);
+                        send(
+                            &mut ws,
+                            json!({ "type": "error", "message": message, "retry_after": retry_after }),
+                        )?;
+                        true
+                    }
+                    _ => false,
+                };
+                let result = if limited {
+                    Ok(None)
+                } else if let Err(e) = super::check(&profile, rcon.game(), &cmd) {
+                    Err(e)
+                } else if pushed.is_some() {
+                    // The response comes back with the rest of the pushed output
+                    rcon.send_unanswered(&cmd).map(|_| None)
+                } else {
+                    rcon.send_cmd(&cmd).map(Some)
+                };
+                let event = match &result {
+                    _ if limited => None,
+                    Ok(_) => Some(Event::info("Command sent")),
+                    Err(e) => Some(Event::warn("Command failed").reason(e)),
+                };
+                if let Some(event) = event {
+                    event
+                        .profile(&label)
+                        .command(&cmd)
+                        .field("source", &source)
+                        .latency(sent.elapsed())
+                        .json_only()
+                        .log();
+                }
+                match result {
+                    Ok(Some(response)) => {
+                        let text: String = response.iter().map(|p| p.body()).collect();
+                        send(
+                            &mut ws,
+                            json!({ "type": "response", "command": cmd, "response": text }),
+                        )?;
+                    }
+                    Ok(None) => {}
+                    Err(RconError::NotAllowed) => send(
+                        &mut ws,
+                        json!({ "type": "error", "message": format!("{:?} isn't allowed", cmd) }),
+                    )?,
+                    Err(RconError::TooLarge { max, .. }) => send(
+                        &mut ws,
+                        json!({
+                            "type": "error",
+                            "message": format!("The command is longer than the server's {} bytes", max),
+                        }),
+                    )?,
+                    Err(RconError::Timeout) => send(
+                        &mut ws,
+                        json!({ "type": "error", "message": "The server didn't answer in time" }),
+                    )?,
+                    Err(_) => {
+                        // Reconnect for the next command rather than dropping the client
+                        send(
+                            &mut ws,
+                            json!({ "type": "error", "message": "Lost the RCON session, reconnecting" }),
+                        )?;
+                        match Rcon::connect_unattended(&profile.client()) {
+                            Ok(session) => {
+                                rcon = session;
+                                open(&mut rcon);
+                                pushed = push_receiver(&rcon);
+                            }
+                            Err(_) => {
+                                send(
+                                    &mut ws,
+                                    json!({ "type": "error", "message": "Unable to reconnect" }),
+                                )?;
+                                return ws.close(None);
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(
+                tungstenite::Error::ConnectionClosed
+                | tungstenite::Error::Protocol(ProtocolError::ResetWithoutClosingHandshake),
+            ) => return Ok(()),
+            Err(e) => return Err(e),
+        }
+
+        if let Some(rx) = &pushed {
+            while let Ok(line) = rx.try_recv() {
+                send(&mut ws, json!({ "type": "message", "text": line }))?;
+            }
+        }
+
+        // Only checked between commands, so one in progress still gets its response
+        if stop.load(Ordering::Relaxed) {
+            ws.close(None)?;
+            return ws.flush();
+        }
+    }
+}
+
+/// Serve WebSocket consoles until SIGTERM, SIGHUP or SIGINT, then close every session once its
+/// command in progress is answered
+pub fn run(args: &Args, main: &Profile, serve: &ServeWsArgs) -> Result<(), RconError> {
+    let fleet = Arc::new(fleet(args, main)?);
+    let auth = Arc::new(Auth::load(args)?);
+    let listener = systemd::listener(&serve.bind)
+        .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+        .map_err(|e| {
+            Event::error(format!("Unable to listen on {}: {}", serve.bind, e))
+                .field("bind", &serve.bind)
+                .log();
+            RconError::TaskFailed
+        })?;
+    let address = listener
+        .local_addr()
+        .map_or_else(|_| serve.bind.clone(), |a| a.to_string());
+    Event::info(format!(
+        "Serving {} servers on ws://{}/servers/<profile>{}",
+        fleet.len(),
+        address,
+        auth.notice()
+    ))
+    .field("bind", address)
+    .log();
+
+    let stop = systemd::stop_flag();
+    let mut sessions = Vec::new();
+    systemd::notify("READY=1");
+    while !stop.load(Ordering::Relaxed) {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => {
+                Event::warn(format!("Unable to accept a connection: {}", e)).log();
+                continue;
+            }
+        };
+        if let Err(e) = stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)))
+        {
+            Event::warn(format!("Unable to set up the connection: {}", e)).log();
+            continue;
+        }
+
+        let fleet = Arc::clone(&fleet);
+        let auth = Arc::clone(&auth);
+        let stop = Arc::clone(&stop);
+        sessions.retain(|s: &thread::JoinHandle<()>| !s.is_finished());
+        sessions.push(thread::spawn(move || {
+            let client = accept(&fleet, &auth, stream).filter(|_| !stop.load(Ordering::Relaxed));
+            if let Some(client) = client {
+                if let Err(e) = session(client, &auth, &stop) {
+                    Event::warn(format!("WebSocket session ended: {}", e)).log();
+                }
+            }
+        }));
+    }
+
+    Event::info("Stopping, finishing the commands in progress").log();
+    systemd::notify("STOPPING=1");
+    for session in sessions {
+        let _ = session.join();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(uri: &str) -> Request {
+        Request::builder().uri(uri).body(()).unwrap()
+    }
+
+    #[test]
+    fn query_tokens_are_percent_decoded() {
+        let token = |query| token(&request(&format!("/servers/mc?{}", query)));
+        assert_eq!(token("token=a%2Bb%2F%3D").as_deref(), Some("a+b/="));
+        assert_eq!(token("x=1&token=plain").as_deref(), Some("plain"));
+        // Malformed escapes are kept as they are
+        assert_eq!(token("token=100%25%zz%4").as_deref(), Some("100%%zz%4"));
+        assert_eq!(token("x=1"), None);
+    }
+}
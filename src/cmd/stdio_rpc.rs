@@ -0,0 +1,241 @@
+use crate::{Args, Profile, Rcon, RconError};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Write},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// Standard JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// An RCON connection or command failed
+const SERVER_ERROR: i64 = -32000;
+
+/// An open session and whether its pushed output is forwarded to the client
+struct Session {
+    rcon: Rcon,
+    /// What the session was opened with, for refusing dangerous commands
+    profile: Profile,
+    subscribed: bool,
+}
+
+/// A failed call, reported to the client as a JSON-RPC error object
+struct Error {
+    code: i64,
+    message: String,
+}
+
+impl Error {
+    fn new(code: i64, message: impl Into<String>) -> Error {
+        Error {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Replies and notifications share stdout with the subscription threads, one line each
+#[derive(Clone)]
+struct Output(Arc<Mutex<io::Stdout>>);
+
+impl Output {
+    fn send(&self, message: Value) {
+        let mut stdout = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = writeln!(stdout, "{}", message);
+        let _ = stdout.flush();
+    }
+
+    fn reply(&self, id: Value, result: Result<Value, Error>) {
+        self.send(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": e.code, "message": e.message },
+            }),
+        });
+    }
+}
+
+/// The client's sessions, numbered in the order they were opened
+struct Server<'a> {
+    args: &'a Args,
+    main: &'a Profile,
+    output: Output,
+    sessions: BTreeMap<u64, Session>,
+    next_id: u64,
+}
+
+impl Server<'_> {
+    fn session(&mut self, params: &Value) -> Result<(u64, &mut Session), Error> {
+        let id = params["session"]
+            .as_u64()
+            .ok_or_else(|| Error::new(INVALID_PARAMS, "Missing the session id"))?;
+        self.sessions
+            .get_mut(&id)
+            .map(|session| (id, session))
+            .ok_or_else(|| Error::new(INVALID_PARAMS, format!("No session {}", id)))
+    }
+
+    /// `connect {profile?}`: open a session to a config profile, or the command line's server
+    fn connect(&mut self, params: &Value) -> Result<Value, Error> {
+        let profile = match params["profile"].as_str() {
+            Some(name) => self
+                .args
+                .resolve_named(name)
+                .map_err(|e| Error::new(INVALID_PARAMS, e.to_string()))?,
+            None => self.main.clone(),
+        };
+        let rcon = Rcon::connect_unattended(&profile.client()).map_err(|e| {
+            Error::new(
+                SERVER_ERROR,
+                format!(
+                    "Unable to connect to {}:{}: {:?}",
+                    profile.ip, profile.port, e
+                ),
+            )
+        })?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let result = json!({
+            "session": id,
+            "profile": profile.name,
+            "server": format!("{}:{}", profile.ip, profile.port),
+            "game": rcon.game().to_string(),
+        });
+        self.sessions.insert(
+            id,
+            Session {
+                rcon,
+                profile,
+                subscribed: false,
+            },
+        );
+        Ok(result)
+    }
+
+    /// `exec {session, command}`: run a command, a subscribed session's response arrives as
+    /// `message` notifications instead
+    fn exec(&mut self, params: &Value) -> Result<Value, Error> {
+        let command = params["command"]
+            .as_str()
+            .ok_or_else(|| Error::new(INVALID_PARAMS, "Missing the command"))?
+            .to_string();
+        let (id, session) = self.session(params)?;
+        // Refused in the reply rather than on stderr, which the client may not read
+        session
+            .profile
+            .check_dangerous(session.rcon.game(), &command)
+            .map_err(|why| Error::new(INVALID_PARAMS, why))?;
+        let result = if session.subscribed {
+            session.rcon.send_unanswered(&command).map(|_| Value::Null)
+        } else {
+            session
+                .rcon
+                .send_cmd(&command)
+                .map(|response| json!(response.iter().map(|p| p.body()).collect::<String>()))
+        };
+        match result {
+            Ok(response) => Ok(json!({ "command": command, "response": response })),
+            Err(e) => {
+                // The session is unusable, the client connects again
+                self.sessions.remove(&id);
+                Err(Error::new(
+                    SERVER_ERROR,
+                    format!("Lost session {}: {:?}", id, e),
+                ))
+            }
+        }
+    }
+
+    /// `subscribe {session}`: forward the console's pushed output as `message` notifications
+    fn subscribe(&mut self, params: &Value) -> Result<Value, Error> {
+        let output = self.output.clone();
+        let (id, session) = self.session(params)?;
+        if session.subscribed {
+            return Ok(json!({ "session": id }));
+        }
+        let stream = session.rcon.split_push_reader().ok_or_else(|| {
+            Error::new(
+                SERVER_ERROR,
+                format!("{} doesn't push its output", session.rcon.game()),
+            )
+        })?;
+        let _ = stream.set_read_timeout(None);
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                output.send(json!({
+                    "jsonrpc": "2.0",
+                    "method": "message",
+                    "params": { "session": id, "text": line.trim_end() },
+                }));
+            }
+            output.send(json!({
+                "jsonrpc": "2.0",
+                "method": "closed",
+                "params": { "session": id },
+            }));
+        });
+        session.subscribed = true;
+        Ok(json!({ "session": id }))
+    }
+
+    fn handle(&mut self, line: &str) {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => {
+                return self
+                    .output
+                    .reply(Value::Null, Err(Error::new(PARSE_ERROR, e.to_string())))
+            }
+        };
+        // Requests without an id are notifications and get no reply
+        let id = request.get("id").cloned();
+        let method = match request["method"].as_str() {
+            Some(method) => method,
+            None => {
+                return self.output.reply(
+                    id.unwrap_or(Value::Null),
+                    Err(Error::new(INVALID_REQUEST, "Missing the method")),
+                )
+            }
+        };
+
+        let params = &request["params"];
+        let result = match method {
+            "connect" => self.connect(params),
+            "exec" => self.exec(params),
+            "subscribe" => self.subscribe(params),
+            _ => Err(Error::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method {:?}", method),
+            )),
+        };
+        if let Some(id) = id {
+            self.output.reply(id, result);
+        }
+    }
+}
+
+/// Answer JSON-RPC requests read line by line from stdin until it closes
+pub fn run(args: &Args, main: &Profile) -> Result<(), RconError> {
+    let mut server = Server {
+        args,
+        main,
+        output: Output(Arc::new(Mutex::new(io::stdout()))),
+        sessions: BTreeMap::new(),
+        next_id: 1,
+    };
+    for line in io::stdin().lock().lines() {
+        let line = line.map_err(|_| RconError::TaskFailed)?;
+        if !line.trim().is_empty() {
+            server.handle(&line);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+/*
+ * `--template` format strings, filled in with what a command's response parsed into.
+ *
+ * Fields are written `{{players.online}}`, a path through the result's JSON with `.` between
+ * keys or list indexes. Lists print joined by `, `, missing values such as a max player count
+ * the game doesn't report print as nothing, and paths the result doesn't have are an error.
+ */
+
+use crate::{Game, Profile, RconError};
+use serde_json::{json, Map, Value};
+
+#[derive(Clone, Debug)]
+enum Part {
+    Text(String),
+    Field(Vec<String>),
+}
+
+/// A parsed `--template`
+#[derive(Clone, Debug)]
+pub struct Template(Vec<Part>);
+
+impl Template {
+    /// Split a template into text and `{{fields}}`, for clap to check before connecting
+    pub fn parse(text: &str) -> Result<Template, String> {
+        let mut parts = Vec::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| format!("unclosed {{{{ in {:?}", text))?;
+            let path = rest[start + 2..start + end].trim();
+            if path.is_empty() {
+                return Err(format!("empty {{{{}}}} in {:?}", text));
+            }
+            parts.push(Part::Field(path.split('.').map(String::from).collect()));
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Template(parts))
+    }
+
+    /// Fill in the fields from `result`, failing on the first one it doesn't have
+    pub fn render(&self, result: &Value) -> Result<String, String> {
+        let mut out = String::new();
+        for part in &self.0 {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Field(path) => {
+                    let value = path
+                        .iter()
+                        .try_fold(result, |value, key| match value {
+                            Value::Array(items) => {
+                                key.parse().ok().and_then(|i: usize| items.get(i))
+                            }
+                            _ => value.get(key),
+                        })
+                        .ok_or_else(|| format!("the result has no {{{{{}}}}}", path.join(".")))?;
+                    out.push_str(&show(value));
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn show(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(show).collect::<Vec<_>>().join(", "),
+        _ => value.to_string(),
+    }
+}
+
+/// What a template can refer to after running `cmd`: the server, the response or error, and
+/// `players`, `stats` or `bans` when the command is the game's one for them
+pub fn result(
+    profile: &Profile,
+    game: Option<Game>,
+    cmd: &str,
+    response: &Result<String, RconError>,
+) -> Value {
+    let mut result = json!({
+        "host": profile.ip,
+        "port": profile.port,
+        "profile": profile.name,
+        "game": game.map(|g| g.to_string()),
+        "cmd": cmd,
+        "failed": response.is_err(),
+        "response": response.as_ref().ok().map(|r| r.trim_end()),
+        "error": response.as_ref().err().map(|e| format!("{:?}", e)),
+    });
+    let (game, text) = match (game, response) {
+        (Some(game), Ok(text)) => (game.profile(), text),
+        _ => return result,
+    };
+
+    let cmd = Some(cmd.trim());
+    if cmd == game.players_command() {
+        let players = game.parse_players(text);
+        result["players"] = json!({
+            "online": players.len(),
+            "max": game.parse_max_players(text),
+            "names": players.iter().map(|p| &p.name).collect::<Vec<_>>(),
+            "ids": players.iter().map(|p| &p.id).collect::<Vec<_>>(),
+        });
+    } else if cmd == game.stats_command() {
+        let stats: Map<String, Value> = game
+            .parse_stats(text)
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), json!(value)))
+            .collect();
+        result["stats"] = Value::Object(stats);
+    } else if cmd == game.bans_command() {
+        let bans = game.parse_bans(text);
+        result["bans"] = json!({ "count": bans.len(), "list": bans });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_fill_in_parsed_results() {
+        let result = serde_json::json!({
+            "host": "10.0.0.5",
+            "players": { "online": 2, "max": null, "names": ["Steve", "Alex"] },
+        });
+        let template = Template::parse("{{host}}: {{ players.online }}/{{players.max}}").unwrap();
+        assert_eq!(template.render(&result).unwrap(), "10.0.0.5: 2/");
+        let template = Template::parse("{{players.names.1}} of {{players.names}}").unwrap();
+        assert_eq!(template.render(&result).unwrap(), "Alex of Steve, Alex");
+        assert!(Template::parse("{{players").is_err());
+        assert!(Template::parse("{{stats.fps}}")
+            .unwrap()
+            .render(&result)
+            .is_err());
+    }
+}
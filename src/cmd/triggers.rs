@@ -0,0 +1,310 @@
+use super::{connect, exec, reconnect, shell_command};
+use crate::{
+    config::TriggerConfig,
+    notify::{Lifecycle, Notifier},
+    systemd, Profile, Rcon, RconError,
+};
+use regex::{Captures, Regex};
+use serde_json::json;
+use std::{
+    io::{BufRead, BufReader},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often `poll` commands run when their trigger has no `interval`
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Pause between attempts to get the session back
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// A configured rule with its pattern compiled
+struct Trigger<'a> {
+    config: &'a TriggerConfig,
+    regex: Regex,
+}
+
+/// A `poll` command and when it's next due
+struct Poll<'a> {
+    cmd: &'a str,
+    interval: Duration,
+    due: Instant,
+}
+
+/// Compile the profile's triggers, reporting every invalid rule
+fn load(profile: &Profile) -> Result<Vec<Trigger<'_>>, RconError> {
+    if profile.triggers.is_empty() {
+        eprintln!("No triggers, add [[profiles.<name>.triggers]] rules to the config");
+        return Err(RconError::TaskFailed);
+    }
+
+    let mut triggers = Vec::new();
+    let mut valid = true;
+    for config in &profile.triggers {
+        if config.command.is_none()
+            && config.run.is_none()
+            && config.webhook.is_none()
+            && config.notify.is_none()
+        {
+            eprintln!(
+                "Trigger {:?} needs a command, run, webhook or notify action",
+                config.pattern
+            );
+            valid = false;
+            continue;
+        }
+        match Regex::new(&config.pattern) {
+            Ok(regex) => triggers.push(Trigger { config, regex }),
+            Err(e) => {
+                eprintln!("Invalid trigger pattern: {}", e);
+                valid = false;
+            }
+        }
+    }
+    if valid {
+        Ok(triggers)
+    } else {
+        Err(RconError::TaskFailed)
+    }
+}
+
+/// Substitute the match's `$1` and `${name}` capture groups into an action
+///
+/// Other `$` are left alone so shell commands can still use environment variables.
+fn expand(caps: &Captures, template: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let digits = after.len() - after.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let (group, len) = if digits > 0 {
+            (
+                after[..digits].parse().ok().and_then(|i| caps.get(i)),
+                digits,
+            )
+        } else if let Some(name) = after.strip_prefix('{').and_then(|a| a.split_once('}')) {
+            let group = match name.0.parse() {
+                Ok(i) => caps.get(i),
+                Err(_) => caps.name(name.0),
+            };
+            (group, name.0.len() + 2)
+        } else {
+            expanded.push('$');
+            rest = after;
+            continue;
+        };
+        expanded.push_str(group.map_or("", |m| m.as_str()));
+        rest = &after[len..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Run a matching trigger's actions, failures are reported but don't stop the watch
+fn fire(
+    trigger: &Trigger,
+    caps: &Captures,
+    line: &str,
+    rcon: &mut Rcon,
+    profile: &Profile,
+    notifier: &Notifier,
+    pushed: bool,
+) {
+    let now = chrono::Local::now();
+    println!(
+        "[{}] /{}/ matched: {}",
+        now.format("%Y-%m-%d %H:%M:%S"),
+        trigger.config.pattern,
+        line
+    );
+
+    if let Some(cmd) = &trigger.config.command {
+        let cmd = expand(caps, cmd);
+        println!("    > {}", cmd);
+        let sent = if pushed {
+            // The response arrives with the rest of the pushed output
+            rcon.send_unanswered(&cmd)
+        } else {
+            exec(rcon, profile, &cmd).map(|response| {
+                let response: String = response.iter().map(|p| p.body()).collect();
+                for line in response.lines() {
+                    println!("    {}", line);
+                }
+                notifier.publish("command", json!({ "command": cmd, "response": response }));
+            })
+        };
+        if sent.is_err() {
+            eprintln!("    Unable to send the command");
+        }
+    }
+
+    if let Some(run) = &trigger.config.run {
+        let run = expand(caps, run);
+        println!("    $ {}", run);
+        match shell_command(&run).env("RUSTCON_MATCH", line).status() {
+            Ok(s) if s.success() => {}
+            Ok(s) => eprintln!("    The command failed with {}", s),
+            Err(e) => eprintln!("    Unable to run the command: {}", e),
+        }
+    }
+
+    if let Some(text) = &trigger.config.notify {
+        notifier.notify(
+            "trigger",
+            &expand(caps, text),
+            json!({ "pattern": trigger.config.pattern, "line": line }),
+        );
+    }
+
+    if let Some(url) = &trigger.config.webhook {
+        let body = json!({
+            "pattern": trigger.config.pattern,
+            "line": line,
+            "profile": profile.name,
+            "server": format!("{}:{}", profile.ip, profile.port),
+            "timestamp": now.to_rfc3339(),
+        });
+        if let Err(e) = ureq::post(url).send_json(body) {
+            eprintln!("    Webhook failed: {}", e);
+        }
+    }
+}
+
+/// Check every line of output against every trigger
+fn check(
+    triggers: &[Trigger],
+    output: &str,
+    rcon: &mut Rcon,
+    profile: &Profile,
+    notifier: &Notifier,
+    pushed: bool,
+) {
+    for line in output.lines() {
+        for trigger in triggers {
+            if let Some(caps) = trigger.regex.captures(line) {
+                fire(trigger, &caps, line, rcon, profile, notifier, pushed);
+            }
+        }
+    }
+}
+
+/// Watch pushed console output and `poll` responses until the session is lost, or `stop` is
+/// raised
+fn watch(
+    rcon: &mut Rcon,
+    profile: &Profile,
+    notifier: &Notifier,
+    triggers: &[Trigger],
+    polls: &mut [Poll],
+    stop: &AtomicBool,
+) -> Result<(), RconError> {
+    // Receive pushed output on a thread so polls still run while the console is quiet
+    let pushed = rcon.split_push_reader().map(|stream| {
+        let (tx, rx) = mpsc::channel();
+        let _ = stream.set_read_timeout(None);
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    });
+    if pushed.is_none() && polls.is_empty() {
+        eprintln!(
+            "{} doesn't push its output, give the triggers a poll command",
+            rcon.game()
+        );
+        return Err(RconError::Unsupported);
+    }
+
+    while !stop.load(Ordering::Relaxed) {
+        let next_due = polls.iter().map(|p| p.due).min();
+        let wait = next_due.map_or(DEFAULT_INTERVAL, |due| {
+            due.saturating_duration_since(Instant::now())
+        });
+        match &pushed {
+            Some(rx) => match rx.recv_timeout(wait.min(systemd::STOP_POLL)) {
+                Ok(line) => check(triggers, line.trim_end(), rcon, profile, notifier, true),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Err(RconError::ConnError),
+            },
+            None => {
+                if systemd::sleep(stop, wait) {
+                    break;
+                }
+            }
+        }
+
+        let now = Instant::now();
+        for poll in polls.iter_mut().filter(|p| p.due <= now) {
+            poll.due = now + poll.interval;
+            if pushed.is_some() {
+                rcon.send_unanswered(poll.cmd)?;
+            } else {
+                let response: String = exec(rcon, profile, poll.cmd)?
+                    .iter()
+                    .map(|p| p.body())
+                    .collect();
+                check(triggers, &response, rcon, profile, notifier, false);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fire the profile's triggers on server output until SIGTERM, SIGHUP or SIGINT, reconnecting
+/// whenever the server goes away
+pub fn run(profile: &Profile) -> Result<(), RconError> {
+    let stop = systemd::stop_flag();
+    let triggers = load(profile)?;
+    let mut polls: Vec<Poll> = Vec::new();
+    for config in &profile.triggers {
+        if let Some(cmd) = &config.poll {
+            if !polls.iter().any(|p| p.cmd == cmd) {
+                polls.push(Poll {
+                    cmd,
+                    interval: config.interval.unwrap_or(DEFAULT_INTERVAL),
+                    due: Instant::now(),
+                });
+            }
+        }
+    }
+
+    let notifier = Notifier::new(profile);
+    let mut lifecycle = Lifecycle::new(&notifier);
+    let mut rcon = connect(profile)?;
+    println!("Watching {} triggers", triggers.len());
+    loop {
+        match watch(&mut rcon, profile, &notifier, &triggers, &mut polls, &stop) {
+            Ok(()) => break,
+            Err(RconError::Unsupported) => return Err(RconError::Unsupported),
+            Err(_) => {}
+        }
+
+        eprintln!("Lost connection to the server, reconnecting...");
+        lifecycle.down(&RconError::ConnError);
+        loop {
+            if systemd::sleep(&stop, RECONNECT_DELAY) {
+                println!("Stopping");
+                return Ok(());
+            }
+            match reconnect(profile) {
+                Ok(session) => {
+                    rcon = session;
+                    break;
+                }
+                Err(e) => lifecycle.down(&e),
+            }
+        }
+        lifecycle.up();
+    }
+    println!("Stopping");
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use super::{connect, exec, read_list, report::csv_row, yaml::to_yaml_item};
+use crate::{
+    game::Player,
+    notify::{Lifecycle, Notifier},
+    systemd, OutputFormat, Profile, RconError, WatchPlayersArgs,
+};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+/// Player identity used to diff consecutive listings
+fn key(player: &Player) -> String {
+    player.id.clone().unwrap_or_else(|| player.name.clone())
+}
+
+/// Whether the player is on the watchlist, an empty watchlist matches everyone
+fn watched(watchlist: &[String], player: &Player) -> bool {
+    watchlist.is_empty()
+        || watchlist.iter().any(|w| {
+            w.eq_ignore_ascii_case(&player.name) || player.id.as_deref() == Some(w.as_str())
+        })
+}
+
+fn load_watchlist(args: &WatchPlayersArgs) -> Result<Vec<String>, RconError> {
+    let mut watchlist = args.watch.clone();
+    if let Some(path) = &args.watchlist {
+        watchlist.extend(read_list(path)?);
+    }
+    Ok(watchlist)
+}
+
+/// Print a join/leave event, as a `time,event,name,id` row as CSV or a list entry as YAML, and
+/// send it to the notification targets
+fn report(notifier: &Notifier, event: &str, player: &Player, format: OutputFormat) {
+    let now = chrono::Local::now();
+    let (sign, verb) = if event == "join" {
+        ('+', "joined")
+    } else {
+        ('-', "left")
+    };
+    match &player.id {
+        _ if format == OutputFormat::Yaml => print!(
+            "{}",
+            to_yaml_item(&json!({
+                "time": now.to_rfc3339(),
+                "event": event,
+                "name": player.name,
+                "id": player.id,
+            }))
+        ),
+        _ if format == OutputFormat::Csv => println!(
+            "{}",
+            csv_row(&[
+                now.to_rfc3339().as_str(),
+                event,
+                &player.name,
+                player.id.as_deref().unwrap_or_default()
+            ])
+        ),
+        Some(id) => println!(
+            "[{}] {} {} ({}) {}",
+            now.format("%Y-%m-%d %H:%M:%S"),
+            sign,
+            player.name,
+            id,
+            verb
+        ),
+        None => println!(
+            "[{}] {} {} {}",
+            now.format("%Y-%m-%d %H:%M:%S"),
+            sign,
+            player.name,
+            verb
+        ),
+    }
+
+    notifier.notify(
+        event,
+        &format!("{} {}", player.name, verb),
+        json!({ "player": player.name, "id": player.id }),
+    );
+}
+
+/// Poll the player list until SIGTERM, SIGHUP or SIGINT, reporting joins and leaves of watched
+/// players
+pub fn run(
+    profile: &Profile,
+    args: &WatchPlayersArgs,
+    format: OutputFormat,
+) -> Result<(), RconError> {
+    // Anything but the events goes to stderr for the formats meant to be parsed
+    let parsed = matches!(format, OutputFormat::Csv | OutputFormat::Yaml);
+    let stop = systemd::stop_flag();
+    let watchlist = load_watchlist(args)?;
+    let notifier = match &args.webhook {
+        Some(url) => Notifier::new(profile).with_webhook(url),
+        None => Notifier::new(profile),
+    };
+    let mut rcon = connect(profile)?;
+    let game = rcon.game();
+    let list = game.profile().players_command().ok_or_else(|| {
+        eprintln!("{} has no player list command", game);
+        RconError::Unsupported
+    })?;
+
+    if format == OutputFormat::Csv {
+        println!("{}", csv_row(&["time", "event", "name", "id"]));
+    }
+    let mut online: Option<BTreeMap<String, Player>> = None;
+    let mut lifecycle = Lifecycle::new(&notifier);
+    loop {
+        match exec(&mut rcon, profile, list) {
+            Ok(response) => {
+                lifecycle.up();
+                let text: String = response.iter().map(|p| p.body()).collect();
+                let current: BTreeMap<String, Player> = game
+                    .profile()
+                    .parse_players(&text)
+                    .into_iter()
+                    .filter(|p| watched(&watchlist, p))
+                    .map(|p| (key(&p), p))
+                    .collect();
+
+                match &online {
+                    None => {
+                        let names: Vec<&str> = current.values().map(|p| p.name.as_str()).collect();
+                        let watching = format!(
+                            "Watching {} players online: {}",
+                            names.len(),
+                            names.join(", ")
+                        );
+                        if parsed {
+                            eprintln!("{}", watching);
+                        } else {
+                            println!("{}", watching);
+                        }
+                    }
+                    Some(previous) => {
+                        for (k, p) in &current {
+                            if !previous.contains_key(k) {
+                                report(&notifier, "join", p, format);
+                            }
+                        }
+                        for (k, p) in previous {
+                            if !current.contains_key(k) {
+                                report(&notifier, "leave", p, format);
+                            }
+                        }
+                    }
+                }
+                online = Some(current);
+            }
+            Err(e) => {
+                eprintln!("Unable to poll the player list, retrying...");
+                lifecycle.down(&e);
+            }
+        }
+
+        if systemd::sleep(&stop, args.interval) {
+            if parsed {
+                eprintln!("Stopping");
+            } else {
+                println!("Stopping");
+            }
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use super::{connect, diff, exec, read_list, targets};
+use crate::{Args, Profile, RconError, WhitelistSyncArgs};
+
+/// Converge every profile's whitelist on the source list, carrying on past failing servers
+pub fn sync(args: &Args, sync: &WhitelistSyncArgs) -> Result<(), RconError> {
+    let names = targets(args, &sync.profiles)?;
+    let canonical = read_list(&sync.source)?;
+    let mut result = Ok(());
+
+    for name in &names {
+        let profile = match args.resolve_named(name) {
+            Ok(profile) => profile,
+            Err(e) => {
+                eprintln!("{}", e);
+                result = Err(RconError::TaskFailed);
+                continue;
+            }
+        };
+        if let Err(e) = sync_server(&profile, &canonical) {
+            eprintln!("{}: whitelist sync failed", name);
+            result = Err(e);
+        }
+    }
+    result
+}
+
+fn sync_server(profile: &Profile, canonical: &[String]) -> Result<(), RconError> {
+    let label = profile.name.as_deref().unwrap_or_default();
+    let mut rcon = connect(profile)?;
+    let game = rcon.game();
+    let list = game.profile().whitelist_command().ok_or_else(|| {
+        eprintln!("{}: {} has no whitelist command", label, game);
+        RconError::Unsupported
+    })?;
+
+    let response: String = exec(&mut rcon, profile, list)?
+        .iter()
+        .map(|p| p.body())
+        .collect();
+    let current = game.profile().parse_whitelist(&response);
+    let diff = diff(canonical, &current);
+    if diff.add.is_empty() && diff.remove.is_empty() {
+        println!("{}: in sync ({} players)", label, current.len());
+        return Ok(());
+    }
+
+    println!("{}:", label);
+    let edits = diff
+        .add
+        .iter()
+        .map(|n| ('+', *n, game.profile().whitelist_add_command(n)))
+        .chain(
+            diff.remove
+                .iter()
+                .map(|n| ('-', *n, game.profile().whitelist_remove_command(n))),
+        );
+    for (sign, player, cmd) in edits {
+        let cmd = cmd.ok_or_else(|| {
+            eprintln!("{}: {} can't edit the whitelist", label, game);
+            RconError::Unsupported
+        })?;
+        exec(&mut rcon, profile, &cmd)?;
+        println!("  {} {}", sign, player);
+    }
+    Ok(())
+}
@@ -0,0 +1,133 @@
+/*
+ * Block-style YAML for `--format yaml`, written out from the same JSON values `--format ansible`
+ * prints.
+ *
+ * Strings are left plain only when no YAML reader could take them for anything else, and are
+ * double-quoted with JSON's escapes otherwise, which YAML reads the same way.
+ */
+
+use serde_json::Value;
+
+/// Words YAML 1.1 readers take for booleans or null when left unquoted
+const RESERVED: [&str; 11] = [
+    "true", "false", "yes", "no", "on", "off", "y", "n", "null", "none", "~",
+];
+
+/// A YAML document of `value`, ending in a newline
+pub fn to_yaml(value: &Value) -> String {
+    let mut out = String::new();
+    match value {
+        Value::Object(map) if !map.is_empty() => block(value, 0, &mut out),
+        Value::Array(items) if !items.is_empty() => block(value, 0, &mut out),
+        _ => {
+            out.push_str(&scalar(value));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// One entry of a top-level sequence, for items printed one at a time as they happen
+pub fn to_yaml_item(value: &Value) -> String {
+    to_yaml(&Value::Array(vec![value.clone()]))
+}
+
+/// Write a non-empty object or array as indented lines
+fn block(value: &Value, indent: usize, out: &mut String) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                out.push_str(&pad);
+                out.push_str(&string(key));
+                out.push(':');
+                nested(value, indent + 2, out);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                out.push_str(&pad);
+                out.push('-');
+                match item {
+                    // The first key goes on the dash's line, the rest line up under it
+                    Value::Object(map) if !map.is_empty() => {
+                        let mut inner = String::new();
+                        block(item, indent + 2, &mut inner);
+                        out.push(' ');
+                        out.push_str(&inner[indent + 2..]);
+                    }
+                    _ => nested(item, indent + 2, out),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The rest of a line ending in `:` or `-`, and the lines of the value under it
+fn nested(value: &Value, indent: usize, out: &mut String) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            out.push('\n');
+            block(value, indent, out);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            block(value, indent, out);
+        }
+        _ => {
+            out.push(' ');
+            out.push_str(&scalar(value));
+            out.push('\n');
+        }
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(s) => string(s),
+        Value::Object(_) => "{}".to_string(),
+        Value::Array(_) => "[]".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+/// A string plain when it starts with a letter and holds nothing YAML gives a meaning to
+fn string(s: &str) -> String {
+    let plain = s.starts_with(|c: char| c.is_ascii_alphabetic())
+        && !s.ends_with(' ')
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || " _-./,()@%+".contains(c))
+        && !RESERVED.iter().any(|r| r.eq_ignore_ascii_case(s));
+    if plain {
+        s.to_string()
+    } else {
+        Value::from(s).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn strings_a_reader_could_misread_are_quoted() {
+        let value = json!({
+            "answer": "no",
+            "bans": [],
+            "motd": "a: b",
+            "name": "Steve",
+            "ok": true,
+            "players": [{ "name": "Alex", "ping": 12 }, "Null"],
+            "port": "27015",
+        });
+        assert_eq!(
+            to_yaml(&value),
+            "answer: \"no\"\nbans: []\nmotd: \"a: b\"\nname: Steve\nok: true\nplayers:\n  - name: \
+             Alex\n    ping: 12\n  - \"Null\"\nport: \"27015\"\n"
+        );
+        assert_eq!(to_yaml(&json!({})), "{}\n");
+        assert_eq!(to_yaml_item(&json!("x")), "- x\n");
+    }
+}
@@ -0,0 +1,537 @@
+/*
+ * Configuration file with named server profiles.
+ */
+
+use crate::{
+    highlight::{Highlights, Rule},
+    notify::NotifyConfig,
+    server_files,
+    table::SortKey,
+    Args, ClientConfig, Game, Protocol, TableStyle,
+};
+pub use rustcon_core::config::RotationConfig;
+use rustcon_core::config::{DEFAULT_AUTH_ATTEMPTS, DEFAULT_MAX_RESPONSE_BYTES};
+use serde::{Deserialize, Deserializer};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    env, fmt, fs, io,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const DEFAULT_IP: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 27015;
+
+/// Contents of the config file
+///
+/// ```toml
+/// [profiles.mc]
+/// ip = "10.0.0.5"
+/// port = 25575
+/// password = "hunter2"
+/// game = "minecraft"
+///
+/// [profiles.mc.schedule]
+/// "0 4 * * *" = "save-all"
+///
+/// [[profiles.mc.triggers]]
+/// pattern = "<(\\w+)> .*free diamonds"
+/// command = "kick $1"
+///
+/// [groups]
+/// eu = ["eu1", "eu2"]
+///
+/// [[tokens]]
+/// token = "c2VjcmV0"
+/// name = "discord-bot"
+/// servers = ["mc"]
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Server profiles by name
+    #[serde(default)]
+    pub profiles: HashMap<String, ProfileConfig>,
+    /// Lists of profile names selected together with `--group`
+    #[serde(default)]
+    pub groups: HashMap<String, Vec<String>>,
+    /// Bearer tokens the bridges accept, anyone can use them while there are none
+    #[serde(default)]
+    pub tokens: Vec<TokenConfig>,
+    /// Requests each client of the bridges can make, per token or per address without tokens
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+/// A `[profiles.<name>]` table, every field falls back to the command line or its default
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    pub ip: Option<String>,
+    pub port: Option<u16>,
+    pub password: Option<String>,
+    pub game: Option<Game>,
+    pub protocol: Option<Protocol>,
+    /// Archive command run by `backup` while saving is paused
+    pub backup_command: Option<String>,
+    /// Commands run by `schedule`, keyed by cron expression
+    #[serde(default)]
+    pub schedule: BTreeMap<String, String>,
+    /// Rules run by `triggers`
+    #[serde(default)]
+    pub triggers: Vec<TriggerConfig>,
+    /// Commands polled by `exporter` [default: the game's player list and stats commands]
+    pub metrics_commands: Option<Vec<String>>,
+    /// Where events like joins, trigger matches and lost connections are announced
+    pub notify: Option<NotifyConfig>,
+    /// JSON lines file recording every command sent to the server
+    pub audit_log: Option<PathBuf>,
+    /// File `schedule` appends each command and its response to, as it prints them
+    pub transcript_log: Option<PathBuf>,
+    /// When the audit and transcript logs are rotated
+    pub log_rotation: Option<RotationConfig>,
+    /// Commands needing confirmation on top of the game's own, a name or the start of a line
+    #[serde(default)]
+    pub dangerous_commands: Vec<String>,
+    /// How long the bridges reuse a command's response, keyed by the command, like
+    /// `status = "5s"`
+    #[serde(default, deserialize_with = "durations")]
+    pub cache: BTreeMap<String, Duration>,
+    /// Passwords tried at the shell's prompt before giving up
+    pub auth_attempts: Option<u32>,
+    /// Largest response kept in memory, in bytes
+    pub max_response_bytes: Option<u64>,
+    /// Rules coloring what matches them in the output shown in the terminal
+    #[serde(default)]
+    pub highlights: Vec<Rule>,
+    /// Commands sent after every login, reconnects included
+    #[serde(default)]
+    pub init_commands: Vec<String>,
+    /// Keep the init commands' responses out of the shell
+    pub quiet_init: Option<bool>,
+}
+
+/// A `[[profiles.<name>.triggers]]` rule firing its actions when `pattern` matches server output
+///
+/// Actions can refer to the pattern's capture groups as `$1` or `${name}`, and `run` commands get
+/// the matching line in `RUSTCON_MATCH`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TriggerConfig {
+    /// Regular expression matched against each line of output
+    pub pattern: String,
+    /// Command whose response is checked every `interval`, instead of pushed console output
+    pub poll: Option<String>,
+    #[serde(default, deserialize_with = "duration")]
+    pub interval: Option<Duration>,
+    /// RCON command to send
+    pub command: Option<String>,
+    /// Local shell command to run
+    pub run: Option<String>,
+    /// URL to POST the match to as JSON
+    pub webhook: Option<String>,
+    /// Message sent to the profile's notification targets
+    pub notify: Option<String>,
+}
+
+/// A `[[tokens]]` entry, sent to the bridges as `Authorization: Bearer <token>`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TokenConfig {
+    pub token: String,
+    /// Who holds the token, shown in logs instead of the token itself
+    pub name: Option<String>,
+    /// Profiles the token can reach [default: all of them]
+    pub servers: Option<Vec<String>>,
+    /// Names of the commands the token can run [default: any]
+    pub commands: Option<Vec<String>>,
+    /// The token's own rate limit, instead of the config's
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+/// `requests` allowed every `per`, in bursts of up to `requests`
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub requests: NonZeroU32,
+    /// [default: 1m]
+    #[serde(default, deserialize_with = "duration")]
+    pub per: Option<Duration>,
+}
+
+/// Parse durations written like on the command line, `"90s"` or `"10m"`
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    crate::duration::parse_duration(&text)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn durations<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, Duration>, D::Error> {
+    BTreeMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, text)| {
+            crate::duration::parse_duration(&text)
+                .map(|duration| (key, duration))
+                .map_err(serde::de::Error::custom)
+        })
+        .collect()
+}
+
+impl ProfileConfig {
+    /// Fields set in `other` take precedence over this profile's
+    pub fn overlay(self, other: ProfileConfig) -> ProfileConfig {
+        ProfileConfig {
+            ip: other.ip.or(self.ip),
+            port: other.port.or(self.port),
+            password: other.password.or(self.password),
+            game: other.game.or(self.game),
+            protocol: other.protocol.or(self.protocol),
+            backup_command: other.backup_command.or(self.backup_command),
+            schedule: if other.schedule.is_empty() {
+                self.schedule
+            } else {
+                other.schedule
+            },
+            triggers: if other.triggers.is_empty() {
+                self.triggers
+            } else {
+                other.triggers
+            },
+            metrics_commands: other.metrics_commands.or(self.metrics_commands),
+            notify: other.notify.or(self.notify),
+            audit_log: other.audit_log.or(self.audit_log),
+            transcript_log: other.transcript_log.or(self.transcript_log),
+            log_rotation: other.log_rotation.or(self.log_rotation),
+            dangerous_commands: if other.dangerous_commands.is_empty() {
+                self.dangerous_commands
+            } else {
+                other.dangerous_commands
+            },
+            cache: if other.cache.is_empty() {
+                self.cache
+            } else {
+                other.cache
+            },
+            auth_attempts: other.auth_attempts.or(self.auth_attempts),
+            max_response_bytes: other.max_response_bytes.or(self.max_response_bytes),
+            init_commands: if other.init_commands.is_empty() {
+                self.init_commands
+            } else {
+                other.init_commands
+            },
+            quiet_init: other.quiet_init.or(self.quiet_init),
+            highlights: if other.highlights.is_empty() {
+                self.highlights
+            } else {
+                other.highlights
+            },
+        }
+    }
+}
+
+/// Connection settings after merging the command line over a config profile
+#[derive(Clone, Debug)]
+pub struct Profile {
+    /// Name of the config profile these settings came from
+    pub name: Option<String>,
+    pub ip: String,
+    pub port: u16,
+    pub password: Option<String>,
+    /// `None` to detect the game after connecting
+    pub game: Option<Game>,
+    pub protocol: Protocol,
+    pub backup_command: Option<String>,
+    pub schedule: BTreeMap<String, String>,
+    pub triggers: Vec<TriggerConfig>,
+    pub metrics_commands: Option<Vec<String>>,
+    pub notify: NotifyConfig,
+    pub audit_log: Option<PathBuf>,
+    pub transcript_log: Option<PathBuf>,
+    pub log_rotation: RotationConfig,
+    /// `--allow-commands`, the only commands sessions send
+    pub allow_commands: Option<Vec<String>>,
+    pub dangerous_commands: Vec<String>,
+    pub cache: BTreeMap<String, Duration>,
+    /// Passwords tried at the shell's prompt before giving up
+    pub auth_attempts: u32,
+    /// `--yes`, dangerous commands are sent without asking
+    pub assume_yes: bool,
+    /// `--raw-output`, server output is shown without escaping control characters
+    pub raw_output: bool,
+    /// `--tables`, how the shell shows responses that parse into rows
+    pub tables: TableStyle,
+    /// `--sort`, the column those rows are sorted by
+    pub sort: Option<SortKey>,
+    /// The config's `highlights`, and those added with `:highlight` since
+    pub highlights: Highlights,
+    /// `--insecure-ok`, passwords go to servers outside the local network without a warning
+    pub insecure_ok: bool,
+    /// `--protocol-strict`, servers straying from the protocol fail commands instead of being
+    /// worked around
+    pub protocol_strict: bool,
+    /// Largest response kept in memory, in bytes
+    pub max_response_bytes: usize,
+    /// `--spill-responses`, what's past `max_response_bytes` goes to a temp file
+    pub spill_responses: bool,
+    /// Sent after every login, reconnects included
+    pub init_commands: Vec<String>,
+    /// The shell doesn't show the init commands' responses
+    pub quiet_init: bool,
+}
+
+impl Profile {
+    /// What the client needs of these settings to open a session
+    pub fn client(&self) -> ClientConfig {
+        ClientConfig {
+            name: self.name.clone(),
+            ip: self.ip.clone(),
+            port: self.port,
+            password: self.password.clone(),
+            game: self.game,
+            protocol: self.protocol,
+            audit_log: self.audit_log.clone(),
+            log_rotation: self.log_rotation,
+            allow_commands: self.allow_commands.clone(),
+            auth_attempts: self.auth_attempts,
+            insecure_ok: self.insecure_ok,
+            protocol_strict: self.protocol_strict,
+            max_response_bytes: self.max_response_bytes,
+            spill_responses: self.spill_responses,
+            init_commands: self.init_commands.clone(),
+        }
+    }
+
+    /// Server output ready to show in a terminal, escaped unless `--raw-output` is given, with
+    /// its highlights colored
+    pub fn display(&self, text: &str) -> String {
+        let text = if self.raw_output {
+            text.into()
+        } else {
+            crate::sanitize::sanitize(text)
+        };
+        self.highlights.paint(text).into_owned()
+    }
+
+    /// Whether `cmd` is one of the game's or the profile's dangerous commands, which match the
+    /// command's name or, when they span several words, the start of the line
+    pub fn is_dangerous(&self, game: Game, cmd: &str) -> bool {
+        let cmd = cmd.trim();
+        let builtin = game.profile().dangerous_commands().iter().copied();
+        let custom = self.dangerous_commands.iter().map(String::as_str);
+        builtin.chain(custom).any(|danger| {
+            let danger = danger.trim();
+            cmd.get(..danger.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(danger))
+                && cmd[danger.len()..]
+                    .chars()
+                    .next()
+                    .is_none_or(char::is_whitespace)
+        })
+    }
+
+    /// Refuse a dangerous command in a subcommand or script, unless `--yes` was given
+    pub fn check_dangerous(&self, game: Game, cmd: &str) -> Result<(), String> {
+        if self.assume_yes || !self.is_dangerous(game, cmd) {
+            Ok(())
+        } else {
+            Err(format!(
+                "{:?} could cause the server to shut down or lose data, pass --yes to send it",
+                cmd
+            ))
+        }
+    }
+}
+
+/// Config file loading error
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    UnknownProfile(String),
+    UnknownGroup(String),
+    /// Neither `server.properties` nor `server.cfg` exists in the server directory
+    NoServerConfig(PathBuf),
+    InvalidServerConfig(PathBuf, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Unable to read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "Invalid config {}: {}", path.display(), e),
+            ConfigError::UnknownProfile(name) => write!(f, "No profile named {:?}", name),
+            ConfigError::UnknownGroup(name) => write!(f, "No group named {:?}", name),
+            ConfigError::NoServerConfig(dir) => write!(
+                f,
+                "No server.properties or server.cfg found in {}",
+                dir.display()
+            ),
+            ConfigError::InvalidServerConfig(path, e) => {
+                write!(f, "Invalid server config {}: {}", path.display(), e)
+            }
+        }
+    }
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/rustcon/config.toml`, `~/.config/rustcon/config.toml` or
+    /// `%APPDATA%\rustcon\config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+        Some(dir.join("rustcon").join("config.toml"))
+    }
+
+    /// Load the config at `path`, or the default location if it exists
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        let path = match path {
+            Some(p) => p.to_path_buf(),
+            None => match Config::default_path() {
+                Some(p) if p.exists() => p,
+                _ => return Ok(Config::default()),
+            },
+        };
+
+        let text = fs::read_to_string(&path).map_err(|e| ConfigError::Io(path.clone(), e))?;
+        toml::from_str(&text).map_err(|e| ConfigError::Parse(path, e))
+    }
+
+    /// Look up a profile by name
+    pub fn profile(&self, name: &str) -> Result<&ProfileConfig, ConfigError> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| ConfigError::UnknownProfile(name.to_string()))
+    }
+
+    /// Look up a group's profile names
+    pub fn group(&self, name: &str) -> Result<&[String], ConfigError> {
+        self.groups
+            .get(name)
+            .map(Vec::as_slice)
+            .ok_or_else(|| ConfigError::UnknownGroup(name.to_string()))
+    }
+}
+
+impl Args {
+    /// Profile names given on the command line followed by the members of `--group`, without
+    /// repeats
+    pub fn targets(&self, names: &[String]) -> Result<Vec<String>, ConfigError> {
+        let mut targets = names.to_vec();
+        if let Some(group) = &self.group {
+            let config = Config::load(self.config.as_deref())?;
+            targets.extend(config.group(group)?.iter().cloned());
+        }
+        let mut seen = Vec::new();
+        targets.retain(|name| {
+            let new = !seen.contains(name);
+            if new {
+                seen.push(name.clone());
+            }
+            new
+        });
+        Ok(targets)
+    }
+
+    /// Resolve connection settings from the command line and the selected config profile
+    pub fn resolve(&self) -> Result<Profile, ConfigError> {
+        let config = Config::load(self.config.as_deref())?;
+        let mut base = match &self.profile {
+            Some(name) => config.profile(name)?.clone(),
+            None => ProfileConfig::default(),
+        };
+        if let Some(dir) = &self.from_server_dir {
+            base = base.overlay(server_files::read_server_dir(dir)?);
+        }
+
+        Ok(self.merge(self.profile.clone(), base, self.ip.clone(), self.port))
+    }
+
+    /// Resolve connection settings for another config profile, as used by subcommands
+    /// spanning several servers
+    ///
+    /// `--ip`, `--port` and `--from-server-dir` describe a single server so only `--game` and
+    /// `--protocol` are applied over the profile.
+    pub fn resolve_named(&self, name: &str) -> Result<Profile, ConfigError> {
+        let config = Config::load(self.config.as_deref())?;
+        let base = config.profile(name)?.clone();
+        Ok(self.merge(Some(name.to_string()), base, None, None))
+    }
+
+    /// Fill in a profile's settings with the command line's and the defaults
+    fn merge(
+        &self,
+        name: Option<String>,
+        base: ProfileConfig,
+        ip: Option<String>,
+        port: Option<u16>,
+    ) -> Profile {
+        Profile {
+            name,
+            ip: ip.or(base.ip).unwrap_or_else(|| DEFAULT_IP.to_string()),
+            port: port.or(base.port).unwrap_or(DEFAULT_PORT),
+            password: base.password,
+            game: self.game.or(base.game),
+            protocol: self.protocol.or(base.protocol).unwrap_or(Protocol::Source),
+            backup_command: base.backup_command,
+            schedule: base.schedule,
+            triggers: base.triggers,
+            metrics_commands: base.metrics_commands,
+            notify: base.notify.unwrap_or_default(),
+            audit_log: base.audit_log,
+            transcript_log: base.transcript_log,
+            log_rotation: base.log_rotation.unwrap_or_default(),
+            dangerous_commands: base.dangerous_commands,
+            cache: base.cache,
+            auth_attempts: self
+                .auth_attempts
+                .or(base.auth_attempts)
+                .unwrap_or(DEFAULT_AUTH_ATTEMPTS)
+                .max(1),
+            assume_yes: self.yes,
+            raw_output: self.raw_output,
+            tables: self.tables,
+            sort: self.sort.clone(),
+            highlights: Highlights::new(base.highlights),
+            insecure_ok: self.insecure_ok,
+            protocol_strict: self.protocol_strict,
+            max_response_bytes: usize::try_from(
+                self.max_response_bytes
+                    .or(base.max_response_bytes)
+                    .unwrap_or(DEFAULT_MAX_RESPONSE_BYTES)
+                    .max(1),
+            )
+            .unwrap_or(usize::MAX),
+            spill_responses: self.spill_responses,
+            init_commands: base.init_commands,
+            quiet_init: base.quiet_init.unwrap_or(false),
+            allow_commands: if self.allow_commands.is_empty() {
+                None
+            } else {
+                Some(self.allow_commands.clone())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Args;
+    use clap::Parser;
+
+    #[test]
+    fn server_output_is_escaped_unless_raw_output() {
+        let profile = |flags: &[&str]| {
+            let args = [&["rustcon", "--config", "/dev/null"][..], flags].concat();
+            Args::try_parse_from(args).unwrap().resolve().unwrap()
+        };
+        let text = "\x1b]0;pwned\x07Steve\tjoined\n";
+        assert_eq!(profile(&[]).display(text), "^[]0;pwned^GSteve\tjoined\n");
+        assert_eq!(profile(&["--raw-output"]).display(text), text);
+    }
+}
@@ -0,0 +1,173 @@
+/*
+ * Five-field cron expressions such as `0 4 * * *` or `30 6 * * 1-5`.
+ */
+
+use chrono::{Datelike, Timelike};
+
+/// A parsed `minute hour day-of-month month day-of-week` expression
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day-of-month or day-of-week was restricted, cron matches either when both are
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+/// Set of allowed values of one field as a bitmask
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("bad step {:?}", step))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let parse = |n: &str| -> Result<u32, String> {
+            n.parse()
+                .ok()
+                .filter(|n| (min..=max).contains(n))
+                .ok_or_else(|| format!("{:?} is outside {}-{}", n, min, max))
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            // `5/15` means every 15 starting at 5
+            None if step > 1 => (parse(range)?, max),
+            None => {
+                let n = parse(range)?;
+                (n, n)
+            }
+        };
+        if start > end {
+            return Err(format!("empty range {:?}", range));
+        }
+        for n in (start..=end).step_by(step as usize) {
+            mask |= 1 << n;
+        }
+    }
+    Ok(mask)
+}
+
+impl Schedule {
+    /// Parse a standard five-field expression, or one of `@hourly`, `@daily`, `@weekly`,
+    /// `@monthly` and `@yearly`
+    pub fn parse(expr: &str) -> Result<Schedule, String> {
+        let expr = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            expr => expr,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "expected five fields (minute hour day month weekday), got {:?}",
+                expr
+            ));
+        }
+
+        let err = |name: &'static str| {
+            move |e: String| format!("{} in the {} field of {:?}", e, name, expr)
+        };
+        let mut weekdays = parse_field(fields[4], 0, 7).map_err(err("weekday"))?;
+        // Both 0 and 7 are Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Schedule {
+            minutes: parse_field(fields[0], 0, 59).map_err(err("minute"))?,
+            hours: parse_field(fields[1], 0, 23).map_err(err("hour"))?,
+            days: parse_field(fields[2], 1, 31).map_err(err("day"))?,
+            months: parse_field(fields[3], 1, 12).map_err(err("month"))?,
+            weekdays,
+            days_restricted: fields[2] != "*",
+            weekdays_restricted: fields[4] != "*",
+        })
+    }
+
+    /// Whether the schedule fires during the minute of `time`
+    pub fn matches<T: Datelike + Timelike>(&self, time: &T) -> bool {
+        let has = |mask: u64, n: u32| mask & (1 << n) != 0;
+        let day = has(self.days, time.day());
+        let weekday = has(self.weekdays, time.weekday().num_days_from_sunday());
+        let day_matches = match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            _ => day && weekday,
+        };
+
+        has(self.minutes, time.minute())
+            && has(self.hours, time.hour())
+            && has(self.months, time.month())
+            && day_matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cron_fields_take_ranges_steps_and_lists() {
+        // June 2nd, 2024 is a Sunday
+        let at = |day, hour, minute| {
+            chrono::NaiveDate::from_ymd_opt(2024, 6, day)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap()
+        };
+        let weekdays = Schedule::parse("30 6 * * 1-5").unwrap();
+        assert!(weekdays.matches(&at(3, 6, 30)));
+        assert!(!weekdays.matches(&at(2, 6, 30)));
+        assert!(!weekdays.matches(&at(3, 6, 31)));
+        let steps = Schedule::parse("*/15 0-12/6 * * *").unwrap();
+        assert!(steps.matches(&at(3, 12, 45)));
+        assert!(!steps.matches(&at(3, 12, 50)));
+        assert!(!steps.matches(&at(3, 3, 0)));
+        // A single value before the step is where the steps start
+        let offset = Schedule::parse("5/20 * * * *").unwrap();
+        assert!([5, 25, 45].iter().all(|m| offset.matches(&at(3, 1, *m))));
+        assert!(!offset.matches(&at(3, 1, 0)));
+        let lists = Schedule::parse("0,30 4 1,15-16 * *").unwrap();
+        assert!(lists.matches(&at(16, 4, 30)));
+        assert!(!lists.matches(&at(14, 4, 30)));
+        // Sunday is 0 and 7, and either day field matches when both are restricted
+        let either = Schedule::parse("0 0 13 * 7").unwrap();
+        assert!(either.matches(&at(2, 0, 0)));
+        assert!(either.matches(&at(13, 0, 0)));
+        assert!(!either.matches(&at(14, 0, 0)));
+        assert_eq!(Schedule::parse("@daily"), Schedule::parse("0 0 * * *"));
+    }
+
+    #[test]
+    fn cron_expressions_with_bad_fields_are_rejected() {
+        for expr in [
+            "0 4 * *",
+            "0 4 * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "10-5 * * * *",
+            "1,,2 * * * *",
+            "@often",
+        ] {
+            assert!(Schedule::parse(expr).is_err(), "{}", expr);
+        }
+        let err = Schedule::parse("0 4 * * mon").unwrap_err();
+        assert!(err.contains("weekday field"), "{}", err);
+    }
+}
@@ -0,0 +1,213 @@
+/*
+ * Colors painted over what matches the profile's `highlights` and the shell's `:highlight`
+ * rules, once server output has been made safe to show.
+ *
+ * Output only gets colors when stdout is a terminal, so piped responses stay plain text. Where
+ * rules overlap, the match starting first wins, then the rule listed first.
+ */
+
+use crate::table::stdout_is_terminal;
+use serde::{Deserialize, Deserializer};
+use std::{
+    borrow::Cow,
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+/// Colors a rule can paint in
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    Red,
+    Green,
+    #[default]
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    Bold,
+}
+
+impl Color {
+    const ALL: [Color; 7] = [
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::Bold,
+    ];
+
+    pub fn parse(name: &str) -> Option<Color> {
+        Color::ALL
+            .iter()
+            .copied()
+            .find(|c| c.to_string().eq_ignore_ascii_case(name))
+    }
+
+    /// The SGR parameter turning the color on
+    fn code(self) -> u8 {
+        match self {
+            Color::Red => 31,
+            Color::Green => 32,
+            Color::Yellow => 33,
+            Color::Blue => 34,
+            Color::Magenta => 35,
+            Color::Cyan => 36,
+            Color::Bold => 1,
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Color::Red => "red",
+            Color::Green => "green",
+            Color::Yellow => "yellow",
+            Color::Blue => "blue",
+            Color::Magenta => "magenta",
+            Color::Cyan => "cyan",
+            Color::Bold => "bold",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A `[[profiles.<name>.highlights]]` rule
+///
+/// ```toml
+/// [[profiles.mc.highlights]]
+/// pattern = "\\b(ERROR|SEVERE)\\b"
+/// color = "red"
+/// line = true
+/// ```
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(deserialize_with = "regex")]
+    pub pattern: regex::Regex,
+    #[serde(default)]
+    pub color: Color,
+    /// Paint the whole line a match is on rather than only the match
+    #[serde(default)]
+    pub line: bool,
+}
+
+fn regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<regex::Regex, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    regex::Regex::new(&text).map_err(serde::de::Error::custom)
+}
+
+/// Rules shared by everything showing a profile's output, so the ones added in the shell apply
+/// to the followed log as well
+#[derive(Clone, Debug, Default)]
+pub struct Highlights(Arc<RwLock<Vec<Rule>>>);
+
+impl Highlights {
+    pub fn new(rules: Vec<Rule>) -> Highlights {
+        Highlights(Arc::new(RwLock::new(rules)))
+    }
+
+    pub fn add(&self, rule: Rule) {
+        self.0.write().unwrap_or_else(|e| e.into_inner()).push(rule);
+    }
+
+    pub fn clear(&self) {
+        self.0.write().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    pub fn rules(&self) -> Vec<Rule> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// `text` with the rules' matches colored, when stdout is a terminal
+    pub fn paint<'a>(&self, text: Cow<'a, str>) -> Cow<'a, str> {
+        let rules = self.0.read().unwrap_or_else(|e| e.into_inner());
+        if rules.is_empty() || !stdout_is_terminal() {
+            return text;
+        }
+        match paint(&rules, &text) {
+            Some(painted) => Cow::Owned(painted),
+            None => text,
+        }
+    }
+}
+
+/// `text` with escape sequences around what `rules` match, or `None` when nothing does
+pub fn paint(rules: &[Rule], text: &str) -> Option<String> {
+    let mut spans = Vec::new();
+    for rule in rules {
+        for m in rule.pattern.find_iter(text) {
+            if m.start() == m.end() {
+                continue;
+            }
+            let span = if rule.line {
+                let start = text[..m.start()].rfind('\n').map_or(0, |i| i + 1);
+                let end = text[m.end()..]
+                    .find('\n')
+                    .map_or(text.len(), |i| m.end() + i);
+                (start, end)
+            } else {
+                (m.start(), m.end())
+            };
+            spans.push((span, rule.color));
+        }
+    }
+    if spans.is_empty() {
+        return None;
+    }
+    // Stable, so at the same start the rule listed first wins
+    spans.sort_by_key(|((start, _), _)| *start);
+
+    let mut out = String::with_capacity(text.len() + spans.len() * 9);
+    let mut at = 0;
+    for ((start, end), color) in spans {
+        if start < at {
+            continue;
+        }
+        out.push_str(&text[at..start]);
+        out.push_str(&format!(
+            "\x1b[{}m{}\x1b[0m",
+            color.code(),
+            &text[start..end]
+        ));
+        at = end;
+    }
+    out.push_str(&text[at..]);
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_color_matches_or_their_lines() {
+        let rule = |pattern, color, line| Rule {
+            pattern: regex::Regex::new(pattern).unwrap(),
+            color,
+            line,
+        };
+        let text = "[INFO] Steve joined\n[WARN] Can't keep up\n";
+        let rules = [
+            rule("Steve", Color::Cyan, false),
+            rule("WARN", Color::Red, true),
+        ];
+        assert_eq!(
+            paint(&rules, text).unwrap(),
+            "[INFO] \x1b[36mSteve\x1b[0m joined\n\x1b[31m[WARN] Can't keep up\x1b[0m\n"
+        );
+        // The first match wins where rules overlap
+        let rules = [
+            rule("joined", Color::Green, false),
+            rule("INFO", Color::Bold, true),
+        ];
+        assert_eq!(
+            paint(&rules, text).unwrap(),
+            "\x1b[1m[INFO] Steve joined\x1b[0m\n[WARN] Can't keep up\n"
+        );
+        assert!(paint(&rules[..1], "nothing here").is_none());
+    }
+}
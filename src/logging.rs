@@ -0,0 +1,156 @@
+/*
+ * What the daemon modes log, as the lines they've always printed or, with `--log-format json`, as
+ * one JSON object per event for Loki, Elasticsearch and the like.
+ *
+ * Every object has the `timestamp`, `level` and `message`, plus the fields the event carries, like
+ * `profile`, `command`, `latency_ms` and `error`. Info goes to stdout and warnings and errors to
+ * stderr, whichever the format. What rustcon-core logs through the `log` crate comes out as events
+ * too, all of it on stderr to keep it out of the responses.
+ */
+
+use crate::LogFormat;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// Whether events are logged as JSON, set once from `--log-format`
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::Relaxed);
+    // Only fails if a logger is already installed
+    if log::set_logger(&Core).is_ok() {
+        log::set_max_level(log::LevelFilter::Info);
+    }
+}
+
+/// Logs what rustcon-core logs as events
+struct Core;
+
+impl log::Log for Core {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info && metadata.target().starts_with("rustcon_core")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let level = match record.level() {
+            log::Level::Error => Level::Error,
+            log::Level::Warn => Level::Warn,
+            _ => Level::Info,
+        };
+        let mut event = Event::new(level, record.args().to_string());
+        event.stderr = true;
+        event.log();
+    }
+
+    fn flush(&self) {}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+/// An event to log, with the fields it's built up with
+#[must_use = "events are only written by `log`"]
+pub struct Event {
+    level: Level,
+    message: String,
+    fields: Map<String, Value>,
+    /// Printed instead of the message in text logs, `None` leaves the event out of them
+    text: Option<String>,
+    /// Written to stderr whatever the level
+    stderr: bool,
+}
+
+impl Event {
+    fn new(level: Level, message: String) -> Event {
+        Event {
+            level,
+            text: Some(message.clone()),
+            message,
+            fields: Map::new(),
+            stderr: false,
+        }
+    }
+
+    pub fn info(message: impl Into<String>) -> Event {
+        Event::new(Level::Info, message.into())
+    }
+
+    pub fn warn(message: impl Into<String>) -> Event {
+        Event::new(Level::Warn, message.into())
+    }
+
+    pub fn error(message: impl Into<String>) -> Event {
+        Event::new(Level::Error, message.into())
+    }
+
+    pub fn field(mut self, key: &str, value: impl Serialize) -> Event {
+        let value = serde_json::to_value(value).unwrap_or(Value::Null);
+        self.fields.insert(key.to_string(), value);
+        self
+    }
+
+    pub fn profile(self, profile: &str) -> Event {
+        self.field("profile", profile)
+    }
+
+    pub fn command(self, command: &str) -> Event {
+        self.field("command", command)
+    }
+
+    pub fn latency(self, took: Duration) -> Event {
+        self.field("latency_ms", took.as_secs_f64() * 1000.0)
+    }
+
+    pub fn reason(self, error: impl fmt::Debug) -> Event {
+        self.field("error", format!("{:?}", error))
+    }
+
+    /// Print `text` in text logs instead of the message
+    pub fn text(mut self, text: impl Into<String>) -> Event {
+        self.text = Some(text.into());
+        self
+    }
+
+    /// Leave the event out of text logs, for the per-command events they've never had
+    pub fn json_only(mut self) -> Event {
+        self.text = None;
+        self
+    }
+
+    pub fn log(self) {
+        let line = if JSON.load(Ordering::Relaxed) {
+            let mut object = Map::new();
+            object.insert(
+                "timestamp".to_string(),
+                Value::String(chrono::Local::now().to_rfc3339()),
+            );
+            let level = serde_json::to_value(self.level).unwrap_or(Value::Null);
+            object.insert("level".to_string(), level);
+            object.insert("message".to_string(), Value::String(self.message));
+            object.extend(self.fields);
+            Value::Object(object).to_string()
+        } else {
+            match self.text {
+                Some(text) => text,
+                None => return,
+            }
+        };
+        match self.level {
+            Level::Info if !self.stderr => println!("{}", line),
+            _ => eprintln!("{}", line),
+        }
+    }
+}
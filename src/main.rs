@@ -1,28 +1,188 @@
-use clap::Parser;
-use rustcon::{Args, Rcon};
+use clap::{CommandFactory, FromArgMatches};
+use rustcon::{
+    audit, cmd, logging, record, session::Summary, Args, BansAction, Command, Game, Profile, Rcon,
+    RconError, WhitelistAction,
+};
 use std::{io, process::exit};
 
+/// Connect, authenticate, and run a single command translated for the server's game, printing
+/// the response bodies
+fn run_once<F>(profile: &Profile, translate: F) -> Result<(), RconError>
+where
+    F: FnOnce(Game) -> Option<String>,
+{
+    let mut rcon = cmd::connect(profile)?;
+
+    // Translate after login so a detected game picks the dialect
+    let translated = translate(rcon.game()).ok_or(RconError::Unsupported)?;
+    let response = cmd::send_checked(&mut rcon, profile, &translated).map_err(|e| {
+        if !matches!(e, RconError::NotAllowed) {
+            eprintln!("Unable to send the command: {translated}");
+        }
+        e
+    })?;
+    for p in response {
+        println!("{}", p.body());
+    }
+    Ok(())
+}
+
 fn main() -> io::Result<()> {
-    let args = Args::parse();
-    println!("Connecting to host at {}:{} ...", args.ip, args.port);
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // The audit log names the subcommand the commands came from
+    audit::set_default_source(if args.stdio_rpc {
+        "stdio-rpc"
+    } else if args.ndjson {
+        "ndjson"
+    } else {
+        matches.subcommand_name().unwrap_or("tty")
+    });
+    logging::set_format(args.log_format);
+    let profile = args.resolve().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(2);
+    });
+
+    if args.stdio_rpc {
+        exit(if cmd::stdio_rpc::run(&args, &profile).is_ok() {
+            0
+        } else {
+            1
+        });
+    }
+
+    if args.ndjson {
+        exit(if cmd::ndjson::run(&profile).is_ok() {
+            0
+        } else {
+            1
+        });
+    }
+
+    // `attach` is the shell with a log, which runs below
+    let attach = match &args.command {
+        Some(Command::Attach(attach)) => Some(attach),
+        _ => None,
+    };
+    if let Some(command) = args.command.as_ref().filter(|_| attach.is_none()) {
+        let result = match command {
+            Command::Exec(exec) => cmd::exec::run(&args, &profile, exec),
+            Command::Player(action) => run_once(&profile, |game| {
+                let cmd = game.profile().player_command(action);
+                if cmd.is_none() {
+                    eprintln!("{} has no equivalent of `player {}`", game, action.name());
+                }
+                cmd
+            }),
+            Command::Lua { print, code } => run_once(&profile, |game| {
+                let cmd = game.profile().lua_command(&code.join(" "), *print);
+                if cmd.is_none() {
+                    eprintln!("{} has no Lua console", game);
+                }
+                cmd
+            }),
+            Command::Announce(announce) => cmd::announce::run(&profile, announce),
+            Command::Restart(restart) => cmd::restart::run(&profile, restart),
+            Command::Backup(backup) => cmd::backup::run(&profile, backup),
+            Command::WatchPlayers(watch) => cmd::watch_players::run(&profile, watch, args.format),
+            Command::Chat(chat) => cmd::chat::run(&profile, chat),
+            Command::Attach(_) => unreachable!("attach runs the shell"),
+            Command::Whitelist(WhitelistAction::Sync(sync)) => cmd::whitelist::sync(&args, sync),
+            Command::Schedule => cmd::schedule::run(&args, &profile),
+            Command::Triggers => cmd::triggers::run(&profile),
+            Command::Exporter(exporter) => cmd::exporter::run(&args, &profile, exporter),
+            Command::ServeHttp(serve) => cmd::serve_http::run(&args, &profile, serve),
+            Command::ServeWs(serve) => cmd::serve_ws::run(&args, &profile, serve),
+            #[cfg(feature = "grpc")]
+            Command::ServeGrpc(serve) => cmd::serve_grpc::run(&args, &profile, serve),
+            Command::Bans(BansAction::Sync(sync)) => cmd::bans::sync(&args, sync),
+            Command::Healthcheck(check) => cmd::healthcheck::run(&profile, check, args.format),
+            Command::Ping(ping) => cmd::ping::run(&profile, ping),
+            Command::Doctor(doctor) => cmd::doctor::run(&profile, doctor),
+            Command::Discover(discover) => cmd::discover::run(&args, discover),
+            Command::Bulk(bulk) => cmd::bulk::run(&profile, bulk, args.format),
+            Command::Diff(diff) => cmd::drift::run(&args, &profile, diff),
+            #[cfg(any(feature = "scripting", feature = "lua"))]
+            Command::Script(script) => cmd::script::run(&profile, script),
+        };
+        exit(if result.is_ok() { 0 } else { 1 });
+    }
+
+    // Kept across reconnects, so the whole session ends up in one recording
+    let mut recording = match &args.record {
+        Some(path) => match record::Recording::create(path) {
+            Ok(recording) => Some(recording),
+            Err(e) => {
+                eprintln!("Unable to record to {}: {}", path.display(), e);
+                exit(1);
+            }
+        },
+        None => None,
+    };
+
+    // Started once, so reconnecting doesn't show the same lines twice
+    let follower = attach.map(|attach| cmd::attach::Follower::start(attach, &profile));
+
+    println!("Connecting to host at {}:{} ...", profile.ip, profile.port);
+
+    // Added up across reconnects and shown however the session ends
+    let mut summary = Summary::default();
+    let finish = |summary: &Summary, recording: &mut Option<record::Recording>, code| {
+        let text = if args.summary_json {
+            summary.json().to_string()
+        } else {
+            summary.text()
+        };
+        println!("{}", text);
+        if let Some(recording) = recording {
+            recording.output(&format!("{}\n", text));
+        }
+        exit(code)
+    };
 
     // Establish connection to RCON server
+    let mut connected = false;
     loop {
-        match Rcon::new(&args) {
+        match Rcon::new(&profile.client()) {
             // Start default rcon shell
             Ok(r) => {
-                if let Err(_) = r.shell() {
-                    eprintln!("Lost connection to RCON server!");
-                    eprintln!("Attempting to reconnect...");
-                    continue;
+                if connected {
+                    summary.reconnected();
+                }
+                connected = true;
+                match rustcon::shell(
+                    r,
+                    &profile,
+                    recording.as_mut(),
+                    follower.as_ref(),
+                    &mut summary,
+                ) {
+                    Ok(_) => finish(&summary, &mut recording, 0),
+                    Err(RconError::AuthError) => finish(&summary, &mut recording, 1),
+                    Err(RconError::Disconnected { reason }) => {
+                        eprintln!("Lost connection to RCON server: {}", reason);
+                        eprintln!("Attempting to reconnect...");
+                        continue;
+                    }
+                    Err(_) => {
+                        eprintln!("Lost connection to RCON server!");
+                        eprintln!("Attempting to reconnect...");
+                        continue;
+                    }
                 }
             }
-            Err(_) => {
+            Err(e) => {
                 eprintln!(
                     "Unable to create an RCON session to {}:{}",
-                    args.ip, args.port
+                    profile.ip, profile.port
+                );
+                eprintln!(
+                    "{}",
+                    e.hint()
+                        .unwrap_or_else(|| "Please confirm the server is running.".to_string())
                 );
-                eprintln!("Please confirm the server is running.");
+                eprintln!("Run `rustcon doctor` to find out which step fails.");
                 let stdin = io::stdin();
                 let mut buffer = String::new();
                 loop {
@@ -33,6 +193,7 @@ fn main() -> io::Result<()> {
                             buffer.clear();
                             break;
                         }
+                        "n" | "no" | "N" | "NO" if connected => finish(&summary, &mut recording, 1),
                         "n" | "no" | "N" | "NO" => exit(1),
                         _ => {
                             buffer.clear();
@@ -0,0 +1,333 @@
+/*
+ * WASI plugins, loaded from the `plugins` directory next to the default config file.
+ *
+ * A plugin is a wasm32-wasip1 reactor module exporting `memory` and
+ * `rustcon_alloc(len: i32) -> i32`, which returns a buffer the host copies strings into. Every
+ * hook is optional:
+ *  - `rustcon_commands() -> i64` lists, one per line, the `:` shell commands the plugin handles
+ *  - `on_command(name, name_len, args, args_len)` runs one of them
+ *  - `on_response(cmd, cmd_len, response, response_len) -> i64` rewrites a response shown in the
+ *    shell, returning 0 to leave it alone
+ *  - `on_event(json, json_len)` sees every notification event of the daemons
+ * Strings returned to the host are packed as `ptr << 32 | len`. Plugins can import
+ * `rustcon.exec(cmd, cmd_len) -> i64` to run a command, getting back the packed response or -1
+ * when there's no session or it failed. Output written to stdout and stderr is shown as is.
+ */
+
+use crate::{config::Config, Rcon};
+use std::{
+    convert::TryFrom,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use wasmtime::{
+    AsContext, AsContextMut, Caller, Engine, Extern, Instance, Linker, Memory, Module, Store,
+};
+use wasmtime_wasi::{
+    p1::{self, WasiP1Ctx},
+    WasiCtxBuilder,
+};
+
+/// `:` commands of the shell itself, which plugins can't take over
+const BUILTIN_COMMANDS: &[&str] = &["script"];
+
+/// Plugins notified of daemon events, loaded on the first event
+static EVENT_PLUGINS: Mutex<Option<Plugins>> = Mutex::new(None);
+
+struct State {
+    wasi: WasiP1Ctx,
+    /// Session lent to the plugin for the call in progress
+    rcon: Option<Rcon>,
+}
+
+struct Plugin {
+    name: String,
+    store: Store<State>,
+    instance: Instance,
+    commands: Vec<String>,
+}
+
+/// Every plugin that loaded
+pub struct Plugins(Vec<Plugin>);
+
+fn pack(ptr: i32, len: usize) -> i64 {
+    (i64::from(ptr) << 32) | len as i64
+}
+
+fn unpack(packed: i64) -> (usize, usize) {
+    ((packed >> 32) as u32 as usize, packed as u32 as usize)
+}
+
+fn read(
+    store: impl AsContext,
+    memory: Memory,
+    (ptr, len): (usize, usize),
+) -> wasmtime::Result<String> {
+    let bytes = memory
+        .data(&store)
+        .get(ptr..ptr + len)
+        .ok_or_else(|| wasmtime::Error::msg("string out of bounds"))?;
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Copy a string into a buffer from the plugin's `rustcon_alloc`
+fn write(
+    mut store: impl AsContextMut,
+    exports: Exports,
+    text: &str,
+) -> wasmtime::Result<(i32, i32)> {
+    let len = i32::try_from(text.len())?;
+    let ptr = exports
+        .alloc
+        .typed::<i32, i32>(&store)?
+        .call(&mut store, len)?;
+    exports
+        .memory
+        .write(&mut store, ptr as usize, text.as_bytes())?;
+    Ok((ptr, len))
+}
+
+/// The exports every call needs
+#[derive(Clone, Copy)]
+struct Exports {
+    memory: Memory,
+    alloc: wasmtime::Func,
+}
+
+impl Exports {
+    fn find(mut get: impl FnMut(&str) -> Option<Extern>) -> wasmtime::Result<Exports> {
+        let memory = get("memory").and_then(Extern::into_memory);
+        let alloc = get("rustcon_alloc").and_then(Extern::into_func);
+        match (memory, alloc) {
+            (Some(memory), Some(alloc)) => Ok(Exports { memory, alloc }),
+            _ => Err(wasmtime::Error::msg(
+                "the module doesn't export memory and rustcon_alloc",
+            )),
+        }
+    }
+}
+
+/// `rustcon.exec`, run on the lent session
+fn exec(mut caller: Caller<'_, State>, ptr: i32, len: i32) -> wasmtime::Result<i64> {
+    let exports = Exports::find(|name| caller.get_export(name))?;
+    let cmd = read(&caller, exports.memory, (ptr as usize, len as usize))?;
+    let response = caller
+        .data_mut()
+        .rcon
+        .as_mut()
+        .and_then(|rcon| rcon.send_cmd(&cmd).ok())
+        .map(|response| response.iter().map(|p| p.body()).collect::<String>());
+    match response {
+        Some(text) => {
+            let (ptr, _) = write(&mut caller, exports, &text)?;
+            Ok(pack(ptr, text.len()))
+        }
+        None => Ok(-1),
+    }
+}
+
+impl Plugin {
+    fn load(engine: &Engine, linker: &Linker<State>, path: &Path) -> wasmtime::Result<Plugin> {
+        let module = Module::from_file(engine, path)?;
+        let wasi = WasiCtxBuilder::new()
+            .inherit_stdout()
+            .inherit_stderr()
+            .build_p1();
+        let mut store = Store::new(engine, State { wasi, rcon: None });
+        let instance = linker.instantiate(&mut store, &module)?;
+        if let Ok(init) = instance.get_typed_func::<(), ()>(&mut store, "_initialize") {
+            init.call(&mut store, ())?;
+        }
+
+        let mut plugin = Plugin {
+            name: path
+                .file_stem()
+                .map_or_else(String::new, |s| s.to_string_lossy().into_owned()),
+            store,
+            instance,
+            commands: Vec::new(),
+        };
+        plugin.exports()?;
+        if let Ok(commands) = plugin
+            .instance
+            .get_typed_func::<(), i64>(&mut plugin.store, "rustcon_commands")
+        {
+            let packed = commands.call(&mut plugin.store, ())?;
+            let exports = plugin.exports()?;
+            plugin.commands = read(&plugin.store, exports.memory, unpack(packed))?
+                .lines()
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        Ok(plugin)
+    }
+
+    fn exports(&mut self) -> wasmtime::Result<Exports> {
+        let (instance, store) = (self.instance, &mut self.store);
+        Exports::find(|name| instance.get_export(&mut *store, name))
+    }
+
+    /// Call a hook taking two strings, lending it the session if there is one
+    fn call2<R: wasmtime::WasmResults>(
+        &mut self,
+        hook: &str,
+        a: &str,
+        b: &str,
+        rcon: &mut Option<Rcon>,
+    ) -> wasmtime::Result<Option<R>> {
+        let func = match self
+            .instance
+            .get_typed_func::<(i32, i32, i32, i32), R>(&mut self.store, hook)
+        {
+            Ok(func) => func,
+            Err(_) => return Ok(None),
+        };
+        let exports = self.exports()?;
+        let (a_ptr, a_len) = write(&mut self.store, exports, a)?;
+        let (b_ptr, b_len) = write(&mut self.store, exports, b)?;
+
+        self.store.data_mut().rcon = rcon.take();
+        let result = func.call(&mut self.store, (a_ptr, a_len, b_ptr, b_len));
+        *rcon = self.store.data_mut().rcon.take();
+        result.map(Some)
+    }
+
+    fn report(&self, error: wasmtime::Error) {
+        eprintln!("Plugin {} failed: {:#}", self.name, error);
+    }
+}
+
+impl Plugins {
+    /// `plugins` next to the default config file
+    pub fn dir() -> Option<PathBuf> {
+        Config::default_path().and_then(|p| p.parent().map(|dir| dir.join("plugins")))
+    }
+
+    /// Every `.wasm` module in the plugins directory, reporting the ones that fail to load
+    pub fn load() -> Plugins {
+        let mut paths: Vec<PathBuf> = match Plugins::dir().map(fs::read_dir) {
+            Some(Ok(entries)) => entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|e| e == "wasm"))
+                .collect(),
+            _ => return Plugins(Vec::new()),
+        };
+        paths.sort();
+
+        let engine = Engine::default();
+        let mut linker = Linker::new(&engine);
+        let linked = p1::add_to_linker_sync(&mut linker, |s: &mut State| &mut s.wasi)
+            .and_then(|_| linker.func_wrap("rustcon", "exec", exec).map(|_| ()));
+        if let Err(e) = linked {
+            eprintln!("Unable to set up plugins: {}", e);
+            return Plugins(Vec::new());
+        }
+
+        let mut plugins = Vec::new();
+        for path in paths {
+            match Plugin::load(&engine, &linker, &path) {
+                Ok(mut plugin) => {
+                    let name = &plugin.name;
+                    plugin.commands.retain(|c| {
+                        let builtin = BUILTIN_COMMANDS.contains(&c.as_str());
+                        if builtin {
+                            eprintln!("Plugin {} can't replace :{}", name, c);
+                        }
+                        !builtin
+                    });
+                    plugins.push(plugin);
+                }
+                Err(e) => eprintln!("Unable to load the plugin {}: {:#}", path.display(), e),
+            }
+        }
+        Plugins(plugins)
+    }
+
+    /// Run a `:` shell command if a plugin handles it, handing the session back either way
+    ///
+    /// Returns `Err` with the session when no plugin has the command.
+    // Both variants are the session itself, handed back rather than copied
+    #[allow(clippy::result_large_err)]
+    pub fn command(&mut self, rcon: Rcon, line: &str) -> Result<Rcon, Rcon> {
+        let mut words = line.splitn(2, char::is_whitespace);
+        let name = words.next().unwrap_or("");
+        let args = words.next().unwrap_or("").trim();
+        let plugin = match self
+            .0
+            .iter_mut()
+            .find(|p| p.commands.iter().any(|c| c == name))
+        {
+            Some(plugin) => plugin,
+            None => return Err(rcon),
+        };
+
+        let mut rcon = Some(rcon);
+        if let Err(e) = plugin.call2::<()>("on_command", name, args, &mut rcon) {
+            plugin.report(e);
+        }
+        Ok(rcon.expect("the session is handed back after every call"))
+    }
+
+    /// Whether any plugin has an `on_response` hook, so responses have to arrive whole before
+    /// they're shown
+    pub fn rewrites_responses(&mut self) -> bool {
+        self.0.iter_mut().any(|plugin| {
+            plugin
+                .instance
+                .get_func(&mut plugin.store, "on_response")
+                .is_some()
+        })
+    }
+
+    /// Let the plugins rewrite a response before it's shown, `None` if none of them changed it
+    pub fn on_response(&mut self, cmd: &str, response: &str) -> Option<String> {
+        let mut rewritten: Option<String> = None;
+        for plugin in &mut self.0 {
+            let current = rewritten.as_deref().unwrap_or(response).to_string();
+            let text = plugin
+                .call2::<i64>("on_response", cmd, &current, &mut None)
+                .and_then(|packed| match packed {
+                    Some(packed) if packed != 0 => {
+                        let exports = plugin.exports()?;
+                        read(&plugin.store, exports.memory, unpack(packed)).map(Some)
+                    }
+                    _ => Ok(None),
+                });
+            match text {
+                Ok(Some(text)) => rewritten = Some(text),
+                Ok(None) => {}
+                Err(e) => plugin.report(e),
+            }
+        }
+        rewritten
+    }
+
+    /// Pass a notification event's JSON to the plugins
+    fn on_event(&mut self, json: &str) {
+        for plugin in &mut self.0 {
+            let func = match plugin
+                .instance
+                .get_typed_func::<(i32, i32), ()>(&mut plugin.store, "on_event")
+            {
+                Ok(func) => func,
+                Err(_) => continue,
+            };
+            let result = plugin
+                .exports()
+                .and_then(|exports| write(&mut plugin.store, exports, json))
+                .and_then(|args| func.call(&mut plugin.store, args));
+            if let Err(e) = result {
+                plugin.report(e);
+            }
+        }
+    }
+}
+
+/// Hand a notification event to the plugins, loading them the first time
+pub fn event(json: &str) {
+    let mut plugins = EVENT_PLUGINS.lock().unwrap_or_else(|e| e.into_inner());
+    plugins.get_or_insert_with(Plugins::load).on_event(json);
+}
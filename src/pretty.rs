@@ -0,0 +1,133 @@
+/*
+ * Pretty-printing of responses that are JSON documents, as some modded servers and WebRCON
+ * bridges answer with, for the shell.
+ *
+ * The document is laid out again from its own text rather than parsed into values, so keys stay
+ * in the order the server sent them. Strings are made safe to show like any other output.
+ */
+
+use crate::sanitize::sanitize;
+use serde::de::IgnoredAny;
+
+const INDENT: &str = "  ";
+
+/// SGR parameters of what's colored
+const KEY: u8 = 36;
+const STRING: u8 = 32;
+const NUMBER: u8 = 33;
+const LITERAL: u8 = 35;
+
+/// Whether `text` starts like a JSON object or array, so it's worth holding back until it's
+/// whole to find out
+pub fn may_be_json(text: &str) -> bool {
+    text.trim_start().starts_with(['{', '['])
+}
+
+/// `text` indented, and colored with `color`, when it's a JSON object or array
+pub fn json(text: &str, color: bool) -> Option<String> {
+    let text = text.trim();
+    if !may_be_json(text) || serde_json::from_str::<IgnoredAny>(text).is_err() {
+        return None;
+    }
+
+    let paint = |out: &mut String, code: u8, token: &str| {
+        if color {
+            out.push_str(&format!("\x1b[{}m{}\x1b[0m", code, token));
+        } else {
+            out.push_str(token);
+        }
+    };
+    let newline = |out: &mut String, depth: usize| {
+        out.push('\n');
+        out.push_str(&INDENT.repeat(depth));
+    };
+
+    let bytes = text.as_bytes();
+    let mut out = String::with_capacity(text.len() * 2);
+    // Whether each open container is an object, where every other string is a key
+    let mut objects = Vec::new();
+    let mut key_next = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                let mut end = i + 1;
+                while bytes[end] != b'"' {
+                    end += if bytes[end] == b'\\' { 2 } else { 1 };
+                }
+                let code = if key_next { KEY } else { STRING };
+                paint(&mut out, code, &sanitize(&text[i..=end]));
+                key_next = false;
+                i = end + 1;
+            }
+            open @ (b'{' | b'[') => {
+                let close = if open == b'{' { '}' } else { ']' };
+                let rest = text[i + 1..].trim_start();
+                if rest.starts_with(close) {
+                    // Empty, kept on one line
+                    out.push(open as char);
+                    out.push(close);
+                    i = bytes.len() - rest.len() + 1;
+                    continue;
+                }
+                out.push(open as char);
+                objects.push(open == b'{');
+                key_next = open == b'{';
+                newline(&mut out, objects.len());
+                i += 1;
+            }
+            close @ (b'}' | b']') => {
+                objects.pop();
+                newline(&mut out, objects.len());
+                out.push(close as char);
+                i += 1;
+            }
+            b',' => {
+                out.push(',');
+                newline(&mut out, objects.len());
+                key_next = objects.last() == Some(&true);
+                i += 1;
+            }
+            b':' => {
+                out.push_str(": ");
+                i += 1;
+            }
+            b if b.is_ascii_whitespace() => i += 1,
+            _ => {
+                let end = text[i..]
+                    .find(|c: char| matches!(c, ',' | ']' | '}') || c.is_ascii_whitespace())
+                    .map_or(bytes.len(), |n| i + n);
+                let token = &text[i..end];
+                let code = match token {
+                    "true" | "false" | "null" => LITERAL,
+                    _ => NUMBER,
+                };
+                paint(&mut out, code, token);
+                i = end;
+            }
+        }
+    }
+    out.push('\n');
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_responses_are_laid_out_in_their_own_order() {
+        let text = r#" {"z":[1, true,{}], "a":{"s":"x,\"}\u001b"},"e":[]}"#;
+        assert_eq!(
+            json(text, false).unwrap(),
+            "{\n  \"z\": [\n    1,\n    true,\n    {}\n  ],\n  \"a\": {\n    \"s\": \"x,\\\"}\\u001b\"\n  },\n  \"e\": []\n}\n"
+        );
+        assert_eq!(
+            json(r#"{"k":"v","n":null}"#, true).unwrap(),
+            "{\n  \x1b[36m\"k\"\x1b[0m: \x1b[32m\"v\"\x1b[0m,\n  \x1b[36m\"n\"\x1b[0m: \x1b[35mnull\x1b[0m\n}\n"
+        );
+        assert!(json("[1, 2", false).is_none());
+        assert!(json("\"just a string\"", false).is_none());
+        assert!(json("There are 0 players online", false).is_none());
+    }
+}
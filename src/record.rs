@@ -0,0 +1,74 @@
+/*
+ * Shell session recordings in the asciinema v2 format.
+ *
+ * A header line describing the terminal is followed by one `[seconds, "o" | "i", text]` line per
+ * write to the terminal or line typed, so `asciinema play` replays the session at its pace.
+ */
+
+use serde_json::json;
+use std::{
+    env,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+pub struct Recording {
+    file: BufWriter<File>,
+    start: Instant,
+}
+
+/// Terminal size from `COLUMNS` and `LINES`, defaulting to 80x24
+fn size() -> (u16, u16) {
+    let var = |name, default| {
+        env::var(name)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    };
+    (var("COLUMNS", 80), var("LINES", 24))
+}
+
+impl Recording {
+    /// Start a recording at `path`, replacing any file there
+    pub fn create(path: &Path) -> io::Result<Recording> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let (width, height) = size();
+        let header = json!({
+            "version": 2,
+            "width": width,
+            "height": height,
+            "timestamp": SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            "env": {
+                "SHELL": env::var("SHELL").ok(),
+                "TERM": env::var("TERM").ok(),
+            },
+        });
+        writeln!(file, "{}", header)?;
+        file.flush()?;
+        Ok(Recording {
+            file,
+            start: Instant::now(),
+        })
+    }
+
+    fn event(&mut self, kind: &str, text: &str) {
+        let time = self.start.elapsed().as_secs_f64();
+        // Flushed every time, so a crash keeps everything up to it
+        let _ =
+            writeln!(self.file, "{}", json!([time, kind, text])).and_then(|_| self.file.flush());
+    }
+
+    /// Text written to the terminal, with line feeds turned into the CRLF a terminal shows
+    pub fn output(&mut self, text: &str) {
+        self.event("o", &text.replace('\n', "\r\n"));
+    }
+
+    /// A line typed by the user
+    pub fn input(&mut self, line: &str) {
+        self.event("i", &format!("{}\r", line));
+    }
+}
@@ -0,0 +1,72 @@
+use super::Host;
+use mlua::{Lua, Table};
+use std::{cell::RefCell, fs, path::Path, rc::Rc, thread, time::Duration};
+
+fn profile(lua: &Lua, host: &Host) -> mlua::Result<Table> {
+    let profile = lua.create_table()?;
+    profile.set("name", host.profile.name.clone())?;
+    profile.set("ip", host.profile.ip.clone())?;
+    profile.set("port", host.profile.port.to_string())?;
+    profile.set("game", host.game().to_string())?;
+    Ok(profile)
+}
+
+fn status(lua: &Lua, host: &mut Host) -> mlua::Result<Table> {
+    let status = host.status().map_err(mlua::Error::RuntimeError)?;
+    let table = lua.create_table()?;
+    if let Some(players) = status.players {
+        table.set("players", lua.create_sequence_from(players)?)?;
+    }
+    let stats = lua.create_table()?;
+    for (stat, value) in status.stats {
+        stats.set(stat, value)?;
+    }
+    table.set("stats", stats)?;
+    Ok(table)
+}
+
+fn exec_file(lua: &Lua, host: &Rc<RefCell<Host>>, path: &Path) -> mlua::Result<()> {
+    let globals = lua.globals();
+
+    let exec_host = Rc::clone(host);
+    globals.set(
+        "exec",
+        lua.create_function(move |_, cmd: String| {
+            exec_host
+                .borrow_mut()
+                .exec(&cmd)
+                .map_err(mlua::Error::RuntimeError)
+        })?,
+    )?;
+    globals.set(
+        "sleep",
+        lua.create_function(|_, ms: u64| {
+            thread::sleep(Duration::from_millis(ms));
+            Ok(())
+        })?,
+    )?;
+    let status_host = Rc::clone(host);
+    globals.set(
+        "parse_status",
+        lua.create_function(move |lua, ()| status(lua, &mut status_host.borrow_mut()))?,
+    )?;
+    globals.set("profile", profile(lua, &host.borrow())?)?;
+
+    let source = fs::read_to_string(path).map_err(mlua::Error::external)?;
+    lua.load(source)
+        .set_name(format!("@{}", path.display()))
+        .exec()
+}
+
+pub fn run(host: Host, path: &Path) -> (Host, Result<(), String>) {
+    let host = Rc::new(RefCell::new(host));
+    let lua = Lua::new();
+    let result = exec_file(&lua, &host, path).map_err(|e| e.to_string());
+
+    // Release the registered functions' handles on the session
+    drop(lua);
+    match Rc::try_unwrap(host) {
+        Ok(host) => (host.into_inner(), result),
+        Err(_) => unreachable!("the interpreter held the only other handles"),
+    }
+}
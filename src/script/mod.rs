@@ -0,0 +1,97 @@
+/*
+ * Automation scripts run against an RCON session, in Rhai (`scripting` feature) or Lua (`lua`
+ * feature).
+ *
+ * Every engine exposes the same host API, so a script only depends on its language:
+ *  - `exec(cmd)` sends a command and returns the response, failing the script if it can't or if
+ *    the command is dangerous and `--yes` wasn't given
+ *  - `sleep(ms)` pauses the script
+ *  - `parse_status()` returns the players online and the game's performance figures
+ *  - `profile` holds the name, ip, port and game of the server
+ */
+
+#[cfg(feature = "lua")]
+mod lua;
+#[cfg(feature = "scripting")]
+mod rhai;
+
+use crate::{Game, Profile, Rcon};
+use std::path::Path;
+
+/// The session a script drives, handed back to the caller when the script ends
+pub struct Host {
+    pub rcon: Rcon,
+    pub profile: Profile,
+}
+
+/// Result of `parse_status()`
+pub struct Status {
+    /// Names of the players online, `None` for games with no player list command
+    pub players: Option<Vec<String>>,
+    pub stats: Vec<(&'static str, f64)>,
+}
+
+impl Host {
+    fn exec(&mut self, cmd: &str) -> Result<String, String> {
+        self.profile.check_dangerous(self.rcon.game(), cmd)?;
+        self.rcon
+            .send_cmd(cmd)
+            .map(|response| response.iter().map(|p| p.body()).collect())
+            .map_err(|e| format!("`{}` failed: {:?}", cmd, e))
+    }
+
+    fn status(&mut self) -> Result<Status, String> {
+        let game = self.rcon.game().profile();
+        let players = match game.players_command() {
+            Some(cmd) => Some(
+                game.parse_players(&self.exec(cmd)?)
+                    .into_iter()
+                    .map(|p| p.name)
+                    .collect(),
+            ),
+            None => None,
+        };
+        let stats = match game.stats_command() {
+            Some(cmd) => game.parse_stats(&self.exec(cmd)?),
+            None => Vec::new(),
+        };
+        Ok(Status { players, stats })
+    }
+
+    fn game(&self) -> Game {
+        self.rcon.game()
+    }
+}
+
+/// Run the script at `path` on the session, picking the engine by file extension
+///
+/// The session is returned even when the script fails, so the shell can carry on with it.
+pub fn run(rcon: Rcon, profile: &Profile, path: &Path) -> (Rcon, Result<(), String>) {
+    let host = Host {
+        rcon,
+        profile: profile.clone(),
+    };
+    let (host, result) = match path.extension().and_then(|e| e.to_str()) {
+        #[cfg(feature = "scripting")]
+        Some("rhai") => rhai::run(host, path),
+        #[cfg(feature = "lua")]
+        Some("lua") => lua::run(host, path),
+        _ => {
+            let message = format!(
+                "No script engine for {}, supported: {}",
+                path.display(),
+                ENGINES.join(", ")
+            );
+            (host, Err(message))
+        }
+    };
+    (host.rcon, result)
+}
+
+/// File extensions of the engines built in
+const ENGINES: &[&str] = &[
+    #[cfg(feature = "scripting")]
+    ".rhai",
+    #[cfg(feature = "lua")]
+    ".lua",
+];
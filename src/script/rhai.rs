@@ -0,0 +1,76 @@
+use super::Host;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope};
+use std::{cell::RefCell, path::Path, rc::Rc, thread, time::Duration};
+
+fn profile(host: &Host) -> Map {
+    let mut profile = Map::new();
+    profile.insert(
+        "name".into(),
+        host.profile
+            .name
+            .clone()
+            .map_or(Dynamic::UNIT, Dynamic::from),
+    );
+    profile.insert("ip".into(), host.profile.ip.clone().into());
+    profile.insert("port".into(), host.profile.port.to_string().into());
+    profile.insert("game".into(), host.game().to_string().into());
+    profile
+}
+
+fn status(host: &mut Host) -> Result<Map, Box<EvalAltResult>> {
+    let status = host.status()?;
+    let mut map = Map::new();
+    map.insert(
+        "players".into(),
+        status.players.map_or(Dynamic::UNIT, |players| {
+            players
+                .into_iter()
+                .map(Dynamic::from)
+                .collect::<Array>()
+                .into()
+        }),
+    );
+    map.insert(
+        "stats".into(),
+        status
+            .stats
+            .into_iter()
+            .map(|(stat, value)| (stat.into(), value.into()))
+            .collect::<Map>()
+            .into(),
+    );
+    Ok(map)
+}
+
+pub fn run(host: Host, path: &Path) -> (Host, Result<(), String>) {
+    let host = Rc::new(RefCell::new(host));
+    let mut engine = Engine::new();
+
+    let exec_host = Rc::clone(&host);
+    engine.register_fn(
+        "exec",
+        move |cmd: &str| -> Result<String, Box<EvalAltResult>> {
+            Ok(exec_host.borrow_mut().exec(cmd)?)
+        },
+    );
+    engine.register_fn("sleep", |ms: i64| {
+        thread::sleep(Duration::from_millis(ms.max(0) as u64))
+    });
+    let status_host = Rc::clone(&host);
+    engine.register_fn("parse_status", move || {
+        status(&mut status_host.borrow_mut())
+    });
+
+    let mut scope = Scope::new();
+    scope.push_constant("profile", profile(&host.borrow()));
+    let result = engine
+        .run_file_with_scope(&mut scope, path.to_path_buf())
+        .map_err(|e| e.to_string());
+
+    // Release the registered functions' handles on the session
+    drop(engine);
+    match Rc::try_unwrap(host) {
+        Ok(host) => (host.into_inner(), result),
+        Err(_) => unreachable!("the engine held the only other handles"),
+    }
+}
@@ -0,0 +1,144 @@
+/*
+ * Discover RCON settings from a game server's own config files.
+ */
+
+use crate::{config::ProfileConfig, ConfigError, Game};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Read the RCON port and password from a Minecraft `server.properties` or Source `server.cfg`
+/// inside `dir`
+pub fn read_server_dir(dir: &Path) -> Result<ProfileConfig, ConfigError> {
+    let properties = dir.join("server.properties");
+    if properties.is_file() {
+        return read_properties(&properties);
+    }
+
+    match find_server_cfg(dir) {
+        Some(cfg) => read_server_cfg(&cfg),
+        None => Err(ConfigError::NoServerConfig(dir.to_path_buf())),
+    }
+}
+
+/// Look for `server.cfg` in the directory itself, its `cfg/`, or a mod folder's `cfg/`
+/// (`csgo/cfg/server.cfg`, `tf/cfg/server.cfg`, ...)
+fn find_server_cfg(dir: &Path) -> Option<PathBuf> {
+    let candidates = [dir.join("server.cfg"), dir.join("cfg").join("server.cfg")];
+    if let Some(cfg) = candidates.iter().find(|p| p.is_file()) {
+        return Some(cfg.clone());
+    }
+
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path().join("cfg").join("server.cfg"))
+        .find(|p| p.is_file())
+}
+
+fn read_file(path: &Path) -> Result<String, ConfigError> {
+    fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))
+}
+
+fn parse_port(path: &Path, value: &str) -> Result<u16, ConfigError> {
+    value.trim().parse().map_err(|_| {
+        ConfigError::InvalidServerConfig(path.to_path_buf(), format!("bad port {:?}", value))
+    })
+}
+
+/// Minecraft's `key=value` properties, RCON is configured with `rcon.port` and `rcon.password`
+fn read_properties(path: &Path) -> Result<ProfileConfig, ConfigError> {
+    let mut profile = ProfileConfig {
+        ip: Some("127.0.0.1".to_string()),
+        port: Some(25575),
+        game: Some(Game::Minecraft),
+        ..ProfileConfig::default()
+    };
+
+    for line in read_file(path)?.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some(("rcon.port", value)) => profile.port = Some(parse_port(path, value)?),
+            Some(("rcon.password", value)) if !value.is_empty() => {
+                profile.password = Some(value.to_string())
+            }
+            Some(("enable-rcon", "false")) => {
+                eprintln!(
+                    "RCON is disabled, set enable-rcon=true in {}",
+                    path.display()
+                )
+            }
+            _ => {}
+        }
+    }
+
+    Ok(profile)
+}
+
+/// Source server configs are console commands like `rcon_password "secret"`
+fn read_server_cfg(path: &Path) -> Result<ProfileConfig, ConfigError> {
+    let mut profile = ProfileConfig {
+        ip: Some("127.0.0.1".to_string()),
+        game: Some(Game::Source),
+        ..ProfileConfig::default()
+    };
+
+    for line in read_file(path)?.lines() {
+        let line = line.split("//").next().unwrap_or("").trim();
+        let (cvar, value) = match line.split_once(char::is_whitespace) {
+            Some((cvar, value)) => (cvar, value.trim().trim_matches('"')),
+            None => continue,
+        };
+        match cvar {
+            "rcon_password" if !value.is_empty() => profile.password = Some(value.to_string()),
+            "hostport" => profile.port = Some(parse_port(path, value)?),
+            _ => {}
+        }
+    }
+
+    Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn rcon_settings_are_read_from_the_servers_own_config() {
+        let dir = env::temp_dir().join(format!("rustcon-test-server-{}", std::process::id()));
+        let minecraft = dir.join("minecraft");
+        fs::create_dir_all(&minecraft).unwrap();
+        fs::write(
+            minecraft.join("server.properties"),
+            "#rcon.port=1\nenable-rcon=true\nrcon.port=25580\nrcon.password=hunter2\n",
+        )
+        .unwrap();
+        let profile = read_server_dir(&minecraft).unwrap();
+        assert_eq!(profile.port, Some(25580));
+        assert_eq!(profile.password.as_deref(), Some("hunter2"));
+        assert_eq!(profile.game, Some(Game::Minecraft));
+
+        // Found in a mod folder's cfg/, with comments and quotes around values
+        let source = dir.join("source");
+        fs::create_dir_all(source.join("tf").join("cfg")).unwrap();
+        fs::write(
+            source.join("tf").join("cfg").join("server.cfg"),
+            "hostname \"My Server\"\nrcon_password \"s3cret\" // changed\nhostport 27016\n",
+        )
+        .unwrap();
+        let profile = read_server_dir(&source).unwrap();
+        assert_eq!(profile.port, Some(27016));
+        assert_eq!(profile.password.as_deref(), Some("s3cret"));
+        assert_eq!(profile.game, Some(Game::Source));
+
+        assert!(read_server_dir(&dir).is_err());
+        fs::write(minecraft.join("server.properties"), "rcon.port=lots\n").unwrap();
+        assert!(read_server_dir(&minecraft).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
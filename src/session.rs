@@ -0,0 +1,110 @@
+/*
+ * What an interactive session did, added up across reconnects and shown when the shell exits.
+ */
+
+use crate::{duration::format_duration, shell::plural, Traffic};
+use serde_json::{json, Value};
+use std::{
+    convert::TryFrom,
+    mem,
+    time::{Duration, Instant},
+};
+
+pub struct Summary {
+    started: Instant,
+    /// Commands sent at the prompt, answered or not
+    pub commands: usize,
+    /// Commands that went unanswered, from timeouts to lost connections
+    pub failed: usize,
+    /// Times the session was lost and opened again
+    pub reconnects: usize,
+    /// Round trips of the answered commands, added up
+    latency: Duration,
+    /// Traffic of the sessions lost before the current one
+    lost: Traffic,
+    /// Traffic of the current session as of its last command
+    current: Traffic,
+}
+
+impl Default for Summary {
+    fn default() -> Summary {
+        Summary {
+            started: Instant::now(),
+            commands: 0,
+            failed: 0,
+            reconnects: 0,
+            latency: Duration::ZERO,
+            lost: Traffic::default(),
+            current: Traffic::default(),
+        }
+    }
+}
+
+impl Summary {
+    /// Count a command sent at the prompt, with its round trip when it was answered, and the
+    /// session's traffic since it opened
+    pub fn command(&mut self, answered: Option<Duration>, traffic: Traffic) {
+        self.commands += 1;
+        match answered {
+            Some(took) => self.latency += took,
+            None => self.failed += 1,
+        }
+        self.current = traffic;
+    }
+
+    /// Count a new session opened after the last was lost
+    pub fn reconnected(&mut self) {
+        self.reconnects += 1;
+        self.lost += mem::take(&mut self.current);
+    }
+
+    /// Traffic of every session so far
+    pub fn traffic(&self) -> Traffic {
+        let mut traffic = self.lost;
+        traffic += self.current;
+        traffic
+    }
+
+    /// Average round trip of the answered commands
+    pub fn average_latency(&self) -> Option<Duration> {
+        let answered = u32::try_from(self.commands - self.failed).ok()?;
+        self.latency.checked_div(answered)
+    }
+
+    pub fn json(&self) -> Value {
+        let traffic = self.traffic();
+        json!({
+            "duration_secs": self.started.elapsed().as_secs_f64(),
+            "commands": self.commands,
+            "failed": self.failed,
+            "packets_sent": traffic.packets_sent,
+            "packets_received": traffic.packets_received,
+            "bytes_sent": traffic.bytes_sent,
+            "bytes_received": traffic.bytes_received,
+            "reconnects": self.reconnects,
+            "average_latency_ms": self.average_latency().map(|l| l.as_secs_f64() * 1000.0),
+        })
+    }
+
+    /// The lines printed when the shell exits
+    pub fn text(&self) -> String {
+        let elapsed = Duration::from_secs(self.started.elapsed().as_secs());
+        let latency = match self.average_latency() {
+            Some(latency) => format!(", {:.1} ms on average", latency.as_secs_f64() * 1000.0),
+            None => String::new(),
+        };
+        let traffic = self.traffic();
+        format!(
+            "Session lasted {}\n  {} sent, {} failed{}\n  {} in {} received, {} in {} sent\n  {}",
+            format_duration(elapsed),
+            plural(self.commands, "command"),
+            self.failed,
+            latency,
+            plural(traffic.bytes_received, "byte"),
+            plural(traffic.packets_received, "packet"),
+            plural(traffic.bytes_sent, "byte"),
+            plural(traffic.packets_sent, "packet"),
+            plural(self.reconnects, "reconnect"),
+        )
+    }
+}
@@ -0,0 +1,1077 @@
+/*
+ * Interactive RCON shell with per-game command completion.
+ */
+
+use crate::{
+    cmd::{attach::Follower, drift::Responses},
+    highlight::{Color, Rule},
+    pretty,
+    record::Recording,
+    session::Summary,
+    table::{self, Table},
+    wrap::Wrapper,
+    Profile, Rcon, RconError, RconResult, TableStyle,
+};
+use regex::Regex;
+use rustcon_core::Counters;
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::Validator,
+    Context, Editor, ExternalPrinter, Helper,
+};
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    net::TcpStream,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often a session with unread output waiting is checked for being closed
+const HANGUP_POLL: Duration = Duration::from_millis(500);
+
+/// How long a command waits for its response before the spinner shows
+const SPINNER_DELAY: Duration = Duration::from_millis(500);
+
+/// How long each frame of the spinner shows
+const SPINNER_FRAME: Duration = Duration::from_millis(100);
+
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Columns right-aligned line numbers take up, before the two spaces after them
+const GUTTER_WIDTH: usize = 5;
+
+/// Esc, as read from the terminal
+#[cfg(unix)]
+const ESC: u8 = 0x1b;
+
+/// Completes the first word of a line from the game's known commands
+struct ShellHelper {
+    commands: &'static [&'static str],
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let word = &line[..pos];
+        if word.contains(' ') {
+            return Ok((pos, Vec::new()));
+        }
+
+        let word = word.to_lowercase();
+        let matches = self
+            .commands
+            .iter()
+            .filter(|c| c.to_lowercase().starts_with(&word))
+            .map(|c| Pair {
+                display: c.to_string(),
+                replacement: format!("{} ", c),
+            })
+            .collect();
+        Ok((0, matches))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// `count` of `noun`, plural unless there's one
+pub(crate) fn plural<N: fmt::Display + PartialEq + From<u8>>(count: N, noun: &str) -> String {
+    let one = count == N::from(1);
+    format!("{} {}{}", count, noun, if one { "" } else { "s" })
+}
+
+/// Commands sent at the prompt this session and whether the server answered them, for `:export`
+#[derive(Default)]
+struct History(Vec<(String, bool)>);
+
+impl History {
+    /// Write the commands to `path` for `rustcon bulk --file` to replay, leaving out the ones
+    /// that failed with `skip_failed`, returning how many were written
+    fn export(&self, path: &Path, skip_failed: bool, server: &str) -> io::Result<usize> {
+        let mut text = format!(
+            "# Sent to {} in a rustcon shell, {}\n# Replay with: rustcon bulk --file {}\n",
+            server,
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            path.display()
+        );
+        let mut written = 0;
+        for (cmd, answered) in &self.0 {
+            if *answered || !skip_failed {
+                if !answered {
+                    text.push_str("# failed:\n");
+                }
+                text.push_str(cmd);
+                text.push('\n');
+                written += 1;
+            }
+        }
+        fs::write(path, text)?;
+        Ok(written)
+    }
+}
+
+/// A shell line split into the command and the regex after its last ` ? `, which picks the
+/// lines of the response to show
+fn split_filter(line: &str) -> Result<(&str, Option<Regex>), regex::Error> {
+    match line.rsplit_once(" ? ") {
+        Some((cmd, pattern)) if !cmd.trim().is_empty() => {
+            Ok((cmd.trim_end(), Some(Regex::new(pattern.trim())?)))
+        }
+        _ => Ok((line, None)),
+    }
+}
+
+/// The rule of `:highlight [--line] <regex> [color]`, yellow unless the last word is a color
+fn highlight_rule(args: &str) -> Result<Rule, String> {
+    let (line, args) = match args.strip_prefix("--line") {
+        Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => {
+            (true, rest.trim())
+        }
+        _ => (false, args),
+    };
+    let (pattern, color) = match args.rsplit_once(char::is_whitespace) {
+        Some((pattern, last)) => match Color::parse(last) {
+            Some(color) => (pattern.trim_end(), color),
+            None => (args, Color::default()),
+        },
+        None => (args, Color::default()),
+    };
+    if pattern.is_empty() {
+        return Err("Usage: :highlight [--line] <regex> [color]".to_string());
+    }
+    let pattern = Regex::new(pattern).map_err(|e| format!("Not a valid regex: {}", e))?;
+    Ok(Rule {
+        pattern,
+        color,
+        line,
+    })
+}
+
+/// Response text as shown, soft-wrapped unless wrapping is off or stdout isn't a terminal
+fn fit(wrapper: &mut Option<Wrapper>, text: String) -> String {
+    match wrapper {
+        Some(wrapper) => wrapper.wrap(&text),
+        None => text,
+    }
+}
+
+/// Line numbers, for `:set linenumbers on`, and packet markers, for `:set verbose on`, put in a
+/// response as it's shown
+struct Gutter {
+    numbers: bool,
+    packets: bool,
+    /// Lines of the response started so far
+    lines: usize,
+    /// Packets of the response shown so far
+    shown: usize,
+    /// Whether what's shown next starts a line
+    line_start: bool,
+}
+
+impl Gutter {
+    fn new(numbers: bool, packets: bool) -> Gutter {
+        Gutter {
+            numbers,
+            packets,
+            lines: 0,
+            shown: 0,
+            line_start: true,
+        }
+    }
+
+    /// `line` after its number in the response
+    fn number(&self, number: usize, line: &str) -> String {
+        if self.numbers {
+            format!("{:>width$}  {}", number, line, width = GUTTER_WIDTH)
+        } else {
+            line.to_string()
+        }
+    }
+
+    /// A packet's text as shown, after a marker of where it starts and its size in `bytes`
+    ///
+    /// Packets starting partway through a line are marked on a line of their own, and the rest of
+    /// the line carries on under it without a number.
+    fn packet(&mut self, text: &str, bytes: usize) -> String {
+        let mut out = String::with_capacity(text.len());
+        if self.packets {
+            self.shown += 1;
+            if !self.line_start {
+                out.push('\n');
+            }
+            out.push_str(&format!(
+                "-- packet {}, {} --\n",
+                self.shown,
+                plural(bytes, "byte")
+            ));
+            if !self.line_start && self.numbers {
+                out.push_str(&" ".repeat(GUTTER_WIDTH + 2));
+            }
+        }
+        for line in text.split_inclusive('\n') {
+            if self.line_start {
+                self.lines += 1;
+                out.push_str(&self.number(self.lines, line));
+            } else {
+                out.push_str(line);
+            }
+            self.line_start = line.ends_with('\n');
+        }
+        out
+    }
+}
+
+/// The rest of a `:` shell command after `name`, when it's the one called `name`
+fn builtin<'a>(meta: &'a str, name: &str) -> Option<&'a str> {
+    let rest = meta.strip_prefix(name)?;
+    if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+        Some(rest.trim())
+    } else {
+        None
+    }
+}
+
+/// Prints the shell's output, copying it into the session recording if there is one
+struct Console<'a>(Option<&'a mut Recording>);
+
+impl Console<'_> {
+    fn line(&mut self, text: impl fmt::Display) {
+        let text = text.to_string();
+        println!("{}", text);
+        if let Some(recording) = &mut self.0 {
+            recording.output(&format!("{}\n", text));
+        }
+    }
+
+    /// Print text as it arrives, which may end partway through a line
+    fn write(&mut self, text: &str) {
+        print!("{}", text);
+        let _ = io::stdout().flush();
+        if let Some(recording) = &mut self.0 {
+            recording.output(text);
+        }
+    }
+
+    fn error(&mut self, text: impl fmt::Display) {
+        let text = text.to_string();
+        eprintln!("{}", text);
+        if let Some(recording) = &mut self.0 {
+            recording.output(&format!("{}\n", text));
+        }
+    }
+
+    fn rule(&mut self) {
+        self.line("=".repeat(80));
+    }
+
+    /// Read a line, recording the prompt, what was typed and its echo
+    fn read(
+        &mut self,
+        editor: &mut Editor<ShellHelper, DefaultHistory>,
+        prompt: &str,
+    ) -> rustyline::Result<String> {
+        if let Some(recording) = &mut self.0 {
+            recording.output(prompt);
+        }
+        let line = editor.readline(prompt)?;
+        if let Some(recording) = &mut self.0 {
+            recording.input(&line);
+            recording.output(&format!("{}\n", line));
+        }
+        Ok(line)
+    }
+}
+
+/// Watches the session while the shell waits for input, so the server shutting down or kicking
+/// the client is reported right away rather than when the next command fails
+struct HangupWatch {
+    stop: Arc<AtomicBool>,
+    reason: Arc<Mutex<Option<String>>>,
+}
+
+impl HangupWatch {
+    fn start<P: ExternalPrinter + Send + 'static>(
+        conn: TcpStream,
+        printer: Option<P>,
+    ) -> Option<HangupWatch> {
+        let watch = HangupWatch {
+            stop: Arc::new(AtomicBool::new(false)),
+            reason: Arc::new(Mutex::new(None)),
+        };
+        let (stop, reason) = (Arc::clone(&watch.stop), Arc::clone(&watch.reason));
+        thread::spawn(move || {
+            let mut printer = printer;
+            let mut byte = [0; 1];
+            while !stop.load(Ordering::Relaxed) {
+                // Peeking leaves responses for the shell to read, and waits up to the read timeout
+                let error = match conn.peek(&mut byte) {
+                    Ok(0) => RconError::closed(),
+                    Ok(_) => {
+                        thread::sleep(HANGUP_POLL);
+                        continue;
+                    }
+                    Err(e) if rustcon_core::is_timeout(&e) => continue,
+                    Err(e) => RconError::from(e),
+                };
+                if let RconError::Disconnected { reason: why } = error {
+                    let message =
+                        format!("Lost the connection: {}. Press Enter to reconnect.", why);
+                    match printer.as_mut() {
+                        Some(p) => {
+                            let _ = p.print(message);
+                        }
+                        None => eprintln!("{}", message),
+                    }
+                    *reason.lock().unwrap_or_else(|e| e.into_inner()) = Some(why);
+                }
+                break;
+            }
+        });
+        Some(watch)
+    }
+
+    /// Why the server hung up, once it has
+    fn reason(&self) -> Option<String> {
+        self.reason
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl Drop for HangupWatch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Lets Esc or Ctrl+C stop waiting for a response while a command runs, for commands that stream
+/// output for a long time
+///
+/// A second Ctrl+C exits as it would without the watch.
+struct AbortWatch {
+    signals: Vec<signal_hook::SigId>,
+    /// Restores the terminal when dropped
+    #[cfg(unix)]
+    _keys: Option<EscWatch>,
+}
+
+impl AbortWatch {
+    fn start(abort: &Arc<AtomicBool>) -> AbortWatch {
+        use signal_hook::consts::SIGINT;
+        let mut signals = Vec::new();
+        // Registered first, so only a Ctrl+C after the flag is raised exits
+        let registered = signal_hook::flag::register_conditional_shutdown(
+            SIGINT,
+            128 + SIGINT,
+            Arc::clone(abort),
+        )
+        .and_then(|exit| {
+            signals.push(exit);
+            signal_hook::flag::register(SIGINT, Arc::clone(abort))
+        });
+        if let Ok(raise) = registered {
+            signals.push(raise);
+        }
+        AbortWatch {
+            signals,
+            #[cfg(unix)]
+            _keys: EscWatch::start(abort),
+        }
+    }
+}
+
+impl Drop for AbortWatch {
+    fn drop(&mut self) {
+        for id in self.signals.drain(..) {
+            signal_hook::low_level::unregister(id);
+        }
+    }
+}
+
+/// Shows a spinner with the time waited and the packets received so far once a command is slow
+/// to answer, so a slow server can be told apart from a hung client
+///
+/// Only drawn when stdout is a terminal, and never recorded.
+struct Spinner {
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Spinner {
+    fn start(traffic: Arc<Counters>) -> Spinner {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = table::stdout_is_terminal().then(|| {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let started = Instant::now();
+                let before = traffic.snapshot().packets_received;
+                // Woken early to stop, or spuriously
+                while !stop.load(Ordering::Relaxed) && started.elapsed() < SPINNER_DELAY {
+                    thread::park_timeout(SPINNER_DELAY.saturating_sub(started.elapsed()));
+                }
+                let mut drawn = false;
+                for frame in SPINNER_FRAMES.iter().cycle() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let packets = traffic.snapshot().packets_received - before;
+                    print!(
+                        "\r{} Waiting {:.1}s, {} so far\x1b[K",
+                        frame,
+                        started.elapsed().as_secs_f64(),
+                        plural(packets, "packet")
+                    );
+                    let _ = io::stdout().flush();
+                    drawn = true;
+                    thread::park_timeout(SPINNER_FRAME);
+                }
+                if drawn {
+                    print!("\r\x1b[K");
+                    let _ = io::stdout().flush();
+                }
+            })
+        });
+        Spinner { stop, thread }
+    }
+
+    /// Clear the spinner, before any output is printed
+    fn stop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Reads keys from the terminal for Esc, with the terminal taking keys as they're pressed without
+/// echoing them for as long as it runs
+///
+/// Keys typed ahead for the next prompt are read too, and lost.
+#[cfg(unix)]
+struct EscWatch {
+    saved: libc::termios,
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+#[cfg(unix)]
+impl EscWatch {
+    fn start(abort: &Arc<AtomicBool>) -> Option<EscWatch> {
+        let fd = libc::STDIN_FILENO;
+        // SAFETY: the termios structs are plain data filled in by `tcgetattr`
+        let saved = unsafe {
+            if libc::isatty(fd) == 0 {
+                return None;
+            }
+            let mut saved: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut saved) != 0 {
+                return None;
+            }
+            let mut keys = saved;
+            keys.c_lflag &= !(libc::ICANON | libc::ECHO);
+            // Reads give up after a tenth of a second, so the thread notices it's stopped
+            keys.c_cc[libc::VMIN] = 0;
+            keys.c_cc[libc::VTIME] = 1;
+            if libc::tcsetattr(fd, libc::TCSANOW, &keys) != 0 {
+                return None;
+            }
+            saved
+        };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (stopped, abort) = (Arc::clone(&stop), Arc::clone(abort));
+        let thread = thread::spawn(move || {
+            let mut keys = [0u8; 16];
+            while !stopped.load(Ordering::Relaxed) {
+                // SAFETY: reads at most the buffer's length into it
+                let len = unsafe { libc::read(fd, keys.as_mut_ptr().cast(), keys.len()) };
+                if len > 0 && keys[..len as usize].contains(&ESC) {
+                    abort.store(true, Ordering::Relaxed);
+                }
+            }
+        });
+        Some(EscWatch {
+            saved,
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for EscWatch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        // SAFETY: restores the settings read by `tcgetattr`
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+/// One line about the server from the game's info command, so it's clear whether it's the one
+/// meant, `None` when the game has none or it isn't answered
+fn banner(rcon: &mut Rcon) -> Option<String> {
+    let game = rcon.game();
+    let cmd = game
+        .profile()
+        .info_command()
+        .filter(|cmd| rcon.allows(cmd))?;
+    let response: String = rcon.send_cmd(cmd).ok()?.iter().map(|p| p.body()).collect();
+    let info = game.profile().parse_info(&response);
+
+    let mut parts = Vec::new();
+    parts.extend(info.name);
+    parts.push(match info.version {
+        Some(version) => format!("{} {}", game, version),
+        None => game.to_string(),
+    });
+    parts.extend(info.map);
+    match (info.players, info.max_players) {
+        (Some(players), Some(max)) => parts.push(format!("{}/{} players", players, max)),
+        (Some(players), None) => parts.push(plural(players, "player")),
+        _ => {}
+    }
+    Some(crate::sanitize::sanitize(&parts.join(" | ")).into_owned())
+}
+
+/// Launch interactive shell to send RCON commands and receive responses
+///
+/// Returns once the user closes the console with Ctrl+C, Ctrl+D or `:quit`, having counted
+/// what was sent in `summary`. Lines starting with `:`
+/// are handled by rustcon rather than sent to the server. Esc or Ctrl+C while a response is
+/// arriving stops waiting for the rest of it. With a `recording`, the prompts, input and
+/// responses are written to it. With a `follower`, the log it follows is shown above the
+/// prompt.
+pub fn shell(
+    mut rcon: Rcon,
+    profile: &Profile,
+    recording: Option<&mut Recording>,
+    follower: Option<&Follower>,
+    summary: &mut Summary,
+) -> RconResult {
+    let mut console = Console(recording);
+    console.line("Authenticating...");
+    rcon.login(&mut crate::prompt_password)?;
+    if let Some(banner) = banner(&mut rcon) {
+        console.line(banner);
+    }
+    for (cmd, response) in rcon.take_init_output() {
+        if profile.quiet_init {
+            continue;
+        }
+        console.line(format!("> {}", cmd));
+        let response = response.trim_end();
+        if !response.is_empty() {
+            console.line(profile.display(response));
+        }
+    }
+
+    let mut editor: Editor<ShellHelper, _> = Editor::new().map_err(|e| {
+        eprintln!("{}", e);
+        RconError::ConnError
+    })?;
+    editor.set_helper(Some(ShellHelper {
+        commands: rcon.game().profile().known_commands(),
+    }));
+    if let Some(follower) = follower {
+        follower.attach(&mut editor);
+    }
+    let watch = rcon
+        .try_clone_conn()
+        .ok()
+        .and_then(|conn| HangupWatch::start(conn, editor.create_external_printer().ok()));
+
+    #[cfg(feature = "plugins")]
+    let mut plugins = crate::plugin::Plugins::load();
+    let mut responses = Responses::default();
+    let mut history = History::default();
+    // The last response's bodies as they were received, for `:save`
+    let mut last = Vec::new();
+    let mut wrap = true;
+    let mut linenumbers = false;
+    let mut verbose = false;
+    let mut sort = profile.sort.clone();
+
+    // Interactive prompt
+    console.rule();
+
+    loop {
+        // Set prompt and read user commands
+        let line = match console.read(&mut editor, "λ: ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(rcon),
+            Err(e) => {
+                console.error(e);
+                return Err(RconError::ConnError);
+            }
+        };
+        if let Some(reason) = watch.as_ref().and_then(HangupWatch::reason) {
+            return Err(RconError::Disconnected { reason });
+        }
+
+        let cmd = line.trim_end();
+        let _ = editor.add_history_entry(cmd);
+        if let Some(meta) = cmd.strip_prefix(':') {
+            #[cfg(feature = "plugins")]
+            match plugins.command(rcon, meta) {
+                Ok(handed_back) => {
+                    rcon = handed_back;
+                    console.rule();
+                    continue;
+                }
+                Err(handed_back) => rcon = handed_back,
+            }
+            if builtin(meta, "quit").is_some() || builtin(meta, "exit").is_some() {
+                return Ok(rcon);
+            }
+            if let Some(setting) = builtin(meta, "set") {
+                let usage = "Usage: :set wrap|linenumbers|verbose on|off";
+                match setting.split_whitespace().collect::<Vec<_>>()[..] {
+                    [] => {
+                        let show = |on| if on { "on" } else { "off" };
+                        console.line(format!("wrap {}", show(wrap)));
+                        console.line(format!("linenumbers {}", show(linenumbers)));
+                        console.line(format!("verbose {}", show(verbose)));
+                    }
+                    [name, value @ ("on" | "off")] => {
+                        let on = value == "on";
+                        match name {
+                            "wrap" => wrap = on,
+                            "linenumbers" => linenumbers = on,
+                            "verbose" => verbose = on,
+                            _ => console.error(usage),
+                        }
+                    }
+                    _ => console.error(usage),
+                }
+                console.rule();
+                continue;
+            }
+            if let Some(key) = builtin(meta, "sort") {
+                match key {
+                    "" => console.line(match &sort {
+                        Some(key) => format!("Tables are sorted by {}", key),
+                        None => "Tables are in the order the server sent".to_string(),
+                    }),
+                    "off" => sort = None,
+                    key => match key.parse() {
+                        Ok(key) => sort = Some(key),
+                        Err(e) => console.error(format!("Usage: :sort <column>[:desc]|off, {}", e)),
+                    },
+                }
+                console.rule();
+                continue;
+            }
+            if let Some(cmd) = builtin(meta, "diff") {
+                diff_command(&mut rcon, profile, &mut console, &mut responses, cmd)?;
+                console.rule();
+                continue;
+            }
+            if let Some(args) = builtin(meta, "export") {
+                let (skip_failed, path) = match args.strip_prefix("--skip-failed") {
+                    Some(path) => (true, path.trim()),
+                    None => (false, args),
+                };
+                if path.is_empty() {
+                    console.error("Usage: :export [--skip-failed] <file>");
+                } else {
+                    let server = (profile.name.clone())
+                        .unwrap_or_else(|| format!("{}:{}", profile.ip, profile.port));
+                    match history.export(path.as_ref(), skip_failed, &server) {
+                        Ok(written) => console.line(format!(
+                            "Wrote {} to {}, replay it with rustcon bulk --file {}",
+                            plural(written, "command"),
+                            path,
+                            path
+                        )),
+                        Err(e) => console.error(format!("Unable to write {}: {}", path, e)),
+                    }
+                }
+                console.rule();
+                continue;
+            }
+            if let Some(path) = builtin(meta, "save") {
+                if path.is_empty() {
+                    console.error("Usage: :save <file>");
+                } else {
+                    match fs::write(path, &last) {
+                        Ok(()) => console.line(format!(
+                            "Wrote {} of the last response to {}",
+                            plural(last.len(), "byte"),
+                            path
+                        )),
+                        Err(e) => console.error(format!("Unable to write {}: {}", path, e)),
+                    }
+                }
+                console.rule();
+                continue;
+            }
+            rcon = meta_command(rcon, profile, &mut console, meta);
+            console.rule();
+            continue;
+        }
+        let (cmd, filter) = match split_filter(cmd) {
+            Ok(split) => split,
+            Err(e) => {
+                console.error(format!("Not sent, the filter isn't a valid regex: {}", e));
+                console.rule();
+                continue;
+            }
+        };
+        let name = cmd.split_whitespace().next().unwrap_or("");
+        if !profile.assume_yes && profile.is_dangerous(rcon.game(), cmd) {
+            let prompt = format!(
+                "Sending {:?} could shut down the server or lose data. Send it? [y/N] ",
+                cmd
+            );
+            let answer = console.read(&mut editor, &prompt).unwrap_or_default();
+            if !matches!(answer.trim(), "y" | "yes" | "Y" | "YES") {
+                console.line("Not sent");
+                console.rule();
+                continue;
+            }
+        }
+        if !rcon.allows(cmd) {
+            console.line(format!(
+                "{:?} isn't in --allow-commands, it wasn't sent.",
+                name
+            ));
+            console.rule();
+            continue;
+        }
+        // Shown as it arrives, unless a plugin may rewrite it, it's filtered, or it's shown as
+        // a table once it's all there. Numbered or marked lines are shown as the server sent
+        // them
+        let tabulate = profile.tables != TableStyle::Off
+            && filter.is_none()
+            && !linenumbers
+            && !verbose
+            && Table::parses(rcon.game(), cmd);
+        #[cfg(feature = "plugins")]
+        let stream = !tabulate && filter.is_none() && !plugins.rewrites_responses();
+        #[cfg(not(feature = "plugins"))]
+        let stream = !tabulate && filter.is_none();
+        // JSON is held back until it's all there to be laid out, unless it's asked for raw
+        // or isn't going to a terminal
+        let pretty = table::stdout_is_terminal() && !profile.raw_output && !linenumbers && !verbose;
+        let mut wrapper = if wrap { Wrapper::for_terminal() } else { None };
+        let mut gutter = Gutter::new(linenumbers, verbose);
+        let started = Instant::now();
+        let mut first = None;
+        let mut held: Option<String> = None;
+        let mut ends_line = true;
+        let result = {
+            let _watch = AbortWatch::start(rcon.abort_flag());
+            let mut spinner = Spinner::start(rcon.counters());
+            if stream {
+                rcon.send_cmd_streaming(cmd, &mut |text| {
+                    let hold =
+                        held.is_some() || pretty && first.is_none() && pretty::may_be_json(text);
+                    first.get_or_insert_with(|| started.elapsed());
+                    if hold {
+                        held.get_or_insert_with(String::new).push_str(text);
+                        return;
+                    }
+                    spinner.stop();
+                    ends_line = text.ends_with('\n');
+                    let text = gutter.packet(&profile.display(text), text.len());
+                    console.write(&fit(&mut wrapper, text));
+                })
+            } else {
+                rcon.send_cmd(cmd)
+            }
+        };
+        let took = started.elapsed();
+        if let Some(held) = held {
+            let text = pretty::json(&held, true).unwrap_or_else(|| profile.display(&held));
+            ends_line = text.ends_with('\n');
+            console.write(&fit(&mut wrapper, text));
+        }
+        if !ends_line {
+            console.write("\n");
+        }
+        if rcon.abort_flag().swap(false, Ordering::Relaxed) {
+            console.error("Stopped waiting, the response may be cut short.");
+        }
+        history.0.push((cmd.to_string(), result.is_ok()));
+        summary.command(result.is_ok().then_some(took), rcon.stats());
+        match result {
+            Ok(response) => {
+                responses.remember(cmd, response.iter().map(|p| p.body()).collect());
+                if !stream {
+                    let text: String = response.iter().map(|p| p.body()).collect();
+                    #[cfg(feature = "plugins")]
+                    let text = plugins.on_response(cmd, &text).unwrap_or(text);
+                    let json = if pretty {
+                        pretty::json(&text, true)
+                    } else {
+                        None
+                    };
+                    match (filter, Table::parse(rcon.game(), cmd, &text)) {
+                        (Some(filter), _) => {
+                            let matched: Vec<(usize, &str)> = (1..)
+                                .zip(text.lines())
+                                .filter(|(_, l)| filter.is_match(l))
+                                .collect();
+                            if matched.is_empty() {
+                                console.line(format!("No lines match {:?}", filter.as_str()));
+                            }
+                            for (number, line) in matched {
+                                let line = gutter.number(number, &profile.display(line));
+                                console.write(&fit(&mut wrapper, line + "\n"));
+                            }
+                        }
+                        (None, Some(mut rows)) if tabulate && !rows.rows.is_empty() => {
+                            if let Some(key) = &sort {
+                                if let Err(e) = rows.sort(key) {
+                                    console.error(e);
+                                }
+                            }
+                            let width = table::terminal_width();
+                            for line in rows.render(profile.tables, width) {
+                                console.line(profile.display(&line));
+                            }
+                        }
+                        (None, _) if json.is_some() => {
+                            console.write(&fit(&mut wrapper, json.unwrap_or_default()));
+                        }
+                        (None, _) => {
+                            let text = text.trim_end_matches('\n');
+                            let bodies: Vec<&str> = response.iter().map(|p| p.body()).collect();
+                            // Rewritten by a plugin, it no longer splits into its packets
+                            let pieces =
+                                if verbose && bodies.concat().trim_end_matches('\n') == text {
+                                    bodies
+                                } else {
+                                    vec![text]
+                                };
+                            for piece in pieces {
+                                let piece = gutter.packet(&profile.display(piece), piece.len());
+                                console.write(&fit(&mut wrapper, piece));
+                            }
+                            if !gutter.line_start || text.is_empty() {
+                                console.write(&fit(&mut wrapper, "\n".to_string()));
+                            }
+                        }
+                    }
+                }
+                last = response
+                    .iter()
+                    .flat_map(|p| p.raw_body())
+                    .copied()
+                    .collect();
+                if std::str::from_utf8(&last).is_err() {
+                    console.line(
+                        "Bytes that aren't text are shown as \\xNN, :save <file> writes them as they came",
+                    );
+                }
+                let bytes: usize = response.iter().map(|p| p.body().len()).sum();
+                console.line(format!(
+                    "{} in {}, {} ms{}",
+                    plural(bytes, "byte"),
+                    plural(response.len(), "packet"),
+                    took.as_millis(),
+                    first.map_or(String::new(), |first| format!(
+                        ", the first after {} ms",
+                        first.as_millis()
+                    ))
+                ));
+            }
+            Err(RconError::TooLarge { max, .. }) => {
+                console.error("Woah there! That command is waaay too long.");
+                console.error(format!("The server takes up to {} bytes.", max));
+            }
+            Err(RconError::Timeout) => {
+                console.error("The server didn't answer in time, it may still be running it.")
+            }
+            Err(RconError::Deviation { detail }) => console.error(format!(
+                "The server broke the protocol, it sent {}.",
+                detail
+            )),
+            Err(e) => {
+                console.error(format!("Unable to send the command: {cmd}"));
+                console.error("There may have been a connection error. Please try again.");
+                return Err(e);
+            }
+        }
+
+        console.rule();
+    }
+}
+
+/// `:diff <cmd>`: send the command and show what changed since the last response to it
+///
+/// Dangerous commands are refused rather than asked about, since they're not worth
+/// resending to compare.
+fn diff_command(
+    rcon: &mut Rcon,
+    profile: &Profile,
+    console: &mut Console,
+    responses: &mut Responses,
+    cmd: &str,
+) -> Result<(), RconError> {
+    if cmd.is_empty() {
+        console.error("Usage: :diff <cmd>");
+        return Ok(());
+    }
+    if profile.is_dangerous(rcon.game(), cmd) {
+        console.error(format!(
+            "{:?} could shut down the server or lose data, it wasn't sent.",
+            cmd
+        ));
+        return Ok(());
+    }
+    if !rcon.allows(cmd) {
+        let name = cmd.split_whitespace().next().unwrap_or("");
+        console.line(format!(
+            "{:?} isn't in --allow-commands, it wasn't sent.",
+            name
+        ));
+        return Ok(());
+    }
+    let response = match rcon.send_cmd(cmd) {
+        Ok(response) => response.iter().map(|p| p.body()).collect(),
+        Err(e) if e.is_disconnect() => {
+            console.error(format!("Unable to send the command: {cmd}"));
+            return Err(e);
+        }
+        Err(e) => {
+            console.error(format!("Unable to send the command: {:?}", e));
+            return Ok(());
+        }
+    };
+    match responses.diff(cmd, response) {
+        Some(Some(diff)) => console.write(&profile.display(&diff)),
+        Some(None) => console.line("The response is the same as last time"),
+        None => console.line(format!(
+            "Nothing to compare with yet, run :diff {} again to see what changed",
+            cmd
+        )),
+    }
+    Ok(())
+}
+
+/// Run a `:` shell command, handing the session back for the next line
+#[cfg_attr(
+    not(any(feature = "scripting", feature = "lua")),
+    allow(unused_variables)
+)]
+fn meta_command(rcon: Rcon, profile: &Profile, console: &mut Console, line: &str) -> Rcon {
+    let mut words = line.splitn(2, char::is_whitespace);
+    match (words.next().unwrap_or(""), words.next().map(str::trim)) {
+        #[cfg(any(feature = "scripting", feature = "lua"))]
+        ("script", Some(path)) if !path.is_empty() => {
+            let (rcon, result) = crate::script::run(rcon, profile, path.as_ref());
+            if let Err(e) = result {
+                console.error(e);
+            }
+            return rcon;
+        }
+        #[cfg(any(feature = "scripting", feature = "lua"))]
+        ("script", _) => console.error("Usage: :script <file>"),
+        #[cfg(not(any(feature = "scripting", feature = "lua")))]
+        ("script", _) => console.error("rustcon was built without a script engine"),
+        ("stats", _) => {
+            let traffic = rcon.stats();
+            console.line(format!(
+                "Sent {} in {}, received {} in {}",
+                plural(traffic.bytes_sent, "byte"),
+                plural(traffic.packets_sent, "packet"),
+                plural(traffic.bytes_received, "byte"),
+                plural(traffic.packets_received, "packet"),
+            ));
+            console.line(format!(
+                "{} failed, {} rejected",
+                plural(traffic.errors, "command"),
+                plural(traffic.rejected_logins, "password"),
+            ));
+        }
+        ("highlight", None) | ("highlight", Some("")) => {
+            let rules = profile.highlights.rules();
+            if rules.is_empty() {
+                console.line(
+                    "Nothing is highlighted, add a rule with :highlight [--line] <regex> [color]",
+                );
+            }
+            for rule in rules {
+                let line = if rule.line { " (whole lines)" } else { "" };
+                console.line(format!("{} in {}{}", rule.pattern, rule.color, line));
+            }
+        }
+        ("highlight", Some("clear")) => profile.highlights.clear(),
+        ("highlight", Some(args)) => match highlight_rule(args) {
+            Ok(rule) => profile.highlights.add(rule),
+            Err(e) => console.error(e),
+        },
+        (name, _) => console.error(format!("Unknown shell command :{}", name)),
+    }
+    rcon
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_trailing_question_mark_filters_the_response() {
+        let (cmd, filter) = split_filter("cvarlist ? ^sv_").unwrap();
+        assert_eq!(cmd, "cvarlist");
+        assert!(filter.unwrap().is_match("sv_cheats"));
+        // Only the last ` ? ` starts the regex
+        let (cmd, filter) = split_filter("say why ? not ? ok").unwrap();
+        assert_eq!(cmd, "say why ? not");
+        assert_eq!(filter.unwrap().as_str(), "ok");
+        assert!(split_filter("say what?").unwrap().1.is_none());
+        assert!(split_filter(" ? x").unwrap().1.is_none());
+        assert!(split_filter("list ? (").is_err());
+    }
+
+    #[test]
+    fn highlight_rules_take_an_optional_color_and_line_flag() {
+        let rule = highlight_rule("--line WARN|ERROR red").unwrap();
+        assert_eq!(rule.pattern.as_str(), "WARN|ERROR");
+        assert_eq!(rule.color, Color::Red);
+        assert!(rule.line);
+        let rule = highlight_rule("joined the game").unwrap();
+        assert_eq!(rule.pattern.as_str(), "joined the game");
+        assert_eq!(rule.color, Color::Yellow);
+        assert!(!rule.line);
+        assert!(highlight_rule("--line").is_err());
+        assert!(highlight_rule("[ blue").is_err());
+    }
+}
@@ -0,0 +1,113 @@
+/*
+ * Running the bridges and daemons as systemd services: socket activation, readiness
+ * notification, and stopping cleanly on SIGTERM.
+ */
+
+use std::{
+    env, io,
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often waits check whether they should stop
+pub const STOP_POLL: Duration = Duration::from_millis(200);
+
+/// First descriptor of the sockets passed by systemd, see sd_listen_fds(3)
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// The socket systemd opened for the service, or a new one bound to `bind`
+pub fn listener(bind: &str) -> io::Result<TcpListener> {
+    #[cfg(unix)]
+    if let Some(listener) = activated() {
+        return Ok(listener);
+    }
+    TcpListener::bind(bind)
+}
+
+/// Take the first socket passed by systemd, if it was meant for this process
+#[cfg(unix)]
+fn activated() -> Option<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+
+    let pid: u32 = env::var("LISTEN_PID").ok()?.parse().ok()?;
+    let fds: i32 = env::var("LISTEN_FDS").ok()?.parse().ok()?;
+    if pid != std::process::id() || fds < 1 {
+        return None;
+    }
+    // Commands run by the service mustn't think the sockets are theirs
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+    // SAFETY: systemd hands the descriptor over to this process, nothing else owns it
+    Some(unsafe { TcpListener::from_raw_fd(LISTEN_FDS_START) })
+}
+
+/// Send a state such as `READY=1` to the service manager, see sd_notify(3)
+///
+/// Does nothing outside of a `Type=notify` unit. Only socket paths are supported, not the
+/// abstract namespace.
+pub fn notify(state: &str) {
+    #[cfg(unix)]
+    if let Ok(path) = env::var("NOTIFY_SOCKET") {
+        use std::os::unix::net::UnixDatagram;
+
+        let sent =
+            UnixDatagram::unbound().and_then(|socket| socket.send_to(state.as_bytes(), &path));
+        if let Err(e) = sent {
+            eprintln!("Unable to notify systemd: {}", e);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+/// A flag raised by SIGTERM, SIGHUP or SIGINT, for servers and daemons to stop taking work and
+/// finish what they have
+///
+/// A second signal while they finish exits at once, with the usual status of 128 plus the signal.
+pub fn stop_flag() -> Arc<AtomicBool> {
+    #[cfg(unix)]
+    use signal_hook::consts::SIGHUP;
+    use signal_hook::consts::{SIGINT, SIGTERM};
+
+    #[cfg(unix)]
+    let signals = [SIGTERM, SIGHUP, SIGINT];
+    #[cfg(not(unix))]
+    let signals = [SIGTERM, SIGINT];
+
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in signals {
+        // Registered first, so only a signal arriving after the flag is raised exits
+        let registered = signal_hook::flag::register_conditional_shutdown(
+            signal,
+            128 + signal,
+            Arc::clone(&stop),
+        )
+        .and_then(|_| signal_hook::flag::register(signal, Arc::clone(&stop)));
+        if let Err(e) = registered {
+            eprintln!("Unable to handle signal {}: {}", signal, e);
+        }
+    }
+    stop
+}
+
+/// Sleep for `duration` unless `stop` is raised first, returning whether it was
+pub fn sleep(stop: &AtomicBool, duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return true;
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return false;
+        }
+        thread::sleep(left.min(STOP_POLL));
+    }
+}
@@ -0,0 +1,343 @@
+/*
+ * Rows parsed out of responses, such as the players online or the ban list, and the aligned
+ * columns the shell shows them in.
+ */
+
+use crate::{cmd::report::csv_row, Game, TableStyle};
+use serde_json::{json, Value};
+use std::{fmt, str::FromStr};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Narrowest a column is cut down to for a table to fit the terminal
+const MIN_COLUMN: usize = 4;
+
+/// The column tables are sorted by, written `name` or `name:desc`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: String,
+    pub descending: bool,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<SortKey, String> {
+        let (column, order) = text.trim().rsplit_once(':').unwrap_or((text.trim(), "asc"));
+        let descending = match order {
+            "asc" => false,
+            "desc" => true,
+            _ => {
+                return Err(format!(
+                    "expected asc or desc after the column, not {:?}",
+                    order
+                ))
+            }
+        };
+        if column.is_empty() {
+            return Err("expected a column to sort by".to_string());
+        }
+        Ok(SortKey {
+            column: column.to_lowercase(),
+            descending,
+        })
+    }
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let order = if self.descending { "desc" } else { "asc" };
+        write!(f, "{}:{}", self.column, order)
+    }
+}
+
+/// Rows parsed out of a response, such as the players online
+pub struct Table {
+    pub header: Vec<&'static str>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Whether the response to `cmd` parses into a table, so it's worth waiting for all of it
+    pub fn parses(game: Game, cmd: &str) -> bool {
+        let profile = game.profile();
+        let cmd = Some(cmd.trim());
+        [
+            profile.players_command(),
+            profile.stats_command(),
+            profile.bans_command(),
+        ]
+        .iter()
+        .any(|c| c.is_some() && *c == cmd)
+    }
+
+    /// The players, performance figures or bans in the response to the game's command for them
+    pub fn parse(game: Game, cmd: &str, response: &str) -> Option<Table> {
+        let profile = game.profile();
+        let cmd = Some(cmd.trim());
+        if cmd == profile.players_command() {
+            let rows = profile
+                .parse_players(response)
+                .into_iter()
+                .map(|p| vec![p.name, p.id.unwrap_or_default()])
+                .collect();
+            Some(Table {
+                header: vec!["name", "id"],
+                rows,
+            })
+        } else if cmd == profile.stats_command() {
+            let rows = profile
+                .parse_stats(response)
+                .into_iter()
+                .map(|(name, value)| vec![name.to_string(), value.to_string()])
+                .collect();
+            Some(Table {
+                header: vec!["stat", "value"],
+                rows,
+            })
+        } else if cmd == profile.bans_command() {
+            let rows = profile
+                .parse_bans(response)
+                .into_iter()
+                .map(|ban| vec![ban])
+                .collect();
+            Some(Table {
+                header: vec!["banned"],
+                rows,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Sort the rows by `key`'s column, as numbers when every cell of it is one, failing with
+    /// the columns there are when the table doesn't have it
+    pub fn sort(&mut self, key: &SortKey) -> Result<(), String> {
+        let column = self
+            .header
+            .iter()
+            .position(|h| h.eq_ignore_ascii_case(&key.column))
+            .ok_or_else(|| {
+                format!(
+                    "There's no {} column to sort by, only {}",
+                    key.column,
+                    self.header.join(", ")
+                )
+            })?;
+        fn cell(row: &[String], column: usize) -> &str {
+            row.get(column).map_or("", String::as_str)
+        }
+        let numeric = self
+            .rows
+            .iter()
+            .all(|row| cell(row, column).parse::<f64>().is_ok());
+        // Stable, so rows that tie stay in the order the server listed them
+        self.rows.sort_by(|a, b| {
+            let (a, b) = (cell(a, column), cell(b, column));
+            let order = if numeric {
+                let number = |cell: &str| cell.parse::<f64>().unwrap_or(0.0);
+                number(a).total_cmp(&number(b))
+            } else {
+                a.to_lowercase().cmp(&b.to_lowercase())
+            };
+            if key.descending {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+        Ok(())
+    }
+
+    /// A list of objects keyed by the header
+    pub fn json(&self) -> Value {
+        let rows = self.rows.iter().map(|row| {
+            let fields = self.header.iter().zip(row);
+            Value::Object(fields.map(|(k, v)| (k.to_string(), json!(v))).collect())
+        });
+        Value::Array(rows.collect())
+    }
+
+    /// Print as CSV, header first
+    pub fn print_csv(&self) {
+        println!("{}", csv_row(&self.header));
+        for row in &self.rows {
+            println!("{}", csv_row(row));
+        }
+    }
+
+    /// Lines of aligned columns no wider than `width`, cutting the widest columns down and
+    /// ending what's cut off with `…`
+    pub fn render(&self, style: TableStyle, width: usize) -> Vec<String> {
+        let columns = self.header.len();
+        let mut widths: Vec<usize> = self.header.iter().map(|h| h.width()).collect();
+        for row in &self.rows {
+            for (w, cell) in widths.iter_mut().zip(row) {
+                *w = (*w).max(cell.width());
+            }
+        }
+
+        // `| a | b |` or `a  b`
+        let bordered = style == TableStyle::Borders;
+        let overhead = if bordered {
+            3 * columns + 1
+        } else {
+            2 * columns.saturating_sub(1)
+        };
+        while widths.iter().sum::<usize>() + overhead > width {
+            match widths.iter_mut().filter(|w| **w > MIN_COLUMN).max() {
+                Some(widest) => *widest -= 1,
+                None => break,
+            }
+        }
+
+        let line = |cells: &mut dyn Iterator<Item = &str>| {
+            let cells: Vec<String> = cells
+                .zip(&widths)
+                .map(|(cell, &w)| {
+                    // Padded by hand, since `format!` counts characters rather than columns
+                    let mut cell = fit(cell, w);
+                    let pad = w.saturating_sub(cell.width());
+                    cell.push_str(&" ".repeat(pad));
+                    cell
+                })
+                .collect();
+            if bordered {
+                format!("| {} |", cells.join(" | "))
+            } else {
+                cells.join("  ").trim_end().to_string()
+            }
+        };
+        let rule = || {
+            let dashes: Vec<String> = widths.iter().map(|w| "-".repeat(w + 2)).collect();
+            format!("+{}+", dashes.join("+"))
+        };
+
+        let mut lines = Vec::new();
+        if bordered {
+            lines.push(rule());
+        }
+        lines.push(line(&mut self.header.iter().copied()));
+        if bordered {
+            lines.push(rule());
+        }
+        for row in &self.rows {
+            lines.push(line(&mut row.iter().map(String::as_str)));
+        }
+        if bordered {
+            lines.push(rule());
+        }
+        lines
+    }
+}
+
+/// `text` cut to `width` columns, the last one `…` when anything was cut
+///
+/// Wide characters such as CJK take up two columns and combining marks none.
+fn fit(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    let mut cut = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > width.saturating_sub(1) {
+            break;
+        }
+        cut.push(c);
+        used += w;
+    }
+    cut.push('…');
+    cut
+}
+
+#[cfg(unix)]
+pub fn stdout_is_terminal() -> bool {
+    // SAFETY: only asks about a file descriptor
+    unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+#[cfg(not(unix))]
+pub fn stdout_is_terminal() -> bool {
+    false
+}
+
+/// Columns of the terminal on stdout, from `COLUMNS` when it can't be asked, defaulting to 80
+pub fn terminal_width() -> usize {
+    #[cfg(unix)]
+    {
+        // SAFETY: `winsize` is plain data filled in by the ioctl
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+            && size.ws_col > 0
+        {
+            return usize::from(size.ws_col);
+        }
+    }
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(80)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_are_cut_to_the_terminal_width() {
+        let table = Table {
+            header: vec!["name", "id"],
+            rows: vec![
+                vec!["Alice".to_string(), "1".to_string()],
+                vec!["Bartholomew".to_string(), "22".to_string()],
+            ],
+        };
+        assert_eq!(
+            table.render(TableStyle::Plain, 80),
+            ["name         id", "Alice        1", "Bartholomew  22"]
+        );
+        let boxed = table.render(TableStyle::Borders, 16);
+        assert_eq!(boxed[4], "| Bartho… | 22 |");
+        assert!(boxed.iter().all(|l| l.chars().count() == 16));
+    }
+
+    #[test]
+    fn tables_sort_by_a_column_as_numbers_or_text() {
+        let names = |table: &Table| -> Vec<String> {
+            table.rows.iter().map(|row| row[0].clone()).collect()
+        };
+        let mut table = Table {
+            header: vec!["name", "id"],
+            rows: vec![
+                vec!["bob".to_string(), "9".to_string()],
+                vec!["Alice".to_string(), "10".to_string()],
+                vec!["carol".to_string(), "9".to_string()],
+            ],
+        };
+        table.sort(&"id:desc".parse().unwrap()).unwrap();
+        assert_eq!(names(&table), ["Alice", "bob", "carol"]);
+        table.sort(&"NAME".parse().unwrap()).unwrap();
+        assert_eq!(names(&table), ["Alice", "bob", "carol"]);
+        table.sort(&"name:desc".parse().unwrap()).unwrap();
+        assert_eq!(names(&table), ["carol", "bob", "Alice"]);
+        assert!(table.sort(&"ping".parse().unwrap()).is_err());
+        assert!("name:up".parse::<SortKey>().is_err());
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        let table = Table {
+            header: vec!["name", "id"],
+            rows: vec![
+                vec!["玩家一".to_string(), "1".to_string()],
+                vec!["e\u{301}vie".to_string(), "2".to_string()],
+            ],
+        };
+        assert_eq!(
+            table.render(TableStyle::Plain, 80),
+            ["name    id", "玩家一  1", "e\u{301}vie    2"]
+        );
+        assert_eq!(table.render(TableStyle::Plain, 8)[1], "玩…   1");
+    }
+}
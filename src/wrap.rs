@@ -0,0 +1,179 @@
+/*
+ * Soft-wrapping of shell output at word boundaries, so long response lines break between words
+ * instead of wherever the terminal edge falls.
+ *
+ * Lines carry on past a break indented by their own leading whitespace and `HANG` more. Color
+ * sequences added by highlighting take up no width, and other characters as many columns as the
+ * terminal gives them. Words too long for a line are broken anyway.
+ */
+
+use crate::table::{stdout_is_terminal, terminal_width};
+use unicode_width::UnicodeWidthChar;
+
+/// Columns continuation lines are indented by past the start of the line they continue
+const HANG: usize = 2;
+
+/// Widest the hanging indent gets, as a share of the terminal's width
+const MAX_INDENT_SHARE: usize = 2;
+
+/// Wraps text as it's printed, remembering where the last of it left off
+pub struct Wrapper {
+    width: usize,
+    column: usize,
+    /// Whitespace written at the start of each continuation line
+    indent: String,
+    /// Whether what comes next is the start of a line, still in its leading whitespace
+    starting: bool,
+    /// Whitespace seen since the last word, written once the next word is placed
+    spaces: String,
+}
+
+impl Wrapper {
+    pub fn new(width: usize) -> Wrapper {
+        Wrapper {
+            width: width.max(HANG + 1),
+            column: 0,
+            indent: String::new(),
+            starting: true,
+            spaces: String::new(),
+        }
+    }
+
+    /// A wrapper for the terminal on stdout, or `None` when stdout isn't one
+    pub fn for_terminal() -> Option<Wrapper> {
+        if stdout_is_terminal() {
+            Some(Wrapper::new(terminal_width()))
+        } else {
+            None
+        }
+    }
+
+    /// `text` with line breaks added where it would run past the edge
+    pub fn wrap(&mut self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            if c == '\n' {
+                self.spaces.clear();
+                out.push('\n');
+                self.column = 0;
+                self.starting = true;
+                rest = &rest[1..];
+            } else if c == ' ' || c == '\t' {
+                let end = rest.find(|c| c != ' ' && c != '\t').unwrap_or(rest.len());
+                if self.starting {
+                    // Leading whitespace stays as it is
+                    out.push_str(&rest[..end]);
+                    self.column += width_of(&rest[..end], self.column);
+                } else {
+                    self.spaces.push_str(&rest[..end]);
+                }
+                rest = &rest[end..];
+            } else {
+                if self.starting {
+                    self.starting = false;
+                    let indent = (self.column + HANG).min(self.width / MAX_INDENT_SHARE);
+                    self.indent = " ".repeat(indent);
+                }
+                let end = rest.find([' ', '\t', '\n']).unwrap_or(rest.len());
+                self.word(&rest[..end], &mut out);
+                rest = &rest[end..];
+            }
+        }
+        out
+    }
+
+    fn word(&mut self, word: &str, out: &mut String) {
+        let spaces = width_of(&self.spaces, self.column);
+        let width = width_of(word, 0);
+        if self.column + spaces + width <= self.width {
+            out.push_str(&self.spaces);
+            self.column += spaces;
+        } else if self.column > self.indent.len() {
+            self.break_line(out);
+        }
+        self.spaces.clear();
+
+        // Words longer than the room left are broken wherever they run out of it, after the
+        // indent there's always room for some of one
+        let mut rest = word;
+        loop {
+            let fits = take_width(rest, self.width.saturating_sub(self.column));
+            out.push_str(&rest[..fits]);
+            self.column += width_of(&rest[..fits], 0);
+            rest = &rest[fits..];
+            if rest.is_empty() {
+                return;
+            }
+            self.break_line(out);
+        }
+    }
+
+    fn break_line(&mut self, out: &mut String) {
+        out.push('\n');
+        out.push_str(&self.indent);
+        self.column = self.indent.len();
+    }
+}
+
+/// Columns `text` takes up starting at `column`, leaving out color sequences
+fn width_of(text: &str, column: usize) -> usize {
+    let mut at = column;
+    let mut escape = false;
+    for c in text.chars() {
+        match c {
+            '\x1b' => escape = true,
+            c if escape => escape = !c.is_ascii_alphabetic(),
+            '\t' => at += 8 - at % 8,
+            c => at += c.width().unwrap_or(0),
+        }
+    }
+    at - column
+}
+
+/// Bytes of the start of `text` fitting in `room` columns, along with the color sequences
+/// among them, wide characters taking up two
+fn take_width(text: &str, room: usize) -> usize {
+    let (mut bytes, mut taken) = (0, 0);
+    let mut escape = false;
+    for (i, c) in text.char_indices() {
+        let width = c.width().unwrap_or(0);
+        if c == '\x1b' || escape {
+            escape = c == '\x1b' || !c.is_ascii_alphabetic();
+        } else if taken + width <= room {
+            // Combining marks take no room, so they stay with the character before them
+            taken += width;
+        } else {
+            break;
+        }
+        bytes = i + c.len_utf8();
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_lines_wrap_between_words_with_a_hanging_indent() {
+        let mut wrapper = Wrapper::new(20);
+        assert_eq!(
+            wrapper.wrap("  sv_cheats 0 - Allow cheats on server\nshort\n"),
+            "  sv_cheats 0 -\n    Allow cheats on\n    server\nshort\n"
+        );
+        // Color sequences take no room, and words too long for a line are broken
+        let mut wrapper = Wrapper::new(10);
+        assert_eq!(
+            wrapper.wrap("\x1b[31mred\x1b[0m text Averyveryverylongname"),
+            "\x1b[31mred\x1b[0m text\n  Averyver\n  yverylon\n  gname"
+        );
+        // Text arriving in pieces carries on from where the last piece ended
+        let mut wrapper = Wrapper::new(10);
+        let pieces = [wrapper.wrap("one two "), wrapper.wrap("three four")];
+        assert_eq!(pieces.concat(), "one two\n  three\n  four");
+        // Wide characters take two columns
+        let mut wrapper = Wrapper::new(6);
+        assert_eq!(wrapper.wrap("玩家一二三"), "玩家一\n  二三");
+    }
+}